@@ -0,0 +1,49 @@
+#!/usr/bin/env bash
+#
+# Summarizer command for `whatidid ingest transcript --summarizer`.
+#
+# Reads the session conversation (USER:/ASSISTANT: paragraphs) on stdin,
+# asks the Anthropic API (Haiku) for a summary and prints it as JSON with
+# title, labels, summary and open_questions. Exits non-zero when the API
+# key is missing or the call fails, so whatidid falls back to its built-in
+# offline summary.
+#
+# Requirements: curl, jq, ANTHROPIC_API_KEY
+
+set -euo pipefail
+
+if [[ -z "${ANTHROPIC_API_KEY:-}" ]]; then
+  echo "ANTHROPIC_API_KEY not set" >&2
+  exit 1
+fi
+
+CONVERSATION="$(cat)"
+
+PROMPT="You are summarizing a Claude Code session for a developer knowledge base.
+Analyze the transcript below, then produce a JSON object with exactly these keys:
+
+- \"title\": A concise (5-10 word) title summarizing what was done this session.
+- \"labels\": Comma-separated topic labels relevant to the work (e.g. \"rust,refactoring,bug-fix\"). Use lowercase kebab-case.
+- \"summary\": 2-4 sentences describing what was accomplished.
+- \"open_questions\": Bullet list (as a single string) of any unresolved questions, TODOs, or issues remaining. Use \"None\" if everything was resolved.
+
+Respond with ONLY valid JSON. No markdown fencing, no commentary.
+
+---
+
+## Session Transcript (tail)
+$CONVERSATION"
+
+API_BODY="$(jq -n --arg prompt "$PROMPT" '{
+  model: "claude-haiku-4-5-20251001",
+  max_tokens: 1024,
+  messages: [{role: "user", content: $prompt}]
+}')"
+
+RESPONSE="$(curl -sf --max-time 30 https://api.anthropic.com/v1/messages \
+  -H "content-type: application/json" \
+  -H "x-api-key: $ANTHROPIC_API_KEY" \
+  -H "anthropic-version: 2023-06-01" \
+  -d "$API_BODY")"
+
+printf '%s' "$RESPONSE" | jq -er '.content[0].text'
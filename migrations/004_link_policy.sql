@@ -0,0 +1,3 @@
+-- Migration 004: Per-space link policy
+-- Spaces allow cross-space links by default; set to 0 to keep a space's link graph self-contained.
+ALTER TABLE spaces ADD COLUMN allow_cross_space_links INTEGER NOT NULL DEFAULT 1;
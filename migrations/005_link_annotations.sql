@@ -0,0 +1,6 @@
+-- Migration 005: Annotate links with a note and creator identity
+-- Links created before this migration are attributed to 'unknown', matching
+-- the default identity when no user or agent can be resolved.
+ALTER TABLE links ADD COLUMN note TEXT NOT NULL DEFAULT '';
+ALTER TABLE links ADD COLUMN created_by_user TEXT NOT NULL DEFAULT 'unknown';
+ALTER TABLE links ADD COLUMN created_by_agent TEXT NOT NULL DEFAULT 'unknown';
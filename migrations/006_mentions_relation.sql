@@ -0,0 +1,26 @@
+-- Migration 006: Allow 'mentions' links
+-- Wiki-style [[references]] in page content are stored as 'mentions' links.
+-- SQLite cannot alter a CHECK constraint, so the links table is rebuilt.
+
+CREATE TABLE links_new (
+    source_id        TEXT NOT NULL REFERENCES pages(id) ON DELETE CASCADE,
+    target_id        TEXT NOT NULL REFERENCES pages(id) ON DELETE CASCADE,
+    relation         TEXT NOT NULL DEFAULT 'relates-to' CHECK(relation IN (
+        'relates-to', 'supersedes', 'depends-on', 'elaborates', 'mentions'
+    )),
+    created_at       TEXT NOT NULL DEFAULT '',
+    updated_at       TEXT NOT NULL DEFAULT '',
+    note             TEXT NOT NULL DEFAULT '',
+    created_by_user  TEXT NOT NULL DEFAULT 'unknown',
+    created_by_agent TEXT NOT NULL DEFAULT 'unknown',
+    PRIMARY KEY (source_id, target_id)
+);
+
+INSERT INTO links_new (source_id, target_id, relation, created_at, updated_at, note, created_by_user, created_by_agent)
+SELECT source_id, target_id, relation, created_at, updated_at, note, created_by_user, created_by_agent FROM links;
+
+DROP TABLE links;
+ALTER TABLE links_new RENAME TO links;
+
+-- Backlink lookups filter on target_id.
+CREATE INDEX idx_links_target ON links(target_id);
@@ -0,0 +1,11 @@
+-- Migration 007: Remember where imported pages came from
+-- Lets importers such as `import adr` find the page they created from a file
+-- on an earlier run and update it instead of creating a duplicate.
+CREATE TABLE page_sources (
+    page_id     TEXT PRIMARY KEY REFERENCES pages(id) ON DELETE CASCADE,
+    kind        TEXT NOT NULL,
+    source_path TEXT NOT NULL,
+    imported_at TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX idx_page_sources_path ON page_sources(kind, source_path);
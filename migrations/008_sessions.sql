@@ -0,0 +1,20 @@
+-- Migration 008: Tie writes to the agent session that made them
+-- Pages and links remember the session that created them. page_revisions
+-- records which session wrote each version of a page, so a session's edits
+-- can be listed too. Writes made outside a session record nothing.
+ALTER TABLE pages ADD COLUMN session_id TEXT DEFAULT NULL;
+ALTER TABLE links ADD COLUMN session_id TEXT DEFAULT NULL;
+
+CREATE TABLE page_revisions (
+    page_id          TEXT    NOT NULL REFERENCES pages(id) ON DELETE CASCADE,
+    version          INTEGER NOT NULL,
+    session_id       TEXT    NOT NULL,
+    created_by_user  TEXT    NOT NULL,
+    created_by_agent TEXT    NOT NULL,
+    created_at       TEXT    NOT NULL,
+    PRIMARY KEY (page_id, version)
+);
+
+CREATE INDEX idx_pages_session ON pages(session_id);
+CREATE INDEX idx_links_session ON links(session_id);
+CREATE INDEX idx_page_revisions_session ON page_revisions(session_id);
@@ -0,0 +1,8 @@
+-- Migration 009: Count how often pages are read
+-- `page get` and search hits bump a page's count, which recall ranking uses
+-- as a usage signal. Local usage only: not exported or synced.
+CREATE TABLE page_reads (
+    page_id      TEXT    PRIMARY KEY REFERENCES pages(id) ON DELETE CASCADE,
+    read_count   INTEGER NOT NULL DEFAULT 0,
+    last_read_at TEXT    NOT NULL
+);
@@ -0,0 +1,15 @@
+-- Migration 010: Relevance feedback on retrieved pages
+-- Each row is one verdict on whether a page was useful for a query. Recall
+-- ranking boosts or demotes pages by the verdicts given for similar queries.
+CREATE TABLE feedback (
+    id               INTEGER PRIMARY KEY AUTOINCREMENT,
+    page_id          TEXT    NOT NULL REFERENCES pages(id) ON DELETE CASCADE,
+    query            TEXT    NOT NULL,
+    useful           INTEGER NOT NULL,
+    created_by_user  TEXT    NOT NULL,
+    created_by_agent TEXT    NOT NULL,
+    session_id       TEXT    DEFAULT NULL,
+    created_at       TEXT    NOT NULL
+);
+
+CREATE INDEX idx_feedback_page ON feedback(page_id);
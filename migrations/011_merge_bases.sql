@@ -0,0 +1,13 @@
+-- Migration 011: Merge bases
+-- After `db merge`, the version of each page in this database and in the
+-- database merged from, keyed by that database's path. The next merge from
+-- the same database compares both sides to it, so a page edited on one side
+-- only isn't mistaken for a conflict.
+CREATE TABLE merge_bases (
+    source        TEXT    NOT NULL,
+    page_id       TEXT    NOT NULL REFERENCES pages(id) ON DELETE CASCADE,
+    local_version INTEGER NOT NULL,
+    other_version INTEGER NOT NULL,
+    merged_at     TEXT    NOT NULL,
+    PRIMARY KEY (source, page_id)
+);
@@ -3,8 +3,9 @@
 #
 # Usage: scripts/search-context.sh "search terms"
 #
-# This is a standalone version of the search logic from the
-# search-before-solving hook, useful for manual testing and debugging.
+# Useful for manual testing and debugging. To see exactly what the
+# UserPromptSubmit hook would add for a prompt, run:
+#   echo '{"prompt": "..."}' | whatidid hook user-prompt-submit
 
 set -euo pipefail
 
@@ -0,0 +1,584 @@
+//! Import of Architecture Decision Records (Nygard and MADR templates).
+//!
+//! Each `NNNN-title.md` file in an ADR directory becomes a `decision` page.
+//! Its headings are mapped onto the decision section schema, its status
+//! becomes a label, and "Superseded by" / "Supersedes" lines become
+//! `supersedes` links. The file each page came from is recorded in
+//! `page_sources`, so importing the same directory again updates the pages
+//! instead of duplicating them.
+
+use crate::db::{self, KbError};
+use crate::models::{AgentIdentity, ImportReport, LinkRelation, PageType};
+use crate::repo;
+use crate::wikilinks;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `page_sources.kind` for pages imported from ADR files.
+pub const SOURCE_KIND: &str = "adr";
+
+/// An ADR file, parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adr {
+    /// The number from the file name (`0007-foo.md` is 7).
+    pub number: u32,
+    pub title: String,
+    /// First word of the status, lowercased (e.g. "accepted", "superseded").
+    pub status: Option<String>,
+    /// Sections keyed by the decision schema where a heading maps onto it.
+    pub sections: serde_json::Map<String, serde_json::Value>,
+    /// Numbers of the ADRs that replace this one.
+    pub superseded_by: Vec<u32>,
+    /// Numbers of the ADRs this one replaces.
+    pub supersedes: Vec<u32>,
+}
+
+impl Adr {
+    /// Page title, prefixed with the ADR number so pages sort and read like the files.
+    pub fn page_title(&self) -> String {
+        format!("ADR-{:04}: {}", self.number, self.title)
+    }
+
+    /// Labels for the page: `adr`, plus `status:<status>` if known.
+    pub fn labels(&self) -> Vec<String> {
+        let mut labels = vec!["adr".to_string()];
+        if let Some(status) = &self.status {
+            labels.push(format!("status:{}", status));
+        }
+        labels
+    }
+}
+
+/// Returns the ADR number of a file name like `0007-use-sqlite.md`.
+pub fn adr_number(file_name: &str) -> Option<u32> {
+    let stem = file_name.strip_suffix(".md")?;
+    let digits: String = stem.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.is_empty() || !stem[digits.len()..].starts_with('-') {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Where a heading's text goes in the decision schema. Headings that only
+/// approximate a schema section keep their heading inside the text.
+fn map_heading(heading: &str) -> (String, bool) {
+    let key = match heading.to_lowercase().as_str() {
+        "context" | "context and problem statement" => return ("context".to_string(), true),
+        "decision drivers" => "context",
+        "considered options" | "options" | "options considered" => return ("options_considered".to_string(), true),
+        "pros and cons of the options" => "options_considered",
+        "decision" | "decision outcome" => return ("decision".to_string(), true),
+        "consequences" => return ("consequences".to_string(), true),
+        "positive consequences" | "negative consequences" => "consequences",
+        other => return (other.split_whitespace().collect::<Vec<_>>().join("_"), true),
+    };
+    (key.to_string(), false)
+}
+
+/// Parses one ADR in either the Nygard or the MADR layout.
+///
+/// Returns `None` if the file name doesn't start with an ADR number.
+pub fn parse_adr(file_name: &str, text: &str) -> Option<Adr> {
+    let number = adr_number(file_name)?;
+    let text = text.replace("\r\n", "\n");
+
+    // MADR 3 puts metadata in YAML frontmatter.
+    let mut status_text: Vec<String> = Vec::new();
+    let mut body = text.as_str();
+    if let Some(rest) = text.strip_prefix("---\n") {
+        if let Some(end) = rest.find("\n---\n") {
+            if let Ok(serde_yaml::Value::Mapping(map)) = serde_yaml::from_str::<serde_yaml::Value>(&rest[..end]) {
+                if let Some(status) = map.get("status").and_then(|v| v.as_str()) {
+                    status_text.push(status.to_string());
+                }
+            }
+            body = &rest[end + 5..];
+        }
+    }
+
+    let mut title = None;
+    let mut sections: Vec<(String, bool, String, Vec<&str>)> = Vec::new();
+    let mut in_status = false;
+    let mut in_fence = false;
+    for line in body.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        if !in_fence {
+            if let Some(h1) = line.strip_prefix("# ") {
+                if title.is_none() {
+                    title = Some(clean_title(h1));
+                    continue;
+                }
+            }
+            let heading = line.strip_prefix("## ").or_else(|| {
+                // MADR nests consequences under the decision outcome.
+                line.strip_prefix("### ")
+                    .filter(|h| h.trim().to_lowercase().contains("consequences"))
+            });
+            if let Some(heading) = heading {
+                let heading = heading.trim();
+                in_status = heading.eq_ignore_ascii_case("status");
+                if !in_status {
+                    let (key, exact) = map_heading(heading);
+                    sections.push((key, exact, heading.to_string(), Vec::new()));
+                }
+                continue;
+            }
+        }
+
+        if in_status {
+            if !line.trim().is_empty() {
+                status_text.push(line.trim().to_string());
+            }
+        } else if let Some((_, _, _, lines)) = sections.last_mut() {
+            lines.push(line);
+        } else if let Some(value) = metadata_value(line, "status") {
+            // Older MADR: "* Status: accepted" bullets before the first section.
+            status_text.push(value.to_string());
+        }
+    }
+
+    let mut merged = serde_json::Map::new();
+    for (key, exact, heading, lines) in sections {
+        let text = lines.join("\n").trim().to_string();
+        if text.is_empty() {
+            continue;
+        }
+        let text = if exact { text } else { format!("### {}\n{}", heading, text) };
+        match merged.get_mut(&key) {
+            Some(serde_json::Value::String(existing)) => {
+                existing.push_str("\n\n");
+                existing.push_str(&text);
+            }
+            _ => {
+                merged.insert(key, serde_json::Value::String(text));
+            }
+        }
+    }
+
+    let status = status_text.first().and_then(|s| {
+        let word: String = s
+            .trim_start_matches(|c: char| !c.is_alphabetic())
+            .chars()
+            .take_while(|c| c.is_alphabetic())
+            .collect();
+        (!word.is_empty()).then(|| word.to_lowercase())
+    });
+    let mut superseded_by = Vec::new();
+    let mut supersedes = Vec::new();
+    for line in &status_text {
+        let lower = line.to_lowercase();
+        if let Some(rest) = after(&lower, "superseded by") {
+            superseded_by.extend(first_number(rest));
+        } else if let Some(rest) = after(&lower, "supersedes") {
+            supersedes.extend(first_number(rest));
+        }
+    }
+
+    Some(Adr {
+        number,
+        title: title.unwrap_or_else(|| file_name.trim_end_matches(".md").to_string()),
+        status,
+        sections: merged,
+        superseded_by,
+        supersedes,
+    })
+}
+
+/// Strips the numbering ADR tools put in titles: "1. Use X", "ADR-0001: Use X".
+fn clean_title(h1: &str) -> String {
+    let h1 = h1.trim();
+    let rest = h1
+        .strip_prefix("ADR")
+        .map(|r| r.trim_start_matches(['-', ' ']))
+        .unwrap_or(h1);
+    let after_digits = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+    if after_digits.len() < rest.len() {
+        if let Some(title) = after_digits
+            .strip_prefix('.')
+            .or_else(|| after_digits.strip_prefix(':'))
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+        {
+            return title.to_string();
+        }
+    }
+    h1.to_string()
+}
+
+/// Value of a `Key: value` line, optionally bulleted, matched case-insensitively.
+fn metadata_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let line = line.trim().trim_start_matches(['*', '-']).trim_start();
+    let (k, v) = line.split_once(':')?;
+    k.trim().eq_ignore_ascii_case(key).then(|| v.trim())
+}
+
+fn after<'a>(haystack: &'a str, needle: &str) -> Option<&'a str> {
+    haystack.find(needle).map(|i| &haystack[i + needle.len()..])
+}
+
+fn first_number(text: &str) -> Option<u32> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let digits: String = text[start..].chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Imports every ADR file in `dir` into a space.
+///
+/// Files whose page already exists from an earlier import are updated if
+/// their title, sections or status changed, and skipped otherwise. Labels
+/// other than `status:*` that were added to a page by hand are kept. The
+/// import runs in one transaction, so a failure leaves nothing behind.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `dir` - Directory containing `NNNN-*.md` files (not searched recursively)
+/// * `space_id` - Space to create new pages in
+/// * `identity` - Recorded as the creator of new pages and links
+///
+/// # Returns
+/// Counts of created, updated and skipped pages and links, plus warnings for
+/// supersede references that couldn't be linked
+pub fn import_dir(
+    conn: &Connection,
+    dir: &Path,
+    space_id: &str,
+    identity: &AgentIdentity,
+) -> Result<ImportReport, KbError> {
+    let mut files: Vec<(PathBuf, Adr)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if let Some(adr) = parse_adr(name, &fs::read_to_string(&path)?) {
+            files.push((path, adr));
+        }
+    }
+    files.sort_by_key(|(_, adr)| adr.number);
+
+    let tx = db::begin_unless_nested(conn)?;
+    let mut report = ImportReport::default();
+    let mut pages_by_number: HashMap<u32, String> = HashMap::new();
+    let mut written: Vec<String> = Vec::new();
+    for (path, adr) in &files {
+        let source = fs::canonicalize(path)?.to_string_lossy().into_owned();
+        let sections = serde_json::Value::Object(adr.sections.clone());
+        let existing = match repo::get_page_source(conn, SOURCE_KIND, &source)? {
+            Some(id) => match repo::get_page(conn, &id) {
+                Ok(page) => Some(page),
+                Err(KbError::NotFound(_)) => None,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+
+        let page_id = match existing {
+            Some(page) => {
+                let mut labels: Vec<String> =
+                    page.labels.iter().filter(|l| !l.starts_with("status:")).cloned().collect();
+                labels.extend(adr.labels());
+                labels.sort();
+                labels.dedup();
+                let mut current = page.labels.clone();
+                current.sort();
+
+                if page.title == adr.page_title() && page.sections.as_ref() == Some(&sections) && labels == current {
+                    report.pages.skipped += 1;
+                } else {
+                    repo::update_page(conn, &page.id, Some(&adr.page_title()), None, Some(&sections), None)?;
+                    repo::set_labels(conn, &page.id, &labels)?;
+                    repo::record_page_session(conn, &page.id, identity)?;
+                    report.pages.updated += 1;
+                    written.push(page.id.clone());
+                }
+                page.id
+            }
+            None => {
+                let page = repo::create_page(
+                    conn,
+                    space_id,
+                    None,
+                    &adr.page_title(),
+                    PageType::Decision,
+                    "",
+                    Some(&sections),
+                    &adr.labels(),
+                    &identity.user,
+                    &identity.agent,
+                )?;
+                repo::set_page_source(conn, &page.id, SOURCE_KIND, &source)?;
+                repo::record_page_session(conn, &page.id, identity)?;
+                report.pages.created += 1;
+                written.push(page.id.clone());
+                page.id
+            }
+        };
+        pages_by_number.insert(adr.number, page_id);
+    }
+
+    // (newer, older): the newer ADR supersedes the older one.
+    let mut pairs: Vec<(u32, u32)> = Vec::new();
+    for (_, adr) in &files {
+        pairs.extend(adr.superseded_by.iter().map(|&newer| (newer, adr.number)));
+        pairs.extend(adr.supersedes.iter().map(|&older| (adr.number, older)));
+    }
+    pairs.sort();
+    pairs.dedup();
+
+    for (newer, older) in pairs {
+        let (Some(source), Some(target)) = (pages_by_number.get(&newer), pages_by_number.get(&older)) else {
+            report.warnings.push(format!(
+                "ADR-{:04} supersedes ADR-{:04}, but one of them is not in this directory",
+                newer, older
+            ));
+            report.links.skipped += 1;
+            continue;
+        };
+        match repo::get_link(conn, source, target) {
+            Ok(_) => {
+                report.links.skipped += 1;
+                continue;
+            }
+            Err(KbError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+        match repo::create_link(
+            conn,
+            source,
+            target,
+            LinkRelation::Supersedes,
+            "From ADR status",
+            &identity.user,
+            &identity.agent,
+        ) {
+            Ok(_) => {
+                repo::record_link_session(conn, source, target, identity)?;
+                report.links.created += 1;
+            }
+            Err(e @ (KbError::InvalidInput(_) | KbError::LinkCycle { .. })) => {
+                report
+                    .warnings
+                    .push(format!("ADR-{:04} supersedes ADR-{:04} not linked: {}", newer, older, e));
+                report.links.skipped += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // After every page is written, so references between ADRs resolve.
+    for page_id in &written {
+        wikilinks::sync_mentions_reporting(conn, page_id, identity, &mut report.warnings)?;
+    }
+    if let Some(tx) = tx {
+        tx.commit()?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NYGARD: &str = "# 2. Use SQLite for storage
+
+Date: 2024-01-05
+
+## Status
+
+Superseded by [7. Use Postgres](0007-use-postgres.md)
+
+## Context
+
+We need somewhere to keep data.
+
+## Decision
+
+We will use SQLite.
+
+## Consequences
+
+No server to run.
+";
+
+    const MADR: &str = "---
+status: accepted
+date: 2024-03-01
+---
+# Use Postgres
+
+## Context and Problem Statement
+
+SQLite can't handle our write load.
+
+## Decision Drivers
+
+* Concurrency
+
+## Considered Options
+
+* SQLite
+* Postgres
+
+## Decision Outcome
+
+Chosen option: \"Postgres\", because it scales.
+
+### Consequences
+
+* Good, because writes scale.
+
+## More Information
+
+Supersedes ADR-0002.
+";
+
+    fn identity() -> AgentIdentity {
+        AgentIdentity { user: "u".to_string(), agent: "a".to_string(), session: None }
+    }
+
+    #[test]
+    fn test_adr_number() {
+        assert_eq!(adr_number("0007-use-postgres.md"), Some(7));
+        assert_eq!(adr_number("12-x.md"), Some(12));
+        assert_eq!(adr_number("README.md"), None);
+        assert_eq!(adr_number("template.md"), None);
+        assert_eq!(adr_number("0001.md"), None);
+        assert_eq!(adr_number("0001-x.txt"), None);
+    }
+
+    #[test]
+    fn test_clean_title() {
+        assert_eq!(clean_title("1. Record architecture decisions"), "Record architecture decisions");
+        assert_eq!(clean_title("ADR-0003: Use Rust"), "Use Rust");
+        assert_eq!(clean_title("Use Rust"), "Use Rust");
+        assert_eq!(clean_title("2024 plans"), "2024 plans");
+    }
+
+    #[test]
+    fn test_parse_nygard() {
+        let adr = parse_adr("0002-use-sqlite.md", NYGARD).expect("parse");
+        assert_eq!(adr.number, 2);
+        assert_eq!(adr.title, "Use SQLite for storage");
+        assert_eq!(adr.page_title(), "ADR-0002: Use SQLite for storage");
+        assert_eq!(adr.status.as_deref(), Some("superseded"));
+        assert_eq!(adr.superseded_by, vec![7]);
+        assert_eq!(adr.sections["context"], "We need somewhere to keep data.");
+        assert_eq!(adr.sections["decision"], "We will use SQLite.");
+        assert_eq!(adr.sections["consequences"], "No server to run.");
+        assert!(!adr.sections.contains_key("status"));
+    }
+
+    #[test]
+    fn test_parse_madr() {
+        let adr = parse_adr("0007-use-postgres.md", MADR).expect("parse");
+        assert_eq!(adr.title, "Use Postgres");
+        assert_eq!(adr.status.as_deref(), Some("accepted"));
+        assert_eq!(
+            adr.sections["context"],
+            "SQLite can't handle our write load.\n\n### Decision Drivers\n* Concurrency"
+        );
+        assert_eq!(adr.sections["options_considered"], "* SQLite\n* Postgres");
+        assert_eq!(adr.sections["decision"], "Chosen option: \"Postgres\", because it scales.");
+        assert_eq!(adr.sections["consequences"], "* Good, because writes scale.");
+        assert_eq!(adr.sections["more_information"], "Supersedes ADR-0002.");
+        // "Supersedes" only counts in the status.
+        assert!(adr.supersedes.is_empty());
+    }
+
+    #[test]
+    fn test_parse_old_madr_status_bullets() {
+        let text = "# Use X\n\n* Status: superseded by [ADR-0005](0005-y.md)\n* Date: 2020-01-01\n\n## Context and Problem Statement\n\nWhy.\n";
+        let adr = parse_adr("0004-use-x.md", text).expect("parse");
+        assert_eq!(adr.status.as_deref(), Some("superseded"));
+        assert_eq!(adr.superseded_by, vec![5]);
+        assert_eq!(adr.sections["context"], "Why.");
+    }
+
+    #[test]
+    fn test_import_dir_is_idempotent() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "adr", "ADRs", "").expect("create space");
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::write(dir.path().join("0002-use-sqlite.md"), NYGARD).unwrap();
+        fs::write(dir.path().join("0007-use-postgres.md"), MADR).unwrap();
+        fs::write(dir.path().join("README.md"), "# ADRs").unwrap();
+
+        let report = import_dir(&conn, dir.path(), &space.id, &identity()).expect("import");
+        assert_eq!(report.pages.created, 2);
+        assert_eq!(report.links.created, 1);
+        assert!(report.warnings.is_empty());
+
+        let old = repo::list_pages(
+            &conn,
+            &repo::PageFilters {
+                space_id: Some(space.id.clone()),
+                page_type: Some(PageType::Decision),
+                label: Some("status:superseded".to_string()),
+                created_by_user: None,
+                created_by_agent: None,
+            },
+        )
+        .expect("list");
+        assert_eq!(old.len(), 1);
+        assert_eq!(old[0].title, "ADR-0002: Use SQLite for storage");
+        let backlinks = repo::list_backlinks(&conn, &old[0].id).expect("backlinks");
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].relation, LinkRelation::Supersedes);
+
+        // A label added by hand survives re-import.
+        repo::add_label(&conn, &old[0].id, "storage").expect("add label");
+        let report = import_dir(&conn, dir.path(), &space.id, &identity()).expect("re-import");
+        assert_eq!(report.pages.created, 0);
+        assert_eq!(report.pages.skipped, 2);
+        assert_eq!(report.links.skipped, 1);
+
+        // Editing the file updates the page in place.
+        fs::write(dir.path().join("0002-use-sqlite.md"), NYGARD.replace("We will use SQLite.", "SQLite it is.")).unwrap();
+        let report = import_dir(&conn, dir.path(), &space.id, &identity()).expect("re-import");
+        assert_eq!(report.pages.updated, 1);
+        assert_eq!(report.pages.skipped, 1);
+        let page = repo::get_page(&conn, &old[0].id).expect("get");
+        assert_eq!(page.sections.unwrap()["decision"], "SQLite it is.");
+        assert!(page.labels.contains(&"storage".to_string()));
+    }
+
+    #[test]
+    fn test_import_dir_warns_on_missing_supersede_target() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "adr", "ADRs", "").expect("create space");
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::write(dir.path().join("0002-use-sqlite.md"), NYGARD).unwrap();
+
+        let report = import_dir(&conn, dir.path(), &space.id, &identity()).expect("import");
+        assert_eq!(report.pages.created, 1);
+        assert_eq!(report.links.skipped, 1);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_import_dir_syncs_mentions_between_adrs() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "adr", "ADRs", "").expect("create space");
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::write(
+            dir.path().join("0002-use-sqlite.md"),
+            NYGARD.replace("No server to run.", "No server to run, unlike [[ADR-0007: Use Postgres]]."),
+        )
+        .unwrap();
+        fs::write(dir.path().join("0007-use-postgres.md"), MADR).unwrap();
+
+        let report = import_dir(&conn, dir.path(), &space.id, &identity()).expect("import");
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        let old: String = conn
+            .query_row("SELECT id FROM pages WHERE title = 'ADR-0002: Use SQLite for storage'", [], |row| row.get(0))
+            .expect("page");
+        let links = repo::list_links(&conn, &old).expect("links");
+        let relations: Vec<LinkRelation> = links.iter().filter(|l| l.source_id == old).map(|l| l.relation).collect();
+        assert_eq!(relations, vec![LinkRelation::Mentions]);
+    }
+}
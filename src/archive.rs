@@ -0,0 +1,581 @@
+//! Whole-knowledge-base export and import as a single JSON document.
+//!
+//! An export is a `KbArchive`: every space, page (with sections and labels)
+//! and link, written with the same `models` types the rest of the CLI
+//! prints. Importing an archive recreates those records exactly — IDs,
+//! timestamps, versions and creators included — so an export followed by an
+//! import into an empty database is lossless.
+
+use crate::db::{self, KbError};
+use crate::models::{ImportReport, KbArchive, Link, Page, Space};
+use crate::repo;
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+
+/// Value of `KbArchive::format`.
+pub const ARCHIVE_FORMAT: &str = "whatidid-kb";
+
+/// Current `KbArchive::format_version`.
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+/// What to do when an imported record already exists in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Keep the existing record.
+    Skip,
+    /// Replace the existing record with the imported one.
+    Overwrite,
+    /// Replace the existing record only if the imported one is newer.
+    NewerWins,
+}
+
+impl ConflictStrategy {
+    /// Parse from a CLI string. Returns None for unrecognized strategies.
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "skip" => Some(Self::Skip),
+            "overwrite" => Some(Self::Overwrite),
+            "newer-wins" => Some(Self::NewerWins),
+            _ => None,
+        }
+    }
+}
+
+/// Options controlling how an archive is imported.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+    pub on_conflict: ConflictStrategy,
+    /// Give every imported space and page a fresh ID instead of keeping the
+    /// archive's. Useful for copying content into a knowledge base that
+    /// already holds the originals.
+    pub remap_ids: bool,
+}
+
+/// Builds an archive of the whole knowledge base, or of a single space.
+///
+/// When exporting one space, links to or from pages outside it are left out.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `space_id` - Optional space to restrict the export to
+///
+/// # Returns
+/// The archive, with spaces, pages and links in creation order
+pub fn export(conn: &Connection, space_id: Option<&str>) -> Result<KbArchive, KbError> {
+    let mut spaces: Vec<Space> = repo::list_spaces(conn)?
+        .into_iter()
+        .filter(|s| space_id.is_none_or(|id| id == s.id))
+        .collect();
+    spaces.sort_by(|a, b| (&a.created_at, &a.id).cmp(&(&b.created_at, &b.id)));
+
+    let filters = repo::PageFilters {
+        space_id: space_id.map(|s| s.to_string()),
+        page_type: None,
+        label: None,
+        created_by_user: None,
+        created_by_agent: None,
+    };
+    let mut pages = repo::list_pages(conn, &filters)?;
+    pages.sort_by(|a, b| (&a.created_at, &a.id).cmp(&(&b.created_at, &b.id)));
+
+    let page_ids: HashSet<&str> = pages.iter().map(|p| p.id.as_str()).collect();
+    let links: Vec<Link> = repo::list_all_links(conn)?
+        .into_iter()
+        .filter(|l| page_ids.contains(l.source_id.as_str()) && page_ids.contains(l.target_id.as_str()))
+        .collect();
+
+    Ok(KbArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        format_version: ARCHIVE_FORMAT_VERSION,
+        schema_version: db::schema_version(conn)?,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        spaces,
+        pages,
+        links,
+    })
+}
+
+/// Parses and checks an archive document.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` if the text is not a whatidid archive, or
+/// was written by a newer, incompatible version.
+pub fn parse_archive(text: &str) -> Result<KbArchive, KbError> {
+    let archive: KbArchive = serde_json::from_str(text)
+        .map_err(|e| KbError::InvalidInput(format!("Not a valid export file: {}", e)))?;
+    if archive.format != ARCHIVE_FORMAT {
+        return Err(KbError::InvalidInput(format!(
+            "Not a whatidid export file (format '{}')",
+            archive.format
+        )));
+    }
+    if archive.format_version > ARCHIVE_FORMAT_VERSION {
+        return Err(KbError::InvalidInput(format!(
+            "Export format version {} is newer than this version of whatidid supports ({})",
+            archive.format_version, ARCHIVE_FORMAT_VERSION
+        )));
+    }
+    Ok(archive)
+}
+
+/// Imports an archive in a single transaction.
+///
+/// Spaces are matched against existing spaces by ID, then by slug; pages by
+/// ID; links by their source and target pages. A match is a conflict, which
+/// `options.on_conflict` resolves. Pages whose space was matched to an
+/// existing space are imported into that space.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `archive` - The archive to import
+/// * `options` - Conflict strategy and ID handling
+///
+/// # Returns
+/// Counts of created, updated and skipped records, plus warnings for records
+/// that were imported differently or not at all
+///
+/// # Errors
+/// Returns `KbError::Db` if a write fails; nothing is imported in that case.
+pub fn import(conn: &Connection, archive: &KbArchive, options: ImportOptions) -> Result<ImportReport, KbError> {
+    let tx = conn.unchecked_transaction()?;
+    let report = import_records(&tx, archive, options)?;
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Imports an archive like `import`, but without a transaction of its own,
+/// for callers that make further changes in the same transaction.
+pub fn import_records(tx: &Connection, archive: &KbArchive, options: ImportOptions) -> Result<ImportReport, KbError> {
+    let mut report = ImportReport::default();
+
+    // Archive space ID -> ID in this database.
+    let mut space_ids: HashMap<String, String> = HashMap::new();
+    for space in &archive.spaces {
+        let existing = match found(repo::get_space_by_id(tx, &space.id))? {
+            Some(s) if !options.remap_ids => Some(s),
+            _ => found(repo::get_space_by_slug(tx, &space.slug))?,
+        };
+        match existing {
+            Some(existing) => {
+                space_ids.insert(space.id.clone(), existing.id.clone());
+                if wins(options.on_conflict, &space.updated_at, &existing.updated_at) {
+                    repo::put_space(tx, &Space { id: existing.id, ..space.clone() })?;
+                    report.spaces.updated += 1;
+                } else {
+                    report.spaces.skipped += 1;
+                }
+            }
+            None => {
+                let id = new_id(&space.id, options.remap_ids);
+                repo::put_space(tx, &Space { id: id.clone(), ..space.clone() })?;
+                space_ids.insert(space.id.clone(), id);
+                report.spaces.created += 1;
+            }
+        }
+    }
+
+    // Archive page ID -> ID in this database.
+    let page_ids: HashMap<String, String> = archive
+        .pages
+        .iter()
+        .map(|p| (p.id.clone(), new_id(&p.id, options.remap_ids)))
+        .collect();
+    let by_id: HashMap<&str, &Page> = archive.pages.iter().map(|p| (p.id.as_str(), p)).collect();
+
+    // Parents must exist before their children, so import depth-first from each page up.
+    let mut done: HashSet<&str> = HashSet::new();
+    for page in &archive.pages {
+        let mut chain = vec![page];
+        let mut on_chain: HashSet<&str> = HashSet::from([page.id.as_str()]);
+        while let Some(parent) = chain
+            .last()
+            .and_then(|p| p.parent_id.as_deref())
+            .filter(|id| !done.contains(id))
+            .and_then(|id| by_id.get(id))
+        {
+            if !on_chain.insert(parent.id.as_str()) {
+                break;
+            }
+            chain.push(parent);
+        }
+        for page in chain.into_iter().rev() {
+            if done.insert(page.id.as_str()) {
+                import_page(tx, page, &space_ids, &page_ids, &done, options, &mut report)?;
+            }
+        }
+    }
+
+    for link in &archive.links {
+        let source_id = page_ids.get(&link.source_id).unwrap_or(&link.source_id);
+        let target_id = page_ids.get(&link.target_id).unwrap_or(&link.target_id);
+        if !page_exists(tx, source_id)? || !page_exists(tx, target_id)? {
+            report.warnings.push(format!(
+                "Link {} -> {} skipped: page not found",
+                link.source_id, link.target_id
+            ));
+            report.links.skipped += 1;
+            continue;
+        }
+
+        let link = Link {
+            source_id: source_id.clone(),
+            target_id: target_id.clone(),
+            ..link.clone()
+        };
+        let existing = found(repo::get_link(tx, source_id, target_id))?;
+        if let Some(existing) = &existing {
+            if !wins(options.on_conflict, &link.updated_at, &existing.updated_at) {
+                report.links.skipped += 1;
+                continue;
+            }
+        }
+        match repo::validate_link(tx, source_id, target_id, link.relation) {
+            Ok(()) => {}
+            Err(e @ (KbError::InvalidInput(_) | KbError::LinkCycle { .. })) => {
+                report
+                    .warnings
+                    .push(format!("Link {} -> {} skipped: {}", link.source_id, link.target_id, e));
+                report.links.skipped += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        repo::put_link(tx, &link)?;
+        if existing.is_some() {
+            report.links.updated += 1;
+        } else {
+            report.links.created += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Imports one page whose parent (if in the archive) has already been handled.
+fn import_page(
+    conn: &Connection,
+    page: &Page,
+    space_ids: &HashMap<String, String>,
+    page_ids: &HashMap<String, String>,
+    done: &HashSet<&str>,
+    options: ImportOptions,
+    report: &mut ImportReport,
+) -> Result<(), KbError> {
+    let archive_id = page.id.as_str();
+    let id = &page_ids[&page.id];
+    let space_id = match space_ids.get(&page.space_id) {
+        Some(id) => Some(id.clone()),
+        // Not in the archive's space list; accept it if the space is already here.
+        None => found(repo::get_space_by_id(conn, &page.space_id))?.map(|s| s.id),
+    };
+    let Some(space_id) = space_id else {
+        report.warnings.push(format!("Page '{}' skipped: space {} not found", page.title, page.space_id));
+        report.pages.skipped += 1;
+        return Ok(());
+    };
+
+    let parent_id = match page.parent_id.as_deref() {
+        None => None,
+        Some(parent) => {
+            let mapped = page_ids.get(parent).map(String::as_str).unwrap_or(parent);
+            // A parent later in a cycle hasn't been written yet; so has a missing one.
+            let ready = (done.contains(parent) || !page_ids.contains_key(parent)) && page_exists(conn, mapped)?;
+            if ready {
+                Some(mapped.to_string())
+            } else {
+                report
+                    .warnings
+                    .push(format!("Page '{}' imported at top level: parent {} not found", page.title, parent));
+                None
+            }
+        }
+    };
+
+    let page = Page {
+        id: id.clone(),
+        space_id,
+        parent_id,
+        ..page.clone()
+    };
+    match found(repo::get_page(conn, id))? {
+        Some(existing) => {
+            let newer = page.version > existing.version
+                || (page.version == existing.version && is_later(&page.updated_at, &existing.updated_at));
+            let replace = match options.on_conflict {
+                ConflictStrategy::Skip => false,
+                ConflictStrategy::Overwrite => true,
+                ConflictStrategy::NewerWins => newer,
+            };
+            if replace {
+                repo::put_page(conn, &page)?;
+                report.pages.updated += 1;
+                report.written_pages.push((archive_id.to_string(), page.id.clone()));
+            } else {
+                report.pages.skipped += 1;
+            }
+        }
+        None => {
+            repo::put_page(conn, &page)?;
+            report.pages.created += 1;
+            report.written_pages.push((archive_id.to_string(), page.id.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Whether an imported record replaces an existing one with the given timestamps.
+fn wins(strategy: ConflictStrategy, imported_updated_at: &str, existing_updated_at: &str) -> bool {
+    match strategy {
+        ConflictStrategy::Skip => false,
+        ConflictStrategy::Overwrite => true,
+        ConflictStrategy::NewerWins => is_later(imported_updated_at, existing_updated_at),
+    }
+}
+
+/// Compares two RFC 3339 timestamps, falling back to string order if either
+/// doesn't parse.
+pub fn is_later(a: &str, b: &str) -> bool {
+    match (
+        chrono::DateTime::parse_from_rfc3339(a),
+        chrono::DateTime::parse_from_rfc3339(b),
+    ) {
+        (Ok(a), Ok(b)) => a > b,
+        _ => a > b,
+    }
+}
+
+fn new_id(id: &str, remap: bool) -> String {
+    if remap {
+        uuid::Uuid::new_v4().to_string()
+    } else {
+        id.to_string()
+    }
+}
+
+fn page_exists(conn: &Connection, id: &str) -> Result<bool, KbError> {
+    Ok(found(repo::get_page(conn, id))?.is_some())
+}
+
+/// Turns `NotFound` into `None`, passing other errors through.
+fn found<T>(result: Result<T, KbError>) -> Result<Option<T>, KbError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(KbError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{LinkRelation, PageType};
+
+    /// Two spaces, a parent/child pair with labels and sections, and a link.
+    fn populate(conn: &Connection) -> (Space, Page, Page) {
+        let space = repo::create_space(conn, "proj", "Project", "desc").expect("create space");
+        repo::create_space(conn, "other", "Other", "").expect("create space");
+        let parent = repo::create_page(
+            conn,
+            &space.id,
+            None,
+            "Use SQLite",
+            PageType::Decision,
+            "",
+            Some(&serde_json::json!({"context": "Need storage", "decision": "SQLite"})),
+            &["db".to_string()],
+            "alice",
+            "agent",
+        )
+        .expect("create parent");
+        let child = repo::create_page(
+            conn, &space.id, Some(&parent.id), "WAL notes", PageType::Reference, "Use WAL.", None, &[], "bob", "agent",
+        )
+        .expect("create child");
+        repo::create_link(conn, &child.id, &parent.id, LinkRelation::Elaborates, "details", "bob", "agent")
+            .expect("create link");
+        (space, parent, child)
+    }
+
+    fn options(on_conflict: ConflictStrategy) -> ImportOptions {
+        ImportOptions { on_conflict, remap_ids: false }
+    }
+
+    #[test]
+    fn test_round_trip_is_lossless() {
+        let source = db::test_connection();
+        populate(&source);
+        let archive = export(&source, None).expect("export");
+        let text = serde_json::to_string(&archive).expect("serialize");
+
+        let target = db::test_connection();
+        let report = import(&target, &parse_archive(&text).expect("parse"), options(ConflictStrategy::Skip))
+            .expect("import");
+        assert_eq!(report.spaces.created, 2);
+        assert_eq!(report.pages.created, 2);
+        assert_eq!(report.links.created, 1);
+        assert!(report.warnings.is_empty());
+
+        let again = export(&target, None).expect("re-export");
+        assert_eq!(
+            serde_json::to_value(&again.spaces).unwrap(),
+            serde_json::to_value(&archive.spaces).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&again.pages).unwrap(),
+            serde_json::to_value(&archive.pages).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&again.links).unwrap(),
+            serde_json::to_value(&archive.links).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_export_single_space() {
+        let conn = db::test_connection();
+        let (space, _, child) = populate(&conn);
+        let other = repo::get_space_by_slug(&conn, "other").expect("get space");
+        let stray = repo::create_page(&conn, &other.id, None, "Stray", PageType::Reference, "", None, &[], "u", "a")
+            .expect("create page");
+        repo::create_link(&conn, &child.id, &stray.id, LinkRelation::RelatesTo, "", "u", "a").expect("link");
+
+        let archive = export(&conn, Some(&space.id)).expect("export");
+        assert_eq!(archive.spaces.len(), 1);
+        assert_eq!(archive.pages.len(), 2);
+        assert_eq!(archive.links.len(), 1, "links leaving the space are dropped");
+        assert_eq!(archive.format, ARCHIVE_FORMAT);
+        assert_eq!(archive.schema_version, db::schema_version(&conn).unwrap());
+    }
+
+    #[test]
+    fn test_parent_imported_before_child() {
+        let source = db::test_connection();
+        populate(&source);
+        let mut archive = export(&source, None).expect("export");
+        archive.pages.reverse();
+
+        let target = db::test_connection();
+        let report = import(&target, &archive, options(ConflictStrategy::Skip)).expect("import");
+        assert_eq!(report.pages.created, 2);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_conflict_strategies() {
+        let conn = db::test_connection();
+        let (_, parent, _) = populate(&conn);
+        let archive = export(&conn, None).expect("export");
+
+        // Skip: importing into itself changes nothing.
+        let report = import(&conn, &archive, options(ConflictStrategy::Skip)).expect("import");
+        assert_eq!(report.pages.skipped, 2);
+        assert_eq!(report.spaces.skipped, 2);
+        assert_eq!(report.links.skipped, 1);
+
+        // Local edit makes the database newer than the archive.
+        repo::update_page(&conn, &parent.id, Some("Use SQLite (edited)"), None, None, None).expect("update");
+
+        let report = import(&conn, &archive, options(ConflictStrategy::NewerWins)).expect("import");
+        assert_eq!(report.pages.updated, 0);
+        assert_eq!(repo::get_page(&conn, &parent.id).unwrap().title, "Use SQLite (edited)");
+
+        let report = import(&conn, &archive, options(ConflictStrategy::Overwrite)).expect("import");
+        assert_eq!(report.pages.updated, 2);
+        let restored = repo::get_page(&conn, &parent.id).unwrap();
+        assert_eq!(restored.title, "Use SQLite");
+        assert_eq!(restored.version, 1);
+        assert_eq!(restored.labels, vec!["db".to_string()]);
+
+        // Now the archive copy is newer.
+        let mut newer = archive.clone();
+        let page = newer.pages.iter_mut().find(|p| p.id == parent.id).unwrap();
+        page.title = "Use SQLite v2".to_string();
+        page.version = 2;
+        let report = import(&conn, &newer, options(ConflictStrategy::NewerWins)).expect("import");
+        assert_eq!(report.pages.updated, 1);
+        assert_eq!(repo::get_page(&conn, &parent.id).unwrap().title, "Use SQLite v2");
+    }
+
+    #[test]
+    fn test_remap_ids_copies_content() {
+        let conn = db::test_connection();
+        let (space, parent, _) = populate(&conn);
+        let archive = export(&conn, Some(&space.id)).expect("export");
+
+        let report = import(&conn, &archive, ImportOptions { on_conflict: ConflictStrategy::Skip, remap_ids: true })
+            .expect("import");
+        // The space matches by slug, so the copies land in it.
+        assert_eq!(report.spaces.skipped, 1);
+        assert_eq!(report.pages.created, 2);
+        assert_eq!(report.links.created, 1);
+
+        let pages = repo::list_pages(
+            &conn,
+            &repo::PageFilters {
+                space_id: Some(space.id.clone()),
+                page_type: None,
+                label: None,
+                created_by_user: None,
+                created_by_agent: None,
+            },
+        )
+        .expect("list");
+        assert_eq!(pages.len(), 4);
+        let copy = pages.iter().find(|p| p.title == "WAL notes" && p.parent_id.as_deref() != Some(parent.id.as_str())).unwrap();
+        let copy_parent = repo::get_page(&conn, copy.parent_id.as_deref().unwrap()).unwrap();
+        assert_eq!(copy_parent.title, "Use SQLite");
+        assert_ne!(copy_parent.id, parent.id);
+        assert_eq!(repo::list_links(&conn, &copy.id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_missing_references_become_warnings() {
+        let source = db::test_connection();
+        let (_, parent, child) = populate(&source);
+        let mut archive = export(&source, None).expect("export");
+        archive.pages.retain(|p| p.id == child.id);
+
+        let target = db::test_connection();
+        let report = import(&target, &archive, options(ConflictStrategy::Skip)).expect("import");
+        assert_eq!(report.pages.created, 1);
+        assert_eq!(report.links.skipped, 1);
+        assert_eq!(report.warnings.len(), 2);
+        assert_eq!(repo::get_page(&target, &child.id).unwrap().parent_id, None);
+        assert!(repo::get_page(&target, &parent.id).is_err());
+    }
+
+    #[test]
+    fn test_links_breaking_rules_become_warnings() {
+        let source = db::test_connection();
+        let (_, parent, child) = populate(&source);
+        let mut archive = export(&source, None).expect("export");
+        // A hand-edited archive: a supersedes cycle and a self-link.
+        let link = archive.links[0].clone();
+        archive.links = vec![
+            Link { relation: LinkRelation::Supersedes, ..link.clone() },
+            Link { source_id: parent.id.clone(), target_id: child.id.clone(), relation: LinkRelation::Supersedes, ..link.clone() },
+            Link { source_id: parent.id.clone(), target_id: parent.id.clone(), ..link },
+        ];
+
+        let target = db::test_connection();
+        let report = import(&target, &archive, options(ConflictStrategy::Skip)).expect("import");
+        assert_eq!(report.links.created, 1);
+        assert_eq!(report.links.skipped, 2);
+        assert_eq!(report.warnings.len(), 2);
+        assert!(repo::check_links(&target).expect("check").violations.is_empty());
+    }
+
+    #[test]
+    fn test_parse_archive_rejects_other_documents() {
+        assert!(matches!(parse_archive("{}"), Err(KbError::InvalidInput(_))));
+        let conn = db::test_connection();
+        let mut archive = export(&conn, None).expect("export");
+        archive.format = "something-else".to_string();
+        let text = serde_json::to_string(&archive).unwrap();
+        assert!(matches!(parse_archive(&text), Err(KbError::InvalidInput(_))));
+        archive.format = ARCHIVE_FORMAT.to_string();
+        archive.format_version = ARCHIVE_FORMAT_VERSION + 1;
+        let text = serde_json::to_string(&archive).unwrap();
+        assert!(matches!(parse_archive(&text), Err(KbError::InvalidInput(_))));
+    }
+}
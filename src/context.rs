@@ -0,0 +1,433 @@
+//! Token-budgeted context packs for agents.
+//!
+//! `build_pack` turns a task description into the most useful knowledge-base
+//! material that fits a token budget. Keywords from the task are searched one
+//! at a time and the results fused by rank; pitfalls and accepted decisions
+//! get a boost, superseded decisions a penalty, and pages linked to the best
+//! matches are pulled in below them. Each page contributes its most relevant
+//! paragraphs, and items are packed greedily in score order until the budget
+//! is spent, the last one trimmed to fit if that leaves enough of it to be
+//! useful.
+//!
+//! Tokens are estimated as one per four characters, which is close for
+//! English prose and errs high for code.
+
+use crate::db::KbError;
+use crate::models::{ContextItem, ContextPack, ContextReason, LinkRelation, Page, PageType};
+use crate::{repo, search};
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+
+/// Words too common to be worth searching for.
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been", "being", "but", "by",
+    "can", "could", "day", "did", "do", "does", "doing", "done", "each", "every", "for", "from", "get", "got", "had",
+    "has", "have", "her", "him", "his", "how", "i", "if", "in", "into", "is", "it", "its", "just", "let", "like",
+    "make", "may", "me", "more", "most", "my", "need", "new", "no", "not", "now", "of", "old", "on", "one", "only",
+    "or", "our", "out", "please", "see", "should", "so", "some", "than", "that", "the", "their", "them", "then",
+    "there", "these", "they", "this", "to", "too", "up", "us", "use", "using", "very", "want", "was", "way", "we",
+    "were", "what", "when", "where", "which", "while", "who", "why", "will", "with", "would", "you", "your",
+];
+
+/// Most keywords taken from a task description.
+pub const MAX_KEYWORDS: usize = 8;
+
+/// Label that marks a page as a pitfall.
+pub const PITFALL_LABEL: &str = "pitfall";
+
+/// How many of the best matches have their linked pages pulled in.
+const LINK_SOURCES: usize = 5;
+
+/// Snippets are never trimmed below this many characters; an item that would
+/// need to be is left out instead.
+const MIN_SNIPPET_CHARS: usize = 160;
+
+/// Estimates the tokens in a piece of text: one per four characters, rounded up.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Extracts search keywords from free text: lowercase words of three or more
+/// letters or digits that aren't stop words, in order of first appearance,
+/// without repeats, at most `MAX_KEYWORDS`.
+pub fn extract_keywords(text: &str) -> Vec<String> {
+    extract_keywords_with(text, 3, MAX_KEYWORDS)
+}
+
+/// Like `extract_keywords`, with the shortest word kept and the most
+/// keywords returned chosen by the caller.
+pub fn extract_keywords_with(text: &str, min_len: usize, limit: usize) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')) {
+        if keywords.len() >= limit {
+            break;
+        }
+        let word = word.trim_matches('-').to_lowercase();
+        if word.is_empty() || word.chars().count() < min_len || STOP_WORDS.contains(&word.as_str()) || keywords.contains(&word) {
+            continue;
+        }
+        keywords.push(word);
+    }
+    keywords
+}
+
+/// A page being considered for the pack.
+struct Candidate {
+    page: Page,
+    score: f64,
+    reason: ContextReason,
+    via: Option<String>,
+}
+
+/// Builds a context pack for a task.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `task` - Free-text description of the task
+/// * `space_id` - Optional space to search in (linked pages may come from anywhere)
+/// * `budget` - Token budget for the Markdown rendering of the pack
+///
+/// # Returns
+/// The pack, with items in score order
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` if the task has no searchable words, or
+/// `KbError::Db` if a query fails.
+pub fn build_pack(conn: &Connection, task: &str, space_id: Option<&str>, budget: usize) -> Result<ContextPack, KbError> {
+    let keywords = extract_keywords(task);
+    if keywords.is_empty() {
+        return Err(KbError::InvalidInput(
+            "The task description has no searchable words".to_string(),
+        ));
+    }
+
+    // Reciprocal-rank fusion over one recall search per keyword, plus a
+    // bonus for keywords in the title.
+    let weights = search::RecallWeights::configured(None)?;
+    let mut candidates: HashMap<String, Candidate> = HashMap::new();
+    for keyword in &keywords {
+        let params = search::SearchParams {
+            query: Some(keyword.clone()),
+            space_id: space_id.map(str::to_string),
+            page_type: None,
+            label: None,
+            created_by_agent: None,
+            section: None,
+        };
+        for (rank, recalled) in search::recall(conn, &params, &weights)?.into_iter().enumerate() {
+            let result = recalled.result;
+            let title_bonus = if result.page.title.to_lowercase().contains(keyword.as_str()) { 0.5 } else { 0.0 };
+            let candidate = candidates.entry(result.page.id.clone()).or_insert_with(|| Candidate {
+                page: result.page,
+                score: 0.0,
+                reason: ContextReason::Match,
+                via: None,
+            });
+            candidate.score += 1.0 / (rank as f64 + 1.0) + title_bonus;
+        }
+    }
+
+    for candidate in candidates.values_mut() {
+        if candidate.page.labels.iter().any(|l| l == PITFALL_LABEL) {
+            candidate.reason = ContextReason::Pitfall;
+            candidate.score *= 1.5;
+        } else if candidate.page.page_type == PageType::Decision {
+            if is_current_decision(conn, &candidate.page)? {
+                candidate.reason = ContextReason::Decision;
+                candidate.score *= 1.25;
+            } else {
+                candidate.score *= 0.5;
+            }
+        }
+    }
+
+    let mut ranked: Vec<Candidate> = candidates.into_values().collect();
+    sort_by_score(&mut ranked);
+
+    let mut seen: HashSet<String> = ranked.iter().map(|c| c.page.id.clone()).collect();
+    let mut linked = Vec::new();
+    for source in ranked.iter().take(LINK_SOURCES) {
+        for link in repo::list_links(conn, &source.page.id)? {
+            let other = if link.source_id == source.page.id { link.target_id } else { link.source_id };
+            if seen.insert(other.clone()) {
+                linked.push(Candidate {
+                    page: repo::get_page(conn, &other)?,
+                    score: source.score * 0.5,
+                    reason: ContextReason::Linked,
+                    via: Some(source.page.id.clone()),
+                });
+            }
+        }
+    }
+    ranked.extend(linked);
+    sort_by_score(&mut ranked);
+
+    let slugs: HashMap<String, String> = repo::list_spaces(conn)?.into_iter().map(|s| (s.id, s.slug)).collect();
+    let mut pack = ContextPack {
+        task: task.to_string(),
+        keywords,
+        budget,
+        estimated_tokens: 0,
+        items: Vec::new(),
+        omitted: 0,
+    };
+    let snippet_cap = budget.max(400);
+    let mut used = estimate_tokens(&render_header(&pack.task));
+    let mut sections_started: HashSet<ContextReason> = HashSet::new();
+    for candidate in ranked {
+        let (snippet, truncated) = best_snippet(&candidate.page.content, &pack.keywords, snippet_cap);
+        let mut item = ContextItem {
+            page_id: candidate.page.id.clone(),
+            title: candidate.page.title.clone(),
+            page_type: candidate.page.page_type,
+            space_slug: slugs.get(&candidate.page.space_id).cloned().unwrap_or_default(),
+            labels: candidate.page.labels.clone(),
+            reason: candidate.reason,
+            via: candidate.via,
+            score: (candidate.score * 1000.0).round() / 1000.0,
+            snippet,
+            truncated,
+        };
+
+        let heading = if sections_started.contains(&item.reason) {
+            0
+        } else {
+            estimate_tokens(&render_section_heading(item.reason))
+        };
+        let remaining = budget.saturating_sub(used + heading);
+        let cost = estimate_tokens(&render_item(&item));
+        if cost > remaining {
+            // Trim the snippet to what's left, if that leaves a useful amount.
+            let overhead = cost * 4 - item.snippet.chars().count();
+            let room = (remaining * 4).saturating_sub(overhead + 4);
+            if room < MIN_SNIPPET_CHARS {
+                pack.omitted += 1;
+                continue;
+            }
+            item.snippet = truncate(&item.snippet, room);
+            item.truncated = true;
+        }
+        used += heading + estimate_tokens(&render_item(&item));
+        sections_started.insert(item.reason);
+        pack.items.push(item);
+    }
+
+    pack.estimated_tokens = estimate_tokens(&render_markdown(&pack));
+    Ok(pack)
+}
+
+/// Whether a decision still stands: nothing supersedes it and its status,
+/// if labelled, is `accepted`.
+pub fn is_current_decision(conn: &Connection, page: &Page) -> Result<bool, KbError> {
+    let status_ok = page
+        .labels
+        .iter()
+        .filter_map(|l| l.strip_prefix("status:"))
+        .all(|status| status == "accepted");
+    if !status_ok {
+        return Ok(false);
+    }
+    let superseded = repo::list_backlinks(conn, &page.id)?
+        .iter()
+        .any(|l| l.relation == LinkRelation::Supersedes);
+    Ok(!superseded)
+}
+
+fn sort_by_score(candidates: &mut [Candidate]) {
+    candidates.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.page.updated_at.cmp(&a.page.updated_at))
+            .then_with(|| a.page.id.cmp(&b.page.id))
+    });
+}
+
+/// Picks the paragraphs of `content` that mention the most keywords, in
+/// their original order, up to `cap` characters. Falls back to the opening
+/// paragraphs if none match. Returns the snippet and whether it was cut short.
+pub fn best_snippet(content: &str, keywords: &[String], cap: usize) -> (String, bool) {
+    let paragraphs: Vec<&str> = content
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect();
+    let hits = |p: &str| {
+        let lower = p.to_lowercase();
+        keywords.iter().map(|k| lower.matches(k.as_str()).count()).sum::<usize>()
+    };
+
+    let mut order: Vec<usize> = (0..paragraphs.len()).filter(|i| hits(paragraphs[*i]) > 0).collect();
+    if order.is_empty() {
+        order = (0..paragraphs.len()).collect();
+    } else {
+        order.sort_by_key(|i| std::cmp::Reverse(hits(paragraphs[*i])));
+    }
+
+    let mut chosen = Vec::new();
+    let mut length = 0;
+    let mut truncated = false;
+    for i in order {
+        let extra = paragraphs[i].chars().count() + if chosen.is_empty() { 0 } else { 2 };
+        if length + extra > cap {
+            truncated = true;
+            if chosen.is_empty() {
+                return (truncate(paragraphs[i], cap), true);
+            }
+            continue;
+        }
+        chosen.push(i);
+        length += extra;
+    }
+    chosen.sort();
+    let snippet = chosen.iter().map(|i| paragraphs[*i]).collect::<Vec<_>>().join("\n\n");
+    (snippet, truncated)
+}
+
+/// Cuts text to at most `max` characters, at a word boundary where possible,
+/// ending with an ellipsis.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max.saturating_sub(1)).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(pos) if pos > cut.len() / 2 => &cut[..pos],
+        _ => &cut[..],
+    };
+    format!("{}…", cut.trim_end())
+}
+
+/// Renders a pack as Markdown, grouped by why each page was included.
+pub fn render_markdown(pack: &ContextPack) -> String {
+    let mut out = render_header(&pack.task);
+    for reason in [
+        ContextReason::Pitfall,
+        ContextReason::Decision,
+        ContextReason::Match,
+        ContextReason::Linked,
+    ] {
+        let items: Vec<&ContextItem> = pack.items.iter().filter(|i| i.reason == reason).collect();
+        if items.is_empty() {
+            continue;
+        }
+        out.push_str(&render_section_heading(reason));
+        for item in items {
+            out.push_str(&render_item(item));
+        }
+    }
+    out
+}
+
+fn render_header(task: &str) -> String {
+    format!("## Context for: {}\n\n", task.lines().next().unwrap_or("").trim())
+}
+
+fn render_section_heading(reason: ContextReason) -> String {
+    let heading = match reason {
+        ContextReason::Pitfall => "Known pitfalls",
+        ContextReason::Decision => "Accepted decisions",
+        ContextReason::Match => "Related pages",
+        ContextReason::Linked => "Linked pages",
+    };
+    format!("### {}\n\n", heading)
+}
+
+fn render_item(item: &ContextItem) -> String {
+    let mut meta = format!("`{}` · {} in {}", item.page_id, item.page_type, item.space_slug);
+    if !item.labels.is_empty() {
+        meta.push_str(&format!(" · {}", item.labels.join(", ")));
+    }
+    if let Some(via) = &item.via {
+        meta.push_str(&format!(" · linked with `{}`", via));
+    }
+    if item.snippet.is_empty() {
+        format!("#### {}\n{}\n\n", item.title, meta)
+    } else {
+        format!("#### {}\n{}\n\n{}\n\n", item.title, meta, item.snippet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn page(conn: &Connection, space_id: &str, title: &str, page_type: PageType, body: &str, labels: &[&str]) -> Page {
+        let labels: Vec<String> = labels.iter().map(|l| l.to_string()).collect();
+        repo::create_page(conn, space_id, None, title, page_type, body, None, &labels, "u", "a").unwrap()
+    }
+
+    #[test]
+    fn test_extract_keywords() {
+        assert_eq!(
+            extract_keywords("How should we fix the SQLite WAL locking? The locking is flaky."),
+            vec!["fix", "sqlite", "wal", "locking", "flaky"]
+        );
+        assert!(extract_keywords("is it on?").is_empty());
+    }
+
+    #[test]
+    fn test_best_snippet_prefers_matching_paragraphs() {
+        let content = "Intro text.\n\nAll about locking here.\n\nUnrelated.\n\nMore locking and wal.";
+        let keywords = vec!["locking".to_string(), "wal".to_string()];
+        let (snippet, truncated) = best_snippet(content, &keywords, 1000);
+        assert_eq!(snippet, "All about locking here.\n\nMore locking and wal.");
+        assert!(!truncated);
+
+        let (snippet, truncated) = best_snippet(content, &keywords, 25);
+        assert_eq!(snippet, "More locking and wal.");
+        assert!(truncated);
+    }
+
+    #[test]
+    fn test_pack_ranks_pitfalls_decisions_and_links() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "proj", "Proj", "").unwrap();
+        let pitfall = page(&conn, &space.id, "WAL checkpoints stall", PageType::Troubleshooting, "Locking stalls under WAL.", &["pitfall"]);
+        let decision = page(&conn, &space.id, "Use WAL mode", PageType::Decision, "We chose WAL for locking.", &[]);
+        let old = page(&conn, &space.id, "Use rollback journal", PageType::Decision, "Locking with the rollback journal.", &[]);
+        repo::create_link(&conn, &decision.id, &old.id, LinkRelation::Supersedes, "", "u", "a").unwrap();
+        let linked = page(&conn, &space.id, "Benchmarks", PageType::Reference, "Numbers only.", &[]);
+        repo::create_link(&conn, &decision.id, &linked.id, LinkRelation::RelatesTo, "", "u", "a").unwrap();
+        page(&conn, &space.id, "Unrelated", PageType::Reference, "Nothing here.", &[]);
+
+        let pack = build_pack(&conn, "Investigate WAL locking", None, 4000).unwrap();
+        let reasons: Vec<(&str, ContextReason)> =
+            pack.items.iter().map(|i| (i.page_id.as_str(), i.reason)).collect();
+        assert_eq!(reasons[0], (pitfall.id.as_str(), ContextReason::Pitfall));
+        assert!(reasons.contains(&(decision.id.as_str(), ContextReason::Decision)));
+        // Superseded decisions are still relevant matches, just not decisions.
+        assert!(reasons.contains(&(old.id.as_str(), ContextReason::Match)));
+        let link = pack.items.iter().find(|i| i.page_id == linked.id).expect("linked page included");
+        assert_eq!(link.reason, ContextReason::Linked);
+        assert_eq!(link.via.as_deref(), Some(decision.id.as_str()));
+        assert_eq!(pack.items.len(), 4);
+
+        let markdown = render_markdown(&pack);
+        assert!(markdown.contains("### Known pitfalls"));
+        assert!(markdown.contains(&format!("`{}`", pitfall.id)));
+        assert_eq!(pack.estimated_tokens, estimate_tokens(&markdown));
+    }
+
+    #[test]
+    fn test_pack_fits_budget() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "proj", "Proj", "").unwrap();
+        for i in 0..10 {
+            let body = format!("Paragraph about caching number {}. {}", i, "filler words ".repeat(80));
+            page(&conn, &space.id, &format!("Caching note {}", i), PageType::Reference, &body, &[]);
+        }
+
+        let pack = build_pack(&conn, "caching", None, 300).unwrap();
+        assert!(pack.estimated_tokens <= 300, "{} tokens", pack.estimated_tokens);
+        assert!(!pack.items.is_empty());
+        assert!(pack.omitted > 0);
+        assert_eq!(pack.items.len() + pack.omitted, 10);
+    }
+
+    #[test]
+    fn test_pack_rejects_empty_task() {
+        let conn = db::test_connection();
+        assert!(matches!(build_pack(&conn, "to be", None, 100), Err(KbError::InvalidInput(_))));
+    }
+}
@@ -4,9 +4,13 @@
 //! for concurrent access (WAL mode, foreign keys, busy timeout), schema
 //! versioning via migrations, and a unified error type for the entire crate.
 
-use rusqlite::Connection;
+use crate::models::{DbCheckReport, DbSource, ForeignKeyViolation, MigrationReport, MigrationState, MigrationStatus};
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, OpenFlags, Transaction};
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
 /// Central error type for the knowledge base application.
@@ -34,78 +38,136 @@ pub enum KbError {
     /// Invalid input provided by the user or caller.
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+
+    /// A git command run by `sync` failed.
+    #[error("Git error: {0}")]
+    Git(String),
+
+    /// A link would close a cycle in a relation that must stay acyclic.
+    #[error("Link would create a '{relation}' cycle: {}", .path.join(" -> "))]
+    LinkCycle { relation: String, path: Vec<String> },
+
+    /// A new page nearly duplicates existing pages, listed as "<id> (<title>)".
+    #[error(
+        "Near-duplicate of existing page(s): {}. Use --on-duplicate append, link or create to proceed",
+        .matches.join(", ")
+    )]
+    Duplicate { matches: Vec<String> },
 }
 
-/// Returns the path to the SQLite database file.
+/// Directory that marks a project and holds its database, found by walking
+/// up from the current directory (like `.git`).
+pub const PROJECT_DIR: &str = ".whatidid";
+
+/// Database file name, inside `PROJECT_DIR` or `~/.knowledge-base`.
+pub const DB_FILE: &str = "kb.db";
+
+/// Resolves the path to the SQLite database file, and where that choice came from.
 ///
 /// Resolution order:
-/// 1. `KB_PATH` environment variable (if set)
-/// 2. `~/.knowledge-base/kb.db` (default)
+/// 1. `path`, from the `--db` flag (if given)
+/// 2. `KB_PATH` environment variable (if set)
+/// 3. `.whatidid/kb.db` in the current directory or the nearest ancestor with a `.whatidid/` directory
+/// 4. `~/.knowledge-base/kb.db` (default)
 ///
 /// Creates the parent directory if it doesn't exist.
 ///
 /// # Errors
 ///
 /// Returns `KbError::Io` if:
-/// - Home directory cannot be determined (when `KB_PATH` is not set)
+/// - Home directory cannot be determined (when no other location applies)
 /// - Parent directory creation fails
 ///
 /// # Examples
 ///
 /// ```no_run
-/// use kb::db::db_path;
+/// use kb::db::locate_db;
 ///
-/// let path = db_path().expect("Failed to get database path");
-/// println!("Database at: {:?}", path);
+/// let (path, source) = locate_db(None).expect("Failed to get database path");
+/// println!("Database at: {:?} ({})", path, source.as_str());
 /// ```
-pub fn db_path() -> Result<PathBuf, KbError> {
-    let path = if let Ok(kb_path) = std::env::var("KB_PATH") {
-        PathBuf::from(kb_path)
+pub fn locate_db(path: Option<&Path>) -> Result<(PathBuf, DbSource), KbError> {
+    let start = std::env::current_dir().ok();
+    locate_db_from(path, start.as_deref())
+}
+
+/// Like `locate_db`, but looks for a project database from `start` instead
+/// of the current directory. Hooks use this with the session's working
+/// directory, which needn't be the directory they were launched from.
+pub fn locate_db_from(path: Option<&Path>, start: Option<&Path>) -> Result<(PathBuf, DbSource), KbError> {
+    let located = if let Some(path) = path {
+        (path.to_path_buf(), DbSource::Custom)
+    } else if let Ok(kb_path) = std::env::var("KB_PATH") {
+        (PathBuf::from(kb_path), DbSource::Custom)
+    } else if let Some(path) = start.and_then(find_project_db) {
+        (path, DbSource::Project)
     } else {
-        let home = dirs::home_dir().ok_or_else(|| {
-            KbError::Io(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Could not determine home directory",
-            ))
-        })?;
-        home.join(".knowledge-base").join("kb.db")
+        (global_db_path()?, DbSource::Global)
     };
 
     // Ensure the parent directory exists
-    if let Some(parent) = path.parent() {
+    if let Some(parent) = located.0.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    Ok(path)
+    Ok(located)
 }
 
-/// Opens a SQLite connection to the default database with proper settings.
-///
-/// Configured for multi-agent concurrent access:
-/// - **WAL mode**: Allows concurrent readers with serialized writers
-/// - **Foreign keys**: Enabled for referential integrity
-/// - **Busy timeout**: 5 seconds to handle write contention gracefully
+/// Returns `~/.knowledge-base/kb.db`, the database shared by every project
+/// without its own.
 ///
 /// # Errors
 ///
-/// Returns `KbError::Db` if the connection cannot be opened or configured.
+/// Returns `KbError::Io` if the home directory cannot be determined.
+pub fn global_db_path() -> Result<PathBuf, KbError> {
+    let home = dirs::home_dir().ok_or_else(|| {
+        KbError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Could not determine home directory",
+        ))
+    })?;
+    Ok(home.join(".knowledge-base").join(DB_FILE))
+}
+
+/// Walks up from `start` to the nearest directory containing `PROJECT_DIR`
+/// and returns the database path inside it.
+pub fn find_project_db(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_DIR))
+        .find(|candidate| candidate.is_dir())
+        .map(|project| project.join(DB_FILE))
+}
+
+/// Creates a project database in `dir/.whatidid/` and brings its schema up
+/// to date. Running it again on an existing project only migrates.
 ///
-/// # Examples
+/// The directory gets a `.gitignore` ignoring everything in it, so the
+/// database isn't committed by accident; use `sync` to share it.
 ///
-/// ```no_run
-/// use kb::db::open_connection;
+/// # Returns
+/// The database path, and whether the project directory was created
 ///
-/// let conn = open_connection().expect("Failed to open connection");
-/// ```
-pub fn open_connection() -> Result<Connection, KbError> {
-    let path = db_path()?;
-    open_connection_at(&path)
+/// # Errors
+///
+/// Returns `KbError::Io` if the directory can't be created, or `KbError::Db`
+/// if the database can't be opened or migrated.
+pub fn init_project(dir: &Path) -> Result<(PathBuf, bool), KbError> {
+    let project = dir.join(PROJECT_DIR);
+    let created = !project.is_dir();
+    fs::create_dir_all(&project)?;
+    if created {
+        fs::write(project.join(".gitignore"), "*\n")?;
+    }
+    let path = project.canonicalize()?.join(DB_FILE);
+    let mut conn = open_connection_at(&path)?;
+    run_migrations(&mut conn)?;
+    Ok((path, created))
 }
 
 /// Opens a SQLite connection at the specified path with proper settings.
 ///
-/// This function is identical to `open_connection()` but accepts an explicit path.
-/// Primarily used for testing with temporary databases.
+/// The path normally comes from `locate_db`; tests pass temporary databases.
 ///
 /// Configured for multi-agent concurrent access:
 /// - **WAL mode**: Allows concurrent readers with serialized writers
@@ -140,66 +202,501 @@ pub fn open_connection_at(path: &std::path::Path) -> Result<Connection, KbError>
     Ok(conn)
 }
 
-/// Runs all pending database migrations.
+/// Begins a transaction on `conn`, unless the caller already has one open.
 ///
-/// Migrations are applied transactionally and idempotently:
-/// 1. Reads the current schema version from `schema_meta` table (0 if table doesn't exist)
-/// 2. Loads embedded migration SQL files
-/// 3. Runs each migration with version > current version, in order
-/// 4. Each migration runs in its own transaction
-///
-/// If any migration fails, the transaction is rolled back and an error is returned.
-///
-/// # Migration Files
+/// Returns `None` when nested: the work joins the outer transaction, which
+/// commits or rolls it back with everything else.
+pub fn begin_unless_nested(conn: &Connection) -> Result<Option<Transaction<'_>>, KbError> {
+    if conn.is_autocommit() {
+        Ok(Some(conn.unchecked_transaction()?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// An in-memory database with foreign keys on and every migration applied,
+/// for tests.
+#[cfg(test)]
+pub fn test_connection() -> Connection {
+    let mut conn = Connection::open_in_memory().expect("open in-memory db");
+    conn.pragma_update(None, "foreign_keys", "ON").expect("enable foreign keys");
+    run_migrations(&mut conn).expect("run migrations");
+    conn
+}
+
+/// An embedded migration: `migrations/NNN_name.sql`.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// The schema version the migration brings the database to.
+    pub version: i64,
+    /// File name without the `.sql` extension, e.g. `001_initial`.
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// SHA-256 of the SQL, recorded when the migration is applied so later
+    /// edits to the file can be detected.
+    pub fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.sql.as_bytes()))
+    }
+}
+
+/// Embedded migrations, in version order.
 ///
 /// Migrations are embedded in the binary using `include_str!`. They must be named
 /// `NNN_description.sql` where NNN is a zero-padded number (e.g., `001_initial.sql`).
+/// `run_migrations` records each one's version in `schema_meta`; the released
+/// 001-003 also set it themselves.
+/// Never edit a migration once released; add a new one instead.
+fn migrations() -> Vec<Migration> {
+    macro_rules! migration {
+        ($version:expr, $name:literal) => {
+            Migration {
+                version: $version,
+                name: $name,
+                sql: include_str!(concat!("../migrations/", $name, ".sql")),
+            }
+        };
+    }
+    vec![
+        migration!(1, "001_initial"),
+        migration!(2, "002_sections"),
+        migration!(3, "003_timestamps"),
+        migration!(4, "004_link_policy"),
+        migration!(5, "005_link_annotations"),
+        migration!(6, "006_mentions_relation"),
+        migration!(7, "007_page_sources"),
+        migration!(8, "008_sessions"),
+        migration!(9, "009_page_reads"),
+        migration!(10, "010_feedback"),
+        migration!(11, "011_merge_bases"),
+    ]
+}
+
+/// The schema version `run_migrations` brings a database up to.
+pub fn latest_schema_version() -> i64 {
+    migrations().last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Bookkeeping table for applied migrations. Created by the migration runner
+/// rather than a migration file, since it has to exist before any of them run.
+const SCHEMA_MIGRATIONS_SQL: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (
+    version    INTEGER PRIMARY KEY,
+    name       TEXT NOT NULL,
+    checksum   TEXT NOT NULL,
+    -- NULL for migrations applied before this table existed.
+    applied_at TEXT
+)";
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool, KbError> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// The schema version of a database: 0 for a fresh database without a
+/// `schema_meta` table.
 ///
-/// Each migration should update `schema_meta.version` to its target version.
+/// # Errors
+///
+/// Returns `KbError::Db` if `schema_meta` exists but can't be read.
+pub fn current_schema_version(conn: &Connection) -> Result<i64, KbError> {
+    if !table_exists(conn, "schema_meta")? {
+        return Ok(0);
+    }
+    schema_version(conn)
+}
+
+/// Rows of `schema_migrations` (with the recorded checksum), or, for a
+/// database migrated before the table existed, the migrations its
+/// `schema_meta` version implies (with no `applied_at`).
+fn applied_migrations(conn: &Connection) -> Result<Vec<MigrationStatus>, KbError> {
+    if table_exists(conn, "schema_migrations")? {
+        let mut stmt =
+            conn.prepare("SELECT version, name, checksum, applied_at FROM schema_migrations ORDER BY version")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(MigrationStatus {
+                    version: row.get(0)?,
+                    name: row.get(1)?,
+                    checksum: row.get(2)?,
+                    state: MigrationState::Applied,
+                    applied_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        if !rows.is_empty() {
+            return Ok(rows);
+        }
+    }
+    let current = current_schema_version(conn)?;
+    Ok(migrations()
+        .into_iter()
+        .filter(|m| m.version <= current)
+        .map(|m| MigrationStatus {
+            version: m.version,
+            name: m.name.to_string(),
+            checksum: m.checksum(),
+            state: MigrationState::Applied,
+            applied_at: None,
+        })
+        .collect())
+}
+
+/// Compares the migrations applied to a database with the ones embedded in
+/// this build, without changing anything.
 ///
 /// # Errors
 ///
-/// Returns `KbError::Db` if:
-/// - Version check query fails
-/// - Migration SQL execution fails
-/// - Transaction commit fails
+/// Returns `KbError::Db` if the bookkeeping tables can't be read.
+pub fn migration_status(conn: &Connection) -> Result<MigrationReport, KbError> {
+    let applied = applied_migrations(conn)?;
+    let embedded = migrations();
+    let highest_applied = applied.iter().map(|m| m.version).max().unwrap_or(0);
+    let mut statuses = Vec::new();
+    let mut problems = Vec::new();
+
+    for m in &embedded {
+        let checksum = m.checksum();
+        let status = match applied.iter().find(|a| a.version == m.version) {
+            Some(a) if a.checksum != checksum => {
+                problems.push(format!(
+                    "Migration {} ({}) was edited after it was applied",
+                    m.version, m.name
+                ));
+                MigrationStatus {
+                    version: m.version,
+                    name: m.name.to_string(),
+                    checksum,
+                    state: MigrationState::Modified,
+                    applied_at: a.applied_at.clone(),
+                }
+            }
+            Some(a) => MigrationStatus {
+                version: m.version,
+                name: m.name.to_string(),
+                checksum,
+                state: MigrationState::Applied,
+                applied_at: a.applied_at.clone(),
+            },
+            None => {
+                if m.version < highest_applied {
+                    problems.push(format!(
+                        "Migration {} ({}) is pending but later migration {} is already applied",
+                        m.version, m.name, highest_applied
+                    ));
+                }
+                MigrationStatus {
+                    version: m.version,
+                    name: m.name.to_string(),
+                    checksum,
+                    state: MigrationState::Pending,
+                    applied_at: None,
+                }
+            }
+        };
+        statuses.push(status);
+    }
+
+    for a in applied {
+        if !embedded.iter().any(|m| m.version == a.version) {
+            problems.push(format!(
+                "Migration {} ({}) is not known to this build; upgrade whatidid",
+                a.version, a.name
+            ));
+            statuses.push(MigrationStatus { state: MigrationState::Unknown, ..a });
+        }
+    }
+
+    let schema_version = current_schema_version(conn)?;
+    if schema_version > latest_schema_version() {
+        problems.push(format!(
+            "Database schema version {} is newer than this build supports ({}); upgrade whatidid",
+            schema_version,
+            latest_schema_version()
+        ));
+    }
+
+    Ok(MigrationReport {
+        schema_version,
+        latest_schema_version: latest_schema_version(),
+        migrations: statuses,
+        problems,
+        applied: Vec::new(),
+        backup: None,
+    })
+}
+
+/// Runs all pending database migrations.
+///
+/// 1. Refuses to touch a database whose schema version is newer than this
+///    build, or whose applied migrations were edited or applied out of order
+///    (see `migration_status`)
+/// 2. Records already-applied migrations in `schema_migrations` if the
+///    database predates that table
+/// 3. Runs each pending migration in order, each in its own transaction
+///    together with its `schema_migrations` row
+///
+/// If any migration fails, its transaction is rolled back and an error is
+/// returned.
+///
+/// # Returns
+/// The versions applied, in order
+///
+/// # Errors
+///
+/// Returns `KbError::InvalidInput` describing the problem if the database is
+/// newer than this build or its migrations don't match, or `KbError::Db` if a
+/// migration fails.
 ///
 /// # Examples
 ///
 /// ```no_run
-/// use kb::db::{open_connection, run_migrations};
+/// use kb::db::{locate_db, open_connection_at, run_migrations};
 ///
-/// let mut conn = open_connection().expect("Failed to open connection");
+/// let (path, _) = locate_db(None).expect("Failed to get database path");
+/// let mut conn = open_connection_at(&path).expect("Failed to open connection");
 /// run_migrations(&mut conn).expect("Failed to run migrations");
 /// ```
-pub fn run_migrations(conn: &mut Connection) -> Result<(), KbError> {
-    // Determine current schema version
-    let current_version: i64 = conn
-        .query_row(
-            "SELECT version FROM schema_meta LIMIT 1",
-            [],
-            |row| row.get(0),
-        )
-        .unwrap_or(0); // Fresh database starts at version 0
-
-    // Embedded migrations
-    let migrations: Vec<(i64, &str)> = vec![
-        (1, include_str!("../migrations/001_initial.sql")),
-        (2, include_str!("../migrations/002_sections.sql")),
-        (3, include_str!("../migrations/003_timestamps.sql")),
-    ];
-
-    // Run pending migrations
-    for (target_version, sql) in migrations {
-        if target_version > current_version {
-            // Run each migration in a transaction
+pub fn run_migrations(conn: &mut Connection) -> Result<Vec<i64>, KbError> {
+    let status = migration_status(conn)?;
+    if !status.problems.is_empty() {
+        return Err(KbError::InvalidInput(status.problems.join("; ")));
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA_MIGRATIONS_SQL)?;
+    for m in status.migrations.iter().filter(|m| m.state == MigrationState::Applied) {
+        tx.execute(
+            "INSERT OR IGNORE INTO schema_migrations (version, name, checksum, applied_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![m.version, m.name, m.checksum, m.applied_at],
+        )?;
+    }
+    tx.commit()?;
+
+    let mut applied = Vec::new();
+    for m in migrations() {
+        if status.migrations.iter().any(|s| s.version == m.version && s.state == MigrationState::Pending) {
             let tx = conn.transaction()?;
-            tx.execute_batch(sql)?;
+            tx.execute_batch(m.sql)?;
+            tx.execute(
+                "UPDATE schema_meta SET version = ?1, updated_at = datetime('now')",
+                [m.version],
+            )?;
+            tx.execute(
+                "INSERT INTO schema_migrations (version, name, checksum, applied_at) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![m.version, m.name, m.checksum(), now],
+            )?;
             tx.commit()?;
+            applied.push(m.version);
         }
     }
 
-    Ok(())
+    Ok(applied)
+}
+
+/// Runs pending migrations on the database file at `path`, backing it up
+/// first unless the database is new.
+///
+/// The backup is written next to the database as
+/// `<file>.pre-v<version>-<timestamp>.bak`.
+///
+/// # Returns
+/// The migrations applied and the backup path, if one was made
+///
+/// # Errors
+///
+/// As `run_migrations`, plus `KbError::Db` if the backup fails (in which
+/// case no migration runs).
+pub fn migrate_with_backup(conn: &mut Connection, path: &Path) -> Result<MigrationReport, KbError> {
+    let status = migration_status(conn)?;
+    let has_pending = status.migrations.iter().any(|m| m.state == MigrationState::Pending);
+
+    let mut backup_path = None;
+    if has_pending && status.problems.is_empty() && status.schema_version > 0 {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let dest = path.with_file_name(format!(
+            "{}.pre-v{}-{}.bak",
+            file_name,
+            latest_schema_version(),
+            chrono::Utc::now().format("%Y%m%dT%H%M%S")
+        ));
+        backup(conn, &dest)?;
+        backup_path = Some(dest);
+    }
+
+    let applied = run_migrations(conn)?;
+    let mut report = migration_status(conn)?;
+    report.applied = applied;
+    report.backup = backup_path;
+    Ok(report)
+}
+
+/// Returns the schema version recorded in `schema_meta`.
+///
+/// # Errors
+///
+/// Returns `KbError::Db` if the table is missing (migrations have not run).
+pub fn schema_version(conn: &Connection) -> Result<i64, KbError> {
+    let version = conn.query_row("SELECT version FROM schema_meta LIMIT 1", [], |row| row.get(0))?;
+    Ok(version)
+}
+
+/// Copies every page of `src` into `dest` with SQLite's online backup API.
+///
+/// The whole database is copied in one step from a single read snapshot, so
+/// under WAL the copy is consistent even while other connections write.
+/// Retries for a few seconds if the destination is locked.
+fn copy_database(src: &Connection, dest: &mut Connection) -> Result<(), KbError> {
+    let backup = Backup::new(src, dest)?;
+    for _ in 0..50 {
+        match backup.step(-1)? {
+            StepResult::Done => return Ok(()),
+            _ => std::thread::sleep(Duration::from_millis(100)),
+        }
+    }
+    Err(KbError::InvalidInput(
+        "Database stayed busy; try again when fewer agents are writing".to_string(),
+    ))
+}
+
+/// Writes a consistent copy of the database to a new file.
+///
+/// Safe to run while agents are writing: the copy reflects one point in time.
+///
+/// # Errors
+///
+/// Returns `KbError::InvalidInput` if `dest` already exists, or
+/// `KbError::Db` if the copy fails.
+pub fn backup(conn: &Connection, dest: &Path) -> Result<(), KbError> {
+    if dest.exists() {
+        return Err(KbError::InvalidInput(format!(
+            "{} already exists; choose a new backup path",
+            dest.display()
+        )));
+    }
+    if let Some(parent) = dest.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut dest_conn = Connection::open(dest)?;
+    copy_database(conn, &mut dest_conn)
+}
+
+/// Replaces the database's contents with a backup, then runs any migrations
+/// the backup predates.
+///
+/// The backup is validated first: it must be a knowledge base (have a
+/// `schema_meta` version) no newer than `latest_schema_version`, and pass
+/// `PRAGMA integrity_check`. Nothing is changed if validation fails.
+///
+/// # Returns
+/// The backup's schema version before migrations ran
+///
+/// # Errors
+///
+/// Returns `KbError::NotFound` if `src` doesn't exist, `KbError::InvalidInput`
+/// if it fails validation, or `KbError::Db` if the restore fails.
+pub fn restore(conn: &mut Connection, src: &Path) -> Result<i64, KbError> {
+    let (src_conn, version) = open_valid_database(src)?;
+    copy_database(&src_conn, conn)?;
+    run_migrations(conn)?;
+    Ok(version)
+}
+
+/// Loads a copy of another knowledge base file into memory and migrates the
+/// copy, leaving the file itself untouched. Validated like `restore`.
+///
+/// # Errors
+///
+/// As `restore`.
+pub fn open_copy(src: &Path) -> Result<Connection, KbError> {
+    let (src_conn, _) = open_valid_database(src)?;
+    let mut copy = Connection::open_in_memory()?;
+    copy_database(&src_conn, &mut copy)?;
+    run_migrations(&mut copy)?;
+    Ok(copy)
+}
+
+/// Opens a database file that is to be copied from, checking that it is an
+/// undamaged knowledge base no newer than this build. Returns its schema version.
+fn open_valid_database(src: &Path) -> Result<(Connection, i64), KbError> {
+    if !src.is_file() {
+        return Err(KbError::NotFound(format!("Database file {}", src.display())));
+    }
+    // Not read-only: integrity_check also validates FTS5 indexes, which needs write access.
+    let src_conn = Connection::open_with_flags(src, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    let version = schema_version(&src_conn).map_err(|_| {
+        KbError::InvalidInput(format!("{} is not a knowledge base database", src.display()))
+    })?;
+    if version > latest_schema_version() {
+        return Err(KbError::InvalidInput(format!(
+            "{} has schema version {}, but this build only supports up to {}; upgrade whatidid first",
+            src.display(),
+            version,
+            latest_schema_version()
+        )));
+    }
+    let integrity: String = src_conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(KbError::InvalidInput(format!(
+            "{} is damaged: {}",
+            src.display(),
+            integrity
+        )));
+    }
+    Ok((src_conn, version))
+}
+
+/// Checks the database for corruption.
+///
+/// Runs `PRAGMA integrity_check`, `PRAGMA foreign_key_check`, and the FTS5
+/// `integrity-check` command, which also compares the search index against
+/// the `pages` table.
+///
+/// # Errors
+///
+/// Returns `KbError::Db` only if a check can't run at all; problems found are
+/// reported in the returned `DbCheckReport`.
+pub fn check(conn: &Connection) -> Result<DbCheckReport, KbError> {
+    let integrity_errors: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?
+        .into_iter()
+        .filter(|line| line != "ok")
+        .collect();
+
+    let foreign_key_violations = conn
+        .prepare("PRAGMA foreign_key_check")?
+        .query_map([], |row| {
+            Ok(ForeignKeyViolation {
+                table: row.get(0)?,
+                rowid: row.get(1)?,
+                parent: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // rank = 1 also checks the index against the external content table.
+    let fts_error = conn
+        .execute("INSERT INTO pages_fts(pages_fts, rank) VALUES ('integrity-check', 1)", [])
+        .err()
+        .map(|e| e.to_string());
+
+    Ok(DbCheckReport {
+        ok: integrity_errors.is_empty() && foreign_key_violations.is_empty() && fts_error.is_none(),
+        schema_version: schema_version(conn).ok(),
+        latest_schema_version: latest_schema_version(),
+        integrity_errors,
+        foreign_key_violations,
+        fts_error,
+    })
 }
 
 #[cfg(test)]
@@ -215,12 +712,12 @@ mod tests {
         // Set the environment variable
         env::set_var("KB_PATH", custom_path.to_str().unwrap());
 
-        let result = db_path().expect("db_path should succeed");
+        let result = locate_db(None).expect("locate_db should succeed");
 
         // Clean up
         env::remove_var("KB_PATH");
 
-        assert_eq!(result, custom_path);
+        assert_eq!(result, (custom_path, DbSource::Custom));
     }
 
     #[test]
@@ -228,13 +725,44 @@ mod tests {
         // Ensure KB_PATH is not set
         env::remove_var("KB_PATH");
 
-        let result = db_path().expect("db_path should succeed with default");
+        let (result, source) = locate_db(None).expect("locate_db should succeed with default");
 
         // Should contain .knowledge-base/kb.db
+        assert_eq!(source, DbSource::Global);
         assert!(result.to_string_lossy().contains(".knowledge-base"));
         assert!(result.to_string_lossy().ends_with("kb.db"));
     }
 
+    #[test]
+    fn test_locate_db_prefers_explicit_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("explicit.db");
+        let (result, source) = locate_db(Some(&path)).unwrap();
+        assert_eq!((result, source), (path.clone(), DbSource::Custom));
+        assert!(path.parent().unwrap().is_dir());
+    }
+
+    #[test]
+    fn test_find_project_db_walks_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_project_db(&nested), None);
+
+        let (path, created) = init_project(dir.path()).unwrap();
+        assert!(created);
+        assert!(dir.path().join(PROJECT_DIR).join(".gitignore").exists());
+        let found = find_project_db(&nested).unwrap();
+        assert_eq!(found.canonicalize().unwrap(), path.canonicalize().unwrap());
+
+        // Initialising again keeps the database.
+        let (again, created) = init_project(dir.path()).unwrap();
+        assert!(!created);
+        assert_eq!(again, path);
+        let conn = open_connection_at(&path).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest_schema_version());
+    }
+
     #[test]
     fn test_migration_creates_tables_from_scratch() {
         // Create an in-memory database
@@ -247,11 +775,11 @@ mod tests {
         // Run migrations
         run_migrations(&mut conn).expect("Migrations should succeed");
 
-        // Verify schema_meta table exists and has version 3
+        // Verify schema_meta table exists and has version 11
         let version: i64 = conn
             .query_row("SELECT version FROM schema_meta", [], |row| row.get(0))
             .expect("schema_meta should exist");
-        assert_eq!(version, 3);
+        assert_eq!(version, 11);
 
         // Verify main tables exist
         let table_names: Vec<String> = conn
@@ -268,6 +796,7 @@ mod tests {
         assert!(table_names.contains(&"labels".to_string()));
         assert!(table_names.contains(&"links".to_string()));
         assert!(table_names.contains(&"pages_fts".to_string()));
+        assert!(table_names.contains(&"schema_migrations".to_string()));
     }
 
     #[test]
@@ -295,7 +824,7 @@ mod tests {
 
         // Version should be unchanged
         assert_eq!(version_after_first, version_after_second);
-        assert_eq!(version_after_second, 3);
+        assert_eq!(version_after_second, 11);
 
         // Verify we can still query tables (no corruption)
         let count: i64 = conn
@@ -330,4 +859,216 @@ mod tests {
         drop(conn);
         let _ = std::fs::remove_file(&db_path);
     }
+
+    fn migrated_db_at(path: &Path) -> Connection {
+        let mut conn = open_connection_at(path).expect("Should open connection");
+        run_migrations(&mut conn).expect("Migrations should succeed");
+        conn
+    }
+
+    fn space_slugs(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT slug FROM spaces ORDER BY slug")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<Vec<String>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_latest_schema_version_matches_migrations() {
+        let mut conn = Connection::open_in_memory().expect("Failed to open in-memory DB");
+        run_migrations(&mut conn).expect("Migrations should succeed");
+        assert_eq!(schema_version(&conn).unwrap(), latest_schema_version());
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = migrated_db_at(&dir.path().join("kb.db"));
+        crate::repo::create_space(&source, "kept", "Kept", "").unwrap();
+
+        let backup_path = dir.path().join("backups").join("kb-backup.db");
+        backup(&source, &backup_path).expect("backup should succeed");
+        let err = backup(&source, &backup_path).unwrap_err();
+        assert!(matches!(err, KbError::InvalidInput(_)));
+
+        let mut target = migrated_db_at(&dir.path().join("other.db"));
+        crate::repo::create_space(&target, "replaced", "Replaced", "").unwrap();
+        let version = restore(&mut target, &backup_path).expect("restore should succeed");
+        assert_eq!(version, latest_schema_version());
+        assert_eq!(space_slugs(&target), vec!["kept".to_string()]);
+        assert!(check(&target).unwrap().ok);
+    }
+
+    #[test]
+    fn test_restore_migrates_older_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_path = dir.path().join("old.db");
+        {
+            let old = Connection::open(&old_path).unwrap();
+            old.execute_batch(include_str!("../migrations/001_initial.sql")).unwrap();
+        }
+
+        let mut conn = migrated_db_at(&dir.path().join("kb.db"));
+        assert_eq!(restore(&mut conn, &old_path).unwrap(), 1);
+        assert_eq!(schema_version(&conn).unwrap(), latest_schema_version());
+    }
+
+    #[test]
+    fn test_restore_rejects_invalid_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = migrated_db_at(&dir.path().join("kb.db"));
+        crate::repo::create_space(&conn, "live", "Live", "").unwrap();
+
+        let missing = restore(&mut conn, &dir.path().join("missing.db")).unwrap_err();
+        assert!(matches!(missing, KbError::NotFound(_)));
+
+        let plain_path = dir.path().join("plain.db");
+        Connection::open(&plain_path).unwrap().execute_batch("CREATE TABLE t (x)").unwrap();
+        let plain = restore(&mut conn, &plain_path).unwrap_err();
+        assert!(plain.to_string().contains("not a knowledge base database"));
+
+        let newer_path = dir.path().join("newer.db");
+        {
+            let newer = migrated_db_at(&newer_path);
+            newer.execute("UPDATE schema_meta SET version = 999", []).unwrap();
+        }
+        let newer = restore(&mut conn, &newer_path).unwrap_err();
+        assert!(newer.to_string().contains("schema version 999"));
+
+        assert_eq!(space_slugs(&conn), vec!["live".to_string()]);
+    }
+
+    #[test]
+    fn test_check_reports_problems() {
+        let mut conn = Connection::open_in_memory().expect("Failed to open in-memory DB");
+        run_migrations(&mut conn).expect("Migrations should succeed");
+        let report = check(&conn).unwrap();
+        assert!(report.ok, "{:?}", report);
+        assert_eq!(report.schema_version, Some(latest_schema_version()));
+
+        conn.pragma_update(None, "foreign_keys", "OFF").unwrap();
+        conn.execute("INSERT INTO labels (page_id, label) VALUES ('no-such-page', 'x')", [])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO pages_fts (rowid, title, content) VALUES (999, 'ghost', 'not in pages')",
+            [],
+        )
+        .unwrap();
+
+        let report = check(&conn).unwrap();
+        assert!(!report.ok);
+        assert!(report.integrity_errors.is_empty());
+        assert_eq!(report.foreign_key_violations.len(), 1);
+        assert_eq!(report.foreign_key_violations[0].table, "labels");
+        assert_eq!(report.foreign_key_violations[0].parent, "pages");
+        assert!(report.fts_error.is_some());
+    }
+
+    fn recorded_migrations(conn: &Connection) -> Vec<(i64, String, Option<String>)> {
+        conn.prepare("SELECT version, checksum, applied_at FROM schema_migrations ORDER BY version")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_run_migrations_records_checksums() {
+        let mut conn = Connection::open_in_memory().expect("Failed to open in-memory DB");
+        let applied = run_migrations(&mut conn).expect("Migrations should succeed");
+        assert_eq!(applied, (1..=latest_schema_version()).collect::<Vec<_>>());
+
+        let recorded = recorded_migrations(&conn);
+        assert_eq!(recorded.len(), migrations().len());
+        for ((version, checksum, applied_at), m) in recorded.iter().zip(migrations()) {
+            assert_eq!(*version, m.version);
+            assert_eq!(*checksum, m.checksum());
+            assert!(applied_at.is_some());
+        }
+        assert!(run_migrations(&mut conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_run_migrations_backfills_untracked_database() {
+        let mut conn = Connection::open_in_memory().expect("Failed to open in-memory DB");
+        for m in migrations().iter().take(3) {
+            conn.execute_batch(m.sql).unwrap();
+        }
+
+        let status = migration_status(&conn).unwrap();
+        assert_eq!(status.schema_version, 3);
+        assert!(status.problems.is_empty());
+        assert_eq!(status.migrations[2].state, MigrationState::Applied);
+        assert_eq!(status.migrations[3].state, MigrationState::Pending);
+
+        let applied = run_migrations(&mut conn).unwrap();
+        assert_eq!(applied, (4..=latest_schema_version()).collect::<Vec<_>>());
+        let recorded = recorded_migrations(&conn);
+        assert!(recorded[..3].iter().all(|(_, _, at)| at.is_none()));
+        assert!(recorded[3..].iter().all(|(_, _, at)| at.is_some()));
+    }
+
+    #[test]
+    fn test_run_migrations_detects_edited_and_out_of_order_migrations() {
+        let mut conn = Connection::open_in_memory().expect("Failed to open in-memory DB");
+        run_migrations(&mut conn).unwrap();
+
+        conn.execute("UPDATE schema_migrations SET checksum = 'old' WHERE version = 2", [])
+            .unwrap();
+        let status = migration_status(&conn).unwrap();
+        assert_eq!(status.migrations[1].state, MigrationState::Modified);
+        let err = run_migrations(&mut conn).unwrap_err();
+        assert!(err.to_string().contains("002_sections) was edited"), "{}", err);
+
+        conn.execute("UPDATE schema_migrations SET checksum = ?1 WHERE version = 2", [migrations()[1].checksum()])
+            .unwrap();
+        conn.execute("DELETE FROM schema_migrations WHERE version = 5", []).unwrap();
+        let err = run_migrations(&mut conn).unwrap_err();
+        assert!(err.to_string().contains("Migration 5 (005_link_annotations) is pending"), "{}", err);
+    }
+
+    #[test]
+    fn test_run_migrations_refuses_newer_database() {
+        let mut conn = Connection::open_in_memory().expect("Failed to open in-memory DB");
+        run_migrations(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_migrations (version, name, checksum, applied_at) VALUES (99, '099_future', 'x', 'now')",
+            [],
+        )
+        .unwrap();
+        conn.execute("UPDATE schema_meta SET version = 99", []).unwrap();
+
+        let status = migration_status(&conn).unwrap();
+        assert_eq!(status.migrations.last().unwrap().state, MigrationState::Unknown);
+        let err = run_migrations(&mut conn).unwrap_err();
+        assert!(err.to_string().contains("newer than this build supports"), "{}", err);
+    }
+
+    #[test]
+    fn test_migrate_with_backup() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // A new database has nothing worth backing up.
+        let fresh_path = dir.path().join("fresh.db");
+        let mut fresh = open_connection_at(&fresh_path).unwrap();
+        let report = migrate_with_backup(&mut fresh, &fresh_path).unwrap();
+        assert!(report.backup.is_none());
+        assert_eq!(report.applied.len(), migrations().len());
+
+        let old_path = dir.path().join("old.db");
+        let mut old = open_connection_at(&old_path).unwrap();
+        old.execute_batch(migrations()[0].sql).unwrap();
+        let report = migrate_with_backup(&mut old, &old_path).unwrap();
+        let backup_path = report.backup.expect("backup should be taken");
+        assert!(backup_path.to_string_lossy().contains(&format!("old.db.pre-v{}-", latest_schema_version())));
+        let backup_conn = Connection::open(&backup_path).unwrap();
+        assert_eq!(schema_version(&backup_conn).unwrap(), 1);
+        assert_eq!(report.schema_version, latest_schema_version());
+
+        // Nothing pending, so no further backups.
+        assert!(migrate_with_backup(&mut old, &old_path).unwrap().backup.is_none());
+    }
 }
@@ -0,0 +1,245 @@
+//! Detection of decisions discussed in a session but never recorded.
+//!
+//! The assistant's replies are split into sentences and matched against
+//! decision patterns ("decided to", "went with", "chose X over Y", ...).
+//! Each match is a candidate decision, kept with the sentences around it.
+//! A candidate counts as recorded when a `decision` page created since the
+//! session started (by the session's agent, if known) shares most of its
+//! keywords. What's left is drafted as `page create --sections` commands
+//! for the agent to review and run.
+
+use crate::context::{extract_keywords_with, truncate};
+use crate::db::KbError;
+use crate::models::{Page, PageType};
+use crate::search::{self, SearchParams};
+use crate::summarize::split_sentences;
+use crate::transcript::{Speaker, Transcript};
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use rusqlite::Connection;
+use std::collections::HashSet;
+
+/// Phrases that suggest a decision was made, as case-insensitive regexes.
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    r"\bdecided to\b",
+    r"\bdecision was\b",
+    r"\bchose to\b",
+    r"\bopted (for|to)\b",
+    r"\bwent with\b",
+    r"\bsettled on\b",
+    r"\b(chose|picked|selected|prefer(red)?) \S+( \S+)? over\b",
+    r"\binstead of using\b",
+    r"\btrade-?offs? (is|was|here)\b",
+    r"\b(architectural|design) decision\b",
+    r"\bweighed the options\b",
+];
+
+/// Shortest keyword compared between a candidate and a page.
+const MIN_KEYWORD_LEN: usize = 3;
+
+/// A sentence that looks like a decision, with the sentences around it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub sentence: String,
+    /// Sentences just before it in the same reply.
+    pub before: Vec<String>,
+    /// Sentences just after it in the same reply.
+    pub after: Vec<String>,
+}
+
+impl Candidate {
+    /// A draft title: the decision sentence, shortened.
+    pub fn draft_title(&self) -> String {
+        truncate(self.sentence.trim_end_matches(['.', '!']), 70)
+    }
+
+    /// Draft sections for a decision page. What the transcript doesn't say
+    /// is left as a placeholder to fill in.
+    pub fn draft_sections(&self) -> serde_json::Value {
+        let or_placeholder = |sentences: &[String], placeholder: &str| {
+            if sentences.is_empty() {
+                placeholder.to_string()
+            } else {
+                sentences.join(" ")
+            }
+        };
+        serde_json::json!({
+            "context": or_placeholder(&self.before, "TODO: why this decision was needed"),
+            "options_considered": "TODO: the options weighed",
+            "decision": self.sentence,
+            "consequences": or_placeholder(&self.after, "TODO: trade-offs and implications"),
+        })
+    }
+}
+
+/// Compiles decision patterns, case-insensitively.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` naming the first pattern that isn't a valid regex.
+pub fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>, KbError> {
+    patterns
+        .iter()
+        .map(|p| {
+            RegexBuilder::new(p)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| KbError::InvalidInput(format!("Invalid decision pattern '{}': {}", p, e)))
+        })
+        .collect()
+}
+
+/// Finds candidate decisions in the assistant's replies, in order and
+/// without repeats, each with up to `context` sentences either side.
+pub fn find_candidates(transcript: &Transcript, patterns: &[Regex], context: usize) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = Vec::new();
+    for turn in transcript.turns.iter().filter(|t| t.speaker == Speaker::Assistant) {
+        let sentences = split_sentences(&turn.text);
+        for (i, sentence) in sentences.iter().enumerate() {
+            if sentence.ends_with('?') || !patterns.iter().any(|p| p.is_match(sentence)) {
+                continue;
+            }
+            if candidates.iter().any(|c| &c.sentence == sentence) {
+                continue;
+            }
+            candidates.push(Candidate {
+                sentence: sentence.clone(),
+                before: sentences[i.saturating_sub(context)..i].to_vec(),
+                after: sentences[i + 1..(i + 1 + context).min(sentences.len())].to_vec(),
+            });
+        }
+    }
+    candidates
+}
+
+/// Decision pages created since `since` (all of them if `None`), by `agent`
+/// if given.
+///
+/// # Errors
+/// Returns `KbError::Db` if the query fails.
+pub fn recorded_since(conn: &Connection, since: Option<&str>, agent: Option<&str>) -> Result<Vec<Page>, KbError> {
+    let since = since.and_then(|s| DateTime::parse_from_rfc3339(s).ok()).map(|t| t.with_timezone(&Utc));
+    let pages = search::search_pages(
+        conn,
+        &SearchParams {
+            query: None,
+            space_id: None,
+            page_type: Some(PageType::Decision),
+            label: None,
+            created_by_agent: agent.map(str::to_string),
+            section: None,
+        },
+    )?;
+    Ok(pages
+        .into_iter()
+        .map(|r| r.page)
+        .filter(|page| match since {
+            Some(since) => DateTime::parse_from_rfc3339(&page.created_at).is_ok_and(|created| created >= since),
+            None => true,
+        })
+        .collect())
+}
+
+/// Whether a page records a candidate: it contains at least half of the
+/// candidate's keywords.
+pub fn is_recorded_by(candidate: &Candidate, page: &Page) -> bool {
+    let keywords = extract_keywords_with(&candidate.sentence, MIN_KEYWORD_LEN, usize::MAX);
+    if keywords.is_empty() {
+        return false;
+    }
+    let text = format!("{}\n{}", page.title, page.content);
+    let page_words: HashSet<String> = extract_keywords_with(&text, MIN_KEYWORD_LEN, usize::MAX).into_iter().collect();
+    let shared = keywords.iter().filter(|k| page_words.contains(*k)).count();
+    shared * 2 >= keywords.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::repo;
+    use crate::transcript::Turn;
+
+    fn default_patterns() -> Vec<Regex> {
+        let patterns: Vec<String> = DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect();
+        compile_patterns(&patterns).unwrap()
+    }
+
+    fn transcript(replies: &[&str]) -> Transcript {
+        Transcript {
+            turns: replies
+                .iter()
+                .map(|text| Turn {
+                    speaker: Speaker::Assistant,
+                    text: text.to_string(),
+                })
+                .collect(),
+            ..Transcript::default()
+        }
+    }
+
+    #[test]
+    fn test_find_candidates_with_context() {
+        let t = transcript(&[
+            "Locks were contended under load. We decided to enable WAL mode for concurrency. Readers no longer block.",
+            "Should we went with it?",
+            "I picked serde_yaml over toml for the frontmatter.",
+            "We decided to enable WAL mode for concurrency.",
+        ]);
+        let candidates = find_candidates(&t, &default_patterns(), 1);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].sentence, "We decided to enable WAL mode for concurrency.");
+        assert_eq!(candidates[0].before, vec!["Locks were contended under load."]);
+        assert_eq!(candidates[0].after, vec!["Readers no longer block."]);
+        assert_eq!(candidates[1].sentence, "I picked serde_yaml over toml for the frontmatter.");
+        assert!(candidates[1].before.is_empty());
+
+        let sections = candidates[1].draft_sections();
+        assert_eq!(sections["decision"], "I picked serde_yaml over toml for the frontmatter.");
+        assert!(sections["context"].as_str().unwrap().starts_with("TODO"));
+    }
+
+    #[test]
+    fn test_custom_patterns() {
+        let t = transcript(&["We will standardise on tokio for async."]);
+        assert!(find_candidates(&t, &default_patterns(), 1).is_empty());
+        let custom = compile_patterns(&["standardi[sz]e on".to_string()]).unwrap();
+        assert_eq!(find_candidates(&t, &custom, 1).len(), 1);
+        assert!(compile_patterns(&["(".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_recorded_by_time_agent_and_keywords() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "proj", "Proj", "").unwrap();
+        let old = repo::create_page(&conn, &space.id, None, "Old", PageType::Decision, "x", None, &[], "u", "claude-code").unwrap();
+        let start = chrono::Utc::now().to_rfc3339();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let wal = repo::create_page(
+            &conn,
+            &space.id,
+            None,
+            "Enable WAL mode",
+            PageType::Decision,
+            "WAL mode improves concurrency.",
+            None,
+            &[],
+            "u",
+            "claude-code",
+        )
+        .unwrap();
+        repo::create_page(&conn, &space.id, None, "Other agent", PageType::Decision, "x", None, &[], "u", "other").unwrap();
+        repo::create_page(&conn, &space.id, None, "Not a decision", PageType::Reference, "x", None, &[], "u", "claude-code")
+            .unwrap();
+
+        let recorded = recorded_since(&conn, Some(&start), Some("claude-code")).unwrap();
+        let ids: Vec<&str> = recorded.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec![wal.id.as_str()]);
+        assert_eq!(recorded_since(&conn, None, None).unwrap().len(), 3);
+        assert!(recorded_since(&conn, None, None).unwrap().iter().any(|p| p.id == old.id));
+
+        let t = transcript(&["We decided to enable WAL mode for concurrency.", "We went with serde_yaml for parsing."]);
+        let candidates = find_candidates(&t, &default_patterns(), 0);
+        assert!(is_recorded_by(&candidates[0], &recorded[0]));
+        assert!(!is_recorded_by(&candidates[1], &recorded[0]));
+    }
+}
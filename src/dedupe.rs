@@ -0,0 +1,218 @@
+//! Duplicate and near-duplicate page detection.
+//!
+//! A page is reduced to the set of adjacent word pairs (shingles) in its
+//! title and content, so rewording a title or a sentence only changes the
+//! pairs around the edit. Each set gets a MinHash signature: the smallest
+//! hash of its shingles under each of `SIGNATURE_LEN` hash functions. The
+//! share of positions where two signatures agree estimates the Jaccard
+//! similarity of the two sets, the share of shingles they have in common.
+
+use crate::db::KbError;
+use crate::models::{DuplicateMatch, DuplicatePair, Page, PageRef};
+use crate::repo;
+use rusqlite::Connection;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+/// Similarity at which a page counts as a near-duplicate.
+pub const DEFAULT_THRESHOLD: f64 = 0.6;
+
+/// Hash functions per signature; the estimate's error is about 1/sqrt of this.
+const SIGNATURE_LEN: usize = 128;
+
+/// A page's MinHash signature. Empty for a page without words, which is
+/// similar to nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature(Vec<u64>);
+
+/// The adjacent word pairs in `text`, lowercased. A single word is its own
+/// shingle.
+fn shingles(text: &str) -> HashSet<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() == 1 {
+        return words.into_iter().collect();
+    }
+    words.windows(2).map(|pair| pair.join(" ")).collect()
+}
+
+/// Finalizer from SplitMix64: spreads a hash so each seed gives an
+/// independent-looking permutation.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Computes the signature of a page's title and content.
+pub fn signature(title: &str, content: &str) -> Signature {
+    let shingles = shingles(&format!("{}\n{}", title, content));
+    if shingles.is_empty() {
+        return Signature(Vec::new());
+    }
+    let hashes: Vec<u64> = shingles
+        .iter()
+        .map(|shingle| {
+            let mut hasher = DefaultHasher::new();
+            shingle.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect();
+    let minimums = (0..SIGNATURE_LEN as u64)
+        .map(|seed| {
+            let offset = mix(seed.wrapping_add(1));
+            hashes.iter().map(|h| mix(h ^ offset)).min().unwrap_or(u64::MAX)
+        })
+        .collect();
+    Signature(minimums)
+}
+
+/// Estimated Jaccard similarity of two signatures, from 0 to 1.
+pub fn similarity(a: &Signature, b: &Signature) -> f64 {
+    if a.0.is_empty() || b.0.is_empty() {
+        return 0.0;
+    }
+    let agree = a.0.iter().zip(&b.0).filter(|(x, y)| x == y).count();
+    agree as f64 / SIGNATURE_LEN as f64
+}
+
+fn round(similarity: f64) -> f64 {
+    (similarity * 1000.0).round() / 1000.0
+}
+
+fn page_ref(page: &Page, space_slug: &str) -> PageRef {
+    PageRef {
+        id: page.id.clone(),
+        title: page.title.clone(),
+        page_type: page.page_type,
+        space_slug: space_slug.to_string(),
+    }
+}
+
+fn space_pages(conn: &Connection, space_id: &str) -> Result<(String, Vec<Page>), KbError> {
+    let slug = repo::get_space_by_id(conn, space_id)?.slug;
+    let filters = repo::PageFilters {
+        space_id: Some(space_id.to_string()),
+        page_type: None,
+        label: None,
+        created_by_user: None,
+        created_by_agent: None,
+    };
+    Ok((slug, repo::list_pages(conn, &filters)?))
+}
+
+/// The pages in a space at least `threshold` similar to the given title and
+/// content, most similar first.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the space doesn't exist.
+pub fn find_similar(
+    conn: &Connection,
+    space_id: &str,
+    title: &str,
+    content: &str,
+    threshold: f64,
+) -> Result<Vec<DuplicateMatch>, KbError> {
+    let (slug, pages) = space_pages(conn, space_id)?;
+    let new = signature(title, content);
+    let mut matches: Vec<DuplicateMatch> = pages
+        .iter()
+        .filter_map(|page| {
+            let similarity = similarity(&new, &signature(&page.title, &page.content));
+            (similarity >= threshold).then(|| DuplicateMatch {
+                page: page_ref(page, &slug),
+                similarity: round(similarity),
+            })
+        })
+        .collect();
+    matches.sort_by(|a, b| b.similarity.total_cmp(&a.similarity).then_with(|| a.page.id.cmp(&b.page.id)));
+    Ok(matches)
+}
+
+/// Every pair of pages in a space at least `threshold` similar, most
+/// similar first.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the space doesn't exist.
+pub fn find_duplicate_pairs(conn: &Connection, space_id: &str, threshold: f64) -> Result<Vec<DuplicatePair>, KbError> {
+    let (slug, mut pages) = space_pages(conn, space_id)?;
+    pages.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+    let signatures: Vec<Signature> = pages.iter().map(|p| signature(&p.title, &p.content)).collect();
+
+    let mut pairs = Vec::new();
+    for i in 0..pages.len() {
+        for j in i + 1..pages.len() {
+            let similarity = similarity(&signatures[i], &signatures[j]);
+            if similarity >= threshold {
+                pairs.push(DuplicatePair {
+                    first: page_ref(&pages[i], &slug),
+                    second: page_ref(&pages[j], &slug),
+                    similarity: round(similarity),
+                });
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PageType;
+
+    const FIX: &str = "The build fails with a linker error on macOS because the system libiconv \
+                       shadows the Homebrew one. Set LIBRARY_PATH to the Homebrew lib directory \
+                       before running cargo build, then clean the target directory.";
+
+    #[test]
+    fn test_similarity_of_reworded_pages() {
+        let same = similarity(&signature("Linker error on macOS", FIX), &signature("Linker error on macOS", FIX));
+        assert_eq!(same, 1.0);
+
+        let reworded = similarity(
+            &signature("Linker error on macOS", FIX),
+            &signature("macOS linker failure", &FIX.replace("then clean", "and clean")),
+        );
+        assert!(reworded > DEFAULT_THRESHOLD, "{}", reworded);
+
+        let unrelated = similarity(
+            &signature("Linker error on macOS", FIX),
+            &signature("Deploy rollback", "Revert the release tag and redeploy the previous image."),
+        );
+        assert!(unrelated < 0.1, "{}", unrelated);
+
+        assert_eq!(similarity(&signature("", ""), &signature("", "")), 0.0);
+    }
+
+    #[test]
+    fn test_find_similar_and_pairs() {
+        let conn = crate::db::test_connection();
+        let space = repo::create_space(&conn, "s", "S", "").expect("create space");
+        let other = repo::create_space(&conn, "o", "O", "").expect("create space");
+        let create = |space_id: &str, title: &str, content: &str| {
+            repo::create_page(&conn, space_id, None, title, PageType::Troubleshooting, content, None, &[], "u", "a")
+                .expect("create page")
+        };
+        let first = create(&space.id, "Linker error on macOS", FIX);
+        let second = create(&space.id, "macOS linker failure", &FIX.replace("then clean", "and clean"));
+        create(&space.id, "Deploy rollback", "Revert the release tag and redeploy the previous image.");
+        create(&other.id, "Linker error on macOS", FIX);
+
+        let matches = find_similar(&conn, &space.id, "Linker error on macOS", FIX, DEFAULT_THRESHOLD).expect("find");
+        let ids: Vec<&str> = matches.iter().map(|m| m.page.id.as_str()).collect();
+        assert_eq!(ids, vec![first.id.as_str(), second.id.as_str()]);
+        assert_eq!(matches[0].similarity, 1.0);
+        assert_eq!(matches[0].page.space_slug, "s");
+
+        let pairs = find_duplicate_pairs(&conn, &space.id, DEFAULT_THRESHOLD).expect("pairs");
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].first.id.as_str(), pairs[0].second.id.as_str()), (first.id.as_str(), second.id.as_str()));
+
+        assert!(matches!(find_similar(&conn, "missing", "t", "c", 0.5), Err(KbError::NotFound(_))));
+    }
+}
@@ -0,0 +1,559 @@
+//! Native handlers for Claude Code hook events.
+//!
+//! Hooks run inside an agent's session, so a handler must never break it:
+//! `main` turns any error from this module into empty output and a zero exit
+//! status. Each handler reads the hook's JSON input from stdin and returns
+//! the text the hook should contribute, or `None` when it has nothing useful
+//! to say.
+//!
+//! `user_prompt_submit` replaces `hooks/search-before-solving.sh`: it pulls
+//! keywords out of the prompt, searches the knowledge base for each of them
+//! (once in general, once for pitfalls) and formats the best matches as
+//! "prior knowledge" for the agent.
+//!
+//! `stop` replaces `hooks/check-decisions.sh`: it looks for decisions in the
+//! session's transcript that no decision page was created for, and asks the
+//! agent to record them, with a draft for each.
+//!
+//! `session_end` replaces `hooks/session-summary.sh`: it ingests the
+//! session's transcript as a session-log page.
+
+use crate::context::{extract_keywords_with, PITFALL_LABEL};
+use crate::db::{self, KbError};
+use crate::decisions;
+use crate::markdown::slugify;
+use crate::models::{AgentIdentity, SearchResult, TranscriptReport};
+use crate::repo;
+use crate::search::{self, RecallWeights, SearchParams};
+use crate::summarize::Summarizer;
+use crate::transcript;
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// The parts of a hook's stdin JSON that handlers use. Claude Code sends
+/// more fields than these; the rest are ignored.
+#[derive(Debug, Default, Deserialize)]
+pub struct HookInput {
+    /// The submitted prompt (`UserPromptSubmit` only).
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// The session's working directory, used to find a project database.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// The session's transcript (JSONL).
+    #[serde(default)]
+    pub transcript_path: Option<PathBuf>,
+    /// Set when the agent is already continuing because a stop hook blocked.
+    #[serde(default)]
+    pub stop_hook_active: bool,
+}
+
+/// Tunables for `user_prompt_submit`. The defaults match the behaviour of
+/// the shell hook it replaces.
+#[derive(Debug, Clone)]
+pub struct PromptThresholds {
+    /// Prompts with fewer keywords than this get no context.
+    pub min_keywords: usize,
+    /// Shortest word counted as a keyword.
+    pub min_keyword_len: usize,
+    /// Most keywords searched for.
+    pub max_keywords: usize,
+    /// Most related pages listed.
+    pub max_results: usize,
+    /// Most pitfalls listed.
+    pub max_pitfalls: usize,
+    /// Excerpts are trimmed to this many characters.
+    pub excerpt_chars: usize,
+}
+
+impl Default for PromptThresholds {
+    fn default() -> Self {
+        Self {
+            min_keywords: 3,
+            min_keyword_len: 5,
+            max_keywords: 5,
+            max_results: 5,
+            max_pitfalls: 3,
+            excerpt_chars: 200,
+        }
+    }
+}
+
+/// Tunables for `stop`.
+#[derive(Debug, Clone)]
+pub struct StopOptions {
+    /// Decision patterns (case-insensitive regexes).
+    pub patterns: Vec<String>,
+    /// Sessions with fewer candidate decisions than this are let through.
+    pub min_mentions: usize,
+    /// Most unrecorded decisions drafted.
+    pub max_candidates: usize,
+    /// Sentences of context kept either side of a decision.
+    pub context_sentences: usize,
+    /// The session's agent: only its decision pages count as recorded.
+    /// Any agent's count when `None`.
+    pub agent: Option<String>,
+}
+
+impl Default for StopOptions {
+    fn default() -> Self {
+        Self {
+            patterns: decisions::DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            min_mentions: 2,
+            max_candidates: 3,
+            context_sentences: 1,
+            agent: None,
+        }
+    }
+}
+
+/// Parses a hook's stdin JSON.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` if the input isn't a JSON object.
+pub fn parse_input(input: &str) -> Result<HookInput, KbError> {
+    serde_json::from_str(input).map_err(|e| KbError::InvalidInput(format!("Invalid hook input: {}", e)))
+}
+
+/// Opens the database a hook should use: `--db` if given, otherwise the one
+/// `locate_db` would find from the session's working directory.
+///
+/// # Errors
+/// Returns `KbError::Io` or `KbError::Db` if the database can't be found,
+/// opened or migrated.
+pub fn open_db(path: Option<&Path>, input: &HookInput) -> Result<Connection, KbError> {
+    let start = match &input.cwd {
+        Some(cwd) => Some(cwd.clone()),
+        None => std::env::current_dir().ok(),
+    };
+    let (db_file, _) = db::locate_db_from(path, start.as_deref())?;
+    let mut conn = db::open_connection_at(&db_file)?;
+    db::migrate_with_backup(&mut conn, &db_file)?;
+    Ok(conn)
+}
+
+/// Handles `UserPromptSubmit`: finds prior knowledge relevant to the prompt.
+///
+/// # Returns
+/// The Markdown context for the agent, or `None` if the prompt is too short
+/// or nothing matched
+///
+/// # Errors
+/// Returns `KbError::Db` if a search fails.
+pub fn user_prompt_submit(
+    conn: &Connection,
+    prompt: &str,
+    thresholds: &PromptThresholds,
+) -> Result<Option<String>, KbError> {
+    let keywords = extract_keywords_with(prompt, thresholds.min_keyword_len, usize::MAX);
+    if keywords.is_empty() || keywords.len() < thresholds.min_keywords {
+        return Ok(None);
+    }
+
+    // One search per keyword: queries are matched as phrases, so searching
+    // for the whole prompt would rarely hit anything.
+    let weights = RecallWeights::configured(None)?;
+    let mut pitfalls = Vec::new();
+    let mut related = Vec::new();
+    for keyword in keywords.iter().take(thresholds.max_keywords) {
+        collect(&mut pitfalls, search_keyword(conn, keyword, Some(PITFALL_LABEL), &weights)?, thresholds.max_pitfalls);
+        let shown: HashSet<String> = pitfalls.iter().map(|r: &SearchResult| r.page.id.clone()).collect();
+        let general = search_keyword(conn, keyword, None, &weights)?
+            .into_iter()
+            .filter(|r| !shown.contains(&r.page.id))
+            .collect();
+        collect(&mut related, general, thresholds.max_results);
+    }
+    // A pitfall found by a later keyword may already be listed as related.
+    let shown: HashSet<&str> = pitfalls.iter().map(|r| r.page.id.as_str()).collect();
+    related.retain(|r| !shown.contains(r.page.id.as_str()));
+
+    if pitfalls.is_empty() && related.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(render_prior_knowledge(&pitfalls, &related, thresholds.excerpt_chars)))
+}
+
+/// Handles `Stop`: checks the session for decisions that weren't recorded.
+///
+/// A decision counts as recorded when a decision page created since the
+/// session started shares most of its keywords (see `decisions`).
+///
+/// # Returns
+/// The reason to block stopping, with a draft `page create` command per
+/// unrecorded decision, or `None` to let the agent stop
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` for an invalid pattern, `KbError::Io` if
+/// the transcript can't be read, or `KbError::Db` if a query fails.
+pub fn stop(conn: &Connection, input: &HookInput, options: &StopOptions) -> Result<Option<String>, KbError> {
+    // Blocking again would keep the agent from ever stopping.
+    if input.stop_hook_active {
+        return Ok(None);
+    }
+    let Some(path) = input.transcript_path.as_deref().filter(|p| p.is_file()) else {
+        return Ok(None);
+    };
+    let patterns = decisions::compile_patterns(&options.patterns)?;
+    let session = transcript::read(path)?;
+
+    let candidates = decisions::find_candidates(&session, &patterns, options.context_sentences);
+    if candidates.is_empty() || candidates.len() < options.min_mentions {
+        return Ok(None);
+    }
+    let recorded = decisions::recorded_since(conn, session.started_at.as_deref(), options.agent.as_deref())?;
+    let unrecorded: Vec<&decisions::Candidate> = candidates
+        .iter()
+        .filter(|c| !recorded.iter().any(|page| decisions::is_recorded_by(c, page)))
+        .take(options.max_candidates)
+        .collect();
+    if unrecorded.is_empty() {
+        return Ok(None);
+    }
+
+    // Suggest the project's space if there is one by the directory's name.
+    let cwd = input.cwd.as_ref().or(session.cwd.as_ref());
+    let space = cwd
+        .and_then(|dir| dir.file_name())
+        .map(|name| slugify(&name.to_string_lossy()))
+        .filter(|slug| repo::get_space_by_slug(conn, slug).is_ok())
+        .unwrap_or_else(|| "<space>".to_string());
+
+    let mut reason = String::from(
+        "It looks like decisions were discussed in this session but not recorded in whatidid.\n\n\
+         Review each one below. Fill in the TODOs and run the command to record it, \
+         or skip it if it isn't a decision worth keeping.\n",
+    );
+    for (i, candidate) in unrecorded.iter().enumerate() {
+        let mut quote = candidate.before.clone();
+        quote.push(format!("**{}**", candidate.sentence));
+        quote.extend(candidate.after.iter().cloned());
+        let mut command = format!(
+            "whatidid page create --space {} --title {} --type decision",
+            space,
+            shell_quote(&candidate.draft_title())
+        );
+        if let Some(agent) = &options.agent {
+            command.push_str(&format!(" --agent {}", shell_quote(agent)));
+        }
+        command.push_str(&format!(" \\\n  --sections {}", shell_quote(&candidate.draft_sections().to_string())));
+        reason.push_str(&format!("\n{}. > {}\n\n```bash\n{}\n```\n", i + 1, quote.join(" "), command));
+    }
+    Ok(Some(reason))
+}
+
+/// Name of the space `session_end` creates when the one asked for is missing.
+const SESSION_SPACE_NAME: &str = "Session Log";
+
+/// Handles `SessionEnd`: records the session's transcript as a page in the
+/// space `space_slug`, creating the space if it doesn't exist.
+///
+/// # Returns
+/// The ingest report, or `None` if the input names no transcript file
+///
+/// # Errors
+/// Returns `KbError::Io` if the transcript can't be read, or the errors of
+/// `transcript::ingest`.
+pub fn session_end(
+    conn: &Connection,
+    input: &HookInput,
+    space_slug: &str,
+    summarizer: &dyn Summarizer,
+    identity: &AgentIdentity,
+) -> Result<Option<TranscriptReport>, KbError> {
+    let Some(path) = input.transcript_path.as_deref().filter(|p| p.is_file()) else {
+        return Ok(None);
+    };
+    let session = transcript::read(path)?;
+    let space = match repo::get_space_by_slug(conn, space_slug) {
+        Ok(space) => space,
+        Err(KbError::NotFound(_)) => repo::create_space(
+            conn,
+            space_slug,
+            SESSION_SPACE_NAME,
+            "Session summaries recorded by the SessionEnd hook",
+        )?,
+        Err(e) => return Err(e),
+    };
+    transcript::ingest(conn, &session, &space.id, None, summarizer, identity).map(Some)
+}
+
+/// Quotes a string for a POSIX shell.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Searches for one keyword, ranked for recall so feedback counts.
+fn search_keyword(
+    conn: &Connection,
+    keyword: &str,
+    label: Option<&str>,
+    weights: &RecallWeights,
+) -> Result<Vec<SearchResult>, KbError> {
+    let params = SearchParams {
+        query: Some(keyword.to_string()),
+        space_id: None,
+        page_type: None,
+        label: label.map(str::to_string),
+        created_by_agent: None,
+        section: None,
+    };
+    Ok(search::recall(conn, &params, weights)?.into_iter().map(|r| r.result).collect())
+}
+
+/// Appends results for pages not already in `into`, up to `limit` in total.
+fn collect(into: &mut Vec<SearchResult>, results: Vec<SearchResult>, limit: usize) {
+    for result in results {
+        if into.len() >= limit {
+            break;
+        }
+        if !into.iter().any(|r| r.page.id == result.page.id) {
+            into.push(result);
+        }
+    }
+}
+
+fn render_prior_knowledge(pitfalls: &[SearchResult], related: &[SearchResult], excerpt_chars: usize) -> String {
+    let mut out = String::from("## Prior Knowledge from whatidid\n\n");
+    if !pitfalls.is_empty() {
+        out.push_str("### Known Pitfalls\n");
+        for result in pitfalls {
+            out.push_str(&render_result(result, excerpt_chars));
+        }
+        out.push('\n');
+    }
+    if !related.is_empty() {
+        out.push_str("### Related Prior Knowledge\n");
+        for result in related {
+            out.push_str(&render_result(result, excerpt_chars));
+        }
+        out.push('\n');
+    }
+    out.push_str("Review these results and reference any relevant findings in your approach.");
+    out
+}
+
+fn render_result(result: &SearchResult, excerpt_chars: usize) -> String {
+    let page = &result.page;
+    let mut line = format!("- **{}** [{}] `{}`", page.title, page.page_type.as_str(), page.id);
+    if !page.labels.is_empty() {
+        line.push_str(&format!(" ({})", page.labels.join(", ")));
+    }
+    line.push('\n');
+    let excerpt = result.excerpt.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !excerpt.is_empty() {
+        line.push_str(&format!("  > {}\n", excerpt.chars().take(excerpt_chars).collect::<String>()));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PageType;
+    use crate::repo;
+
+    fn page(conn: &Connection, space_id: &str, title: &str, body: &str, labels: &[&str]) -> String {
+        let labels: Vec<String> = labels.iter().map(|l| l.to_string()).collect();
+        repo::create_page(conn, space_id, None, title, PageType::Reference, body, None, &labels, "u", "a")
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn test_parse_input_ignores_unknown_fields() {
+        let input = parse_input(r#"{"session_id": "s1", "prompt": "hello", "cwd": "/tmp/x", "hook_event_name": "UserPromptSubmit"}"#)
+            .unwrap();
+        assert_eq!(input.prompt.as_deref(), Some("hello"));
+        assert_eq!(input.cwd, Some(PathBuf::from("/tmp/x")));
+        assert!(parse_input("not json").is_err());
+    }
+
+    #[test]
+    fn test_short_prompts_get_no_context() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "proj", "Proj", "").unwrap();
+        page(&conn, &space.id, "Migrations", "Migrations lock the table.", &[]);
+
+        let thresholds = PromptThresholds::default();
+        assert_eq!(user_prompt_submit(&conn, "fix migrations", &thresholds).unwrap(), None);
+        // Nothing matches any keyword.
+        assert_eq!(
+            user_prompt_submit(&conn, "refactor parser tokenizer lexer", &thresholds).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_prompt_context_lists_pitfalls_then_related() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "proj", "Proj", "").unwrap();
+        let pitfall = page(&conn, &space.id, "Migrations deadlock", "Running migrations concurrently deadlocks.", &["pitfall"]);
+        let related = page(&conn, &space.id, "Migration runner", "The runner applies migrations in order.", &[]);
+        page(&conn, &space.id, "Unrelated", "Nothing to see.", &[]);
+
+        let text = user_prompt_submit(&conn, "Please debug failing migrations during startup", &PromptThresholds::default())
+            .unwrap()
+            .expect("context");
+        let pitfalls_at = text.find("### Known Pitfalls").expect("pitfall section");
+        let related_at = text.find("### Related Prior Knowledge").expect("related section");
+        assert!(pitfalls_at < related_at);
+        assert!(text.contains(&format!("- **Migrations deadlock** [reference] `{}` (pitfall)", pitfall)));
+        assert!(text.contains(&format!("`{}`", related)));
+        // The pitfall isn't repeated under related knowledge.
+        assert_eq!(text.matches(&pitfall).count(), 1);
+        assert!(!text.contains("Unrelated"));
+        assert!(text.ends_with("reference any relevant findings in your approach."));
+    }
+
+    #[test]
+    fn test_thresholds_are_honoured() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "proj", "Proj", "").unwrap();
+        for i in 0..4 {
+            page(&conn, &space.id, &format!("Cache note {}", i), &format!("caching detail {}", "x".repeat(300)), &[]);
+        }
+
+        let thresholds = PromptThresholds {
+            min_keywords: 1,
+            max_results: 2,
+            excerpt_chars: 20,
+            ..PromptThresholds::default()
+        };
+        let text = user_prompt_submit(&conn, "caching", &thresholds).unwrap().expect("context");
+        assert_eq!(text.matches("\n- **Cache note").count(), 2);
+        assert!(text.lines().filter(|l| l.starts_with("  > ")).all(|l| l.chars().count() <= 24));
+    }
+
+    fn write_transcript(dir: &Path, started_at: &str, replies: &[&str]) -> PathBuf {
+        let mut text = String::new();
+        for reply in replies {
+            let entry = serde_json::json!({
+                "type": "assistant",
+                "sessionId": "s-1",
+                "timestamp": started_at,
+                "message": {"role": "assistant", "content": [{"type": "text", "text": reply}]},
+            });
+            text.push_str(&format!("{}\n", entry));
+        }
+        let path = dir.join("s-1.jsonl");
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_stop_blocks_on_unrecorded_decisions() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "my-app", "My App", "").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let start = chrono::Utc::now().to_rfc3339();
+        let path = write_transcript(
+            dir.path(),
+            &start,
+            &[
+                "Locks were contended. We decided to enable WAL mode for concurrency. Readers no longer block.",
+                "I went with serde_yaml for parsing the frontmatter.",
+            ],
+        );
+        let input = HookInput {
+            cwd: Some(PathBuf::from("/work/my-app")),
+            transcript_path: Some(path),
+            ..HookInput::default()
+        };
+        let options = StopOptions {
+            agent: Some("claude-code".to_string()),
+            ..StopOptions::default()
+        };
+
+        let reason = stop(&conn, &input, &options).unwrap().expect("block");
+        assert!(reason.contains("> Locks were contended. **We decided to enable WAL mode for concurrency.** Readers no longer block."));
+        assert!(reason.contains("whatidid page create --space my-app --title 'We decided to enable WAL mode for concurrency' --type decision --agent 'claude-code'"));
+        assert!(reason.contains(r#""decision":"I went with serde_yaml for parsing the frontmatter.""#));
+
+        // Recording one decision leaves only the other.
+        repo::create_page(
+            &conn,
+            &space.id,
+            None,
+            "Enable WAL mode",
+            PageType::Decision,
+            "WAL mode for concurrency.",
+            None,
+            &[],
+            "u",
+            "claude-code",
+        )
+        .unwrap();
+        let reason = stop(&conn, &input, &options).unwrap().expect("block");
+        assert!(!reason.contains("WAL"));
+        assert!(reason.contains("serde_yaml"));
+
+        // Fewer mentions than the threshold, or a stop hook already active: let it stop.
+        let relaxed = StopOptions {
+            min_mentions: 3,
+            ..options.clone()
+        };
+        assert_eq!(stop(&conn, &input, &relaxed).unwrap(), None);
+        let active = HookInput {
+            stop_hook_active: true,
+            ..input
+        };
+        assert_eq!(stop(&conn, &active, &options).unwrap(), None);
+    }
+
+    #[test]
+    fn test_stop_ignores_decisions_recorded_before_the_session() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "proj", "Proj", "").unwrap();
+        repo::create_page(&conn, &space.id, None, "Serde YAML", PageType::Decision, "serde_yaml parsing frontmatter", None, &[], "u", "a")
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_transcript(
+            dir.path(),
+            &chrono::Utc::now().to_rfc3339(),
+            &["We went with serde_yaml for parsing the frontmatter. We decided to keep it."],
+        );
+        let input = HookInput {
+            transcript_path: Some(path),
+            ..HookInput::default()
+        };
+        let reason = stop(&conn, &input, &StopOptions::default()).unwrap().expect("block");
+        assert!(reason.contains("--space <space>"));
+        assert!(reason.contains("serde_yaml"));
+    }
+
+    #[test]
+    fn test_session_end_creates_space_and_updates_page() {
+        let conn = db::test_connection();
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_transcript(
+            dir.path(),
+            &chrono::Utc::now().to_rfc3339(),
+            &["I added retries to the upload client so transient network errors no longer fail the sync."],
+        );
+        let input = HookInput {
+            transcript_path: Some(path),
+            ..HookInput::default()
+        };
+        let identity = AgentIdentity {
+            user: "u".to_string(),
+            agent: "claude-code".to_string(),
+            session: None,
+        };
+        let summarizer = crate::summarize::Extractive::default();
+
+        let report = session_end(&conn, &input, "session-log", &summarizer, &identity).unwrap().expect("report");
+        assert_eq!(report.action, crate::models::IngestAction::Created);
+        let space = repo::get_space_by_slug(&conn, "session-log").unwrap();
+        assert_eq!(repo::get_page(&conn, &report.page_id).unwrap().space_id, space.id);
+
+        let again = session_end(&conn, &input, "session-log", &summarizer, &identity).unwrap().expect("report");
+        assert_eq!(again.action, crate::models::IngestAction::Unchanged);
+        assert_eq!(again.page_id, report.page_id);
+
+        assert!(session_end(&conn, &HookInput::default(), "session-log", &summarizer, &identity).unwrap().is_none());
+    }
+}
@@ -0,0 +1,584 @@
+//! Registering whatidid's hooks in Claude Code settings files.
+//!
+//! Claude Code reads hooks from `~/.claude/settings.json` (every session)
+//! and `.claude/settings.json` in a project. Hooks live under
+//! `hooks.<Event>` as a list of matcher groups, each with its own list of
+//! commands:
+//!
+//! ```json
+//! {"hooks": {"Stop": [{"hooks": [{"type": "command", "command": "whatidid hook stop"}]}]}}
+//! ```
+//!
+//! Installing adds a group for each of our hooks that isn't registered yet
+//! and leaves everything else in the file alone, so other tools' hooks and
+//! settings survive. A hook still registered as one of the shell scripts in
+//! `hooks/` is recognised by the script's name and replaced with the
+//! subcommand that superseded it. Uninstalling removes our commands and any
+//! group, event or `hooks` object that leaves empty. Keys keep the order
+//! the file lists them in.
+
+use crate::db::KbError;
+use crate::models::{HookAction, HookReport, HookScope, HookState};
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One of the hooks whatidid provides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookSpec {
+    /// Name used by `--hook`, the same as the shell script's.
+    pub name: &'static str,
+    /// Claude Code event the hook runs on.
+    pub event: &'static str,
+    /// The command registered for it.
+    pub command: &'static str,
+    /// The shell script it replaces.
+    pub script: &'static str,
+    /// Seconds Claude Code waits before giving up on it.
+    pub timeout: u64,
+}
+
+/// Every hook, in the order they run in a session.
+pub const HOOKS: &[HookSpec] = &[
+    HookSpec {
+        name: "search-before-solving",
+        event: "UserPromptSubmit",
+        command: "whatidid hook user-prompt-submit",
+        script: "search-before-solving.sh",
+        timeout: 10,
+    },
+    HookSpec {
+        name: "check-decisions",
+        event: "Stop",
+        command: "whatidid --agent claude-code hook stop",
+        script: "check-decisions.sh",
+        timeout: 30,
+    },
+    HookSpec {
+        name: "session-summary",
+        event: "SessionEnd",
+        command: "whatidid --agent claude-code hook session-end",
+        script: "session-summary.sh",
+        timeout: 60,
+    },
+];
+
+impl HookSpec {
+    /// The `whatidid hook` subcommand the hook runs.
+    fn subcommand(&self) -> &'static str {
+        self.command.rsplit(' ').next().unwrap_or(self.command)
+    }
+
+    /// How `command` runs this hook, if it does: as `whatidid [flags] hook
+    /// <subcommand> [flags]`, or as the shell script at any path.
+    fn state_of(&self, command: &str) -> Option<HookState> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let file_name = |word: &str| Path::new(word.trim_matches(['"', '\''])).file_name().map(|n| n.to_os_string());
+        let is_native = words.first().and_then(|w| file_name(w)).is_some_and(|n| n == "whatidid")
+            && words.windows(2).any(|pair| pair == ["hook", self.subcommand()]);
+        if is_native {
+            Some(HookState::Native)
+        } else if words.iter().any(|w| file_name(w).is_some_and(|n| n == self.script)) {
+            Some(HookState::Script)
+        } else {
+            None
+        }
+    }
+
+    fn entry(&self) -> Value {
+        serde_json::json!({"type": "command", "command": self.command, "timeout": self.timeout})
+    }
+}
+
+/// The settings file for `scope`: under `home` for global hooks, under
+/// `project` for project hooks.
+pub fn settings_path(scope: HookScope, home: &Path, project: &Path) -> PathBuf {
+    match scope {
+        HookScope::Global => home.join(".claude").join("settings.json"),
+        HookScope::Project => project.join(".claude").join("settings.json"),
+    }
+}
+
+/// The hooks named by `--hook`, in `HOOKS` order; all of them if `names`
+/// is empty.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` for a name that isn't a hook.
+pub fn select(names: &[String]) -> Result<Vec<&'static HookSpec>, KbError> {
+    if let Some(unknown) = names.iter().find(|n| !HOOKS.iter().any(|h| h.name == n.as_str())) {
+        let known: Vec<&str> = HOOKS.iter().map(|h| h.name).collect();
+        return Err(KbError::InvalidInput(format!(
+            "Unknown hook '{}' (expected one of: {})",
+            unknown,
+            known.join(", ")
+        )));
+    }
+    Ok(HOOKS
+        .iter()
+        .filter(|h| names.is_empty() || names.iter().any(|n| n == h.name))
+        .collect())
+}
+
+/// Reports how each of `hooks` is registered in the settings file at `path`.
+///
+/// # Errors
+/// Returns `KbError::Io` if the file can't be read, or
+/// `KbError::InvalidInput` if it isn't valid settings JSON.
+pub fn status(path: &Path, scope: HookScope, hooks: &[&HookSpec]) -> Result<Vec<HookReport>, KbError> {
+    let (settings, _) = read_settings(path)?;
+    hooks.iter().map(|hook| report(&settings, path, scope, hook, None)).collect()
+}
+
+/// Registers each of `hooks` in the settings file at `path`, creating it if
+/// needed. Hooks already registered as the subcommand are left as they
+/// are; ones registered as their shell script are switched to the
+/// subcommand. The file is only written if something changed.
+///
+/// # Errors
+/// As `status`, plus `KbError::Io` if the file can't be written.
+pub fn install(path: &Path, scope: HookScope, hooks: &[&HookSpec]) -> Result<Vec<HookReport>, KbError> {
+    let (mut settings, order) = read_settings(path)?;
+    let mut actions = Vec::new();
+    for hook in hooks {
+        let states = registered(&settings, hook)?;
+        let action = if states.contains(&HookState::Script) {
+            remove(&mut settings, hook, HookState::Script)?;
+            if !states.contains(&HookState::Native) {
+                add(&mut settings, hook)?;
+            }
+            HookAction::Replaced
+        } else if states.contains(&HookState::Native) {
+            HookAction::Unchanged
+        } else {
+            add(&mut settings, hook)?;
+            HookAction::Installed
+        };
+        actions.push(action);
+    }
+    if actions.iter().any(|a| *a != HookAction::Unchanged) {
+        write_settings(path, &settings, &order)?;
+    }
+    hooks
+        .iter()
+        .zip(actions)
+        .map(|(hook, action)| report(&settings, path, scope, hook, Some(action)))
+        .collect()
+}
+
+/// Removes every registration of each of `hooks`, subcommand or script,
+/// from the settings file at `path`. A missing file is left missing.
+///
+/// # Errors
+/// As `install`.
+pub fn uninstall(path: &Path, scope: HookScope, hooks: &[&HookSpec]) -> Result<Vec<HookReport>, KbError> {
+    let (mut settings, order) = read_settings(path)?;
+    let mut actions = Vec::new();
+    for hook in hooks {
+        let mut removed = remove(&mut settings, hook, HookState::Native)?;
+        removed |= remove(&mut settings, hook, HookState::Script)?;
+        actions.push(if removed { HookAction::Removed } else { HookAction::Unchanged });
+    }
+    if actions.contains(&HookAction::Removed) {
+        write_settings(path, &settings, &order)?;
+    }
+    hooks
+        .iter()
+        .zip(actions)
+        .map(|(hook, action)| report(&settings, path, scope, hook, Some(action)))
+        .collect()
+}
+
+fn report(
+    settings: &Value,
+    path: &Path,
+    scope: HookScope,
+    hook: &HookSpec,
+    action: Option<HookAction>,
+) -> Result<HookReport, KbError> {
+    let commands: Vec<String> = commands(settings, hook.event)?
+        .filter(|command| hook.state_of(command).is_some())
+        .map(str::to_string)
+        .collect();
+    let states = registered(settings, hook)?;
+    let state = if states.contains(&HookState::Native) {
+        HookState::Native
+    } else if states.contains(&HookState::Script) {
+        HookState::Script
+    } else {
+        HookState::NotInstalled
+    };
+    Ok(HookReport {
+        hook: hook.name.to_string(),
+        event: hook.event.to_string(),
+        scope,
+        settings: path.display().to_string(),
+        state,
+        commands,
+        action,
+    })
+}
+
+/// The order of the keys of each object in a JSON document, which
+/// `serde_json::Value` doesn't keep: its objects sort their keys.
+#[derive(Debug, Default)]
+struct KeyOrder {
+    keys: Vec<(String, KeyOrder)>,
+    items: Vec<KeyOrder>,
+}
+
+impl<'de> Deserialize<'de> for KeyOrder {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyOrderVisitor;
+
+        impl<'de> Visitor<'de> for KeyOrderVisitor {
+            type Value = KeyOrder;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("any JSON value")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<KeyOrder, A::Error> {
+                let mut order = KeyOrder::default();
+                while let Some((key, value)) = map.next_entry::<String, KeyOrder>()? {
+                    order.keys.push((key, value));
+                }
+                Ok(order)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<KeyOrder, A::Error> {
+                let mut order = KeyOrder::default();
+                while let Some(item) = seq.next_element()? {
+                    order.items.push(item);
+                }
+                Ok(order)
+            }
+
+            fn visit_bool<E>(self, _: bool) -> Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+
+            fn visit_i64<E>(self, _: i64) -> Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+
+            fn visit_u64<E>(self, _: u64) -> Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+
+            fn visit_f64<E>(self, _: f64) -> Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+
+            fn visit_str<E>(self, _: &str) -> Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+
+            fn visit_unit<E>(self) -> Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+        }
+
+        deserializer.deserialize_any(KeyOrderVisitor)
+    }
+}
+
+/// Serializes a value with each object's keys in the order `order` lists
+/// them; keys it doesn't list (ones just added) come after, sorted.
+struct Ordered<'a>(&'a Value, &'a KeyOrder);
+
+impl Serialize for Ordered<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let empty = KeyOrder::default();
+        match self.0 {
+            Value::Object(object) => {
+                let mut map = serializer.serialize_map(Some(object.len()))?;
+                for (key, order) in &self.1.keys {
+                    if let Some(value) = object.get(key) {
+                        map.serialize_entry(key, &Ordered(value, order))?;
+                    }
+                }
+                for (key, value) in object {
+                    if !self.1.keys.iter().any(|(k, _)| k == key) {
+                        map.serialize_entry(key, &Ordered(value, &empty))?;
+                    }
+                }
+                map.end()
+            }
+            Value::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for (i, item) in items.iter().enumerate() {
+                    seq.serialize_element(&Ordered(item, self.1.items.get(i).unwrap_or(&empty)))?;
+                }
+                seq.end()
+            }
+            value => value.serialize(serializer),
+        }
+    }
+}
+
+/// Reads a settings file and the order of its keys; a missing file reads
+/// as `{}`.
+fn read_settings(path: &Path) -> Result<(Value, KeyOrder), KbError> {
+    if !path.exists() {
+        return Ok((Value::Object(Map::new()), KeyOrder::default()));
+    }
+    let text = fs::read_to_string(path)?;
+    if text.trim().is_empty() {
+        return Ok((Value::Object(Map::new()), KeyOrder::default()));
+    }
+    let not_json = |e: serde_json::Error| KbError::InvalidInput(format!("{} is not valid JSON: {}", path.display(), e));
+    let settings: Value = serde_json::from_str(&text).map_err(not_json)?;
+    if !settings.is_object() {
+        return Err(KbError::InvalidInput(format!("{} is not a JSON object", path.display())));
+    }
+    let order: KeyOrder = serde_json::from_str(&text).map_err(not_json)?;
+    Ok((settings, order))
+}
+
+/// Writes a settings file through a temporary file, so a failed write
+/// can't leave it half-written. Keys keep their order in `order`.
+fn write_settings(path: &Path, settings: &Value, order: &KeyOrder) -> Result<(), KbError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let text =
+        serde_json::to_string_pretty(&Ordered(settings, order)).map_err(|e| KbError::InvalidInput(e.to_string()))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, format!("{}\n", text))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn invalid(what: &str) -> KbError {
+    KbError::InvalidInput(format!("Unexpected settings format: {}", what))
+}
+
+/// The matcher groups registered for `event`, if any.
+fn groups<'a>(settings: &'a Value, event: &str) -> Result<&'a [Value], KbError> {
+    match settings.get("hooks") {
+        None => Ok(&[]),
+        Some(Value::Object(hooks)) => match hooks.get(event) {
+            None => Ok(&[]),
+            Some(Value::Array(groups)) => Ok(groups),
+            Some(_) => Err(invalid(&format!("hooks.{} is not a list", event))),
+        },
+        Some(_) => Err(invalid("hooks is not an object")),
+    }
+}
+
+/// Every command registered for `event`.
+fn commands<'a>(settings: &'a Value, event: &str) -> Result<impl Iterator<Item = &'a str>, KbError> {
+    Ok(groups(settings, event)?
+        .iter()
+        .filter_map(|group| group.get("hooks").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|entry| entry.get("command").and_then(Value::as_str)))
+}
+
+/// How `hook` is registered, once per registration.
+fn registered(settings: &Value, hook: &HookSpec) -> Result<Vec<HookState>, KbError> {
+    Ok(commands(settings, hook.event)?.filter_map(|c| hook.state_of(c)).collect())
+}
+
+/// Adds `hook` in a matcher group of its own.
+fn add(settings: &mut Value, hook: &HookSpec) -> Result<(), KbError> {
+    let hooks = settings
+        .as_object_mut()
+        .ok_or_else(|| invalid("not an object"))?
+        .entry("hooks")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(|| invalid("hooks is not an object"))?;
+    hooks
+        .entry(hook.event)
+        .or_insert_with(|| Value::Array(Vec::new()))
+        .as_array_mut()
+        .ok_or_else(|| invalid(&format!("hooks.{} is not a list", hook.event)))?
+        .push(serde_json::json!({"hooks": [hook.entry()]}));
+    Ok(())
+}
+
+/// Removes the registrations of `hook` in `state`, then any group, event
+/// or `hooks` object left empty.
+///
+/// # Returns
+/// Whether anything was removed
+fn remove(settings: &mut Value, hook: &HookSpec, state: HookState) -> Result<bool, KbError> {
+    // Validates the structure, so the lookups below can't fail.
+    if groups(settings, hook.event)?.is_empty() {
+        return Ok(false);
+    }
+    let Some(Value::Object(hooks)) = settings.get_mut("hooks") else {
+        return Ok(false);
+    };
+    let Some(Value::Array(groups)) = hooks.get_mut(hook.event) else {
+        return Ok(false);
+    };
+    let mut removed = false;
+    groups.retain_mut(|group| {
+        let Some(Value::Array(entries)) = group.get_mut("hooks") else {
+            return true;
+        };
+        let before = entries.len();
+        entries.retain(|entry| entry.get("command").and_then(Value::as_str).and_then(|c| hook.state_of(c)) != Some(state));
+        if entries.len() == before {
+            return true;
+        }
+        removed = true;
+        !entries.is_empty()
+    });
+    if groups.is_empty() {
+        hooks.remove(hook.event);
+    }
+    if hooks.is_empty() {
+        if let Some(settings) = settings.as_object_mut() {
+            settings.remove("hooks");
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(name: &str) -> &'static HookSpec {
+        select(&[name.to_string()]).unwrap()[0]
+    }
+
+    fn read(path: &Path) -> Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_select_hooks() {
+        assert_eq!(select(&[]).unwrap().len(), HOOKS.len());
+        let picked = select(&["session-summary".to_string(), "check-decisions".to_string()]).unwrap();
+        let names: Vec<&str> = picked.iter().map(|h| h.name).collect();
+        assert_eq!(names, vec!["check-decisions", "session-summary"]);
+        assert!(select(&["nope".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_recognises_native_and_script_commands() {
+        let stop = hook("check-decisions");
+        assert_eq!(stop.state_of("whatidid hook stop"), Some(HookState::Native));
+        assert_eq!(stop.state_of("/usr/local/bin/whatidid --agent x hook stop --min-mentions 1"), Some(HookState::Native));
+        assert_eq!(stop.state_of("\"$CLAUDE_PROJECT_DIR\"/hooks/check-decisions.sh"), Some(HookState::Script));
+        assert_eq!(stop.state_of("$CLAUDE_PROJECT_DIR/hooks/check-decisions.sh"), Some(HookState::Script));
+        assert_eq!(stop.state_of("whatidid hook user-prompt-submit"), None);
+        assert_eq!(stop.state_of("other-tool hook stop"), None);
+    }
+
+    #[test]
+    fn test_install_preserves_other_settings_and_is_idempotent() {
+        let home = tempfile::tempdir().unwrap();
+        let path = settings_path(HookScope::Global, home.path(), Path::new("/unused"));
+        assert_eq!(path, home.path().join(".claude/settings.json"));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            r#"{"model": "opus", "hooks": {"Stop": [{"hooks": [{"type": "command", "command": "notify-send done"}]}]}}"#,
+        )
+        .unwrap();
+
+        let reports = install(&path, HookScope::Global, &select(&[]).unwrap()).unwrap();
+        assert!(reports.iter().all(|r| r.action == Some(HookAction::Installed) && r.state == HookState::Native));
+        let settings = read(&path);
+        assert_eq!(settings["model"], "opus");
+        let stop = settings["hooks"]["Stop"].as_array().unwrap();
+        assert_eq!(stop.len(), 2);
+        assert_eq!(stop[0]["hooks"][0]["command"], "notify-send done");
+        assert_eq!(stop[1]["hooks"][0]["command"], "whatidid --agent claude-code hook stop");
+        assert_eq!(settings["hooks"]["SessionEnd"][0]["hooks"][0]["timeout"], 60);
+
+        let written = fs::read_to_string(&path).unwrap();
+        let again = install(&path, HookScope::Global, &select(&[]).unwrap()).unwrap();
+        assert!(again.iter().all(|r| r.action == Some(HookAction::Unchanged)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), written);
+    }
+
+    #[test]
+    fn test_install_keeps_key_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(&path, r#"{"theme": "dark", "model": "opus", "env": {"Z": "1", "A": "2"}}"#).unwrap();
+
+        install(&path, HookScope::Global, &select(&["check-decisions".to_string()]).unwrap()).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let at = |key: &str| text.find(&format!("\"{}\"", key)).unwrap();
+        assert!(at("theme") < at("model") && at("model") < at("env") && at("env") < at("hooks"));
+        assert!(at("Z") < at("A"));
+    }
+
+    #[test]
+    fn test_install_replaces_scripts() {
+        let project = tempfile::tempdir().unwrap();
+        let path = settings_path(HookScope::Project, Path::new("/unused"), project.path());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            r#"{"hooks": {"SessionEnd": [{"hooks": [
+                {"type": "command", "command": "$CLAUDE_PROJECT_DIR/hooks/session-summary.sh"},
+                {"type": "command", "command": "echo bye"}
+            ]}]}}"#,
+        )
+        .unwrap();
+        let summary = hook("session-summary");
+        assert_eq!(status(&path, HookScope::Project, &[summary]).unwrap()[0].state, HookState::Script);
+
+        let reports = install(&path, HookScope::Project, &[summary]).unwrap();
+        assert_eq!(reports[0].action, Some(HookAction::Replaced));
+        assert_eq!(reports[0].commands, vec![summary.command]);
+        let groups = read(&path)["hooks"]["SessionEnd"].as_array().unwrap().clone();
+        assert_eq!(groups[0]["hooks"].as_array().unwrap().len(), 1);
+        assert_eq!(groups[0]["hooks"][0]["command"], "echo bye");
+        assert_eq!(groups[1]["hooks"][0]["command"], summary.command);
+    }
+
+    #[test]
+    fn test_uninstall_removes_only_ours() {
+        let home = tempfile::tempdir().unwrap();
+        let path = settings_path(HookScope::Global, home.path(), home.path());
+        let hooks = select(&[]).unwrap();
+
+        // Nothing installed: the file isn't created.
+        let reports = uninstall(&path, HookScope::Global, &hooks).unwrap();
+        assert!(reports.iter().all(|r| r.action == Some(HookAction::Unchanged)));
+        assert!(!path.exists());
+
+        install(&path, HookScope::Global, &hooks).unwrap();
+        let mut settings = read(&path);
+        settings["env"] = serde_json::json!({"KB_AGENT": "claude-code"});
+        settings["hooks"]["Stop"][0]["hooks"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!({"type": "command", "command": "notify-send done"}));
+        fs::write(&path, settings.to_string()).unwrap();
+
+        let reports = uninstall(&path, HookScope::Global, &hooks).unwrap();
+        assert!(reports.iter().all(|r| r.action == Some(HookAction::Removed) && r.state == HookState::NotInstalled));
+        let settings = read(&path);
+        assert_eq!(
+            settings,
+            serde_json::json!({
+                "env": {"KB_AGENT": "claude-code"},
+                "hooks": {"Stop": [{"hooks": [{"type": "command", "command": "notify-send done"}]}]},
+            })
+        );
+    }
+
+    #[test]
+    fn test_rejects_malformed_settings_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        for text in ["{not json", "[]", r#"{"hooks": {"Stop": {}}}"#] {
+            fs::write(&path, text).unwrap();
+            assert!(matches!(install(&path, HookScope::Project, &select(&[]).unwrap()), Err(KbError::InvalidInput(_))));
+            assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        }
+    }
+}
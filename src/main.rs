@@ -4,17 +4,33 @@
 //! pages, links, and search. All output is JSON by default (for agent consumption)
 //! with an optional `--pretty` flag for human readability.
 
+mod adr;
+mod archive;
+mod context;
 mod db;
+mod dedupe;
+mod decisions;
+mod hook;
+mod hook_install;
+mod markdown;
+mod merge;
 mod models;
+mod obsidian;
 mod output;
+mod publish;
 mod repo;
 mod search;
+mod summarize;
+mod sync;
+mod transcript;
 mod tui;
+mod wikilinks;
 
 use clap::{Parser, Subcommand};
-use models::{AgentIdentity, LinkRelation, PageType};
+use models::{AgentIdentity, DbSource, LinkRelation, OnDuplicate, PageType, PageWriteResult, Sourced};
 use output::OutputMode;
 use std::io::{self, Read as _};
+use std::path::PathBuf;
 use std::process;
 
 /// A local knowledge base CLI for AI agents.
@@ -37,12 +53,27 @@ struct Cli {
     #[arg(long, global = true)]
     agent: Option<String>,
 
+    /// Record writes as made in this agent session (default: $KB_SESSION).
+    #[arg(long, global = true)]
+    session: Option<String>,
+
+    /// Use this database file (default: $KB_PATH, then the nearest
+    /// .whatidid/kb.db, then ~/.knowledge-base/kb.db).
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Create a project database in .whatidid/, used by every command run in
+    /// this directory or below it.
+    Init {
+        /// Directory to create .whatidid/ in (default: the current directory).
+        dir: Option<PathBuf>,
+    },
     /// Manage spaces (top-level organizational units).
     Space {
         #[command(subcommand)]
@@ -72,6 +103,43 @@ enum Commands {
         /// Filter to pages containing a specific section key.
         #[arg(long)]
         section: Option<String>,
+        /// Also search the global database, tagging each result with its source.
+        #[arg(long)]
+        include_global: bool,
+        /// Rank for agent memory: blend relevance with recency, read counts,
+        /// pitfall and accepted-decision boosts and a superseded penalty,
+        /// with each result's score breakdown.
+        #[arg(long)]
+        recall: bool,
+        /// Recall weight overrides, e.g. "recency=1,usage=0" (applied over
+        /// $KB_RECALL_WEIGHTS). Implies --recall.
+        #[arg(long)]
+        weights: Option<String>,
+    },
+    /// Gather the knowledge most relevant to a task into a bundle that fits
+    /// a token budget: pitfalls, accepted decisions, matching and linked
+    /// pages, each cited by page ID.
+    Context {
+        /// Description of the task.
+        task: String,
+        /// Token budget for the bundle (estimated at four characters per token).
+        #[arg(long, default_value_t = 4000)]
+        budget: usize,
+        /// Only search this space (linked pages may come from any space).
+        #[arg(long)]
+        space: Option<String>,
+        /// Output format (json, markdown).
+        #[arg(long, default_value = "json")]
+        format: String,
+    },
+    /// List pairs of pages in a space that nearly duplicate each other.
+    Dedupe {
+        /// Space slug.
+        #[arg(long)]
+        space: String,
+        /// Similarity (0 to 1) from which two pages count as near-duplicates.
+        #[arg(long, default_value_t = dedupe::DEFAULT_THRESHOLD)]
+        threshold: f64,
     },
     /// Manage links between pages.
     Link {
@@ -80,6 +148,279 @@ enum Commands {
     },
     /// Interactive TUI browser for exploring spaces and pages.
     Browse,
+    /// Back up, restore or check the database file.
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+    /// Export the knowledge base (or one space) as a single JSON document, a
+    /// directory of Markdown files, or an Obsidian vault.
+    Export {
+        /// Export format (json, markdown, obsidian).
+        #[arg(long, default_value = "json")]
+        format: String,
+        /// Only export this space. Required for markdown; for obsidian, all
+        /// spaces are exported as top-level folders when omitted.
+        #[arg(long)]
+        space: Option<String>,
+        /// Write to this file (json) or directory (markdown, obsidian) instead of stdout.
+        #[arg(long, short, visible_alias = "out")]
+        output: Option<PathBuf>,
+    },
+    /// Import spaces, pages, labels and links from an export file or Markdown directory.
+    #[command(args_conflicts_with_subcommands = true)]
+    Import {
+        #[command(subcommand)]
+        source: Option<ImportSource>,
+        /// Export file to read ("-" for stdin), or a Markdown directory.
+        file: Option<PathBuf>,
+        /// Import format (json, markdown).
+        #[arg(long, default_value = "json")]
+        format: String,
+        /// Markdown only: import every page into this space instead of the one in its frontmatter.
+        #[arg(long)]
+        space: Option<String>,
+        /// What to do with records that already exist (skip, overwrite, newer-wins).
+        #[arg(long, default_value = "skip")]
+        on_conflict: String,
+        /// Give imported spaces and pages new IDs instead of keeping the exported ones.
+        #[arg(long)]
+        remap_ids: bool,
+    },
+    /// Render a space as a static HTML site (pages, listings, backlinks and search).
+    Publish {
+        /// The space to publish.
+        #[arg(long)]
+        space: String,
+        /// Directory to write the site to.
+        #[arg(long, short, visible_alias = "out")]
+        output: PathBuf,
+    },
+    /// Sync the knowledge base with a git repository: commit local changes,
+    /// merge in the remote's and push the result.
+    Sync {
+        /// The repository's working tree (created if missing).
+        #[arg(long)]
+        repo: PathBuf,
+        /// URL or path of the remote to pull from and push to, saved as 'origin'.
+        #[arg(long)]
+        remote: Option<String>,
+        /// Commit and merge, but don't push.
+        #[arg(long)]
+        no_push: bool,
+    },
+    /// Record agent activity as pages.
+    Ingest {
+        #[command(subcommand)]
+        source: IngestSource,
+    },
+    /// Tell the knowledge base whether a page found for a query was useful.
+    /// Recall ranking boosts or demotes pages by feedback for similar queries.
+    #[command(
+        args_conflicts_with_subcommands = true,
+        group(clap::ArgGroup::new("verdict").required(true).args(["useful", "not_useful"]))
+    )]
+    Feedback {
+        #[command(subcommand)]
+        action: Option<FeedbackAction>,
+        /// The page the verdict is about.
+        #[arg(required = true)]
+        page_id: Option<String>,
+        /// The query the page was retrieved for.
+        #[arg(long, required = true)]
+        query: Option<String>,
+        /// The page helped.
+        #[arg(long)]
+        useful: bool,
+        /// The page didn't help.
+        #[arg(long)]
+        not_useful: bool,
+    },
+    /// List agent sessions, or show what one created and modified.
+    Session {
+        #[command(subcommand)]
+        action: SessionAction,
+    },
+    /// Handle a Claude Code hook event, reading the hook's JSON from stdin.
+    /// Never fails: errors produce no output.
+    Hook {
+        #[command(subcommand)]
+        event: HookEvent,
+    },
+    /// Register whatidid's hooks in Claude Code settings, or show where they
+    /// are registered.
+    Hooks {
+        #[command(subcommand)]
+        action: HooksAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum HooksAction {
+    /// Register hooks, leaving other settings and hooks alone. Hooks
+    /// registered as shell scripts are switched to `whatidid hook`.
+    Install {
+        /// Install in .claude/settings.json in the current directory.
+        #[arg(long, conflicts_with = "global")]
+        project: bool,
+        /// Install in ~/.claude/settings.json (the default).
+        #[arg(long)]
+        global: bool,
+        /// Only this hook (search-before-solving, check-decisions,
+        /// session-summary); repeat for several. Default: all of them.
+        #[arg(long)]
+        hook: Vec<String>,
+    },
+    /// Remove hooks, as subcommands or shell scripts.
+    Uninstall {
+        /// Only from the project settings (default: project and global).
+        #[arg(long, conflicts_with = "global")]
+        project: bool,
+        /// Only from the global settings (default: project and global).
+        #[arg(long)]
+        global: bool,
+        /// Only this hook; repeat for several. Default: all of them.
+        #[arg(long)]
+        hook: Vec<String>,
+    },
+    /// Show which hooks are registered in which settings file.
+    Status {
+        /// Only the project settings (default: project and global).
+        #[arg(long, conflicts_with = "global")]
+        project: bool,
+        /// Only the global settings (default: project and global).
+        #[arg(long)]
+        global: bool,
+        /// Only this hook; repeat for several. Default: all of them.
+        #[arg(long)]
+        hook: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionAction {
+    /// List sessions that wrote to the knowledge base, most recent first.
+    List,
+    /// Show the pages and links a session created or modified.
+    Show {
+        /// The session ID.
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum FeedbackAction {
+    /// List pages that are read often but have never been marked useful:
+    /// candidates for a rewrite or deletion.
+    Report {
+        /// Only pages read at least this many times.
+        #[arg(long, default_value_t = 5)]
+        min_reads: i64,
+        /// Only pages in this space.
+        #[arg(long)]
+        space: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum IngestSource {
+    /// Write a Claude Code session transcript (JSONL) as a session-log page:
+    /// requests, files changed, commits and open questions. Re-ingesting a
+    /// session updates its page.
+    Transcript {
+        /// The transcript file. Subagent transcripts next to it are included.
+        path: PathBuf,
+        /// Space to create the page in.
+        #[arg(long)]
+        space: String,
+        /// Page title (default: the summarizer's, else the session's first request).
+        #[arg(long)]
+        title: Option<String>,
+        /// Shell command that summarizes the conversation on its stdin
+        /// (default: $KB_SUMMARIZER, else the built-in offline summary).
+        #[arg(long)]
+        summarizer: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum HookEvent {
+    /// Search for prior knowledge about the submitted prompt and print it as
+    /// a `{"result": ...}` payload.
+    UserPromptSubmit {
+        /// Prompts with fewer keywords than this get no context.
+        #[arg(long, default_value_t = 3)]
+        min_keywords: usize,
+        /// Shortest word counted as a keyword.
+        #[arg(long, default_value_t = 5)]
+        min_keyword_len: usize,
+        /// Most keywords searched for.
+        #[arg(long, default_value_t = 5)]
+        max_keywords: usize,
+        /// Most related pages listed.
+        #[arg(long, default_value_t = 5)]
+        max_results: usize,
+        /// Most pitfalls listed.
+        #[arg(long, default_value_t = 3)]
+        max_pitfalls: usize,
+        /// Trim excerpts to this many characters.
+        #[arg(long, default_value_t = 200)]
+        excerpt_chars: usize,
+    },
+    /// Check the session transcript for decisions that no decision page was
+    /// created for, and block stopping with a draft for each.
+    Stop {
+        /// Decision pattern (case-insensitive regex); repeat for several.
+        /// Replaces the built-in patterns.
+        #[arg(long)]
+        pattern: Vec<String>,
+        /// Let sessions with fewer candidate decisions than this stop.
+        #[arg(long, default_value_t = 2)]
+        min_mentions: usize,
+        /// Most unrecorded decisions drafted.
+        #[arg(long, default_value_t = 3)]
+        max_candidates: usize,
+        /// Sentences of context kept either side of a decision.
+        #[arg(long, default_value_t = 1)]
+        context_sentences: usize,
+    },
+    /// Record the session's transcript as a session-log page, as
+    /// `ingest transcript` does. Prints nothing.
+    SessionEnd {
+        /// Space to record sessions in (created if missing).
+        #[arg(long, default_value = "session-log")]
+        space: String,
+        /// Shell command that summarizes the conversation on its stdin
+        /// (default: $KB_SUMMARIZER, else the built-in offline summary).
+        #[arg(long)]
+        summarizer: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportSource {
+    /// Import Architecture Decision Records (Nygard or MADR) as decision pages.
+    /// Re-running updates the pages created from each file.
+    Adr {
+        /// Directory containing NNNN-title.md files.
+        dir: PathBuf,
+        /// Space to create the pages in.
+        #[arg(long)]
+        space: String,
+    },
+    /// Import an Obsidian or Logseq vault. Folders become parent pages (or
+    /// spaces), tags become labels and wikilinks become links. Re-running
+    /// updates the pages created from each note.
+    Obsidian {
+        /// The vault's root directory.
+        vault: PathBuf,
+        /// Space to create the pages in (with --folders spaces, for notes at the vault root).
+        #[arg(long)]
+        space: String,
+        /// What vault folders become (pages, spaces).
+        #[arg(long, default_value = "pages")]
+        folders: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -107,6 +448,14 @@ enum SpaceAction {
         /// The space slug.
         slug: String,
     },
+    /// Set a space's link policy.
+    Policy {
+        /// The space slug.
+        slug: String,
+        /// Whether pages in this space may link to or from other spaces (allow, deny).
+        #[arg(long)]
+        cross_space_links: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -138,11 +487,22 @@ enum PageAction {
         /// Mutually exclusive with --body.
         #[arg(long)]
         sections: Option<String>,
+        /// What to do if the page nearly duplicates one in the space: refuse,
+        /// append (to the closest match instead), link (to the closest match)
+        /// or create.
+        #[arg(long, default_value = "refuse")]
+        on_duplicate: String,
+        /// Similarity (0 to 1) from which a page counts as a near-duplicate.
+        #[arg(long, default_value_t = dedupe::DEFAULT_THRESHOLD)]
+        duplicate_threshold: f64,
     },
     /// Get a page by ID.
     Get {
         /// The page ID.
         id: String,
+        /// Include the space slug and links with linked page titles.
+        #[arg(long)]
+        expand: bool,
     },
     /// Update a page's title and/or content.
     Update {
@@ -195,11 +555,42 @@ enum PageAction {
         /// Filter by creating agent.
         #[arg(long)]
         created_by_agent: Option<String>,
+        /// Also list pages from the global database, tagging each with its source.
+        #[arg(long)]
+        include_global: bool,
     },
     /// Delete a page by ID.
     Delete {
         /// The page ID.
         id: String,
+        /// Also delete all descendant pages. Without this, a page with children cannot be deleted.
+        #[arg(long)]
+        recursive: bool,
+    },
+    /// Show the page hierarchy as a tree.
+    Tree {
+        /// Only show pages in this space.
+        #[arg(long)]
+        space: Option<String>,
+        /// Start the tree at this page instead of at the top-level pages.
+        #[arg(long)]
+        root: Option<String>,
+    },
+    /// Move a page under a different parent.
+    Reparent {
+        /// The page ID.
+        id: String,
+        /// New parent page ID (same space), or "none" to make the page top-level.
+        #[arg(long)]
+        parent: String,
+    },
+    /// List links pointing at a page, including automatic [[wiki link]] mentions.
+    Backlinks {
+        /// The page ID.
+        id: String,
+        /// Include the title, type and space slug of the linking pages.
+        #[arg(long)]
+        expand: bool,
     },
     /// Show the expected sections schema for a page type.
     Schema {
@@ -220,11 +611,30 @@ enum LinkAction {
         /// Relationship type (relates-to, supersedes, depends-on, elaborates).
         #[arg(long, default_value = "relates-to")]
         relation: String,
+        /// Why the two pages are related.
+        #[arg(long, default_value = "")]
+        note: String,
+    },
+    /// Update a link's relation and/or note.
+    Update {
+        /// Source page ID.
+        source: String,
+        /// Target page ID.
+        target: String,
+        /// New relationship type.
+        #[arg(long)]
+        relation: Option<String>,
+        /// New note. Pass an empty string to clear it.
+        #[arg(long)]
+        note: Option<String>,
     },
     /// List all links for a page.
     List {
         /// Page ID to list links for.
         page_id: String,
+        /// Include the title, type and space slug of the pages on both ends.
+        #[arg(long)]
+        expand: bool,
     },
     /// Delete a link between two pages.
     Delete {
@@ -233,6 +643,42 @@ enum LinkAction {
         /// Target page ID.
         target: String,
     },
+    /// Audit all links for self-links, cycles and cross-space policy violations.
+    Check,
+}
+
+#[derive(Subcommand)]
+enum DbAction {
+    /// Write a consistent copy of the database to a new file. Safe while agents are writing.
+    Backup {
+        /// Path for the backup file (must not exist).
+        path: PathBuf,
+    },
+    /// Replace the database with a backup, after checking its schema version.
+    Restore {
+        /// Backup file to restore from.
+        path: PathBuf,
+    },
+    /// Run SQLite integrity, foreign key and full-text index checks.
+    Check,
+    /// Apply pending schema migrations, backing the database up first.
+    Migrate {
+        /// Only show which migrations are applied, pending or modified.
+        #[arg(long, conflicts_with = "dry_run")]
+        status: bool,
+        /// Show what would be applied without changing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Merge another knowledge-base database into this one. Pages edited in
+    /// both keep the newer version; conflicts are reported.
+    Merge {
+        /// The database file to merge from (left unchanged).
+        other: PathBuf,
+        /// Also keep the older version of each conflicting page, as a sibling page.
+        #[arg(long)]
+        keep_conflicts: bool,
+    },
 }
 
 /// Resolve the agent identity from CLI flags, env vars, and system defaults.
@@ -250,7 +696,9 @@ fn resolve_identity(cli: &Cli) -> AgentIdentity {
         .or_else(|| std::env::var("KB_AGENT").ok())
         .unwrap_or_else(|| "unknown".to_string());
 
-    AgentIdentity { user, agent }
+    let session = cli.session.clone().or_else(|| std::env::var("KB_SESSION").ok());
+
+    AgentIdentity { user, agent, session }
 }
 
 /// Read body content from --body flag or --stdin.
@@ -285,15 +733,96 @@ fn parse_page_type(s: &str) -> Result<PageType, db::KbError> {
 }
 
 /// Parse a link relation string, returning InvalidInput on failure.
-fn parse_link_relation(s: &str) -> Result<LinkRelation, db::KbError> {
-    LinkRelation::from_str(s).ok_or_else(|| {
+///
+/// `mentions` is rejected: those links are derived from [[wiki links]] in
+/// page content and would be overwritten on the next page write.
+fn parse_on_duplicate(s: &str) -> Result<OnDuplicate, db::KbError> {
+    OnDuplicate::from_str(s).ok_or_else(|| {
         db::KbError::InvalidInput(format!(
-            "Unknown relation '{}'. Valid relations: relates-to, supersedes, depends-on, elaborates",
+            "Unknown --on-duplicate '{}'. Valid values: refuse, append, link, create",
             s
         ))
     })
 }
 
+fn check_threshold(threshold: f64) -> Result<f64, db::KbError> {
+    if threshold > 0.0 && threshold <= 1.0 {
+        Ok(threshold)
+    } else {
+        Err(db::KbError::InvalidInput(format!(
+            "Duplicate threshold must be above 0 and at most 1, got {}",
+            threshold
+        )))
+    }
+}
+
+fn parse_link_relation(s: &str) -> Result<LinkRelation, db::KbError> {
+    match LinkRelation::from_str(s) {
+        Some(LinkRelation::Mentions) => Err(db::KbError::InvalidInput(
+            "'mentions' links are managed automatically from [[wiki links]] in page content".to_string(),
+        )),
+        Some(rel) => Ok(rel),
+        None => Err(db::KbError::InvalidInput(format!(
+            "Unknown relation '{}'. Valid relations: relates-to, supersedes, depends-on, elaborates",
+            s
+        ))),
+    }
+}
+
+/// Print a freshly written page along with any [[wiki link]] references
+/// that did not resolve.
+fn print_page_write(conn: &rusqlite::Connection, mode: OutputMode, result: &PageWriteResult) {
+    output::print(mode, result, || {
+        let slug = repo::get_space_by_id(conn, &result.page.space_id)
+            .map(|s| s.slug)
+            .unwrap_or_else(|_| result.page.space_id.clone());
+        output::print_pretty_page_write(result, &slug)
+    });
+}
+
+/// Runs a query against the database in use and then the global database,
+/// tagging each result with the database it came from.
+///
+/// The global database is only opened if it exists and isn't the one in use.
+/// A database without the queried space contributes no results; the
+/// `NotFound` error is only returned if neither has it.
+fn query_with_global<T>(
+    conn: &rusqlite::Connection,
+    db_file: &std::path::Path,
+    source: DbSource,
+    query: impl Fn(&rusqlite::Connection) -> Result<Vec<T>, db::KbError>,
+) -> Result<Vec<Sourced<T>>, db::KbError> {
+    let mut dbs = vec![(source, None, db_file.to_path_buf())];
+    let global = db::global_db_path()?;
+    let same_file = match (db_file.canonicalize(), global.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    if global.exists() && !same_file {
+        let mut global_conn = db::open_connection_at(&global)?;
+        db::migrate_with_backup(&mut global_conn, &global)?;
+        dbs.push((DbSource::Global, Some(global_conn), global));
+    }
+
+    let mut results = Vec::new();
+    let mut not_found = Vec::new();
+    for (source, own_conn, path) in &dbs {
+        match query(own_conn.as_ref().unwrap_or(conn)) {
+            Ok(items) => results.extend(items.into_iter().map(|item| Sourced {
+                source: *source,
+                db: path.display().to_string(),
+                item,
+            })),
+            Err(e @ db::KbError::NotFound(_)) => not_found.push(e),
+            Err(e) => return Err(e),
+        }
+    }
+    if not_found.len() == dbs.len() {
+        return Err(not_found.remove(0));
+    }
+    Ok(results)
+}
+
 fn run() -> Result<(), db::KbError> {
     let cli = Cli::parse();
     let mode = if cli.pretty {
@@ -302,11 +831,54 @@ fn run() -> Result<(), db::KbError> {
         OutputMode::Json
     };
 
-    // Open database and run migrations.
-    let mut conn = db::open_connection()?;
-    db::run_migrations(&mut conn)?;
+    // `init` creates a database rather than opening the one in use.
+    if let Commands::Init { dir } = &cli.command {
+        let dir = match dir {
+            Some(dir) => dir.clone(),
+            None => std::env::current_dir()?,
+        };
+        let (path, created) = db::init_project(&dir)?;
+        let msg = serde_json::json!({"db": path, "created": created});
+        output::print(mode, &msg, || {
+            if created {
+                println!("Created project database {}", path.display())
+            } else {
+                println!("Project database {} already exists; schema is up to date", path.display())
+            }
+        });
+        return Ok(());
+    }
+
+    // Hook settings live in Claude Code's files, not the database.
+    if let Commands::Hooks { action } = &cli.command {
+        let reports = run_hooks_action(action)?;
+        output::print(mode, &reports, || output::print_pretty_hook_reports(&reports));
+        return Ok(());
+    }
+
+    // Hooks find and open their own database, and swallow every error so a
+    // broken knowledge base never breaks the session they run in.
+    if let Commands::Hook { event } = &cli.command {
+        if let Ok(Some(payload)) = run_hook(cli.db.as_deref(), resolve_identity(&cli), event) {
+            println!("{}", payload);
+        }
+        return Ok(());
+    }
+
+    // Open database and run migrations, backing it up first if any are
+    // pending. `db` commands skip migrations so a damaged, half-migrated or
+    // newer database can still be checked, backed up or restored.
+    let (db_file, db_source) = db::locate_db(cli.db.as_deref())?;
+    let mut conn = db::open_connection_at(&db_file)?;
+    if !matches!(cli.command, Commands::Db { .. }) {
+        db::migrate_with_backup(&mut conn, &db_file)?;
+    }
 
     match &cli.command {
+        Commands::Init { .. } => unreachable!("init is handled before the database is opened"),
+        Commands::Hook { .. } => unreachable!("hooks are handled before the database is opened"),
+        Commands::Hooks { .. } => unreachable!("hook settings are handled before the database is opened"),
+
         // =====================================================================
         // Space commands
         // =====================================================================
@@ -342,6 +914,23 @@ fn run() -> Result<(), db::KbError> {
                 let msg = serde_json::json!({"deleted": slug});
                 output::print(mode, &msg, || println!("Deleted space '{}'", slug));
             }
+            SpaceAction::Policy {
+                slug,
+                cross_space_links,
+            } => {
+                let allow = match cross_space_links.as_str() {
+                    "allow" => true,
+                    "deny" => false,
+                    other => {
+                        return Err(db::KbError::InvalidInput(format!(
+                            "Unknown cross-space link policy '{}'. Valid values: allow, deny",
+                            other
+                        )))
+                    }
+                };
+                let space = repo::set_cross_space_links(&conn, slug, allow)?;
+                output::print(mode, &space, || output::print_pretty_space(&space));
+            }
         },
 
         // =====================================================================
@@ -357,10 +946,14 @@ fn run() -> Result<(), db::KbError> {
                 body,
                 stdin,
                 sections,
+                on_duplicate,
+                duplicate_threshold,
             } => {
                 let identity = resolve_identity(&cli);
                 let space_id = resolve_space_id(&conn, space)?;
                 let page_type = parse_page_type(r#type)?;
+                let on_duplicate = parse_on_duplicate(on_duplicate)?;
+                let threshold = check_threshold(*duplicate_threshold)?;
 
                 // Parse sections JSON if provided
                 let sections_value: Option<serde_json::Value> = match sections {
@@ -388,7 +981,7 @@ fn run() -> Result<(), db::KbError> {
                     .filter(|s| !s.is_empty())
                     .collect();
 
-                let page = repo::create_page(
+                let result = repo::create_page_checked(
                     &conn,
                     &space_id,
                     parent.as_deref(),
@@ -397,14 +990,22 @@ fn run() -> Result<(), db::KbError> {
                     &content,
                     sections_value.as_ref(),
                     &label_vec,
-                    &identity.user,
-                    &identity.agent,
+                    &identity,
+                    on_duplicate,
+                    threshold,
                 )?;
-                output::print(mode, &page, || output::print_pretty_page(&page));
+                print_page_write(&conn, mode, &result);
             }
-            PageAction::Get { id } => {
-                let page = repo::get_page(&conn, id)?;
-                output::print(mode, &page, || output::print_pretty_page(&page));
+            PageAction::Get { id, expand } => {
+                // Pretty output always shows titles, so it always needs the expanded form.
+                repo::record_reads(&conn, &[id.as_str()])?;
+                if *expand || mode == OutputMode::Pretty {
+                    let page = repo::get_page_expanded(&conn, id)?;
+                    output::print(mode, &page, || output::print_pretty_expanded_page(&page));
+                } else {
+                    let page = repo::get_page(&conn, id)?;
+                    output::print_json(&page);
+                }
             }
             PageAction::Update {
                 id,
@@ -431,6 +1032,7 @@ fn run() -> Result<(), db::KbError> {
                     None => None,
                 };
 
+                let tx = conn.unchecked_transaction()?;
                 repo::update_page(
                     &conn,
                     id,
@@ -449,8 +1051,9 @@ fn run() -> Result<(), db::KbError> {
                     repo::set_labels(&conn, id, &label_vec)?;
                 }
 
-                let page = repo::get_page(&conn, id)?;
-                output::print(mode, &page, || output::print_pretty_page(&page));
+                let result = repo::finish_page_write(&conn, id, &resolve_identity(&cli), Vec::new())?;
+                tx.commit()?;
+                print_page_write(&conn, mode, &result);
             }
             PageAction::Append { id, body, stdin } => {
                 let content = read_body(body, *stdin)?;
@@ -459,8 +1062,11 @@ fn run() -> Result<(), db::KbError> {
                         "No content to append. Use --body or --stdin.".to_string(),
                     ));
                 }
-                let page = repo::append_to_page(&conn, id, &content)?;
-                output::print(mode, &page, || output::print_pretty_page(&page));
+                let tx = conn.unchecked_transaction()?;
+                repo::append_to_page(&conn, id, &content)?;
+                let result = repo::finish_page_write(&conn, id, &resolve_identity(&cli), Vec::new())?;
+                tx.commit()?;
+                print_page_write(&conn, mode, &result);
             }
             PageAction::List {
                 space,
@@ -468,29 +1074,77 @@ fn run() -> Result<(), db::KbError> {
                 label,
                 created_by_user,
                 created_by_agent,
+                include_global,
             } => {
-                let space_id = match space {
-                    Some(slug) => Some(resolve_space_id(&conn, slug)?),
-                    None => None,
-                };
                 let page_type = match r#type {
                     Some(t) => Some(parse_page_type(t)?),
                     None => None,
                 };
-                let filters = repo::PageFilters {
-                    space_id,
-                    page_type,
-                    label: label.clone(),
-                    created_by_user: created_by_user.clone(),
-                    created_by_agent: created_by_agent.clone(),
+                let list = |conn: &rusqlite::Connection| {
+                    let space_id = match space {
+                        Some(slug) => Some(resolve_space_id(conn, slug)?),
+                        None => None,
+                    };
+                    let filters = repo::PageFilters {
+                        space_id,
+                        page_type,
+                        label: label.clone(),
+                        created_by_user: created_by_user.clone(),
+                        created_by_agent: created_by_agent.clone(),
+                    };
+                    repo::list_pages(conn, &filters)
+                };
+                if *include_global {
+                    let pages = query_with_global(&conn, &db_file, db_source, list)?;
+                    output::print(mode, &pages, || output::print_pretty_by_source(&pages, output::print_pretty_pages));
+                } else {
+                    let pages = list(&conn)?;
+                    output::print(mode, &pages, || output::print_pretty_pages(&pages));
+                }
+            }
+            PageAction::Delete { id, recursive } => {
+                if *recursive {
+                    let deleted = repo::delete_page_recursive(&conn, id)?;
+                    let msg = serde_json::json!({"deleted": id, "deleted_ids": deleted});
+                    output::print(mode, &msg, || {
+                        println!("Deleted page '{}' and {} descendant(s)", id, deleted.len() - 1)
+                    });
+                } else {
+                    repo::delete_page(&conn, id)?;
+                    let msg = serde_json::json!({"deleted": id});
+                    output::print(mode, &msg, || println!("Deleted page '{}'", id));
+                }
+            }
+            PageAction::Tree { space, root } => {
+                let space_id = match space {
+                    Some(slug) => Some(resolve_space_id(&conn, slug)?),
+                    None => None,
                 };
-                let pages = repo::list_pages(&conn, &filters)?;
-                output::print(mode, &pages, || output::print_pretty_pages(&pages));
+                let tree = repo::page_tree(&conn, space_id.as_deref(), root.as_deref())?;
+                output::print(mode, &tree, || output::print_pretty_page_tree(&tree));
             }
-            PageAction::Delete { id } => {
-                repo::delete_page(&conn, id)?;
-                let msg = serde_json::json!({"deleted": id});
-                output::print(mode, &msg, || println!("Deleted page '{}'", id));
+            PageAction::Reparent { id, parent } => {
+                let parent_id = match parent.as_str() {
+                    "none" => None,
+                    p => Some(p),
+                };
+                let page = repo::reparent_page(&conn, id, parent_id)?;
+                repo::record_page_session(&conn, id, &resolve_identity(&cli))?;
+                output::print(mode, &page, || {
+                    let slug = repo::get_space_by_id(&conn, &page.space_id)
+                        .map(|s| s.slug)
+                        .unwrap_or_else(|_| page.space_id.clone());
+                    output::print_pretty_page(&page, &slug)
+                });
+            }
+            PageAction::Backlinks { id, expand } => {
+                if *expand || mode == OutputMode::Pretty {
+                    let links = repo::list_backlinks_expanded(&conn, id)?;
+                    output::print(mode, &links, || output::print_pretty_expanded_links(&links));
+                } else {
+                    let links = repo::list_backlinks(&conn, id)?;
+                    output::print_json(&links);
+                }
             }
             PageAction::Schema { r#type } => {
                 let page_type = parse_page_type(r#type)?;
@@ -529,29 +1183,172 @@ fn run() -> Result<(), db::KbError> {
             label,
             created_by_agent,
             section,
+            include_global,
+            recall,
+            weights,
         } => {
+            let page_type = match r#type {
+                Some(t) => Some(parse_page_type(t)?),
+                None => None,
+            };
+            let params_for = |conn: &rusqlite::Connection| -> Result<search::SearchParams, db::KbError> {
+                let space_id = match space {
+                    Some(slug) => Some(resolve_space_id(conn, slug)?),
+                    None => None,
+                };
+                Ok(search::SearchParams {
+                    query: query.clone(),
+                    space_id,
+                    page_type,
+                    label: label.clone(),
+                    created_by_agent: created_by_agent.clone(),
+                    section: section.clone(),
+                })
+            };
+            if *recall || weights.is_some() {
+                let weights = search::RecallWeights::configured(weights.as_deref())?;
+                let run_recall = |conn: &rusqlite::Connection| {
+                    let results = search::recall(conn, &params_for(conn)?, &weights)?;
+                    let ids: Vec<&str> = results.iter().map(|r| r.result.page.id.as_str()).collect();
+                    repo::record_reads(conn, &ids)?;
+                    Ok(results)
+                };
+                if *include_global {
+                    let results = query_with_global(&conn, &db_file, db_source, run_recall)?;
+                    output::print(mode, &results, || {
+                        output::print_pretty_by_source(&results, output::print_pretty_recall_results)
+                    });
+                } else {
+                    let results = run_recall(&conn)?;
+                    output::print(mode, &results, || output::print_pretty_recall_results(&results));
+                }
+                return Ok(());
+            }
+            let run_search = |conn: &rusqlite::Connection| {
+                let results = search::search_pages(conn, &params_for(conn)?)?;
+                let ids: Vec<&str> = results.iter().map(|r| r.page.id.as_str()).collect();
+                repo::record_reads(conn, &ids)?;
+                Ok(results)
+            };
+            if *include_global {
+                let results = query_with_global(&conn, &db_file, db_source, run_search)?;
+                output::print(mode, &results, || {
+                    output::print_pretty_by_source(&results, output::print_pretty_search_results)
+                });
+            } else {
+                let results = run_search(&conn)?;
+                output::print(mode, &results, || {
+                    output::print_pretty_search_results(&results)
+                });
+            }
+        }
+
+        // =====================================================================
+        // Context command
+        // =====================================================================
+        Commands::Context {
+            task,
+            budget,
+            space,
+            format,
+        } => {
+            if format != "json" && format != "markdown" {
+                return Err(db::KbError::InvalidInput(format!(
+                    "Unknown context format '{}'. Valid formats: json, markdown",
+                    format
+                )));
+            }
             let space_id = match space {
                 Some(slug) => Some(resolve_space_id(&conn, slug)?),
                 None => None,
             };
-            let page_type = match r#type {
-                Some(t) => Some(parse_page_type(t)?),
+            let pack = context::build_pack(&conn, task, space_id.as_deref(), *budget)?;
+            if format == "markdown" {
+                print!("{}", context::render_markdown(&pack));
+            } else {
+                output::print(mode, &pack, || print!("{}", context::render_markdown(&pack)));
+            }
+        }
+
+        // =====================================================================
+        // Ingest commands
+        // =====================================================================
+        Commands::Ingest {
+            source:
+                IngestSource::Transcript {
+                    path,
+                    space,
+                    title,
+                    summarizer,
+                },
+        } => {
+            let space_id = resolve_space_id(&conn, space)?;
+            let parsed = transcript::read(path)?;
+            let summarizer = summarize::configured(summarizer.as_deref());
+            let report = transcript::ingest(
+                &conn,
+                &parsed,
+                &space_id,
+                title.as_deref(),
+                summarizer.as_ref(),
+                &resolve_identity(&cli),
+            )?;
+            output::print(mode, &report, || output::print_pretty_transcript_report(&report));
+        }
+
+        // =====================================================================
+        // Dedupe commands
+        // =====================================================================
+        Commands::Dedupe { space, threshold } => {
+            let space_id = resolve_space_id(&conn, space)?;
+            let pairs = dedupe::find_duplicate_pairs(&conn, &space_id, check_threshold(*threshold)?)?;
+            output::print(mode, &pairs, || output::print_pretty_duplicate_pairs(&pairs));
+        }
+
+        // =====================================================================
+        // Feedback commands
+        // =====================================================================
+        Commands::Feedback {
+            action: Some(FeedbackAction::Report { min_reads, space }),
+            ..
+        } => {
+            let space_id = match space {
+                Some(slug) => Some(resolve_space_id(&conn, slug)?),
                 None => None,
             };
-            let params = search::SearchParams {
-                query: query.clone(),
-                space_id,
-                page_type,
-                label: label.clone(),
-                created_by_agent: created_by_agent.clone(),
-                section: section.clone(),
+            let entries = repo::feedback_report(&conn, space_id.as_deref(), *min_reads)?;
+            output::print(mode, &entries, || output::print_pretty_feedback_report(&entries));
+        }
+        Commands::Feedback {
+            action: None,
+            page_id,
+            query,
+            useful,
+            ..
+        } => {
+            // clap requires these without a subcommand.
+            let (Some(page_id), Some(query)) = (page_id, query) else {
+                unreachable!("page ID and --query are required")
             };
-            let results = search::search_pages(&conn, &params)?;
-            output::print(mode, &results, || {
-                output::print_pretty_search_results(&results)
-            });
+            let identity = resolve_identity(&cli);
+            let feedback = repo::record_feedback(&conn, page_id, query, *useful, &identity)?;
+            output::print(mode, &feedback, || output::print_pretty_feedback(&feedback));
         }
 
+        // =====================================================================
+        // Session commands
+        // =====================================================================
+        Commands::Session { action } => match action {
+            SessionAction::List => {
+                let sessions = repo::list_sessions(&conn)?;
+                output::print(mode, &sessions, || output::print_pretty_sessions(&sessions));
+            }
+            SessionAction::Show { id } => {
+                let activity = repo::get_session(&conn, id)?;
+                output::print(mode, &activity, || output::print_pretty_session_activity(&activity));
+            }
+        },
+
         // =====================================================================
         // Link commands
         // =====================================================================
@@ -560,14 +1357,57 @@ fn run() -> Result<(), db::KbError> {
                 source,
                 target,
                 relation,
+                note,
             } => {
+                let identity = resolve_identity(&cli);
                 let rel = parse_link_relation(relation)?;
-                let link = repo::create_link(&conn, source, target, rel)?;
-                output::print(mode, &link, || output::print_pretty_link(&link));
+                let mut link = repo::create_link(
+                    &conn,
+                    source,
+                    target,
+                    rel,
+                    note,
+                    &identity.user,
+                    &identity.agent,
+                )?;
+                repo::record_link_session(&conn, source, target, &identity)?;
+                link.session_id = identity.session;
+                if mode == OutputMode::Pretty {
+                    output::print_pretty_expanded_link(&repo::get_link_expanded(&conn, source, target)?);
+                } else {
+                    output::print_json(&link);
+                }
+            }
+            LinkAction::Update {
+                source,
+                target,
+                relation,
+                note,
+            } => {
+                if relation.is_none() && note.is_none() {
+                    return Err(db::KbError::InvalidInput(
+                        "Nothing to update. Use --relation and/or --note.".to_string(),
+                    ));
+                }
+                let rel = match relation {
+                    Some(r) => Some(parse_link_relation(r)?),
+                    None => None,
+                };
+                let link = repo::update_link(&conn, source, target, rel, note.as_deref())?;
+                if mode == OutputMode::Pretty {
+                    output::print_pretty_expanded_link(&repo::get_link_expanded(&conn, source, target)?);
+                } else {
+                    output::print_json(&link);
+                }
             }
-            LinkAction::List { page_id } => {
-                let links = repo::list_links(&conn, page_id)?;
-                output::print(mode, &links, || output::print_pretty_links(&links));
+            LinkAction::List { page_id, expand } => {
+                if *expand || mode == OutputMode::Pretty {
+                    let links = repo::list_links_expanded(&conn, page_id)?;
+                    output::print(mode, &links, || output::print_pretty_expanded_links(&links));
+                } else {
+                    let links = repo::list_links(&conn, page_id)?;
+                    output::print_json(&links);
+                }
             }
             LinkAction::Delete { source, target } => {
                 repo::delete_link(&conn, source, target)?;
@@ -576,19 +1416,324 @@ fn run() -> Result<(), db::KbError> {
                     println!("Deleted link {} -> {}", source, target)
                 });
             }
+            LinkAction::Check => {
+                let report = repo::check_links(&conn)?;
+                output::print(mode, &report, || output::print_pretty_link_check_report(&report));
+            }
         },
 
         // =====================================================================
         // Browse command (interactive TUI)
         // =====================================================================
         Commands::Browse => {
-            tui::run_browse(&conn)?;
+            tui::run_browse(&conn, &resolve_identity(&cli))?;
+        }
+
+        // =====================================================================
+        // Database maintenance commands
+        // =====================================================================
+        Commands::Db { action } => match action {
+            DbAction::Backup { path } => {
+                db::backup(&conn, path)?;
+                let msg = serde_json::json!({"backup": path});
+                output::print(mode, &msg, || println!("Backed up database to {}", path.display()));
+            }
+            DbAction::Restore { path } => {
+                let from_version = db::restore(&mut conn, path)?;
+                let msg = serde_json::json!({
+                    "restored": path,
+                    "backup_schema_version": from_version,
+                    "schema_version": db::schema_version(&conn)?,
+                });
+                output::print(mode, &msg, || {
+                    println!("Restored database from {} (schema version {})", path.display(), from_version)
+                });
+            }
+            DbAction::Check => {
+                let report = db::check(&conn)?;
+                output::print(mode, &report, || output::print_pretty_db_check_report(&report));
+            }
+            DbAction::Migrate { status, dry_run } => {
+                let report = if *status || *dry_run {
+                    let report = db::migration_status(&conn)?;
+                    if *dry_run && !report.problems.is_empty() {
+                        return Err(db::KbError::InvalidInput(report.problems.join("; ")));
+                    }
+                    report
+                } else {
+                    db::migrate_with_backup(&mut conn, &db_file)?
+                };
+                output::print(mode, &report, || output::print_pretty_migration_report(&report, *dry_run));
+            }
+            DbAction::Merge { other, keep_conflicts } => {
+                db::migrate_with_backup(&mut conn, &db_file)?;
+                let report = merge::merge_file(&conn, &db_file, other, *keep_conflicts)?;
+                output::print(mode, &report, || output::print_pretty_merge_report(&report));
+            }
+        },
+
+        // =====================================================================
+        // Export / import commands
+        // =====================================================================
+        Commands::Export { format, space, output } => {
+            let space_id = match space {
+                Some(slug) => Some(resolve_space_id(&conn, slug)?),
+                None => None,
+            };
+            match format.as_str() {
+                "json" => {}
+                "markdown" => {
+                    let (Some(space_id), Some(dir)) = (space_id, output) else {
+                        return Err(db::KbError::InvalidInput(
+                            "Markdown export needs --space and --out <DIR>".to_string(),
+                        ));
+                    };
+                    let written = markdown::export_space(&conn, &space_id, dir)?;
+                    let msg = serde_json::json!({"exported": dir, "pages": written.len()});
+                    output::print(mode, &msg, || {
+                        println!("Exported {} page(s) to {}", written.len(), dir.display())
+                    });
+                    return Ok(());
+                }
+                "obsidian" => {
+                    let Some(dir) = output else {
+                        return Err(db::KbError::InvalidInput("Obsidian export needs --out <DIR>".to_string()));
+                    };
+                    let written = obsidian::export_vault(&conn, space_id.as_deref(), dir)?;
+                    let msg = serde_json::json!({"exported": dir, "pages": written.len()});
+                    output::print(mode, &msg, || {
+                        println!("Exported {} page(s) to vault {}", written.len(), dir.display())
+                    });
+                    return Ok(());
+                }
+                other => {
+                    return Err(db::KbError::InvalidInput(format!(
+                        "Unknown export format '{}'. Valid formats: json, markdown, obsidian",
+                        other
+                    )))
+                }
+            }
+            let archive = archive::export(&conn, space_id.as_deref())?;
+            match output {
+                Some(path) => {
+                    let json = serde_json::to_string_pretty(&archive).expect("failed to serialize to JSON");
+                    std::fs::write(path, json + "\n")?;
+                    let msg = serde_json::json!({
+                        "exported": path,
+                        "spaces": archive.spaces.len(),
+                        "pages": archive.pages.len(),
+                        "links": archive.links.len(),
+                    });
+                    output::print(mode, &msg, || {
+                        println!(
+                            "Exported {} space(s), {} page(s), {} link(s) to {}",
+                            archive.spaces.len(),
+                            archive.pages.len(),
+                            archive.links.len(),
+                            path.display()
+                        )
+                    });
+                }
+                None => output::print(mode, &archive, || {
+                    println!("{}", serde_json::to_string_pretty(&archive).expect("failed to serialize to JSON"))
+                }),
+            }
+        }
+        Commands::Publish { space, output } => {
+            let space_id = resolve_space_id(&conn, space)?;
+            let site = publish::publish_space(&conn, &space_id, output)?;
+            let msg = serde_json::json!({"published": output, "pages": site.pages, "files": site.files.len()});
+            output::print(mode, &msg, || {
+                println!(
+                    "Published {} page(s) to {}; open {}",
+                    site.pages,
+                    output.display(),
+                    output.join("index.html").display()
+                )
+            });
+        }
+        Commands::Sync { repo, remote, no_push } => {
+            let report = sync::sync(&conn, repo, remote.as_deref(), !*no_push, &resolve_identity(&cli))?;
+            output::print(mode, &report, || output::print_pretty_sync_report(&report));
+        }
+        Commands::Import {
+            source: Some(ImportSource::Adr { dir, space }),
+            ..
+        } => {
+            let space_id = resolve_space_id(&conn, space)?;
+            let report = adr::import_dir(&conn, dir, &space_id, &resolve_identity(&cli))?;
+            output::print(mode, &report, || output::print_pretty_import_report(&report));
+        }
+        Commands::Import {
+            source: Some(ImportSource::Obsidian { vault, space, folders }),
+            ..
+        } => {
+            let folders = obsidian::FolderMode::from_str(folders).ok_or_else(|| {
+                db::KbError::InvalidInput(format!("Unknown folder mode '{}'. Valid modes: pages, spaces", folders))
+            })?;
+            let space_id = resolve_space_id(&conn, space)?;
+            let report = obsidian::import_vault(&conn, vault, &space_id, folders, &resolve_identity(&cli))?;
+            output::print(mode, &report, || output::print_pretty_import_report(&report));
+        }
+        Commands::Import {
+            source: None,
+            file,
+            format,
+            space,
+            on_conflict,
+            remap_ids,
+        } => {
+            let Some(file) = file else {
+                return Err(db::KbError::InvalidInput(
+                    "Missing file to import. Pass a file, a directory, or an import source such as 'adr'".to_string(),
+                ));
+            };
+            let on_conflict = archive::ConflictStrategy::from_str(on_conflict).ok_or_else(|| {
+                db::KbError::InvalidInput(format!(
+                    "Unknown conflict strategy '{}'. Valid strategies: skip, overwrite, newer-wins",
+                    on_conflict
+                ))
+            })?;
+            let identity = resolve_identity(&cli);
+            let parsed = match format.as_str() {
+                "json" => {
+                    if space.is_some() {
+                        return Err(db::KbError::InvalidInput(
+                            "--space only applies to markdown imports".to_string(),
+                        ));
+                    }
+                    let text = if file.as_os_str() == "-" {
+                        read_body(&None, true)?
+                    } else {
+                        std::fs::read_to_string(file)?
+                    };
+                    archive::parse_archive(&text)?
+                }
+                "markdown" => markdown::read_dir(&conn, file, space.as_deref(), &identity)?,
+                other => {
+                    return Err(db::KbError::InvalidInput(format!(
+                        "Unknown import format '{}'. Valid formats: json, markdown",
+                        other
+                    )))
+                }
+            };
+            let options = archive::ImportOptions {
+                on_conflict,
+                remap_ids: *remap_ids,
+            };
+            let report = if format == "markdown" {
+                markdown::import(&conn, &parsed, options, &identity)?
+            } else {
+                archive::import(&conn, &parsed, options)?
+            };
+            output::print(mode, &report, || output::print_pretty_import_report(&report));
         }
     }
 
     Ok(())
 }
 
+/// Runs a hook handler on stdin, returning the JSON payload to print, if any.
+///
+/// `identity` is the user and agent the hook acts as; an `unknown` agent
+/// counts as none.
+fn run_hook(
+    db_path: Option<&std::path::Path>,
+    identity: AgentIdentity,
+    event: &HookEvent,
+) -> Result<Option<String>, db::KbError> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    let input = hook::parse_input(&input)?;
+    let payload = match event {
+        HookEvent::UserPromptSubmit {
+            min_keywords,
+            min_keyword_len,
+            max_keywords,
+            max_results,
+            max_pitfalls,
+            excerpt_chars,
+        } => {
+            let prompt = match input.prompt.as_deref() {
+                Some(prompt) if !prompt.trim().is_empty() => prompt,
+                _ => return Ok(None),
+            };
+            let thresholds = hook::PromptThresholds {
+                min_keywords: *min_keywords,
+                min_keyword_len: *min_keyword_len,
+                max_keywords: *max_keywords,
+                max_results: *max_results,
+                max_pitfalls: *max_pitfalls,
+                excerpt_chars: *excerpt_chars,
+            };
+            let conn = hook::open_db(db_path, &input)?;
+            hook::user_prompt_submit(&conn, prompt, &thresholds)?.map(|text| serde_json::json!({ "result": text }))
+        }
+        HookEvent::Stop {
+            pattern,
+            min_mentions,
+            max_candidates,
+            context_sentences,
+        } => {
+            let mut options = hook::StopOptions {
+                min_mentions: *min_mentions,
+                max_candidates: *max_candidates,
+                context_sentences: *context_sentences,
+                agent: Some(identity.agent).filter(|agent| agent != "unknown"),
+                ..hook::StopOptions::default()
+            };
+            if !pattern.is_empty() {
+                options.patterns = pattern.clone();
+            }
+            let conn = hook::open_db(db_path, &input)?;
+            hook::stop(&conn, &input, &options)?
+                .map(|reason| serde_json::json!({ "decision": "block", "reason": reason }))
+        }
+        HookEvent::SessionEnd { space, summarizer } => {
+            let summarizer = summarize::configured(summarizer.as_deref());
+            let conn = hook::open_db(db_path, &input)?;
+            hook::session_end(&conn, &input, space, summarizer.as_ref(), &identity)?;
+            None
+        }
+    };
+    Ok(payload.map(|payload| payload.to_string()))
+}
+
+/// Installs, uninstalls or reports hooks in the settings files `action`
+/// selects.
+fn run_hooks_action(action: &HooksAction) -> Result<Vec<models::HookReport>, db::KbError> {
+    use models::HookScope;
+    let (project, global, hook, install) = match action {
+        HooksAction::Install { project, global, hook } => (*project, *global, hook, true),
+        HooksAction::Uninstall { project, global, hook } | HooksAction::Status { project, global, hook } => {
+            (*project, *global, hook, false)
+        }
+    };
+    let scopes = match (project, global) {
+        (true, _) => vec![HookScope::Project],
+        (_, true) => vec![HookScope::Global],
+        // Installing touches one file; the rest look at both.
+        _ if install => vec![HookScope::Global],
+        _ => vec![HookScope::Project, HookScope::Global],
+    };
+    let hooks = hook_install::select(hook)?;
+    let home = dirs::home_dir().ok_or_else(|| {
+        db::KbError::Io(io::Error::new(io::ErrorKind::NotFound, "Could not determine home directory"))
+    })?;
+    let project_dir = std::env::current_dir()?;
+
+    let mut reports = Vec::new();
+    for scope in scopes {
+        let path = hook_install::settings_path(scope, &home, &project_dir);
+        reports.extend(match action {
+            HooksAction::Install { .. } => hook_install::install(&path, scope, &hooks)?,
+            HooksAction::Uninstall { .. } => hook_install::uninstall(&path, scope, &hooks)?,
+            HooksAction::Status { .. } => hook_install::status(&path, scope, &hooks)?,
+        });
+    }
+    Ok(reports)
+}
+
 fn main() {
     if let Err(e) = run() {
         // Output errors as JSON for agent consumption.
@@ -0,0 +1,612 @@
+//! Export and import a space as a directory of Markdown files.
+//!
+//! Each page becomes one `.md` file with YAML frontmatter holding its
+//! metadata and outgoing links. Child pages go in a folder named after their
+//! parent's file, so the directory tree mirrors `parent_id`:
+//!
+//! ```text
+//! out/
+//!   use-sqlite.md
+//!   use-sqlite/
+//!     wal-notes.md
+//! ```
+//!
+//! Structured pages are written as `## Heading` blocks, exactly as
+//! `sections_to_content` renders them, and parsed back into `sections` on
+//! import using the page type's section schema. Import goes through
+//! `archive::import`, so it has the same conflict handling as JSON imports.
+
+use crate::db::KbError;
+use crate::models::{
+    sections_to_content, AgentIdentity, ImportReport, KbArchive, Link, LinkRelation, Page, PageTreeNode,
+    PageType, Space,
+};
+use crate::{archive, db, repo, wikilinks};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Page metadata stored in a file's frontmatter.
+///
+/// Everything but `title` and `type` is optional, so hand-written files can
+/// be imported too.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frontmatter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub title: String,
+    #[serde(rename = "type")]
+    pub page_type: PageType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub space: Option<String>,
+    /// Parent page ID. When absent, the parent is taken from the folder the file is in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// Whether the body is the page's structured sections rather than freeform content.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sections: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<FrontmatterLink>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by_user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i64>,
+}
+
+/// An outgoing link in a file's frontmatter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrontmatterLink {
+    /// Target page ID.
+    pub target: String,
+    /// Target page title, for readers of the file. Ignored on import.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default = "default_relation")]
+    pub relation: LinkRelation,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+}
+
+fn default_relation() -> LinkRelation {
+    LinkRelation::RelatesTo
+}
+
+/// Renders a page as a Markdown file with frontmatter.
+///
+/// `mentions` links are left out: they come from `[[wiki links]]` in the
+/// body and are recreated from it on import.
+pub fn render_page(page: &Page, space_slug: &str, links: &[(Link, String)]) -> String {
+    let frontmatter = Frontmatter {
+        id: Some(page.id.clone()),
+        title: page.title.clone(),
+        page_type: page.page_type,
+        space: Some(space_slug.to_string()),
+        parent: page.parent_id.clone(),
+        labels: page.labels.clone(),
+        sections: page.sections.is_some(),
+        links: links
+            .iter()
+            .filter(|(link, _)| link.relation != LinkRelation::Mentions)
+            .map(|(link, title)| FrontmatterLink {
+                target: link.target_id.clone(),
+                title: Some(title.clone()),
+                relation: link.relation,
+                note: link.note.clone(),
+            })
+            .collect(),
+        created_by_user: Some(page.created_by_user.clone()),
+        created_by_agent: Some(page.created_by_agent.clone()),
+        created_at: Some(page.created_at.clone()),
+        updated_at: Some(page.updated_at.clone()),
+        version: Some(page.version),
+    };
+    let yaml = serde_yaml::to_string(&frontmatter).expect("failed to serialize frontmatter");
+    let body = match &page.sections {
+        Some(sections) => sections_to_content(sections, page.page_type),
+        None => page.content.clone(),
+    };
+    format!("---\n{}---\n\n{}\n", yaml, body)
+}
+
+/// Splits a Markdown file into its frontmatter and body.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` if the file has no frontmatter or the
+/// frontmatter is invalid.
+pub fn parse_page(text: &str) -> Result<(Frontmatter, String), KbError> {
+    let (yaml, body) = split_frontmatter(text)?;
+    let frontmatter: Frontmatter = serde_yaml::from_str(&yaml)
+        .map_err(|e| KbError::InvalidInput(format!("Invalid frontmatter: {}", e)))?;
+    Ok((frontmatter, body))
+}
+
+/// Splits a file written as `---\n<yaml>---\n\n<body>\n` into its YAML and
+/// body, undoing the blank line and final newline around the body.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` if the file has no closed frontmatter.
+pub fn split_frontmatter(text: &str) -> Result<(String, String), KbError> {
+    let text = text.replace("\r\n", "\n");
+    let rest = text
+        .strip_prefix("---\n")
+        .ok_or_else(|| KbError::InvalidInput("File does not start with '---' frontmatter".to_string()))?;
+    let (yaml, body) = match rest.find("\n---\n") {
+        Some(end) => (&rest[..end + 1], &rest[end + 5..]),
+        None => match rest.strip_suffix("\n---") {
+            Some(yaml) => (yaml, ""),
+            None => return Err(KbError::InvalidInput("Frontmatter is not closed with '---'".to_string())),
+        },
+    };
+    let body = body.strip_prefix('\n').unwrap_or(body);
+    let body = body.strip_suffix('\n').unwrap_or(body);
+    Ok((yaml.to_string(), body.to_string()))
+}
+
+/// Parses `## Heading` blocks back into a sections object.
+///
+/// Headings are matched against the page type's section names
+/// (case-insensitively); any other heading becomes a key by lowercasing it
+/// and joining words with underscores. Returns `None` if the body has text
+/// before its first heading, since it can't then be plain sections.
+pub fn body_to_sections(body: &str, page_type: PageType) -> Option<serde_json::Value> {
+    let schema = page_type.section_schema().unwrap_or_default();
+    let mut sections = serde_json::Map::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+    let mut in_fence = false;
+
+    for line in body.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        let heading = if in_fence { None } else { line.strip_prefix("## ") };
+        match heading {
+            Some(name) => {
+                if let Some((key, lines)) = current.take() {
+                    sections.insert(key, section_text(&lines));
+                }
+                let name = name.trim();
+                let key = schema
+                    .iter()
+                    .find(|d| d.name.eq_ignore_ascii_case(name))
+                    .map(|d| d.key.to_string())
+                    .unwrap_or_else(|| name.to_lowercase().split_whitespace().collect::<Vec<_>>().join("_"));
+                current = Some((key, Vec::new()));
+            }
+            None => match current.as_mut() {
+                Some((_, lines)) => lines.push(line),
+                None if line.trim().is_empty() => {}
+                None => return None,
+            },
+        }
+    }
+    if let Some((key, lines)) = current.take() {
+        sections.insert(key, section_text(&lines));
+    }
+
+    if sections.is_empty() {
+        None
+    } else {
+        Some(serde_json::Value::Object(sections))
+    }
+}
+
+/// Joins a section's lines, dropping the blank separator line before the next heading.
+fn section_text(lines: &[&str]) -> serde_json::Value {
+    serde_json::Value::String(lines.join("\n").trim_end_matches('\n').to_string())
+}
+
+/// Writes every page in a space to `dir`, one file per page.
+///
+/// Existing files with the same names are overwritten; other files in `dir`
+/// are left alone.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `space_id` - The space to export
+/// * `dir` - Output directory, created if missing
+///
+/// # Returns
+/// The paths of the files written
+pub fn export_space(conn: &Connection, space_id: &str, dir: &Path) -> Result<Vec<PathBuf>, KbError> {
+    let space = repo::get_space_by_id(conn, space_id)?;
+    let tree = repo::page_tree(conn, Some(space_id), None)?;
+    fs::create_dir_all(dir)?;
+
+    let mut written = Vec::new();
+    let mut stack: Vec<(PathBuf, Vec<PageTreeNode>)> = vec![(dir.to_path_buf(), tree)];
+    while let Some((folder, nodes)) = stack.pop() {
+        let mut used = HashSet::new();
+        for node in nodes {
+            let stem = unique_stem(&slugify(&node.title), &mut used);
+            let page = repo::get_page(conn, &node.id)?;
+            let links: Vec<(Link, String)> = repo::list_links_expanded(conn, &page.id)?
+                .into_iter()
+                .filter(|l| l.link.source_id == page.id)
+                .map(|l| (l.link, l.target.title))
+                .collect();
+
+            let path = folder.join(format!("{}.md", stem));
+            fs::write(&path, render_page(&page, &space.slug, &links))?;
+            written.push(path);
+
+            if !node.children.is_empty() {
+                let child_dir = folder.join(&stem);
+                fs::create_dir_all(&child_dir)?;
+                stack.push((child_dir, node.children));
+            }
+        }
+    }
+    written.sort();
+    Ok(written)
+}
+
+/// Reads a directory written by `export_space` (or by hand) into an archive
+/// ready for `archive::import`.
+///
+/// Pages go into `space_slug` if given, otherwise into the space named in
+/// each file's frontmatter. A space that doesn't exist yet is created. Files
+/// without an `id` get a new one; files without a `parent` take the page
+/// whose file sits next to their folder as parent. Missing timestamps,
+/// authors and versions are filled in from `identity` and the current time.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` naming the file if a file can't be parsed,
+/// or if neither `space_slug` nor the frontmatter names a space.
+pub fn read_dir(
+    conn: &Connection,
+    dir: &Path,
+    space_slug: Option<&str>,
+    identity: &AgentIdentity,
+) -> Result<KbArchive, KbError> {
+    let mut files = Vec::new();
+    collect_markdown_files(dir, &mut files)?;
+    files.sort();
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut spaces: HashMap<String, Space> = HashMap::new();
+    let mut new_spaces: Vec<Space> = Vec::new();
+    let mut parsed: Vec<(PathBuf, Frontmatter, String, Page)> = Vec::new();
+
+    for path in files {
+        let text = fs::read_to_string(&path)?;
+        let (fm, body) =
+            parse_page(&text).map_err(|e| KbError::InvalidInput(format!("{}: {}", path.display(), e)))?;
+
+        let slug = space_slug.map(str::to_string).or_else(|| fm.space.clone()).ok_or_else(|| {
+            KbError::InvalidInput(format!("{}: no space in frontmatter; pass --space", path.display()))
+        })?;
+        if !spaces.contains_key(&slug) {
+            let space = match repo::get_space_by_slug(conn, &slug) {
+                Ok(space) => space,
+                Err(KbError::NotFound(_)) => {
+                    let space = Space {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        slug: slug.clone(),
+                        name: slug.clone(),
+                        description: String::new(),
+                        allow_cross_space_links: true,
+                        created_at: now.clone(),
+                        updated_at: now.clone(),
+                    };
+                    new_spaces.push(space.clone());
+                    space
+                }
+                Err(e) => return Err(e),
+            };
+            spaces.insert(slug.clone(), space);
+        }
+
+        let sections = if fm.sections || fm.page_type.section_schema().is_some() {
+            body_to_sections(&body, fm.page_type)
+                .filter(|s| fm.sections || has_schema_section(s, fm.page_type))
+        } else {
+            None
+        };
+        let content = match &sections {
+            Some(s) => sections_to_content(s, fm.page_type),
+            None => body.clone(),
+        };
+
+        let page = Page {
+            id: fm.id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            space_id: spaces[&slug].id.clone(),
+            parent_id: fm.parent.clone(),
+            title: fm.title.clone(),
+            page_type: fm.page_type,
+            content,
+            sections,
+            created_by_user: fm.created_by_user.clone().unwrap_or_else(|| identity.user.clone()),
+            created_by_agent: fm.created_by_agent.clone().unwrap_or_else(|| identity.agent.clone()),
+            created_at: fm.created_at.clone().unwrap_or_else(|| now.clone()),
+            updated_at: fm.updated_at.clone().unwrap_or_else(|| now.clone()),
+            version: fm.version.unwrap_or(1),
+            session_id: None,
+            labels: fm.labels.clone(),
+        };
+        parsed.push((path, fm, body, page));
+    }
+
+    // `foo/bar.md` is a child of `foo.md`.
+    let ids_by_stem: HashMap<PathBuf, String> = parsed
+        .iter()
+        .map(|(path, _, _, page)| (path.with_extension(""), page.id.clone()))
+        .collect();
+    let mut pages = Vec::new();
+    let mut links = Vec::new();
+    for (path, fm, _, mut page) in parsed {
+        if page.parent_id.is_none() {
+            page.parent_id = path.parent().and_then(|folder| ids_by_stem.get(folder)).cloned();
+        }
+        for link in fm.links {
+            links.push(Link {
+                source_id: page.id.clone(),
+                target_id: link.target,
+                relation: link.relation,
+                note: link.note,
+                created_by_user: identity.user.clone(),
+                created_by_agent: identity.agent.clone(),
+                created_at: page.updated_at.clone(),
+                updated_at: page.updated_at.clone(),
+                session_id: None,
+            });
+        }
+        pages.push(page);
+    }
+
+    Ok(KbArchive {
+        format: archive::ARCHIVE_FORMAT.to_string(),
+        format_version: archive::ARCHIVE_FORMAT_VERSION,
+        schema_version: db::schema_version(conn)?,
+        exported_at: now,
+        spaces: new_spaces,
+        pages,
+        links,
+    })
+}
+
+/// Whether any parsed section key belongs to the page type's schema.
+fn has_schema_section(sections: &serde_json::Value, page_type: PageType) -> bool {
+    let schema = page_type.section_schema().unwrap_or_default();
+    sections
+        .as_object()
+        .is_some_and(|obj| obj.keys().any(|k| schema.iter().any(|d| d.key == k)))
+}
+
+fn collect_markdown_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), KbError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_markdown_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "md") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Imports an archive read by `read_dir` in a single transaction, then turns
+/// the `[[wiki links]]` in the written pages into `mentions` links.
+///
+/// References that don't resolve are reported as warnings.
+///
+/// # Errors
+/// Returns `KbError::Db` if a write fails; nothing is imported in that case.
+pub fn import(
+    conn: &Connection,
+    archive: &KbArchive,
+    options: archive::ImportOptions,
+    identity: &AgentIdentity,
+) -> Result<ImportReport, KbError> {
+    let tx = conn.unchecked_transaction()?;
+    let mut report = archive::import_records(&tx, archive, options)?;
+    // Mentions use database IDs, which differ from the files' with `remap_ids`.
+    for (_, page_id) in report.written_pages.clone() {
+        wikilinks::sync_mentions_reporting(&tx, &page_id, identity, &mut report.warnings)?;
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Turns a title into a file name stem: lowercase ASCII letters, digits and dashes.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug.trim_end_matches('-').chars().take(60).collect();
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "page".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Appends `-2`, `-3`, ... until the stem hasn't been used in this folder.
+pub fn unique_stem(stem: &str, used: &mut HashSet<String>) -> String {
+    let mut candidate = stem.to_string();
+    let mut n = 2;
+    while !used.insert(candidate.clone()) {
+        candidate = format!("{}-{}", stem, n);
+        n += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::{ConflictStrategy, ImportOptions};
+
+    fn identity() -> AgentIdentity {
+        AgentIdentity { user: "importer".to_string(), agent: "test".to_string(), session: None }
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Use SQLite (not Postgres)!"), "use-sqlite-not-postgres");
+        assert_eq!(slugify("  ---  "), "page");
+        assert_eq!(slugify("Ünïcode title"), "n-code-title");
+        let mut used = HashSet::new();
+        assert_eq!(unique_stem("a", &mut used), "a");
+        assert_eq!(unique_stem("a", &mut used), "a-2");
+        assert_eq!(unique_stem("a", &mut used), "a-3");
+    }
+
+    #[test]
+    fn test_body_to_sections_inverts_sections_to_content() {
+        let sections = serde_json::json!({
+            "context": "We need storage.\n\nMore context.",
+            "options_considered": "1. Postgres\n2. SQLite",
+            "decision": "SQLite.",
+            "extra_notes": "Something else",
+        });
+        let body = sections_to_content(&sections, PageType::Decision);
+        assert_eq!(body_to_sections(&body, PageType::Decision), Some(sections));
+    }
+
+    #[test]
+    fn test_body_to_sections_freeform() {
+        assert_eq!(body_to_sections("Intro text\n## Heading\nmore", PageType::Decision), None);
+        assert_eq!(body_to_sections("no headings", PageType::Reference), None);
+        let fenced = "## Steps\n```\n## not a heading\n```";
+        let sections = body_to_sections(fenced, PageType::Runbook).expect("sections");
+        assert_eq!(sections["steps"], "```\n## not a heading\n```");
+    }
+
+    #[test]
+    fn test_parse_page() {
+        let text = "---\ntitle: Hello\ntype: runbook\nlabels: [ops]\n---\n\n## Steps\nDo it\n";
+        let (fm, body) = parse_page(text).expect("parse");
+        assert_eq!(fm.title, "Hello");
+        assert_eq!(fm.page_type, PageType::Runbook);
+        assert_eq!(fm.labels, vec!["ops".to_string()]);
+        assert!(fm.id.is_none());
+        assert_eq!(body, "## Steps\nDo it");
+
+        assert!(matches!(parse_page("no frontmatter"), Err(KbError::InvalidInput(_))));
+        assert!(matches!(parse_page("---\ntitle: x\n"), Err(KbError::InvalidInput(_))));
+        assert!(matches!(parse_page("---\ntitle: x\n---\n"), Err(KbError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_round_trip_through_directory() {
+        let source = db::test_connection();
+        let space = repo::create_space(&source, "proj", "Project", "").expect("create space");
+        let parent = repo::create_page(
+            &source,
+            &space.id,
+            None,
+            "Use SQLite",
+            PageType::Decision,
+            "",
+            Some(&serde_json::json!({"context": "Need storage", "options_considered": "A, B", "decision": "SQLite"})),
+            &["db".to_string()],
+            "alice",
+            "agent",
+        )
+        .expect("create parent");
+        let child = repo::create_page(
+            &source, &space.id, Some(&parent.id), "WAL: notes", PageType::Reference, "Use WAL.\n\n---\nok", None,
+            &[], "bob", "agent",
+        )
+        .expect("create child");
+        repo::create_link(&source, &child.id, &parent.id, LinkRelation::Elaborates, "why", "bob", "agent")
+            .expect("create link");
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let written = export_space(&source, &space.id, dir.path()).expect("export");
+        assert_eq!(written.len(), 2);
+        assert!(dir.path().join("use-sqlite.md").exists());
+        assert!(dir.path().join("use-sqlite").join("wal-notes.md").exists());
+
+        let target = db::test_connection();
+        let archive = read_dir(&target, dir.path(), None, &identity()).expect("read dir");
+        let options = ImportOptions { on_conflict: ConflictStrategy::Skip, remap_ids: false };
+        let report = archive::import(&target, &archive, options).expect("import");
+        assert_eq!(report.spaces.created, 1);
+        assert_eq!(report.pages.created, 2);
+        assert_eq!(report.links.created, 1);
+
+        let imported = repo::get_page(&target, &parent.id).expect("get parent");
+        assert_eq!(imported.sections, parent.sections);
+        assert_eq!(imported.content, parent.content);
+        assert_eq!(imported.labels, parent.labels);
+        assert_eq!(imported.version, parent.version);
+        let imported_child = repo::get_page(&target, &child.id).expect("get child");
+        assert_eq!(imported_child.content, child.content);
+        assert_eq!(imported_child.parent_id, Some(parent.id.clone()));
+        assert_eq!(imported_child.created_by_user, "bob");
+        let link = repo::get_link(&target, &child.id, &parent.id).expect("get link");
+        assert_eq!(link.relation, LinkRelation::Elaborates);
+        assert_eq!(link.note, "why");
+    }
+
+    #[test]
+    fn test_read_hand_written_directory() {
+        let conn = db::test_connection();
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::write(dir.path().join("ops.md"), "---\ntitle: Ops\ntype: reference\n---\n\nAll about ops.\n").unwrap();
+        fs::create_dir(dir.path().join("ops")).unwrap();
+        fs::write(
+            dir.path().join("ops").join("restart.md"),
+            "---\ntitle: Restart\ntype: runbook\n---\n\n## Steps\n1. Restart it\n",
+        )
+        .unwrap();
+
+        assert!(matches!(read_dir(&conn, dir.path(), None, &identity()), Err(KbError::InvalidInput(_))));
+
+        let archive = read_dir(&conn, dir.path(), Some("notes"), &identity()).expect("read dir");
+        assert_eq!(archive.spaces.len(), 1);
+        assert_eq!(archive.spaces[0].slug, "notes");
+        let ops = archive.pages.iter().find(|p| p.title == "Ops").unwrap();
+        let restart = archive.pages.iter().find(|p| p.title == "Restart").unwrap();
+        assert_eq!(restart.parent_id.as_deref(), Some(ops.id.as_str()));
+        assert_eq!(restart.sections, Some(serde_json::json!({"steps": "1. Restart it"})));
+        assert_eq!(restart.created_by_user, "importer");
+        assert!(ops.sections.is_none());
+        assert_eq!(ops.content, "All about ops.");
+    }
+
+    #[test]
+    fn test_import_syncs_mentions_with_remapped_ids() {
+        let conn = db::test_connection();
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::write(dir.path().join("ops.md"), "---\ntitle: Ops\ntype: reference\n---\n\nAll about ops.\n").unwrap();
+        fs::write(
+            dir.path().join("restart.md"),
+            "---\ntitle: Restart\ntype: reference\n---\n\nSee [[Ops]] and [[Nowhere]].\n",
+        )
+        .unwrap();
+        let options = ImportOptions { on_conflict: ConflictStrategy::Skip, remap_ids: true };
+
+        let archive = read_dir(&conn, dir.path(), Some("notes"), &identity()).expect("read dir");
+        let report = import(&conn, &archive, options, &identity()).expect("import");
+        assert_eq!(report.pages.created, 2);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("[[Nowhere]]"));
+
+        let (_, restart) = report.written_pages.iter().find(|(id, _)| {
+            archive.pages.iter().any(|p| &p.id == id && p.title == "Restart")
+        }).expect("restart written");
+        let links = repo::list_links(&conn, restart).expect("links");
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].relation, LinkRelation::Mentions);
+        assert_eq!(repo::get_page(&conn, &links[0].target_id).expect("target").title, "Ops");
+        assert!(archive.pages.iter().all(|p| &p.id != restart), "ids were remapped");
+    }
+}
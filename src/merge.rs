@@ -0,0 +1,379 @@
+//! Merging another knowledge-base database into this one.
+//!
+//! Team members keep their own `kb.db`; merging unions them. The other
+//! database is exported as a `KbArchive` and imported with the
+//! `NewerWins` strategy: spaces match by ID then slug, pages by ID, links by
+//! their pages, and a page's labels are the union of both sides.
+//!
+//! Each merge records the version every page has on both sides in
+//! `merge_bases`, keyed by the other database's path. The next merge from
+//! the same database compares both sides to it: a page edited on one side
+//! only takes that edit. A page edited on both sides is a conflict: the
+//! newer version (by `version`, then `updated_at`) is kept, and the conflict
+//! is reported; optionally the other version is kept too, as a sibling page
+//! labelled `merge-conflict`. Before the first merge from a database there
+//! is no base, so a page past version 1 on both sides counts as edited on
+//! both.
+
+use crate::archive::{self, ConflictStrategy, ImportOptions};
+use crate::db::{self, KbError};
+use crate::models::{AgentIdentity, MergeReport, Page, PageConflict};
+use crate::repo;
+use crate::wikilinks;
+use rusqlite::Connection;
+use std::path::Path;
+
+/// `page_sources.kind` for conflict copies, keyed by the page, version and
+/// timestamp they preserve, so merging again doesn't copy them twice.
+pub const CONFLICT_SOURCE_KIND: &str = "merge-conflict";
+
+/// Label added to conflict copies.
+pub const CONFLICT_LABEL: &str = "merge-conflict";
+
+/// Merges the database file at `other` into `conn` in a single transaction.
+///
+/// The other file is only read: it is copied into memory and migrated there
+/// first, so databases at older schema versions can be merged too. Merging
+/// the same file again changes nothing.
+///
+/// # Arguments
+/// * `conn` - Database connection to merge into
+/// * `this` - Path of the database `conn` is open on
+/// * `other` - Path of the database to merge from
+/// * `keep_conflicts` - Keep the losing version of each conflicting page as a sibling page
+///
+/// # Returns
+/// Created, updated and skipped counts, plus every conflicting page and which side was kept
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` if `other` is this database, isn't a
+/// knowledge base, or is newer than this build. Nothing is merged on error.
+pub fn merge_file(
+    conn: &Connection,
+    this: &Path,
+    other: &Path,
+    keep_conflicts: bool,
+) -> Result<MergeReport, KbError> {
+    if let (Ok(a), Ok(b)) = (this.canonicalize(), other.canonicalize()) {
+        if a == b {
+            return Err(KbError::InvalidInput("Cannot merge a database into itself".to_string()));
+        }
+    }
+    let other_conn = db::open_copy(other)?;
+    let source = other.canonicalize()?.to_string_lossy().into_owned();
+    merge(conn, &other_conn, &source, keep_conflicts)
+}
+
+/// Merges every record of `other` into `conn`. `source` names the other
+/// database in `merge_bases`. See `merge_file`.
+pub fn merge(conn: &Connection, other: &Connection, source: &str, keep_conflicts: bool) -> Result<MergeReport, KbError> {
+    let mut incoming = archive::export(other, None)?;
+    let tx = conn.unchecked_transaction()?;
+
+    let mut conflicts = Vec::new();
+    let mut other_versions = Vec::new();
+    for page in &mut incoming.pages {
+        other_versions.push((page.id.clone(), page.version));
+        let local = match repo::get_page(&tx, &page.id) {
+            Ok(local) => local,
+            Err(KbError::NotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+
+        // Labels merge as a set, whichever version is kept.
+        let mut labels = local.labels.clone();
+        labels.extend(page.labels.iter().cloned());
+        labels.sort();
+        labels.dedup();
+        for label in labels.iter().filter(|l| !local.labels.contains(l)) {
+            repo::add_label(&tx, &local.id, label)?;
+        }
+        page.labels = labels;
+
+        if same_content(&local, page) {
+            continue;
+        }
+        // Without a base, a side still at version 1 hasn't been edited.
+        let (local_edited, incoming_edited) = match repo::get_merge_base(&tx, source, &page.id)? {
+            Some((local_base, other_base)) => (local.version != local_base, page.version != other_base),
+            None => (local.version > 1, page.version > 1),
+        };
+        match (local_edited, incoming_edited) {
+            (true, true) => {}
+            (false, true) => {
+                // Theirs replaces ours even if its version number is lower.
+                if page.version <= local.version {
+                    page.version = local.version + 1;
+                }
+                continue;
+            }
+            (true, false) => {
+                // Ours stays: the import finds nothing newer to write.
+                *page = Page { labels: std::mem::take(&mut page.labels), ..local };
+                continue;
+            }
+            (false, false) => continue,
+        }
+
+        let incoming_wins = page.version > local.version
+            || (page.version == local.version && archive::is_later(&page.updated_at, &local.updated_at));
+        let loser = if incoming_wins { &local } else { &*page };
+        let copy_id = if keep_conflicts {
+            Some(keep_copy(&tx, &local, loser)?)
+        } else {
+            None
+        };
+        conflicts.push(PageConflict {
+            page_id: page.id.clone(),
+            title: local.title.clone(),
+            local_version: local.version,
+            local_updated_at: local.updated_at.clone(),
+            incoming_version: page.version,
+            incoming_updated_at: page.updated_at.clone(),
+            kept: if incoming_wins { "incoming" } else { "local" }.to_string(),
+            copy_id,
+        });
+    }
+
+    let options = ImportOptions {
+        on_conflict: ConflictStrategy::NewerWins,
+        remap_ids: false,
+    };
+    let mut counts = archive::import_records(&tx, &incoming, options)?;
+    for (_, page_id) in counts.written_pages.clone() {
+        // Mentions are attributed to the page's author, outside any session.
+        let page = repo::get_page(&tx, &page_id)?;
+        let author = AgentIdentity { user: page.created_by_user, agent: page.created_by_agent, session: None };
+        wikilinks::sync_mentions_reporting(&tx, &page_id, &author, &mut counts.warnings)?;
+    }
+    for (page_id, other_version) in other_versions {
+        match repo::get_page(&tx, &page_id) {
+            Ok(local) => repo::set_merge_base(&tx, source, &page_id, local.version, other_version)?,
+            // Skipped by the import.
+            Err(KbError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    tx.commit()?;
+    Ok(MergeReport { counts, conflicts })
+}
+
+/// Whether two versions of a page say the same thing, ignoring version
+/// numbers, timestamps and labels.
+fn same_content(a: &Page, b: &Page) -> bool {
+    a.title == b.title
+        && a.page_type == b.page_type
+        && a.content == b.content
+        && a.sections == b.sections
+        && a.parent_id == b.parent_id
+}
+
+/// Saves the losing version of a conflicting page as a sibling of the local
+/// page, unless an earlier merge already did. Returns the copy's ID.
+fn keep_copy(conn: &Connection, local: &Page, loser: &Page) -> Result<String, KbError> {
+    let key = format!("{}@v{}@{}", loser.id, loser.version, loser.updated_at);
+    if let Some(id) = repo::get_page_source(conn, CONFLICT_SOURCE_KIND, &key)? {
+        return Ok(id);
+    }
+
+    let mut labels = loser.labels.clone();
+    labels.push(CONFLICT_LABEL.to_string());
+    labels.sort();
+    labels.dedup();
+    let copy = Page {
+        id: uuid::Uuid::new_v4().to_string(),
+        space_id: local.space_id.clone(),
+        parent_id: local.parent_id.clone(),
+        title: format!("{} (conflicting copy, v{})", loser.title, loser.version),
+        labels,
+        ..loser.clone()
+    };
+    repo::put_page(conn, &copy)?;
+    repo::set_page_source(conn, &copy.id, CONFLICT_SOURCE_KIND, &key)?;
+    Ok(copy.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{LinkRelation, PageType};
+
+    fn page(conn: &Connection, space_id: &str, title: &str, body: &str) -> Page {
+        repo::create_page(conn, space_id, None, title, PageType::Reference, body, None, &[], "u", "a").unwrap()
+    }
+
+    /// Two databases that share a space and a page, as if one was copied from the other.
+    fn shared_pair() -> (Connection, Connection, Page) {
+        let local = db::test_connection();
+        let space = repo::create_space(&local, "team", "Team", "").unwrap();
+        let shared = page(&local, &space.id, "Shared", "original");
+        let other = db::test_connection();
+        archive::import(&other, &archive::export(&local, None).unwrap(), ImportOptions {
+            on_conflict: ConflictStrategy::Skip,
+            remap_ids: false,
+        })
+        .unwrap();
+        (local, other, shared)
+    }
+
+    #[test]
+    fn test_merge_unions_records() {
+        let (local, other, shared) = shared_pair();
+        let team = repo::get_space_by_slug(&other, "team").unwrap();
+        let theirs = page(&other, &team.id, "Theirs", "from the other machine");
+        repo::set_labels(&other, &theirs.id, &["remote".to_string()]).unwrap();
+        repo::create_link(&other, &theirs.id, &shared.id, LinkRelation::RelatesTo, "", "u", "a").unwrap();
+        let ops = repo::create_space(&other, "ops", "Ops", "").unwrap();
+        page(&other, &ops.id, "Runbook", "steps");
+
+        let report = merge(&local, &other, "other", false).unwrap();
+        assert_eq!(report.counts.spaces.created, 1);
+        assert_eq!(report.counts.pages.created, 2);
+        assert_eq!(report.counts.links.created, 1);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(repo::get_page(&local, &theirs.id).unwrap().labels, vec!["remote".to_string()]);
+        assert_eq!(repo::list_backlinks(&local, &shared.id).unwrap().len(), 1);
+
+        // Idempotent: a second merge changes nothing.
+        let again = merge(&local, &other, "other", false).unwrap();
+        assert_eq!(again.counts.pages.created + again.counts.pages.updated, 0);
+        assert_eq!(again.counts.links.created + again.counts.links.updated, 0);
+    }
+
+    #[test]
+    fn test_merge_matches_spaces_by_slug() {
+        let local = db::test_connection();
+        let mine = repo::create_space(&local, "team", "Team", "").unwrap();
+        let other = db::test_connection();
+        let theirs = repo::create_space(&other, "team", "Team", "").unwrap();
+        let p = page(&other, &theirs.id, "Theirs", "x");
+
+        merge(&local, &other, "other", false).unwrap();
+        assert_eq!(repo::list_spaces(&local).unwrap().len(), 1);
+        assert_eq!(repo::get_page(&local, &p.id).unwrap().space_id, mine.id);
+    }
+
+    #[test]
+    fn test_merge_resolves_concurrent_edits_by_version() {
+        let (local, other, shared) = shared_pair();
+        // Edited once here, twice there: theirs is newer.
+        repo::update_page(&local, &shared.id, None, Some("local edit"), None, None).unwrap();
+        repo::update_page(&other, &shared.id, None, Some("remote edit"), None, None).unwrap();
+        repo::update_page(&other, &shared.id, None, Some("remote edit 2"), None, None).unwrap();
+
+        let report = merge(&local, &other, "other", false).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!(conflict.kept, "incoming");
+        assert_eq!((conflict.local_version, conflict.incoming_version), (2, 3));
+        assert!(conflict.copy_id.is_none());
+        assert_eq!(repo::get_page(&local, &shared.id).unwrap().content, "remote edit 2");
+
+        // Now both sides agree.
+        assert!(merge(&local, &other, "other", false).unwrap().conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_fast_forwards_and_unions_labels() {
+        let (local, other, shared) = shared_pair();
+        repo::add_label(&local, &shared.id, "mine").unwrap();
+        repo::add_label(&other, &shared.id, "theirs").unwrap();
+        repo::update_page(&other, &shared.id, None, Some("remote edit"), None, None).unwrap();
+
+        let report = merge(&local, &other, "other", false).unwrap();
+        assert!(report.conflicts.is_empty());
+        let merged = repo::get_page(&local, &shared.id).unwrap();
+        assert_eq!(merged.content, "remote edit");
+        assert_eq!(merged.labels, vec!["mine".to_string(), "theirs".to_string()]);
+
+        // A label added on its own still comes across.
+        repo::add_label(&other, &shared.id, "later").unwrap();
+        merge(&local, &other, "other", false).unwrap();
+        let merged = repo::get_page(&local, &shared.id).unwrap();
+        assert_eq!(merged.labels, vec!["later".to_string(), "mine".to_string(), "theirs".to_string()]);
+    }
+
+    #[test]
+    fn test_merge_takes_one_sided_edits_since_last_merge() {
+        let (local, other, shared) = shared_pair();
+        repo::update_page(&local, &shared.id, None, Some("agreed"), None, None).unwrap();
+        repo::update_page(&other, &shared.id, None, Some("agreed"), None, None).unwrap();
+        assert!(merge(&local, &other, "other", false).unwrap().conflicts.is_empty());
+
+        // Both at v2; only this side moves on.
+        repo::update_page(&local, &shared.id, None, Some("local edit"), None, None).unwrap();
+        let report = merge(&local, &other, "other", false).unwrap();
+        assert!(report.conflicts.is_empty());
+        let kept = repo::get_page(&local, &shared.id).unwrap();
+        assert_eq!((kept.version, kept.content.as_str()), (3, "local edit"));
+
+        // Then only the other side, to a version below ours.
+        repo::update_page(&other, &shared.id, None, Some("remote edit"), None, None).unwrap();
+        let report = merge(&local, &other, "other", false).unwrap();
+        assert!(report.conflicts.is_empty());
+        let taken = repo::get_page(&local, &shared.id).unwrap();
+        assert_eq!((taken.version, taken.content.as_str()), (4, "remote edit"));
+
+        // Edits on both sides since then still conflict.
+        repo::update_page(&local, &shared.id, None, Some("local again"), None, None).unwrap();
+        repo::update_page(&other, &shared.id, None, Some("remote again"), None, None).unwrap();
+        assert_eq!(merge(&local, &other, "other", false).unwrap().conflicts.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_keeps_conflicting_copy() {
+        let (local, other, shared) = shared_pair();
+        repo::update_page(&local, &shared.id, None, Some("local edit"), None, None).unwrap();
+        repo::update_page(&local, &shared.id, None, Some("local edit 2"), None, None).unwrap();
+        repo::update_page(&other, &shared.id, None, Some("remote edit"), None, None).unwrap();
+
+        let report = merge(&local, &other, "other", true).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].kept, "local");
+        let copy_id = report.conflicts[0].copy_id.clone().expect("copy kept");
+        let copy = repo::get_page(&local, &copy_id).unwrap();
+        assert_eq!(copy.title, "Shared (conflicting copy, v2)");
+        assert_eq!(copy.content, "remote edit");
+        assert_eq!(copy.labels, vec![CONFLICT_LABEL.to_string()]);
+        assert_eq!(copy.space_id, shared.space_id);
+        assert_eq!(repo::get_page(&local, &shared.id).unwrap().content, "local edit 2");
+
+        // The conflict is settled: merging again reports nothing.
+        assert!(merge(&local, &other, "other", true).unwrap().conflicts.is_empty());
+        // Merging the same pages from elsewhere reports it again but reuses the copy.
+        let again = merge(&local, &other, "other copy", true).unwrap();
+        assert_eq!(again.conflicts[0].copy_id, Some(copy_id));
+        let filters = repo::PageFilters {
+            space_id: None,
+            page_type: None,
+            label: Some(CONFLICT_LABEL.to_string()),
+            created_by_user: None,
+            created_by_agent: None,
+        };
+        assert_eq!(repo::list_pages(&local, &filters).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_merge_syncs_mentions() {
+        let (local, other, shared) = shared_pair();
+        let team = repo::get_space_by_slug(&other, "team").unwrap();
+        let theirs = page(&other, &team.id, "Theirs", "See [[Shared]] and [[Nowhere]].");
+
+        let report = merge(&local, &other, "other", false).unwrap();
+        let links = repo::list_links(&local, &theirs.id).unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!((links[0].target_id.as_str(), links[0].relation), (shared.id.as_str(), LinkRelation::Mentions));
+        assert_eq!(report.counts.warnings.len(), 1);
+        assert!(report.counts.warnings[0].contains("[[Nowhere]]"));
+    }
+
+    #[test]
+    fn test_merge_file_rejects_self() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kb.db");
+        let mut conn = db::open_connection_at(&path).unwrap();
+        db::run_migrations(&mut conn).unwrap();
+        let err = merge_file(&conn, &path, &path, false).unwrap_err();
+        assert!(err.to_string().contains("into itself"));
+    }
+}
@@ -4,24 +4,25 @@
 //! the CLI layer (clap), and the output layer (serde_json). They are kept
 //! simple — plain data, no business logic.
 
-use serde::Serialize;
-use serde_json;
+use serde::{Deserialize, Serialize};
 
 /// A top-level organizational unit. Not tied to a git repo — can represent
 /// any project, team, or domain the user wants to organize knowledge around.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Space {
     pub id: String,
     pub slug: String,
     pub name: String,
     pub description: String,
+    /// Whether pages in this space may link to (or be linked from) pages in other spaces.
+    pub allow_cross_space_links: bool,
     pub created_at: String,
     pub updated_at: String,
 }
 
 /// The primary knowledge document. Belongs to a space, has a type that hints
 /// at its structure, and optionally nests under a parent page for hierarchy.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Page {
     pub id: String,
     pub space_id: String,
@@ -29,20 +30,24 @@ pub struct Page {
     pub title: String,
     pub page_type: PageType,
     pub content: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub sections: Option<serde_json::Value>,
     pub created_by_user: String,
     pub created_by_agent: String,
     pub created_at: String,
     pub updated_at: String,
     pub version: i64,
+    /// The agent session that created this page, if it was created in one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
     /// Labels attached to this page (populated on read, not stored in the pages table).
+    #[serde(default)]
     pub labels: Vec<String>,
 }
 
 /// Constrained set of page types. Each suggests a different content structure,
 /// but the content itself is freeform markdown — no hard schema enforcement.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum PageType {
     Decision,
@@ -80,7 +85,8 @@ impl PageType {
     }
 
     /// Returns the expected section schema for this page type, if any.
-    /// Returns None for freeform types (SessionLog, Reference).
+    /// Returns None for freeform types (Reference). Session-log sections are
+    /// all optional, so a plain `--body` log is still valid.
     pub fn section_schema(&self) -> Option<Vec<SectionDef>> {
         match self {
             Self::Decision => Some(vec![
@@ -105,7 +111,13 @@ impl PageType {
                 SectionDef { key: "steps", name: "Steps", required: true },
                 SectionDef { key: "rollback", name: "Rollback", required: false },
             ]),
-            Self::SessionLog | Self::Reference => None,
+            Self::SessionLog => Some(vec![
+                SectionDef { key: "summary", name: "Summary", required: false },
+                SectionDef { key: "open_questions", name: "Open Questions", required: false },
+                SectionDef { key: "files_changed", name: "Files Changed", required: false },
+                SectionDef { key: "commits", name: "Git Commits", required: false },
+            ]),
+            Self::Reference => None,
         }
     }
 }
@@ -191,23 +203,70 @@ pub fn sections_to_content(sections: &serde_json::Value, page_type: PageType) ->
 }
 
 /// A typed directional relationship between two pages.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Link {
     pub source_id: String,
     pub target_id: String,
     pub relation: LinkRelation,
+    /// Free-text explanation of why the two pages are related. Empty if none was given.
+    pub note: String,
+    pub created_by_user: String,
+    pub created_by_agent: String,
     pub created_at: String,
     pub updated_at: String,
+    /// The agent session that created this link, if it was created in one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+}
+
+/// Just enough about a page to identify it to a human: used on each end of
+/// an expanded link instead of a bare UUID.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageRef {
+    pub id: String,
+    pub title: String,
+    pub page_type: PageType,
+    pub space_slug: String,
+}
+
+/// A link with the title, type and space of the pages on both ends resolved.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpandedLink {
+    #[serde(flatten)]
+    pub link: Link,
+    pub source: PageRef,
+    pub target: PageRef,
+}
+
+/// A page with its space slug and links resolved, as returned by `page get --expand`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpandedPage {
+    #[serde(flatten)]
+    pub page: Page,
+    pub space_slug: String,
+    pub links: Vec<ExpandedLink>,
+}
+
+/// A page and its descendants, as returned by `page tree`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageTreeNode {
+    pub id: String,
+    pub title: String,
+    pub page_type: PageType,
+    pub space_slug: String,
+    pub children: Vec<PageTreeNode>,
 }
 
 /// The kind of relationship between two linked pages.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum LinkRelation {
     RelatesTo,
     Supersedes,
     DependsOn,
     Elaborates,
+    /// Created automatically from a `[[wiki link]]` in the source page's content.
+    Mentions,
 }
 
 impl LinkRelation {
@@ -217,6 +276,7 @@ impl LinkRelation {
             "supersedes" => Some(Self::Supersedes),
             "depends-on" => Some(Self::DependsOn),
             "elaborates" => Some(Self::Elaborates),
+            "mentions" => Some(Self::Mentions),
             _ => None,
         }
     }
@@ -227,8 +287,15 @@ impl LinkRelation {
             Self::Supersedes => "supersedes",
             Self::DependsOn => "depends-on",
             Self::Elaborates => "elaborates",
+            Self::Mentions => "mentions",
         }
     }
+
+    /// Whether links of this relation must form a DAG. A page cannot
+    /// (transitively) supersede or depend on itself.
+    pub fn is_acyclic(&self) -> bool {
+        matches!(self, Self::Supersedes | Self::DependsOn)
+    }
 }
 
 impl std::fmt::Display for LinkRelation {
@@ -237,6 +304,273 @@ impl std::fmt::Display for LinkRelation {
     }
 }
 
+/// The rule an existing link breaks, as reported by `link check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkViolationKind {
+    /// The link points from a page to itself.
+    SelfLink,
+    /// The link is part of a cycle in an acyclic relation.
+    Cycle,
+    /// The link crosses spaces and one of the spaces forbids that.
+    CrossSpace,
+}
+
+impl LinkViolationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SelfLink => "self-link",
+            Self::Cycle => "cycle",
+            Self::CrossSpace => "cross-space",
+        }
+    }
+}
+
+/// A single integrity problem found while auditing links.
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkViolation {
+    pub kind: LinkViolationKind,
+    pub source_id: String,
+    pub target_id: String,
+    pub relation: LinkRelation,
+    /// Page IDs forming the cycle, starting and ending at the same page. Empty for non-cycle violations.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<String>,
+    pub detail: String,
+}
+
+/// Result of auditing every link in the knowledge base.
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkCheckReport {
+    pub links_checked: usize,
+    pub violations: Vec<LinkViolation>,
+}
+
+/// Where a migration stands in a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// Applied, but the embedded SQL has changed since.
+    Modified,
+    /// Applied by a newer build; not embedded in this one.
+    Unknown,
+}
+
+/// One migration's status, as reported by `db migrate --status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    /// SHA-256 of the embedded SQL (or the recorded one, for unknown migrations).
+    pub checksum: String,
+    pub state: MigrationState,
+    /// None for pending migrations and ones applied before tracking began.
+    pub applied_at: Option<String>,
+}
+
+/// Result of checking or running migrations.
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub schema_version: i64,
+    pub latest_schema_version: i64,
+    pub migrations: Vec<MigrationStatus>,
+    /// Edited, out-of-order or unknown migrations; migrations won't run while any exist.
+    pub problems: Vec<String>,
+    /// Versions applied by this run.
+    pub applied: Vec<i64>,
+    /// Backup taken before migrating, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<std::path::PathBuf>,
+}
+
+/// A row whose foreign key points at a missing parent row.
+#[derive(Debug, Clone, Serialize)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    /// The table the missing row should be in.
+    pub parent: String,
+}
+
+/// Result of checking the database file for corruption.
+#[derive(Debug, Clone, Serialize)]
+pub struct DbCheckReport {
+    /// True when every check passed.
+    pub ok: bool,
+    /// None if `schema_meta` is missing or unreadable.
+    pub schema_version: Option<i64>,
+    pub latest_schema_version: i64,
+    /// Problems reported by `PRAGMA integrity_check`.
+    pub integrity_errors: Vec<String>,
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+    /// Why the full-text index is inconsistent with `pages`, if it is.
+    pub fts_error: Option<String>,
+}
+
+/// A `[[wiki link]]` in page content that could not be turned into a link.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnresolvedLink {
+    /// The reference text between the brackets.
+    pub reference: String,
+    pub reason: String,
+}
+
+/// A page as returned by create/update/append, along with any `[[wiki links]]`
+/// in its content that did not resolve to a page.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageWriteResult {
+    #[serde(flatten)]
+    pub page: Page,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unresolved_links: Vec<UnresolvedLink>,
+    /// Existing pages the new one nearly duplicates, for `page create
+    /// --on-duplicate`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<DuplicateMatch>,
+}
+
+/// What `page create` does when the new page nearly duplicates an existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// Fail, listing the matching pages.
+    Refuse,
+    /// Append the new content to the closest match instead of creating a page.
+    Append,
+    /// Create the page and link it to the closest match.
+    Link,
+    /// Create the page anyway, reporting the matches.
+    Create,
+}
+
+impl OnDuplicate {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "refuse" => Some(OnDuplicate::Refuse),
+            "append" => Some(OnDuplicate::Append),
+            "link" => Some(OnDuplicate::Link),
+            "create" => Some(OnDuplicate::Create),
+            _ => None,
+        }
+    }
+}
+
+/// An existing page similar to a page being written.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateMatch {
+    #[serde(flatten)]
+    pub page: PageRef,
+    /// Estimated share of word pairs the two pages have in common (0..1).
+    pub similarity: f64,
+}
+
+/// Two pages in a space similar enough to be duplicates, as listed by `dedupe`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicatePair {
+    pub first: PageRef,
+    pub second: PageRef,
+    pub similarity: f64,
+}
+
+/// A complete, self-describing snapshot of a knowledge base (or part of one),
+/// as written by `export --format json` and read by `import`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KbArchive {
+    /// Always `"whatidid-kb"`; lets `import` reject unrelated JSON files.
+    pub format: String,
+    /// Version of this document layout. Bumped on incompatible changes.
+    pub format_version: u32,
+    /// Database schema version of the exporting knowledge base.
+    pub schema_version: i64,
+    pub exported_at: String,
+    pub spaces: Vec<Space>,
+    /// Pages with their labels.
+    pub pages: Vec<Page>,
+    pub links: Vec<Link>,
+}
+
+/// What happened to one kind of record during an import.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportCounts {
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// Summary of an import.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub spaces: ImportCounts,
+    pub pages: ImportCounts,
+    pub links: ImportCounts,
+    /// Records that could not be imported as-is, and what was done instead.
+    pub warnings: Vec<String>,
+    /// (archive ID, database ID) of each page created or updated, in import order.
+    #[serde(skip)]
+    pub written_pages: Vec<(String, String)>,
+}
+
+/// A page edited in both databases being merged.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageConflict {
+    pub page_id: String,
+    pub title: String,
+    pub local_version: i64,
+    pub local_updated_at: String,
+    pub incoming_version: i64,
+    pub incoming_updated_at: String,
+    /// Which version the page now has: "local" or "incoming".
+    pub kept: String,
+    /// The sibling page holding the other version, when conflicts are kept.
+    pub copy_id: Option<String>,
+}
+
+/// Summary of a database merge.
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeReport {
+    #[serde(flatten)]
+    pub counts: ImportReport,
+    pub conflicts: Vec<PageConflict>,
+}
+
+/// Records created, updated and deleted in the local database by a sync.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncCounts {
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+/// A page edited on both sides of a sync in ways that couldn't be combined
+/// cleanly.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncConflict {
+    pub page_id: String,
+    pub title: String,
+    /// Fields changed differently on both sides. For `content` (and
+    /// sections), the page now holds both versions between conflict markers;
+    /// for other fields the most recently updated side's value was kept.
+    pub fields: Vec<String>,
+}
+
+/// Summary of a git sync.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    pub repo: String,
+    /// Commit recording this knowledge base's changes since the last sync, if any.
+    pub committed: Option<String>,
+    /// Merge (or fast-forward) commit bringing in the remote's changes, if any.
+    pub merged: Option<String>,
+    pub pushed: bool,
+    /// Changes made to the local database by pulling.
+    pub spaces: SyncCounts,
+    pub pages: SyncCounts,
+    pub links: SyncCounts,
+    pub conflicts: Vec<SyncConflict>,
+    pub warnings: Vec<String>,
+}
+
 /// A search result with a relevance snippet from FTS5.
 #[derive(Debug, Clone, Serialize)]
 pub struct SearchResult {
@@ -245,12 +579,278 @@ pub struct SearchResult {
     pub excerpt: String,
 }
 
+/// A search result scored by `search --recall`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecallResult {
+    #[serde(flatten)]
+    pub result: SearchResult,
+    /// The sum of the breakdown's terms.
+    pub score: f64,
+    pub breakdown: ScoreBreakdown,
+}
+
+/// The weighted terms of a recall score.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ScoreBreakdown {
+    /// bm25 relevance, scaled so the best match scores the full weight.
+    pub relevance: f64,
+    /// Decays by half every half-life since the page was last updated.
+    pub recency: f64,
+    /// Log of the page's read count, scaled against the most-read result.
+    pub usage: f64,
+    /// Labelled `pitfall`.
+    pub pitfall: f64,
+    /// A decision that still stands.
+    pub accepted: f64,
+    /// Superseded or labelled `status:superseded`; zero or negative.
+    pub superseded: f64,
+    /// Net feedback given for similar queries; negative if mostly not useful.
+    pub feedback: f64,
+}
+
+/// One verdict on whether a page was useful for a query.
+#[derive(Debug, Clone, Serialize)]
+pub struct Feedback {
+    pub id: i64,
+    pub page_id: String,
+    pub query: String,
+    pub useful: bool,
+    pub created_by_user: String,
+    pub created_by_agent: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub created_at: String,
+}
+
+/// A page that is read often but has never been marked useful, as listed
+/// by `feedback report`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedbackReportEntry {
+    #[serde(flatten)]
+    pub page: PageRef,
+    /// Times the page was returned by search or `page get`.
+    pub reads: i64,
+    pub not_useful: i64,
+    pub last_read_at: String,
+}
+
+/// Why a page was included in a context pack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContextReason {
+    /// Labelled `pitfall` and matches the task.
+    Pitfall,
+    /// A decision that matches the task and hasn't been superseded.
+    Decision,
+    /// Matches the task.
+    Match,
+    /// Linked to (or from) one of the best matches.
+    Linked,
+}
+
+/// One page's contribution to a context pack.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContextItem {
+    pub page_id: String,
+    pub title: String,
+    pub page_type: PageType,
+    pub space_slug: String,
+    pub labels: Vec<String>,
+    pub reason: ContextReason,
+    /// For linked pages, the matching page they are linked with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub via: Option<String>,
+    pub score: f64,
+    /// The most relevant paragraphs of the page.
+    pub snippet: String,
+    /// Whether the snippet was cut short to fit the budget.
+    pub truncated: bool,
+}
+
+/// The best material on a task that fits a token budget, as built by `context`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContextPack {
+    pub task: String,
+    pub keywords: Vec<String>,
+    pub budget: usize,
+    /// Estimated tokens of the Markdown rendering of the pack.
+    pub estimated_tokens: usize,
+    pub items: Vec<ContextItem>,
+    /// Relevant pages left out because they didn't fit.
+    pub omitted: usize,
+}
+
+/// Which database a command is using, or a result came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DbSource {
+    /// The nearest `.whatidid/` directory's database.
+    Project,
+    /// `~/.knowledge-base/kb.db`.
+    Global,
+    /// A database named by `--db` or `KB_PATH`.
+    Custom,
+}
+
+impl DbSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Project => "project",
+            Self::Global => "global",
+            Self::Custom => "custom",
+        }
+    }
+}
+
+/// A result from one of several databases queried together.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sourced<T> {
+    pub source: DbSource,
+    /// Path of the database, for passing to `--db` in follow-up commands.
+    pub db: String,
+    #[serde(flatten)]
+    pub item: T,
+}
+
+/// A tool call that failed during an agent session.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolError {
+    /// Name of the tool, e.g. `Bash` or `Edit`.
+    pub tool: String,
+    /// First line of the error output.
+    pub message: String,
+}
+
+/// What `ingest transcript` did with a session's page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IngestAction {
+    Created,
+    Updated,
+    /// The session was ingested before and nothing has changed since.
+    Unchanged,
+}
+
+/// Summary of an ingested session transcript and the page written for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptReport {
+    pub session_id: String,
+    pub page_id: String,
+    pub title: String,
+    pub action: IngestAction,
+    /// Which summarizer wrote the summary (`extractive` or `command`).
+    pub summarizer: String,
+    /// Problems that didn't stop the ingestion, such as a failed summarizer.
+    pub warnings: Vec<String>,
+    /// The user's requests, in order.
+    pub requests: Vec<String>,
+    pub files_changed: Vec<String>,
+    /// Shell commands run, in order.
+    pub commands: Vec<String>,
+    /// First line of each commit's output, e.g. `[main 1a2b3c4] Fix parser`.
+    pub commits: Vec<String>,
+    pub errors: Vec<ToolError>,
+}
+
+/// One agent session's writes, as listed by `session list`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    /// Agents that wrote in the session.
+    pub agents: Vec<String>,
+    pub first_write_at: String,
+    pub last_write_at: String,
+    pub pages_created: usize,
+    /// Pages the session changed but didn't create.
+    pub pages_modified: usize,
+    pub links_created: usize,
+}
+
+/// A page a session wrote, with the versions it wrote.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionPage {
+    #[serde(flatten)]
+    pub page: PageRef,
+    pub versions: Vec<i64>,
+}
+
+/// Everything an agent session created or modified, as shown by `session show`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionActivity {
+    pub session_id: String,
+    pub pages_created: Vec<SessionPage>,
+    /// Pages the session changed but didn't create.
+    pub pages_modified: Vec<SessionPage>,
+    pub links_created: Vec<ExpandedLink>,
+}
+
+/// Which Claude Code settings file a hook is registered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookScope {
+    /// `~/.claude/settings.json`, used by every session.
+    Global,
+    /// `.claude/settings.json` in the project directory.
+    Project,
+}
+
+impl HookScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Project => "project",
+        }
+    }
+}
+
+/// How a hook is registered in a settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookState {
+    /// Runs the `whatidid hook` subcommand.
+    Native,
+    /// Runs the shell script from `hooks/` that the subcommand replaces.
+    Script,
+    NotInstalled,
+}
+
+/// What `hooks install` or `hooks uninstall` did with a hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookAction {
+    Installed,
+    /// The shell script was registered; the subcommand replaced it.
+    Replaced,
+    Removed,
+    /// The hook was already installed (or, uninstalling, already absent).
+    Unchanged,
+}
+
+/// A whatidid hook in one settings file, as reported by `hooks status`,
+/// `hooks install` and `hooks uninstall`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookReport {
+    pub hook: String,
+    /// The Claude Code event it runs on, e.g. `SessionEnd`.
+    pub event: String,
+    pub scope: HookScope,
+    pub settings: String,
+    pub state: HookState,
+    /// The registered commands (none when not installed).
+    pub commands: Vec<String>,
+    /// Only set by `install` and `uninstall`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<HookAction>,
+}
+
 /// Identity of the agent performing an operation.
 /// Resolved from CLI flags, env vars, or system defaults.
 #[derive(Debug, Clone)]
 pub struct AgentIdentity {
     pub user: String,
     pub agent: String,
+    /// The agent session writes are made in, if any.
+    pub session: Option<String>,
 }
 
 #[cfg(test)]
@@ -280,7 +880,7 @@ mod tests {
 
     #[test]
     fn link_relation_roundtrip() {
-        let relations = ["relates-to", "supersedes", "depends-on", "elaborates"];
+        let relations = ["relates-to", "supersedes", "depends-on", "elaborates", "mentions"];
         for r in relations {
             let parsed =
                 LinkRelation::from_str(r).unwrap_or_else(|| panic!("should parse '{}'", r));
@@ -293,6 +893,15 @@ mod tests {
         assert!(LinkRelation::from_str("blocks").is_none());
     }
 
+    #[test]
+    fn only_supersedes_and_depends_on_are_acyclic() {
+        assert!(LinkRelation::Supersedes.is_acyclic());
+        assert!(LinkRelation::DependsOn.is_acyclic());
+        assert!(!LinkRelation::RelatesTo.is_acyclic());
+        assert!(!LinkRelation::Elaborates.is_acyclic());
+        assert!(!LinkRelation::Mentions.is_acyclic());
+    }
+
     #[test]
     fn decision_has_section_schema() {
         let schema = PageType::Decision.section_schema().expect("Decision should have schema");
@@ -304,8 +913,11 @@ mod tests {
     }
 
     #[test]
-    fn session_log_has_no_schema() {
-        assert!(PageType::SessionLog.section_schema().is_none());
+    fn session_log_sections_are_optional() {
+        let schema = PageType::SessionLog.section_schema().expect("SessionLog should have schema");
+        assert_eq!(schema.len(), 4);
+        assert_eq!(schema[0].key, "summary");
+        assert!(schema.iter().all(|d| !d.required));
     }
 
     #[test]
@@ -0,0 +1,753 @@
+//! Obsidian and Logseq vault import and export.
+//!
+//! A vault is a folder of Markdown notes that link to each other with
+//! `[[wikilinks]]`. On import, each note becomes a page, its folder becomes
+//! its parent page (or, with `FolderMode::Spaces`, a top-level folder becomes
+//! a space), frontmatter `tags` become labels, and wikilinks are rewritten to
+//! page titles so they turn into `mentions` links. On export, the hierarchy
+//! is written back as folders and every link becomes a wikilink, so
+//! Obsidian's graph view shows the same connections as `page backlinks`.
+
+use crate::db::{self, KbError};
+use crate::markdown::slugify;
+use crate::models::{AgentIdentity, ImportReport, LinkRelation, Page, PageType};
+use crate::{repo, wikilinks};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `page_sources.kind` for pages imported from a vault.
+pub const SOURCE_KIND: &str = "obsidian";
+
+/// Marks the list of explicit links appended to exported notes. Everything
+/// from here to the end of a note is dropped on import, since the links it
+/// lists already exist.
+const LINKS_MARKER: &str = "<!-- whatidid:links -->";
+
+/// How vault folders map onto the knowledge base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderMode {
+    /// Every folder becomes a parent page for the notes inside it.
+    Pages,
+    /// Each top-level folder becomes a space; deeper folders become parent pages.
+    Spaces,
+}
+
+impl FolderMode {
+    /// Parse from a CLI string. Returns None for unrecognized modes.
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "pages" => Some(Self::Pages),
+            "spaces" => Some(Self::Spaces),
+            _ => None,
+        }
+    }
+}
+
+/// A note read from a vault.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub title: String,
+    pub page_type: PageType,
+    pub labels: Vec<String>,
+    pub body: String,
+}
+
+/// Parses a note's frontmatter (Obsidian) or leading `key:: value`
+/// properties (Logseq), falling back to the file stem for the title.
+///
+/// Recognised keys are `title`, `type` (a page type; anything else means
+/// `reference`) and `tags`.
+pub fn parse_note(stem: &str, text: &str) -> Note {
+    let text = text.replace("\r\n", "\n");
+    let mut title = None;
+    let mut page_type = None;
+    let mut labels: Vec<String> = Vec::new();
+    let mut body = text.as_str();
+
+    if let Some(rest) = text.strip_prefix("---\n") {
+        if let Some(end) = rest.find("\n---") {
+            if let Ok(serde_yaml::Value::Mapping(map)) = serde_yaml::from_str::<serde_yaml::Value>(&rest[..end]) {
+                title = map.get("title").and_then(|v| v.as_str()).map(str::to_string);
+                page_type = map.get("type").and_then(|v| v.as_str()).and_then(PageType::from_str);
+                match map.get("tags") {
+                    Some(serde_yaml::Value::Sequence(tags)) => {
+                        labels.extend(tags.iter().filter_map(|t| t.as_str()).map(str::to_string))
+                    }
+                    Some(serde_yaml::Value::String(tags)) => {
+                        labels.extend(tags.split([',', ' ']).map(str::to_string))
+                    }
+                    _ => {}
+                }
+            }
+            body = rest[end + 4..].strip_prefix('\n').unwrap_or(&rest[end + 4..]);
+        }
+    } else {
+        // Logseq page properties: "key:: value" lines at the very top.
+        let mut consumed = 0;
+        for line in text.split_inclusive('\n') {
+            let Some((key, value)) = line.split_once(":: ") else {
+                break;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "title" => title = Some(value.to_string()),
+                "type" => page_type = PageType::from_str(value),
+                "tags" => labels.extend(
+                    value
+                        .split(',')
+                        .map(|t| t.trim().trim_start_matches("[[").trim_end_matches("]]").to_string()),
+                ),
+                _ => {}
+            }
+            consumed += line.len();
+        }
+        body = &text[consumed..];
+    }
+
+    let mut labels: Vec<String> = labels
+        .into_iter()
+        .map(|l| l.trim().trim_start_matches('#').to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+    let mut seen = HashSet::new();
+    labels.retain(|l| seen.insert(l.clone()));
+
+    let body = match body.find(LINKS_MARKER) {
+        Some(i) => &body[..i],
+        None => body,
+    };
+
+    Note {
+        // Logseq stores namespaced pages ("a/b") as "a___b.md".
+        title: title.unwrap_or_else(|| stem.replace("___", "/")),
+        page_type: page_type.unwrap_or(PageType::Reference),
+        labels,
+        body: body.trim_matches('\n').to_string(),
+    }
+}
+
+/// A note plus where it lives, for the import pass.
+struct VaultNote {
+    note: Note,
+    /// Path relative to the vault root.
+    path: PathBuf,
+    space_id: String,
+    space_slug: String,
+    /// Path relative to its space's root folder, without the `.md` extension.
+    key: PathBuf,
+}
+
+/// Import state: pages created so far, so folders and notes are each
+/// turned into a page exactly once.
+struct VaultImport<'a> {
+    conn: &'a Connection,
+    identity: &'a AgentIdentity,
+    root: PathBuf,
+    notes: Vec<VaultNote>,
+    by_key: HashMap<(String, PathBuf), usize>,
+    note_pages: Vec<Option<String>>,
+    folder_pages: HashMap<(String, PathBuf), Option<String>>,
+    report: ImportReport,
+}
+
+impl VaultImport<'_> {
+    /// The page standing for a folder: a note named after the folder (next to
+    /// it or inside it), or else an empty placeholder page.
+    fn folder_page(&mut self, space_id: &str, dir: &Path) -> Result<Option<String>, KbError> {
+        if dir.as_os_str().is_empty() {
+            return Ok(None);
+        }
+        let memo_key = (space_id.to_string(), dir.to_path_buf());
+        if let Some(page) = self.folder_pages.get(&memo_key) {
+            return Ok(page.clone());
+        }
+
+        let name = dir.file_name().unwrap_or_default();
+        let folder_note = self
+            .by_key
+            .get(&memo_key)
+            .or_else(|| self.by_key.get(&(space_id.to_string(), dir.join(name))))
+            .copied();
+        let page = match folder_note {
+            Some(idx) => self.note_page(idx)?,
+            None => {
+                let parent = self.folder_page(space_id, dir.parent().unwrap_or(Path::new("")))?;
+                let source = self.root.join(dir);
+                let title = name.to_string_lossy().into_owned();
+                self.upsert(&source, space_id, parent.as_deref(), &title, PageType::Reference, "", &[])?
+            }
+        };
+        self.folder_pages.insert(memo_key, Some(page.clone()));
+        Ok(Some(page))
+    }
+
+    /// The page for a note, creating (or updating) it and its ancestors first.
+    fn note_page(&mut self, idx: usize) -> Result<String, KbError> {
+        if let Some(page) = &self.note_pages[idx] {
+            return Ok(page.clone());
+        }
+        let key = self.notes[idx].key.clone();
+        let space_id = self.notes[idx].space_id.clone();
+        let dir = key.parent().unwrap_or(Path::new("")).to_path_buf();
+        // A folder note inside its own folder ("a/a.md") belongs to the folder's parent.
+        let parent_dir = if !dir.as_os_str().is_empty() && key.file_name() == dir.file_name() {
+            dir.parent().unwrap_or(Path::new("")).to_path_buf()
+        } else {
+            dir
+        };
+        let parent = self.folder_page(&space_id, &parent_dir)?;
+
+        let note = &self.notes[idx];
+        let source = self.root.join(&note.path);
+        let content = self.rewrite_links(idx);
+        let (title, page_type, labels) = (note.note.title.clone(), note.note.page_type, note.note.labels.clone());
+        let page = self.upsert(&source, &space_id, parent.as_deref(), &title, page_type, &content, &labels)?;
+        self.note_pages[idx] = Some(page.clone());
+        Ok(page)
+    }
+
+    /// Rewrites a note's wikilinks, which name files, to the page titles
+    /// `wikilinks::resolve_reference` understands.
+    fn rewrite_links(&self, idx: usize) -> String {
+        let source = &self.notes[idx];
+        wikilinks::rewrite_references(&source.note.body, |target| {
+            let target = target.strip_suffix(".md").unwrap_or(target).to_lowercase();
+            let matches: Vec<&VaultNote> = self
+                .notes
+                .iter()
+                .filter(|n| {
+                    let path = n.path.with_extension("").to_string_lossy().to_lowercase();
+                    let stem = n.key.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+                    path == target || stem == target || n.note.title.to_lowercase() == target
+                })
+                .collect();
+            match matches.as_slice() {
+                [n] if n.space_id == source.space_id => Some(n.note.title.clone()),
+                [n] => Some(format!("{}:{}", n.space_slug, n.note.title)),
+                _ => None,
+            }
+        })
+    }
+
+    /// Creates the page for a source path, or updates the page created from it
+    /// on an earlier import.
+    #[allow(clippy::too_many_arguments)]
+    fn upsert(
+        &mut self,
+        source: &Path,
+        space_id: &str,
+        parent: Option<&str>,
+        title: &str,
+        page_type: PageType,
+        content: &str,
+        labels: &[String],
+    ) -> Result<String, KbError> {
+        let source = fs::canonicalize(source)?.to_string_lossy().into_owned();
+        let existing = match repo::get_page_source(self.conn, SOURCE_KIND, &source)? {
+            Some(id) => match repo::get_page(self.conn, &id) {
+                Ok(page) => Some(page),
+                Err(KbError::NotFound(_)) => None,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+
+        let Some(page) = existing else {
+            let page = repo::create_page(
+                self.conn,
+                space_id,
+                parent,
+                title,
+                page_type,
+                content,
+                None,
+                labels,
+                &self.identity.user,
+                &self.identity.agent,
+            )?;
+            repo::set_page_source(self.conn, &page.id, SOURCE_KIND, &source)?;
+            repo::record_page_session(self.conn, &page.id, self.identity)?;
+            self.report.pages.created += 1;
+            return Ok(page.id);
+        };
+
+        let mut changed = false;
+        if page.title != title || page.content != content {
+            repo::update_page(self.conn, &page.id, Some(title), Some(content), None, None)?;
+            changed = true;
+        }
+        let (mut old_labels, mut new_labels) = (page.labels.clone(), labels.to_vec());
+        old_labels.sort();
+        new_labels.sort();
+        if old_labels != new_labels {
+            repo::set_labels(self.conn, &page.id, labels)?;
+            changed = true;
+        }
+        if page.parent_id.as_deref() != parent {
+            repo::reparent_page(self.conn, &page.id, parent)?;
+            changed = true;
+        }
+        if changed {
+            repo::record_page_session(self.conn, &page.id, self.identity)?;
+            self.report.pages.updated += 1;
+        } else {
+            self.report.pages.skipped += 1;
+        }
+        Ok(page.id)
+    }
+}
+
+/// Imports every note in a vault.
+///
+/// Hidden folders (such as `.obsidian`) and Logseq's `logseq/` settings
+/// folder are skipped. Re-importing the same vault updates the pages created
+/// from each note and folder instead of duplicating them. The import runs in
+/// one transaction, so a failure leaves nothing behind.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `vault` - The vault's root folder
+/// * `space_id` - Space for notes (with `FolderMode::Spaces`, for notes at the vault root)
+/// * `mode` - Whether folders become parent pages or spaces
+/// * `identity` - Recorded as the creator of new pages and links
+///
+/// # Returns
+/// Counts of created spaces and created, updated and unchanged pages, plus a
+/// warning for each wikilink that didn't resolve to a page
+pub fn import_vault(
+    conn: &Connection,
+    vault: &Path,
+    space_id: &str,
+    mode: FolderMode,
+    identity: &AgentIdentity,
+) -> Result<ImportReport, KbError> {
+    let default_space = repo::get_space_by_id(conn, space_id)?;
+    let mut files = Vec::new();
+    collect_notes(vault, Path::new(""), &mut files)?;
+    files.sort();
+
+    let tx = db::begin_unless_nested(conn)?;
+    let mut report = ImportReport::default();
+    let mut spaces: HashMap<String, (String, String)> = HashMap::new();
+    let mut notes = Vec::new();
+    for path in files {
+        let text = fs::read_to_string(vault.join(&path))?;
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let note = parse_note(&stem, &text);
+
+        let mut components = path.components();
+        let (space_id, space_slug, key) = match mode {
+            FolderMode::Spaces if path.components().count() > 1 => {
+                let folder = components.next().unwrap().as_os_str().to_string_lossy().into_owned();
+                if !spaces.contains_key(&folder) {
+                    let slug = slugify(&folder);
+                    let space = match repo::get_space_by_slug(conn, &slug) {
+                        Ok(space) => space,
+                        Err(KbError::NotFound(_)) => {
+                            report.spaces.created += 1;
+                            repo::create_space(conn, &slug, &folder, "")?
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    spaces.insert(folder.clone(), (space.id, space.slug));
+                }
+                let (id, slug) = spaces[&folder].clone();
+                (id, slug, components.as_path().with_extension(""))
+            }
+            _ => (
+                default_space.id.clone(),
+                default_space.slug.clone(),
+                path.with_extension(""),
+            ),
+        };
+        notes.push(VaultNote { note, path, space_id, space_slug, key });
+    }
+
+    let by_key = notes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| ((n.space_id.clone(), n.key.clone()), i))
+        .collect();
+    let mut import = VaultImport {
+        conn,
+        identity,
+        root: vault.to_path_buf(),
+        note_pages: vec![None; notes.len()],
+        notes,
+        by_key,
+        folder_pages: HashMap::new(),
+        report,
+    };
+    let mut page_ids = Vec::new();
+    for idx in 0..import.notes.len() {
+        page_ids.push(import.note_page(idx)?);
+    }
+
+    let mut report = import.report;
+    for (idx, page_id) in page_ids.iter().enumerate() {
+        for link in wikilinks::sync_mentions(conn, page_id, identity)? {
+            // Embedded images and attachments are wikilinks too, but not to pages.
+            let is_attachment = Path::new(&link.reference)
+                .extension()
+                .is_some_and(|ext| ext != "md");
+            if !is_attachment {
+                report.warnings.push(format!(
+                    "{}: unresolved [[{}]]: {}",
+                    import.notes[idx].path.display(),
+                    link.reference,
+                    link.reason
+                ));
+            }
+        }
+    }
+    if let Some(tx) = tx {
+        tx.commit()?;
+    }
+    Ok(report)
+}
+
+fn collect_notes(root: &Path, rel: &Path, files: &mut Vec<PathBuf>) -> Result<(), KbError> {
+    for entry in fs::read_dir(root.join(rel))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name_str = name.to_string_lossy();
+        if name_str.starts_with('.') || (rel.as_os_str().is_empty() && name_str == "logseq") {
+            continue;
+        }
+        let path = rel.join(&name);
+        if entry.file_type()?.is_dir() {
+            collect_notes(root, &path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "md") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Frontmatter written on exported notes.
+#[derive(Serialize)]
+struct NoteFrontmatter<'a> {
+    id: &'a str,
+    /// Only written when the file name had to differ from the title.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(rename = "type")]
+    page_type: PageType,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    created: &'a str,
+    updated: &'a str,
+}
+
+/// Turns a label into a valid Obsidian tag: no spaces, and `:` becomes a `/`
+/// nested-tag separator (so `status:accepted` is `status/accepted`).
+fn label_to_tag(label: &str) -> String {
+    label.trim().replace(char::is_whitespace, "-").replace(':', "/")
+}
+
+/// Removes characters Obsidian doesn't allow in note names.
+fn file_stem_for(title: &str) -> String {
+    let stem: String = title
+        .chars()
+        .filter(|c| !matches!(c, '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']'))
+        .collect();
+    let stem = stem.trim().trim_start_matches('.').trim();
+    if stem.is_empty() {
+        "Untitled".to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+/// Writes a space (or every space) as an Obsidian vault.
+///
+/// Each page becomes `Title.md`; child pages go in a folder next to their
+/// parent's note. When exporting every space, each space gets a top-level
+/// folder, matching `FolderMode::Spaces` on import. `[[references]]` in
+/// content are rewritten to the exported note names, and explicit links are
+/// listed as wikilinks at the end of each note.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `space_id` - Optional space to export; all spaces when `None`
+/// * `dir` - The vault folder, created if missing
+///
+/// # Returns
+/// The paths of the notes written
+pub fn export_vault(conn: &Connection, space_id: Option<&str>, dir: &Path) -> Result<Vec<PathBuf>, KbError> {
+    let spaces = match space_id {
+        Some(id) => vec![repo::get_space_by_id(conn, id)?],
+        None => repo::list_spaces(conn)?,
+    };
+
+    // Lay out every note first, so links can point at their final names.
+    let mut layout: HashMap<String, PathBuf> = HashMap::new();
+    let mut order: Vec<String> = Vec::new();
+    for space in &spaces {
+        let root = if space_id.is_some() { PathBuf::new() } else { PathBuf::from(&space.slug) };
+        let mut stack = vec![(root, repo::page_tree(conn, Some(&space.id), None)?)];
+        while let Some((folder, nodes)) = stack.pop() {
+            let mut used = HashSet::new();
+            for node in nodes {
+                let base = file_stem_for(&node.title);
+                let mut stem = base.clone();
+                let mut n = 2;
+                while !used.insert(stem.to_lowercase()) {
+                    stem = format!("{} ({})", base, n);
+                    n += 1;
+                }
+                layout.insert(node.id.clone(), folder.join(&stem));
+                order.push(node.id.clone());
+                if !node.children.is_empty() {
+                    stack.push((folder.join(&stem), node.children));
+                }
+            }
+        }
+    }
+
+    // Obsidian resolves a bare name if it's unique in the vault; otherwise use the path.
+    let mut stem_counts: HashMap<String, usize> = HashMap::new();
+    for path in layout.values() {
+        let stem = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+        *stem_counts.entry(stem).or_default() += 1;
+    }
+    let link_target = |id: &str| -> Option<String> {
+        let path = layout.get(id)?;
+        let stem = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if stem_counts[&stem.to_lowercase()] == 1 {
+            Some(stem)
+        } else {
+            Some(path.to_string_lossy().replace('\\', "/"))
+        }
+    };
+
+    let mut written = Vec::new();
+    for id in &order {
+        let page = repo::get_page(conn, id)?;
+        let rel = &layout[id];
+        let content = rewrite_for_vault(conn, &page, &link_target)?;
+
+        let mut links_block = Vec::new();
+        for expanded in repo::list_links_expanded(conn, &page.id)? {
+            let link = &expanded.link;
+            if link.source_id != page.id || link.relation == LinkRelation::Mentions {
+                continue;
+            }
+            let target = link_target(&link.target_id)
+                .unwrap_or_else(|| format!("{}:{}", expanded.target.space_slug, expanded.target.title));
+            let mut line = format!("- {}:: [[{}]]", link.relation, target);
+            if !link.note.is_empty() {
+                line.push_str(&format!(" — {}", link.note));
+            }
+            links_block.push(line);
+        }
+
+        let stem = rel.file_name().unwrap_or_default().to_string_lossy();
+        let frontmatter = NoteFrontmatter {
+            id: &page.id,
+            title: (stem != page.title).then_some(page.title.as_str()),
+            page_type: page.page_type,
+            tags: page.labels.iter().map(|l| label_to_tag(l)).collect(),
+            created: &page.created_at,
+            updated: &page.updated_at,
+        };
+        let mut text = format!(
+            "---\n{}---\n\n{}\n",
+            serde_yaml::to_string(&frontmatter).expect("failed to serialize frontmatter"),
+            content
+        );
+        if !links_block.is_empty() {
+            text.push_str(&format!("\n{}\n## Links\n\n{}\n", LINKS_MARKER, links_block.join("\n")));
+        }
+
+        let path = dir.join(rel).with_extension("md");
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, text)?;
+        written.push(path);
+    }
+    written.sort();
+    Ok(written)
+}
+
+/// Points a page's `[[references]]` at exported note names.
+fn rewrite_for_vault(
+    conn: &Connection,
+    page: &Page,
+    link_target: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, KbError> {
+    let mut error = None;
+    let content = wikilinks::rewrite_references(&page.content, |reference| {
+        match wikilinks::resolve_reference(conn, page, reference) {
+            Ok(Ok(id)) => link_target(&id),
+            Ok(Err(_)) => None,
+            Err(e) => {
+                error.get_or_insert(e);
+                None
+            }
+        }
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> AgentIdentity {
+        AgentIdentity { user: "u".to_string(), agent: "a".to_string(), session: None }
+    }
+
+    fn write(root: &Path, rel: &str, text: &str) {
+        let path = root.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn titles_under(conn: &Connection, parent: &str) -> Vec<String> {
+        repo::list_child_pages(conn, parent).unwrap().into_iter().map(|p| p.title).collect()
+    }
+
+    #[test]
+    fn test_parse_note_frontmatter() {
+        let note = parse_note("Use SQLite", "---\ntags: [db, '#storage']\ntype: decision\n---\nBody [[x]]\n");
+        assert_eq!(note.title, "Use SQLite");
+        assert_eq!(note.page_type, PageType::Decision);
+        assert_eq!(note.labels, vec!["db".to_string(), "storage".to_string()]);
+        assert_eq!(note.body, "Body [[x]]");
+
+        let note = parse_note("x", "---\ntags: a, b\ntitle: Real title\n---\n\ntext");
+        assert_eq!(note.title, "Real title");
+        assert_eq!(note.page_type, PageType::Reference);
+        assert_eq!(note.labels, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_note_logseq_properties() {
+        let note = parse_note("proj___db", "tags:: [[db]], ops\ntype:: runbook\n\n- step one\n");
+        assert_eq!(note.title, "proj/db");
+        assert_eq!(note.page_type, PageType::Runbook);
+        assert_eq!(note.labels, vec!["db".to_string(), "ops".to_string()]);
+        assert_eq!(note.body, "- step one");
+    }
+
+    #[test]
+    fn test_parse_note_dedupes_repeated_tags() {
+        let note = parse_note("x", "---\ntags: [db, ops, '#db', ops]\n---\ntext");
+        assert_eq!(note.labels, vec!["db".to_string(), "ops".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_note_drops_exported_links_block() {
+        let text = format!("---\ntype: reference\n---\n\nBody\n\n{}\n## Links\n\n- relates-to:: [[B]]\n", LINKS_MARKER);
+        assert_eq!(parse_note("A", &text).body, "Body");
+    }
+
+    #[test]
+    fn test_label_to_tag_and_file_stem() {
+        assert_eq!(label_to_tag("status:accepted"), "status/accepted");
+        assert_eq!(label_to_tag("two words"), "two-words");
+        assert_eq!(file_stem_for("ADR-0001: Use SQLite?"), "ADR-0001 Use SQLite");
+        assert_eq!(file_stem_for("///"), "Untitled");
+    }
+
+    #[test]
+    fn test_import_vault_folders_as_pages() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "notes", "Notes", "").unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        write(vault.path(), "Projects.md", "Overview of [[Alpha]].");
+        write(vault.path(), "Projects/Alpha.md", "---\ntags: [active]\n---\nSee [[Projects/Beta|beta]] and ![[diagram.png]].");
+        write(vault.path(), "Projects/Beta.md", "Beta notes");
+        write(vault.path(), "Areas/Ops/Ops.md", "Ops folder note, links [[Missing]]");
+        write(vault.path(), "Areas/Ops/Restart.md", "Restart steps");
+        write(vault.path(), ".obsidian/app.md", "ignored");
+
+        let report = import_vault(&conn, vault.path(), &space.id, FolderMode::Pages, &identity()).unwrap();
+        // Five notes plus a placeholder page for the "Areas" folder.
+        assert_eq!(report.pages.created, 6);
+        assert_eq!(report.warnings.len(), 1, "{:?}", report.warnings);
+        assert!(report.warnings[0].contains("Missing"));
+
+        let roots: Vec<String> = repo::list_top_level_pages(&conn, &space.id)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.title)
+            .collect();
+        assert_eq!(roots, vec!["Areas".to_string(), "Projects".to_string()]);
+        let projects = repo::list_top_level_pages(&conn, &space.id).unwrap().remove(1);
+        assert_eq!(titles_under(&conn, &projects.id), vec!["Alpha".to_string(), "Beta".to_string()]);
+        let areas = repo::list_top_level_pages(&conn, &space.id).unwrap().remove(0);
+        assert_eq!(titles_under(&conn, &areas.id), vec!["Ops".to_string()]);
+
+        let alpha = repo::list_child_pages(&conn, &projects.id).unwrap().remove(0);
+        assert_eq!(alpha.labels, vec!["active".to_string()]);
+        assert!(alpha.content.contains("[[Beta|beta]]"));
+        let links = repo::list_links(&conn, &alpha.id).unwrap();
+        assert!(links.iter().any(|l| l.relation == LinkRelation::Mentions && l.source_id == alpha.id));
+
+        // Re-importing changes nothing.
+        let report = import_vault(&conn, vault.path(), &space.id, FolderMode::Pages, &identity()).unwrap();
+        assert_eq!(report.pages.created, 0);
+        assert_eq!(report.pages.updated, 0);
+        assert_eq!(report.pages.skipped, 6);
+    }
+
+    #[test]
+    fn test_import_vault_folders_as_spaces() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "inbox", "Inbox", "").unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        write(vault.path(), "Loose note.md", "Points at [[Design]]");
+        write(vault.path(), "Work Stuff/Design.md", "Design doc");
+
+        let report = import_vault(&conn, vault.path(), &space.id, FolderMode::Spaces, &identity()).unwrap();
+        assert_eq!(report.spaces.created, 1);
+        assert_eq!(report.pages.created, 2);
+        let work = repo::get_space_by_slug(&conn, "work-stuff").unwrap();
+        assert_eq!(work.name, "Work Stuff");
+        let loose = repo::list_top_level_pages(&conn, &space.id).unwrap().remove(0);
+        assert_eq!(loose.content, "Points at [[work-stuff:Design]]");
+        assert_eq!(repo::list_links(&conn, &loose.id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_export_vault_round_trip() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "kb", "KB", "").unwrap();
+        let parent = repo::create_page(&conn, &space.id, None, "Parent: one", PageType::Reference, "Top", None, &["status:ok".to_string()], "u", "a").unwrap();
+        let child = repo::create_page(&conn, &space.id, Some(&parent.id), "Child", PageType::Reference, "Up to [[Parent: one]]", None, &[], "u", "a").unwrap();
+        wikilinks::sync_mentions(&conn, &child.id, &identity()).unwrap();
+        repo::create_link(&conn, &parent.id, &child.id, LinkRelation::Elaborates, "more", "u", "a").unwrap();
+
+        let vault = tempfile::tempdir().unwrap();
+        let written = export_vault(&conn, Some(&space.id), vault.path()).unwrap();
+        assert_eq!(written.len(), 2);
+        let parent_text = fs::read_to_string(vault.path().join("Parent one.md")).unwrap();
+        assert!(parent_text.contains("title: 'Parent: one'") || parent_text.contains("title: \"Parent: one\""), "{}", parent_text);
+        assert!(parent_text.contains("- status/ok"));
+        assert!(parent_text.contains("- elaborates:: [[Child]] — more"));
+        let child_text = fs::read_to_string(vault.path().join("Parent one").join("Child.md")).unwrap();
+        assert!(child_text.contains("Up to [[Parent one]]"));
+
+        let target = db::test_connection();
+        let space2 = repo::create_space(&target, "copy", "Copy", "").unwrap();
+        let report = import_vault(&target, vault.path(), &space2.id, FolderMode::Pages, &identity()).unwrap();
+        assert_eq!(report.pages.created, 2);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        let top = repo::list_top_level_pages(&target, &space2.id).unwrap();
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].title, "Parent: one");
+        assert_eq!(top[0].content, "Top");
+        assert_eq!(titles_under(&target, &top[0].id), vec!["Child".to_string()]);
+        let copied_child = repo::list_child_pages(&target, &top[0].id).unwrap().remove(0);
+        assert_eq!(copied_child.content, "Up to [[Parent: one]]");
+    }
+}
@@ -8,7 +8,10 @@
 //! stable machine-readable output. The pretty format emphasizes readability
 //! with labeled fields and structured layouts.
 
-use crate::models::{Link, Page, SearchResult, Space};
+use crate::models::{
+    DbCheckReport, DuplicatePair, ExpandedLink, Feedback, FeedbackReportEntry, MergeReport, MigrationReport, MigrationState, ExpandedPage, ImportReport, LinkCheckReport, Page, PageRef, PageTreeNode, PageWriteResult,
+    HookAction, HookReport, HookState, IngestAction, RecallResult, SearchResult, SessionActivity, SessionSummary, Sourced, Space, SyncReport, TranscriptReport,
+};
 use serde::Serialize;
 
 /// Output mode for CLI results.
@@ -43,12 +46,18 @@ pub fn print_json<T: Serialize>(value: &T) {
 /// Name:  My Project
 /// ID:    <uuid>
 /// Desc:  Some description
+/// Links: cross-space allowed
 /// ```
 pub fn print_pretty_space(space: &Space) {
     println!("Space: {}", space.slug);
     println!("Name:  {}", space.name);
     println!("ID:    {}", space.id);
     println!("Desc:  {}", space.description);
+    if space.allow_cross_space_links {
+        println!("Links: cross-space allowed");
+    } else {
+        println!("Links: cross-space denied");
+    }
     println!("Created: {}", space.created_at);
     println!("Updated: {}", space.updated_at);
 }
@@ -59,7 +68,7 @@ pub fn print_pretty_space(space: &Space) {
 /// ```text
 /// Title:   Some Decision
 /// ID:      <uuid>
-/// Space:   <space_id>
+/// Space:   my-project
 /// Type:    decision
 /// Labels:  security, auth
 /// Author:  logan / claude-code
@@ -69,10 +78,10 @@ pub fn print_pretty_space(space: &Space) {
 ///
 /// <content>
 /// ```
-pub fn print_pretty_page(page: &Page) {
+pub fn print_pretty_page(page: &Page, space_slug: &str) {
     println!("Title:   {}", page.title);
     println!("ID:      {}", page.id);
-    println!("Space:   {}", page.space_id);
+    println!("Space:   {}", space_slug);
     println!("Type:    {}", page.page_type);
 
     if page.labels.is_empty() {
@@ -82,6 +91,9 @@ pub fn print_pretty_page(page: &Page) {
     }
 
     println!("Author:  {} / {}", page.created_by_user, page.created_by_agent);
+    if let Some(session) = &page.session_id {
+        println!("Session: {}", session);
+    }
     println!("Version: {}", page.version);
     println!("Created: {}", page.created_at);
     println!("Updated: {}", page.updated_at);
@@ -134,6 +146,90 @@ pub fn print_pretty_page(page: &Page) {
     }
 }
 
+/// Print a page after create/update/append, followed by any `[[wiki links]]`
+/// that did not resolve.
+///
+/// Format:
+/// ```text
+/// <page, as print_pretty_page>
+///
+/// Unresolved links:
+///   [[Missing Page]] - No page titled 'Missing Page'
+/// ```
+pub fn print_pretty_page_write(result: &PageWriteResult, space_slug: &str) {
+    print_pretty_page(&result.page, space_slug);
+    if !result.unresolved_links.is_empty() {
+        println!();
+        println!("Unresolved links:");
+        for u in &result.unresolved_links {
+            println!("  [[{}]] - {}", u.reference, u.reason);
+        }
+    }
+    if !result.duplicates.is_empty() {
+        println!();
+        println!("Near-duplicates:");
+        for d in &result.duplicates {
+            println!("  {}  {} (similarity {})", d.page.id, format_page_ref(&d.page), d.similarity);
+        }
+    }
+}
+
+/// Print near-duplicate page pairs, most similar first.
+///
+/// Format:
+/// ```text
+/// 0.875  Linker error on macOS [troubleshooting] (my-project)
+///        macOS linker failure [troubleshooting] (my-project)
+///        <first_id> <second_id>
+/// ```
+pub fn print_pretty_duplicate_pairs(pairs: &[DuplicatePair]) {
+    if pairs.is_empty() {
+        println!("(no duplicates)");
+        return;
+    }
+    for (i, pair) in pairs.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{:.3}  {}", pair.similarity, format_page_ref(&pair.first));
+        println!("       {}", format_page_ref(&pair.second));
+        println!("       {} {}", pair.first.id, pair.second.id);
+    }
+}
+
+/// Print a page followed by its links, with linked pages shown by title.
+///
+/// Format:
+/// ```text
+/// <page, as print_pretty_page>
+///
+/// --- Links ---
+///   supersedes -> Chose MySQL [decision] (my-project)
+///   mentions <- Weekly notes [session-log] (my-project)
+/// ```
+pub fn print_pretty_expanded_page(expanded: &ExpandedPage) {
+    print_pretty_page(&expanded.page, &expanded.space_slug);
+    if !expanded.links.is_empty() {
+        println!();
+        println!("--- Links ---");
+        for l in &expanded.links {
+            if l.link.source_id == expanded.page.id {
+                println!("  {} -> {}", l.link.relation, format_page_ref(&l.target));
+            } else {
+                println!("  {} <- {}", l.link.relation, format_page_ref(&l.source));
+            }
+            if !l.link.note.is_empty() {
+                println!("      {}", l.link.note);
+            }
+        }
+    }
+}
+
+/// Format a linked page as `Title [type] (space-slug)`.
+pub fn format_page_ref(page: &PageRef) -> String {
+    format!("{} [{}] ({})", page.title, page.page_type, page.space_slug)
+}
+
 /// Print a list of pages as a table-like summary.
 ///
 /// Each page is printed on one line with key fields: id, type, title, author.
@@ -158,6 +254,188 @@ pub fn print_pretty_pages(pages: &[Page]) {
     }
 }
 
+/// Print results from several databases, one group per database, each
+/// printed with `print`.
+pub fn print_pretty_by_source<T: Clone>(results: &[Sourced<T>], print: impl Fn(&[T])) {
+    if results.is_empty() {
+        print(&[]);
+        return;
+    }
+
+    let mut start = 0;
+    while start < results.len() {
+        let db = &results[start].db;
+        let end = start + results[start..].iter().take_while(|r| &r.db == db).count();
+        if start > 0 {
+            println!();
+        }
+        println!("== {} ({}) ==", results[start].source.as_str(), db);
+        let items: Vec<T> = results[start..end].iter().map(|r| r.item.clone()).collect();
+        print(&items);
+        start = end;
+    }
+}
+
+/// Print an import summary followed by any warnings.
+pub fn print_pretty_import_report(report: &ImportReport) {
+    for (kind, counts) in [("Spaces", &report.spaces), ("Pages", &report.pages), ("Links", &report.links)] {
+        println!(
+            "{:<7} {} created, {} updated, {} skipped",
+            format!("{}:", kind),
+            counts.created,
+            counts.updated,
+            counts.skipped
+        );
+    }
+    if !report.warnings.is_empty() {
+        println!();
+        println!("Warnings:");
+        for warning in &report.warnings {
+            println!("  {}", warning);
+        }
+    }
+}
+
+/// Print what was ingested from a session transcript.
+pub fn print_pretty_transcript_report(report: &TranscriptReport) {
+    let action = match report.action {
+        IngestAction::Created => "Created",
+        IngestAction::Updated => "Updated",
+        IngestAction::Unchanged => "Unchanged",
+    };
+    println!("{} {} ({})", action, report.title, report.page_id);
+    println!("Session:  {}", report.session_id);
+    println!(
+        "Activity: {} requests, {} commands, {} files changed, {} commits, {} failed tool calls",
+        report.requests.len(),
+        report.commands.len(),
+        report.files_changed.len(),
+        report.commits.len(),
+        report.errors.len()
+    );
+    for error in &report.errors {
+        println!("  {}: {}", error.tool, error.message);
+    }
+    println!("Summary:  {}", report.summarizer);
+    for warning in &report.warnings {
+        println!("Warning:  {}", warning);
+    }
+}
+
+/// Print hook registrations, one line per hook and settings file.
+pub fn print_pretty_hook_reports(reports: &[HookReport]) {
+    for report in reports {
+        let state = match (report.action, report.state) {
+            (Some(HookAction::Installed), _) => "installed",
+            (Some(HookAction::Replaced), _) => "installed, replacing the shell script",
+            (Some(HookAction::Removed), _) => "removed",
+            (_, HookState::Native) => "installed",
+            (_, HookState::Script) => "installed (shell script)",
+            (_, HookState::NotInstalled) => "not installed",
+        };
+        let unchanged = if report.action == Some(HookAction::Unchanged) { " (unchanged)" } else { "" };
+        println!(
+            "{:<22} {:<17} {:<8} {}{}",
+            report.hook,
+            report.event,
+            report.scope.as_str(),
+            state,
+            unchanged
+        );
+        println!("    {}", report.settings);
+        for command in &report.commands {
+            println!("    $ {}", command);
+        }
+    }
+}
+
+/// Print a merge summary: import counts, then each conflicting page.
+pub fn print_pretty_merge_report(report: &MergeReport) {
+    print_pretty_import_report(&report.counts);
+    if report.conflicts.is_empty() {
+        return;
+    }
+    println!();
+    println!("Conflicts:");
+    for c in &report.conflicts {
+        println!(
+            "  {} ({}): local v{}, incoming v{}; kept {}",
+            c.title, c.page_id, c.local_version, c.incoming_version, c.kept
+        );
+        if let Some(copy) = &c.copy_id {
+            println!("    other version saved as {}", copy);
+        }
+    }
+}
+
+/// Print a sync summary: commits made, changes pulled and conflicts.
+pub fn print_pretty_sync_report(report: &SyncReport) {
+    match &report.committed {
+        Some(commit) => println!("Committed local changes as {}", short_sha(commit)),
+        None => println!("No local changes to commit"),
+    }
+    if let Some(commit) = &report.merged {
+        println!("Merged remote changes at {}", short_sha(commit));
+        for (kind, counts) in [("Spaces", &report.spaces), ("Pages", &report.pages), ("Links", &report.links)] {
+            println!(
+                "  {}: {} created, {} updated, {} deleted",
+                kind, counts.created, counts.updated, counts.deleted
+            );
+        }
+    }
+    if report.pushed {
+        println!("Pushed to origin");
+    }
+    if !report.conflicts.is_empty() {
+        println!();
+        println!("Conflicts:");
+        for c in &report.conflicts {
+            println!("  {} ({}): {}", c.title, c.page_id, c.fields.join(", "));
+        }
+    }
+    if !report.warnings.is_empty() {
+        println!();
+        println!("Warnings:");
+        for warning in &report.warnings {
+            println!("  {}", warning);
+        }
+    }
+}
+
+fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(12)]
+}
+
+/// Print a page hierarchy as an indented tree.
+///
+/// Roots are grouped under a header for their space.
+pub fn print_pretty_page_tree(roots: &[PageTreeNode]) {
+    if roots.is_empty() {
+        println!("(no pages)");
+        return;
+    }
+
+    let mut current_space: Option<&str> = None;
+    for root in roots {
+        if current_space != Some(root.space_slug.as_str()) {
+            if current_space.is_some() {
+                println!();
+            }
+            println!("{}", root.space_slug);
+            current_space = Some(root.space_slug.as_str());
+        }
+        print_tree_node(root, "");
+    }
+}
+
+fn print_tree_node(node: &PageTreeNode, indent: &str) {
+    println!("{}- {} [{}] {}", indent, node.title, node.page_type, node.id);
+    let child_indent = format!("{}  ", indent);
+    for child in &node.children {
+        print_tree_node(child, &child_indent);
+    }
+}
+
 /// Print search results with excerpts.
 ///
 /// Each result shows the page's key metadata and the FTS5 excerpt showing
@@ -173,48 +451,275 @@ pub fn print_pretty_search_results(results: &[SearchResult]) {
         if i > 0 {
             println!();
         }
+        print_pretty_search_result(result);
+    }
+}
 
-        println!("Title:  {}", result.page.title);
-        println!("ID:     {}", result.page.id);
-        println!("Type:   {}", result.page.page_type);
-        println!("Space:  {}", result.page.space_id);
+fn print_pretty_search_result(result: &SearchResult) {
+    println!("Title:  {}", result.page.title);
+    println!("ID:     {}", result.page.id);
+    println!("Type:   {}", result.page.page_type);
+    println!("Space:  {}", result.page.space_id);
 
-        if !result.page.labels.is_empty() {
-            println!("Labels: {}", result.page.labels.join(", "));
-        }
+    if !result.page.labels.is_empty() {
+        println!("Labels: {}", result.page.labels.join(", "));
+    }
 
-        if !result.excerpt.is_empty() {
-            println!("Match:  {}", result.excerpt);
+    if !result.excerpt.is_empty() {
+        println!("Match:  {}", result.excerpt);
+    } else {
+        // Fallback to showing content prefix if no excerpt
+        let preview = if result.page.content.len() > 200 {
+            format!("{}...", &result.page.content[..200])
         } else {
-            // Fallback to showing content prefix if no excerpt
-            let preview = if result.page.content.len() > 200 {
-                format!("{}...", &result.page.content[..200])
-            } else {
-                result.page.content.clone()
-            };
-            println!("Preview: {}", preview);
+            result.page.content.clone()
+        };
+        println!("Preview: {}", preview);
+    }
+}
+
+/// Print recall results as search results, each with its score and the
+/// terms that contributed to it.
+///
+/// Format:
+/// ```text
+/// Score:  1.412 (relevance 1.000, recency 0.412)
+/// Title:  ...
+/// ```
+pub fn print_pretty_recall_results(results: &[RecallResult]) {
+    if results.is_empty() {
+        println!("(no results)");
+        return;
+    }
+
+    for (i, recalled) in results.iter().enumerate() {
+        if i > 0 {
+            println!();
         }
+        let b = &recalled.breakdown;
+        let terms: Vec<String> = [
+            ("relevance", b.relevance),
+            ("recency", b.recency),
+            ("usage", b.usage),
+            ("pitfall", b.pitfall),
+            ("accepted", b.accepted),
+            ("superseded", b.superseded),
+            ("feedback", b.feedback),
+        ]
+        .iter()
+        .filter(|(_, value)| *value != 0.0)
+        .map(|(name, value)| format!("{} {:.3}", name, value))
+        .collect();
+        println!("Score:  {:.3} ({})", recalled.score, terms.join(", "));
+        print_pretty_search_result(&recalled.result);
     }
 }
 
-/// Print a single link in human-readable format.
+/// Print a single expanded link, naming both pages by title.
+///
+/// Format:
+/// ```text
+/// Chose Postgres [decision] (proj) --[supersedes]--> Chose MySQL [decision] (proj)
+///   IDs:  <source_id> -> <target_id>
+///   Note: <note>
+///   By:   <user> / <agent> (<created_at>)
+/// ```
+pub fn print_pretty_expanded_link(link: &ExpandedLink) {
+    println!(
+        "{} --[{}]--> {}",
+        format_page_ref(&link.source),
+        link.link.relation,
+        format_page_ref(&link.target)
+    );
+    println!("  IDs:  {} -> {}", link.link.source_id, link.link.target_id);
+    if !link.link.note.is_empty() {
+        println!("  Note: {}", link.link.note);
+    }
+    println!(
+        "  By:   {} / {} ({})",
+        link.link.created_by_user, link.link.created_by_agent, link.link.created_at
+    );
+}
+
+/// Print a recorded feedback verdict.
+pub fn print_pretty_feedback(feedback: &Feedback) {
+    let verdict = if feedback.useful { "useful" } else { "not useful" };
+    println!("Recorded: {} {} for \"{}\"", feedback.page_id, verdict, feedback.query);
+}
+
+/// Print pages that are read often but never marked useful, one line each.
 ///
-/// Format: `<source_id> --[<relation>]--> <target_id> (<created_at>)`
-pub fn print_pretty_link(link: &Link) {
-    println!("{} --[{}]--> {} ({})", link.source_id, link.relation, link.target_id, link.created_at);
+/// Format:
+/// ```text
+/// <page_id>  <title> [<type>] (<space>)  12 reads, 3 not useful
+/// ```
+pub fn print_pretty_feedback_report(entries: &[FeedbackReportEntry]) {
+    if entries.is_empty() {
+        println!("(no pages)");
+        return;
+    }
+    for entry in entries {
+        println!(
+            "{}  {} [{}] ({})  {} reads, {} not useful",
+            entry.page.id, entry.page.title, entry.page.page_type, entry.page.space_slug, entry.reads, entry.not_useful
+        );
+    }
 }
 
-/// Print a list of links.
+/// Print sessions, one line each.
 ///
-/// Each link is printed on one line showing the relationship graphically.
-pub fn print_pretty_links(links: &[Link]) {
+/// Format:
+/// ```text
+/// <session_id>  2024-01-15T10:30:00Z .. 2024-01-15T11:00:00Z  claude-code  2 created, 1 modified, 3 links
+/// ```
+pub fn print_pretty_sessions(sessions: &[SessionSummary]) {
+    if sessions.is_empty() {
+        println!("(no sessions)");
+        return;
+    }
+    for session in sessions {
+        println!(
+            "{}  {} .. {}  {}  {} created, {} modified, {} links",
+            session.session_id,
+            session.first_write_at,
+            session.last_write_at,
+            session.agents.join(", "),
+            session.pages_created,
+            session.pages_modified,
+            session.links_created
+        );
+    }
+}
+
+/// Print what a session created and modified, grouped by kind.
+pub fn print_pretty_session_activity(activity: &SessionActivity) {
+    println!("Session: {}", activity.session_id);
+    let groups = [("Created", &activity.pages_created), ("Modified", &activity.pages_modified)];
+    for (heading, pages) in groups {
+        if pages.is_empty() {
+            continue;
+        }
+        println!();
+        println!("{}:", heading);
+        for page in pages {
+            let versions: Vec<String> = page.versions.iter().map(|v| format!("v{}", v)).collect();
+            println!("  {}  {}  {}", page.page.id, format_page_ref(&page.page), versions.join(" "));
+        }
+    }
+    if !activity.links_created.is_empty() {
+        println!();
+        println!("Links:");
+        for link in &activity.links_created {
+            println!(
+                "  {} --[{}]--> {}",
+                format_page_ref(&link.source),
+                link.link.relation,
+                format_page_ref(&link.target)
+            );
+        }
+    }
+}
+
+/// Print a list of expanded links.
+pub fn print_pretty_expanded_links(links: &[ExpandedLink]) {
     if links.is_empty() {
         println!("(no links)");
         return;
     }
 
     for link in links {
-        print_pretty_link(link);
+        print_pretty_expanded_link(link);
+    }
+}
+
+/// Print the result of a link audit.
+///
+/// Format:
+/// ```text
+/// Checked 12 links: 2 violation(s)
+/// [cycle] <source_id> --[supersedes]--> <target_id>
+///   'supersedes' cycle: a -> b -> a
+/// ```
+pub fn print_pretty_link_check_report(report: &LinkCheckReport) {
+    if report.violations.is_empty() {
+        println!("Checked {} links: no violations", report.links_checked);
+        return;
+    }
+
+    println!(
+        "Checked {} links: {} violation(s)",
+        report.links_checked,
+        report.violations.len()
+    );
+    for v in &report.violations {
+        println!("[{}] {} --[{}]--> {}", v.kind.as_str(), v.source_id, v.relation, v.target_id);
+        println!("  {}", v.detail);
+    }
+}
+
+/// Print a database check report: one line per check, then any problems.
+pub fn print_pretty_db_check_report(report: &DbCheckReport) {
+    let status = |ok: bool| if ok { "ok" } else { "FAILED" };
+    match report.schema_version {
+        Some(v) if v == report.latest_schema_version => println!("Schema version:  {}", v),
+        Some(v) => println!("Schema version:  {} (latest is {})", v, report.latest_schema_version),
+        None => println!("Schema version:  unknown (schema_meta missing)"),
+    }
+    println!("Integrity:       {}", status(report.integrity_errors.is_empty()));
+    for e in &report.integrity_errors {
+        println!("  {}", e);
+    }
+    println!("Foreign keys:    {}", status(report.foreign_key_violations.is_empty()));
+    for v in &report.foreign_key_violations {
+        match v.rowid {
+            Some(rowid) => println!("  {} row {} references a missing {} row", v.table, rowid, v.parent),
+            None => println!("  {} references a missing {} row", v.table, v.parent),
+        }
+    }
+    println!("Search index:    {}", status(report.fts_error.is_none()));
+    if let Some(e) = &report.fts_error {
+        println!("  {}", e);
+    }
+}
+
+/// Print migration status, or with `dry_run`, what `db migrate` would apply.
+pub fn print_pretty_migration_report(report: &MigrationReport, dry_run: bool) {
+    println!(
+        "Schema version {} (latest {})",
+        report.schema_version, report.latest_schema_version
+    );
+    for m in &report.migrations {
+        let state = match m.state {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::Modified => "MODIFIED",
+            MigrationState::Unknown => "UNKNOWN",
+        };
+        let applied_at = m.applied_at.as_deref().unwrap_or("");
+        let line = format!("  {:>3} {:<28} {:<9} {}", m.version, m.name, state, applied_at);
+        println!("{}", line.trim_end());
+    }
+    for p in &report.problems {
+        println!("Problem: {}", p);
+    }
+
+    let pending: Vec<String> = report
+        .migrations
+        .iter()
+        .filter(|m| m.state == MigrationState::Pending)
+        .map(|m| m.name.clone())
+        .collect();
+    if dry_run {
+        if pending.is_empty() {
+            println!("Nothing to apply");
+        } else {
+            println!("Would apply: {}", pending.join(", "));
+        }
+    } else if !report.applied.is_empty() {
+        println!("Applied {} migration(s)", report.applied.len());
+    }
+    if let Some(backup) = &report.backup {
+        println!("Backup: {}", backup.display());
     }
 }
 
@@ -255,6 +760,7 @@ mod tests {
             slug: "test-project".to_string(),
             name: "Test Project".to_string(),
             description: "A test space for unit tests".to_string(),
+            allow_cross_space_links: true,
             created_at: "2024-01-15T10:00:00Z".to_string(),
             updated_at: "2024-01-15T10:00:00Z".to_string(),
         }
@@ -275,6 +781,7 @@ mod tests {
             updated_at: "2024-01-15T12:00:00Z".to_string(),
             version: 1,
             labels: vec!["test".to_string(), "decision".to_string()],
+            session_id: None,
         }
     }
 
@@ -316,8 +823,12 @@ mod tests {
             source_id: "660e8400-e29b-41d4-a716-446655440001".to_string(),
             target_id: "770e8400-e29b-41d4-a716-446655440002".to_string(),
             relation: LinkRelation::RelatesTo,
+            note: "Both cover auth".to_string(),
+            created_by_user: "testuser".to_string(),
+            created_by_agent: "test-agent".to_string(),
             created_at: "2024-01-15T13:00:00Z".to_string(),
             updated_at: "2024-01-15T13:00:00Z".to_string(),
+            session_id: None,
         }
     }
 
@@ -400,6 +911,9 @@ mod tests {
         assert_eq!(parsed["source_id"], "660e8400-e29b-41d4-a716-446655440001");
         assert_eq!(parsed["target_id"], "770e8400-e29b-41d4-a716-446655440002");
         assert_eq!(parsed["relation"], "relates-to");
+        assert_eq!(parsed["note"], "Both cover auth");
+        assert_eq!(parsed["created_by_user"], "testuser");
+        assert_eq!(parsed["created_by_agent"], "test-agent");
         assert_eq!(parsed["created_at"], "2024-01-15T13:00:00Z");
         assert_eq!(parsed["updated_at"], "2024-01-15T13:00:00Z");
     }
@@ -441,8 +955,12 @@ mod tests {
                 source_id: "770e8400-e29b-41d4-a716-446655440002".to_string(),
                 target_id: "880e8400-e29b-41d4-a716-446655440003".to_string(),
                 relation: LinkRelation::Supersedes,
+                note: String::new(),
+                created_by_user: "testuser".to_string(),
+                created_by_agent: "test-agent".to_string(),
                 created_at: "2024-01-15T13:00:00Z".to_string(),
                 updated_at: "2024-01-15T13:00:00Z".to_string(),
+                session_id: None,
             },
         ];
         let json = serde_json::to_string(&links).expect("should serialize");
@@ -491,6 +1009,7 @@ mod tests {
             (LinkRelation::Supersedes, "supersedes"),
             (LinkRelation::DependsOn, "depends-on"),
             (LinkRelation::Elaborates, "elaborates"),
+            (LinkRelation::Mentions, "mentions"),
         ];
 
         for (relation, expected_json_value) in relations {
@@ -629,4 +1148,56 @@ mod tests {
         // sections should be absent (not null) due to skip_serializing_if
         assert!(parsed.get("sections").is_none());
     }
+
+    #[test]
+    fn test_page_write_result_flattens_page() {
+        let result = PageWriteResult {
+            page: fixture_page(),
+            unresolved_links: vec![crate::models::UnresolvedLink {
+                reference: "Missing".to_string(),
+                reason: "No page titled 'Missing'".to_string(),
+            }],
+            duplicates: vec![],
+        };
+        let parsed: serde_json::Value = serde_json::to_value(&result).expect("should serialize");
+        assert_eq!(parsed["id"], "660e8400-e29b-41d4-a716-446655440001");
+        assert_eq!(parsed["unresolved_links"][0]["reference"], "Missing");
+
+        let clean = PageWriteResult { page: fixture_page(), unresolved_links: vec![], duplicates: vec![] };
+        let parsed: serde_json::Value = serde_json::to_value(&clean).expect("should serialize");
+        assert!(parsed.get("unresolved_links").is_none());
+        assert!(parsed.get("duplicates").is_none());
+    }
+
+    #[test]
+    fn test_format_page_ref() {
+        let page_ref = PageRef {
+            id: "id".to_string(),
+            title: "Chose Postgres".to_string(),
+            page_type: PageType::Decision,
+            space_slug: "proj".to_string(),
+        };
+        assert_eq!(format_page_ref(&page_ref), "Chose Postgres [decision] (proj)");
+    }
+
+    #[test]
+    fn test_expanded_link_json_flattens_link() {
+        let page_ref = |id: &str, title: &str| PageRef {
+            id: id.to_string(),
+            title: title.to_string(),
+            page_type: PageType::Reference,
+            space_slug: "test-project".to_string(),
+        };
+        let link = fixture_link();
+        let expanded = ExpandedLink {
+            source: page_ref(&link.source_id, "Source"),
+            target: page_ref(&link.target_id, "Target"),
+            link,
+        };
+        let parsed = serde_json::to_value(&expanded).expect("should serialize");
+        assert_eq!(parsed["relation"], "relates-to");
+        assert_eq!(parsed["target_id"], "770e8400-e29b-41d4-a716-446655440002");
+        assert_eq!(parsed["target"]["title"], "Target");
+        assert_eq!(parsed["source"]["space_slug"], "test-project");
+    }
 }
@@ -0,0 +1,610 @@
+//! Static HTML site generation.
+//!
+//! `publish_space` renders a space as a self-contained folder of HTML files
+//! that can be opened straight from disk or served by any static host, so
+//! people who don't use the CLI can read the knowledge base:
+//!
+//! ```text
+//! index.html            space overview: recent pages, types, labels
+//! pages/<id>.html       one per page, with links and backlinks
+//! types/<type>.html     pages of each type
+//! labels/<label>.html   pages with each label
+//! search-index.js       titles, labels and text for client-side search
+//! search.js, style.css
+//! ```
+//!
+//! Every file shows the page hierarchy as a sidebar and has a search box.
+//! The search index is a script rather than JSON so it also loads from
+//! `file://` URLs, where browsers block `fetch`.
+
+use crate::db::KbError;
+use crate::markdown::{slugify, unique_stem};
+use crate::models::{ExpandedLink, Page, PageRef, PageTreeNode, PageType, Space};
+use crate::{repo, wikilinks};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const STYLE_CSS: &str = r#"* { box-sizing: border-box; }
+body { margin: 0; font: 16px/1.55 -apple-system, BlinkMacSystemFont, "Segoe UI", Helvetica, Arial, sans-serif; color: #1f2328; }
+a { color: #0969da; text-decoration: none; }
+a:hover { text-decoration: underline; }
+header { display: flex; align-items: center; gap: 1rem; padding: .6rem 1.2rem; border-bottom: 1px solid #d0d7de; background: #f6f8fa; position: relative; }
+header .site { font-weight: 600; color: #1f2328; }
+#search { flex: 1; max-width: 28rem; padding: .35rem .6rem; border: 1px solid #d0d7de; border-radius: 6px; font: inherit; }
+#search-results { position: absolute; top: 100%; left: 1.2rem; z-index: 1; width: 32rem; max-width: 90vw; margin: 0; padding: 0; list-style: none; background: #fff; border: 1px solid #d0d7de; border-radius: 6px; box-shadow: 0 8px 24px rgba(0,0,0,.12); }
+#search-results:empty { display: none; }
+#search-results li { padding: .4rem .7rem; border-bottom: 1px solid #eaeef2; }
+.layout { display: flex; align-items: flex-start; }
+.sidebar { width: 17rem; flex-shrink: 0; padding: 1rem; border-right: 1px solid #d0d7de; font-size: 14px; position: sticky; top: 0; max-height: 100vh; overflow: auto; }
+.sidebar ul { list-style: none; margin: 0; padding-left: .9rem; }
+.sidebar > ul { padding-left: 0; }
+.sidebar .current { font-weight: 600; color: #1f2328; }
+main { flex: 1; min-width: 0; max-width: 52rem; padding: 1rem 2rem 3rem; }
+.breadcrumbs, .meta { color: #59636e; font-size: 14px; }
+.type, .label, .relation { display: inline-block; padding: 0 .45rem; margin-right: .3rem; border-radius: 1rem; font-size: 12px; background: #ddf4ff; }
+.label { background: #fff8c5; }
+.relation { background: #eaeef2; }
+.external { color: #59636e; }
+pre { padding: .8rem; overflow: auto; background: #f6f8fa; border-radius: 6px; }
+code { font-size: 90%; }
+table { border-collapse: collapse; }
+th, td { padding: .3rem .6rem; border: 1px solid #d0d7de; }
+blockquote { margin-left: 0; padding-left: 1rem; border-left: 3px solid #d0d7de; color: #59636e; }
+"#;
+
+const SEARCH_JS: &str = r#"(function () {
+  var input = document.getElementById("search");
+  var results = document.getElementById("search-results");
+  var root = document.body.getAttribute("data-root");
+  var index = window.SEARCH_INDEX || [];
+
+  // Every term must match somewhere; title matches count most.
+  function score(doc, terms) {
+    var total = 0;
+    for (var i = 0; i < terms.length; i++) {
+      var term = terms[i], s = 0;
+      if (doc.title.toLowerCase().indexOf(term) >= 0) s += 5;
+      if (doc.labels.join(" ").toLowerCase().indexOf(term) >= 0) s += 3;
+      if (doc.text.indexOf(term) >= 0) s += 1;
+      if (s === 0) return 0;
+      total += s;
+    }
+    return total;
+  }
+
+  input.addEventListener("input", function () {
+    var terms = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+    results.innerHTML = "";
+    if (!terms.length) return;
+    index
+      .map(function (doc) { return [score(doc, terms), doc]; })
+      .filter(function (r) { return r[0] > 0; })
+      .sort(function (a, b) { return b[0] - a[0]; })
+      .slice(0, 20)
+      .forEach(function (r) {
+        var li = document.createElement("li");
+        var a = document.createElement("a");
+        a.href = root + r[1].url;
+        a.textContent = r[1].title;
+        var type = document.createElement("span");
+        type.className = "type";
+        type.textContent = r[1].type;
+        li.appendChild(a);
+        li.appendChild(document.createTextNode(" "));
+        li.appendChild(type);
+        results.appendChild(li);
+      });
+  });
+})();
+"#;
+
+/// What `publish_space` wrote.
+#[derive(Debug, Serialize)]
+pub struct PublishedSite {
+    pub pages: usize,
+    pub files: Vec<PathBuf>,
+}
+
+/// One entry in `search-index.js`.
+#[derive(Serialize)]
+struct SearchEntry<'a> {
+    url: String,
+    title: &'a str,
+    #[serde(rename = "type")]
+    page_type: PageType,
+    labels: &'a [String],
+    /// Lowercased page text, so the browser only lowercases the query.
+    text: String,
+}
+
+/// Everything needed to render one space.
+struct Site {
+    space: Space,
+    pages: HashMap<String, Page>,
+    tree: Vec<PageTreeNode>,
+    /// File name (without `.html`) for each label's listing page.
+    label_files: BTreeMap<String, String>,
+}
+
+/// Renders a space as a static HTML site.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `space_id` - The space to publish
+/// * `dir` - Output folder, created if missing. Files from earlier runs are
+///   overwritten but not removed.
+///
+/// # Returns
+/// The number of pages published and every file written
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the space doesn't exist, or `KbError::Io`
+/// if a file can't be written.
+pub fn publish_space(conn: &Connection, space_id: &str, dir: &Path) -> Result<PublishedSite, KbError> {
+    let space = repo::get_space_by_id(conn, space_id)?;
+    let filters = repo::PageFilters {
+        space_id: Some(space.id.clone()),
+        page_type: None,
+        label: None,
+        created_by_user: None,
+        created_by_agent: None,
+    };
+    let pages: HashMap<String, Page> = repo::list_pages(conn, &filters)?
+        .into_iter()
+        .map(|p| (p.id.clone(), p))
+        .collect();
+
+    let mut labels: Vec<&String> = pages.values().flat_map(|p| &p.labels).collect();
+    labels.sort();
+    labels.dedup();
+    let mut used = HashSet::new();
+    let label_files = labels
+        .into_iter()
+        .map(|l| (l.clone(), unique_stem(&slugify(l), &mut used)))
+        .collect();
+
+    let site = Site {
+        tree: repo::page_tree(conn, Some(&space.id), None)?,
+        space,
+        pages,
+        label_files,
+    };
+
+    for sub in ["pages", "types", "labels"] {
+        fs::create_dir_all(dir.join(sub))?;
+    }
+    let mut files = Vec::new();
+    let mut write = |rel: String, text: String| -> Result<(), KbError> {
+        let path = dir.join(rel);
+        fs::write(&path, text)?;
+        files.push(path);
+        Ok(())
+    };
+
+    write("style.css".to_string(), STYLE_CSS.to_string())?;
+    write("search.js".to_string(), SEARCH_JS.to_string())?;
+    write("search-index.js".to_string(), search_index(&site))?;
+    write("index.html".to_string(), render_index(&site))?;
+
+    let mut sorted: Vec<&Page> = site.pages.values().collect();
+    sorted.sort_by_key(|p| p.title.to_lowercase());
+    for page in &sorted {
+        let links = repo::list_links_expanded(conn, &page.id)?;
+        let body = link_references(conn, page, &site)?;
+        write(format!("pages/{}.html", page.id), render_page(&site, page, &body, &links))?;
+    }
+
+    let mut types: Vec<PageType> = sorted.iter().map(|p| p.page_type).collect();
+    types.sort_by_key(|t| t.as_str());
+    types.dedup();
+    for page_type in types {
+        let listed: Vec<&Page> = sorted.iter().copied().filter(|p| p.page_type == page_type).collect();
+        let main = format!("<h1>{}</h1>\n{}", escape(page_type.as_str()), page_list("../", &listed));
+        write(
+            format!("types/{}.html", page_type.as_str()),
+            layout(&site, "../", page_type.as_str(), None, &main),
+        )?;
+    }
+
+    for (label, file) in &site.label_files {
+        let listed: Vec<&Page> = sorted.iter().copied().filter(|p| p.labels.contains(label)).collect();
+        let main = format!("<h1>Label: {}</h1>\n{}", escape(label), page_list("../", &listed));
+        write(format!("labels/{}.html", file), layout(&site, "../", label, None, &main))?;
+    }
+
+    files.sort();
+    Ok(PublishedSite { pages: site.pages.len(), files })
+}
+
+/// Escapes text for use in HTML content and attribute values.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Renders markdown to HTML. Raw HTML in the source is shown as text rather
+/// than passed through, and links and images to anything but web pages, mail
+/// addresses and relative paths lead nowhere, since page content comes from
+/// agents.
+fn render_markdown(text: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let parser = Parser::new_ext(text, options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image { link_type, dest_url, title, id }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, parser);
+    out
+}
+
+/// Returns `url` if it is relative or uses http, https or mailto, and `#`
+/// otherwise, so `javascript:` and `data:` URLs can't run in the published site.
+fn safe_url(url: CowStr<'_>) -> CowStr<'_> {
+    // Browsers ignore control characters and whitespace inside a scheme.
+    let cleaned: String = url.chars().filter(|c| !c.is_ascii_control() && !c.is_whitespace()).collect();
+    let scheme = match cleaned.find([':', '/', '?', '#']) {
+        Some(i) if cleaned[i..].starts_with(':') => &cleaned[..i],
+        _ => return url,
+    };
+    if ["http", "https", "mailto"].iter().any(|s| scheme.eq_ignore_ascii_case(s)) {
+        url
+    } else {
+        CowStr::Borrowed("#")
+    }
+}
+
+/// Turns `[[references]]` into markdown links to published pages. References
+/// to pages outside the space (or to nothing) become plain text.
+fn link_references(conn: &Connection, page: &Page, site: &Site) -> Result<String, KbError> {
+    let mut error = None;
+    let body = wikilinks::replace_references(&page.content, |target, suffix| {
+        let text = suffix.split_once('|').map(|(_, alias)| alias.trim()).unwrap_or(target);
+        let text = text.replace('[', "\\[").replace(']', "\\]");
+        match wikilinks::resolve_reference(conn, page, target) {
+            Ok(Ok(id)) if site.pages.contains_key(&id) => Some(format!("[{}](../pages/{}.html)", text, id)),
+            Ok(_) => Some(text),
+            Err(e) => {
+                error.get_or_insert(e);
+                None
+            }
+        }
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(body),
+    }
+}
+
+fn search_index(site: &Site) -> String {
+    let mut entries: Vec<SearchEntry> = site
+        .pages
+        .values()
+        .map(|p| SearchEntry {
+            url: format!("pages/{}.html", p.id),
+            title: &p.title,
+            page_type: p.page_type,
+            labels: &p.labels,
+            text: p.content.to_lowercase(),
+        })
+        .collect();
+    entries.sort_by(|a, b| a.url.cmp(&b.url));
+    format!(
+        "window.SEARCH_INDEX = {};\n",
+        serde_json::to_string(&entries).expect("failed to serialize search index")
+    )
+}
+
+/// Wraps a page's main content in the shared header, sidebar and scripts.
+/// `root` is the relative path back to the site root ("" or "../").
+fn layout(site: &Site, root: &str, title: &str, current: Option<&str>, main: &str) -> String {
+    let mut sidebar = String::new();
+    sidebar_tree(&mut sidebar, root, &site.tree, current);
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title} · {space}</title>
+<link rel="stylesheet" href="{root}style.css">
+</head>
+<body data-root="{root}">
+<header>
+<a class="site" href="{root}index.html">{space}</a>
+<input id="search" type="search" placeholder="Search pages…" autocomplete="off">
+<ul id="search-results"></ul>
+</header>
+<div class="layout">
+<nav class="sidebar">
+{sidebar}</nav>
+<main>
+{main}
+</main>
+</div>
+<script src="{root}search-index.js"></script>
+<script src="{root}search.js"></script>
+</body>
+</html>
+"#,
+        title = escape(title),
+        space = escape(&site.space.name),
+        root = root,
+        sidebar = sidebar,
+        main = main,
+    )
+}
+
+fn sidebar_tree(out: &mut String, root: &str, nodes: &[PageTreeNode], current: Option<&str>) {
+    if nodes.is_empty() {
+        return;
+    }
+    out.push_str("<ul>\n");
+    for node in nodes {
+        let class = if current == Some(node.id.as_str()) { " class=\"current\"" } else { "" };
+        out.push_str(&format!(
+            "<li><a{} href=\"{}pages/{}.html\">{}</a>",
+            class,
+            root,
+            node.id,
+            escape(&node.title)
+        ));
+        sidebar_tree(out, root, &node.children, current);
+        out.push_str("</li>\n");
+    }
+    out.push_str("</ul>\n");
+}
+
+fn type_badge(root: &str, page_type: PageType) -> String {
+    format!("<a class=\"type\" href=\"{}types/{}.html\">{}</a>", root, page_type.as_str(), page_type.as_str())
+}
+
+fn label_badges(site: &Site, root: &str, labels: &[String]) -> String {
+    labels
+        .iter()
+        .map(|l| format!("<a class=\"label\" href=\"{}labels/{}.html\">{}</a>", root, site.label_files[l], escape(l)))
+        .collect()
+}
+
+/// A list of pages with their type and last update, for listings.
+fn page_list(root: &str, pages: &[&Page]) -> String {
+    if pages.is_empty() {
+        return "<p>No pages.</p>\n".to_string();
+    }
+    let mut out = String::from("<ul>\n");
+    for page in pages {
+        out.push_str(&format!(
+            "<li><a href=\"{}pages/{}.html\">{}</a> <span class=\"type\">{}</span> <span class=\"meta\">{}</span></li>\n",
+            root,
+            page.id,
+            escape(&page.title),
+            page.page_type.as_str(),
+            date(&page.updated_at)
+        ));
+    }
+    out.push_str("</ul>\n");
+    out
+}
+
+/// The date part of an RFC 3339 timestamp.
+fn date(timestamp: &str) -> &str {
+    timestamp.get(..10).unwrap_or(timestamp)
+}
+
+/// A link to a page in the site, or its space and title if it's elsewhere.
+fn page_ref(site: &Site, page: &PageRef) -> String {
+    if site.pages.contains_key(&page.id) {
+        format!("<a href=\"../pages/{}.html\">{}</a>", page.id, escape(&page.title))
+    } else {
+        format!("<span class=\"external\">{}: {}</span>", escape(&page.space_slug), escape(&page.title))
+    }
+}
+
+fn render_index(site: &Site) -> String {
+    let mut main = format!("<h1>{}</h1>\n", escape(&site.space.name));
+    if !site.space.description.is_empty() {
+        main.push_str(&format!("<p>{}</p>\n", escape(&site.space.description)));
+    }
+    main.push_str(&format!("<p class=\"meta\">{} page(s)</p>\n", site.pages.len()));
+
+    let mut recent: Vec<&Page> = site.pages.values().collect();
+    recent.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then_with(|| a.title.cmp(&b.title)));
+    recent.truncate(10);
+    main.push_str("<h2>Recently updated</h2>\n");
+    main.push_str(&page_list("", &recent));
+
+    let mut type_counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for page in site.pages.values() {
+        *type_counts.entry(page.page_type.as_str()).or_default() += 1;
+    }
+    if !type_counts.is_empty() {
+        main.push_str("<h2>By type</h2>\n<ul>\n");
+        for (page_type, count) in type_counts {
+            main.push_str(&format!("<li><a href=\"types/{0}.html\">{0}</a> ({1})</li>\n", page_type, count));
+        }
+        main.push_str("</ul>\n");
+    }
+
+    if !site.label_files.is_empty() {
+        main.push_str("<h2>By label</h2>\n<ul>\n");
+        for (label, file) in &site.label_files {
+            let count = site.pages.values().filter(|p| p.labels.contains(label)).count();
+            main.push_str(&format!(
+                "<li><a href=\"labels/{}.html\">{}</a> ({})</li>\n",
+                file,
+                escape(label),
+                count
+            ));
+        }
+        main.push_str("</ul>\n");
+    }
+
+    layout(site, "", &site.space.name, None, &main)
+}
+
+fn render_page(site: &Site, page: &Page, body: &str, links: &[ExpandedLink]) -> String {
+    let mut main = String::new();
+
+    let mut ancestors = Vec::new();
+    let mut parent = page.parent_id.as_deref().and_then(|id| site.pages.get(id));
+    while let Some(p) = parent {
+        ancestors.push(format!("<a href=\"{}.html\">{}</a>", p.id, escape(&p.title)));
+        parent = p.parent_id.as_deref().and_then(|id| site.pages.get(id));
+    }
+    if !ancestors.is_empty() {
+        ancestors.reverse();
+        main.push_str(&format!("<p class=\"breadcrumbs\">{}</p>\n", ancestors.join(" / ")));
+    }
+
+    main.push_str(&format!("<h1>{}</h1>\n", escape(&page.title)));
+    main.push_str(&format!(
+        "<p class=\"meta\">{}{} Created by {} ({}) · updated {} · version {}</p>\n",
+        type_badge("../", page.page_type),
+        label_badges(site, "../", &page.labels),
+        escape(&page.created_by_user),
+        escape(&page.created_by_agent),
+        date(&page.updated_at),
+        page.version
+    ));
+    main.push_str("<article>\n");
+    main.push_str(&render_markdown(body));
+    main.push_str("</article>\n");
+
+    let outgoing: Vec<&ExpandedLink> = links.iter().filter(|l| l.link.source_id == page.id).collect();
+    if !outgoing.is_empty() {
+        main.push_str("<h2>Links</h2>\n<ul>\n");
+        for l in outgoing {
+            main.push_str(&format!("<li><span class=\"relation\">{}</span> {}", l.link.relation, page_ref(site, &l.target)));
+            if !l.link.note.is_empty() {
+                main.push_str(&format!(" — {}", escape(&l.link.note)));
+            }
+            main.push_str("</li>\n");
+        }
+        main.push_str("</ul>\n");
+    }
+
+    let incoming: Vec<&ExpandedLink> = links.iter().filter(|l| l.link.target_id == page.id).collect();
+    if !incoming.is_empty() {
+        main.push_str("<h2>Backlinks</h2>\n<ul>\n");
+        for l in incoming {
+            main.push_str(&format!("<li>{} <span class=\"relation\">{}</span>", page_ref(site, &l.source), l.link.relation));
+            if !l.link.note.is_empty() {
+                main.push_str(&format!(" — {}", escape(&l.link.note)));
+            }
+            main.push_str("</li>\n");
+        }
+        main.push_str("</ul>\n");
+    }
+
+    layout(site, "../", &page.title, Some(&page.id), &main)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::{AgentIdentity, LinkRelation};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("<a href=\"x\">Tom & Jerry's</a>"), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;");
+    }
+
+    #[test]
+    fn test_render_markdown_escapes_raw_html() {
+        let html = render_markdown("# Title\n\n**bold** <script>alert(1)</script>\n\n| a |\n|---|\n| b |\n");
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<strong>bold</strong>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<table>"));
+    }
+
+    #[test]
+    fn test_render_markdown_neutralises_unsafe_urls() {
+        let html = render_markdown(
+            "[a](javascript:alert(1)) [b](<JavaScript\t:alert(1)>) ![c](data:text/html,x) \
+             [d](https://example.com) [e](mailto:x@example.com) [f](../pages/1.html) [g](notes/a:b.md)",
+        );
+        assert!(!html.to_lowercase().contains("script:"), "{}", html);
+        assert!(!html.contains("data:"), "{}", html);
+        assert_eq!(html.matches("href=\"#\"").count(), 2, "{}", html);
+        assert!(html.contains("src=\"#\""));
+        assert!(html.contains("href=\"https://example.com\""));
+        assert!(html.contains("href=\"mailto:x@example.com\""));
+        assert!(html.contains("href=\"../pages/1.html\""));
+        assert!(html.contains("href=\"notes/a:b.md\""));
+    }
+
+    #[test]
+    fn test_publish_space() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "kb", "Team <KB>", "Our decisions").unwrap();
+        let other = repo::create_space(&conn, "other", "Other", "").unwrap();
+        let labels = vec!["status:accepted".to_string()];
+        let sections = serde_json::json!({"context": "We need storage", "decision": "See [[Notes|the notes]] and [[Nowhere]]"});
+        let decision = repo::create_page(&conn, &space.id, None, "Use SQLite", PageType::Decision, "", Some(&sections), &labels, "u", "a").unwrap();
+        let notes = repo::create_page(&conn, &space.id, Some(&decision.id), "Notes", PageType::Reference, "WAL mode", None, &[], "u", "a").unwrap();
+        let elsewhere = repo::create_page(&conn, &other.id, None, "Elsewhere", PageType::Reference, "", None, &[], "u", "a").unwrap();
+        let identity = AgentIdentity { user: "u".to_string(), agent: "a".to_string(), session: None };
+        wikilinks::sync_mentions(&conn, &decision.id, &identity).unwrap();
+        repo::create_link(&conn, &notes.id, &decision.id, LinkRelation::Elaborates, "details", "u", "a").unwrap();
+        repo::set_cross_space_links(&conn, "kb", true).unwrap();
+        repo::set_cross_space_links(&conn, "other", true).unwrap();
+        repo::create_link(&conn, &elsewhere.id, &decision.id, LinkRelation::RelatesTo, "", "u", "a").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let site = publish_space(&conn, &space.id, dir.path()).unwrap();
+        assert_eq!(site.pages, 2);
+        for file in ["index.html", "style.css", "search.js", "search-index.js", "types/decision.html", "types/reference.html", "labels/status-accepted.html"] {
+            assert!(dir.path().join(file).exists(), "missing {}", file);
+        }
+
+        let index = fs::read_to_string(dir.path().join("index.html")).unwrap();
+        assert!(index.contains("<h1>Team &lt;KB&gt;</h1>"));
+        assert!(index.contains("href=\"labels/status-accepted.html\">status:accepted</a> (1)"));
+
+        let page = fs::read_to_string(dir.path().join(format!("pages/{}.html", decision.id))).unwrap();
+        assert!(page.contains("<h2>Context</h2>"));
+        assert!(page.contains(&format!("<a href=\"../pages/{}.html\">the notes</a>", notes.id)));
+        assert!(page.contains("and Nowhere"));
+        assert!(page.contains(&format!("<a class=\"current\" href=\"../pages/{}.html\">Use SQLite</a>", decision.id)));
+        assert!(page.contains("<h2>Backlinks</h2>"));
+        assert!(page.contains("<span class=\"relation\">elaborates</span> — details"));
+        assert!(page.contains("<span class=\"external\">other: Elsewhere</span>"));
+
+        let child = fs::read_to_string(dir.path().join(format!("pages/{}.html", notes.id))).unwrap();
+        assert!(child.contains(&format!("<p class=\"breadcrumbs\"><a href=\"{}.html\">Use SQLite</a></p>", decision.id)));
+
+        let index_js = fs::read_to_string(dir.path().join("search-index.js")).unwrap();
+        assert!(index_js.starts_with("window.SEARCH_INDEX = ["));
+        assert!(index_js.contains("\"title\":\"Use SQLite\""));
+        assert!(index_js.contains("wal mode"));
+        assert!(!index_js.contains("Elsewhere"));
+    }
+}
@@ -7,9 +7,17 @@
 //! No traits, no generics, no repository pattern — just simple functions that
 //! map between Rust structs and SQLite tables.
 
-use crate::db::KbError;
-use crate::models::{sections_to_content, Link, LinkRelation, Page, PageType, Space};
-use rusqlite::Connection;
+use crate::db::{self, KbError};
+use crate::dedupe;
+use crate::models::{
+    sections_to_content, AgentIdentity, DuplicateMatch, ExpandedLink, ExpandedPage, Feedback, FeedbackReportEntry,
+    Link, LinkCheckReport, LinkRelation,
+    LinkViolation, LinkViolationKind, OnDuplicate, Page, PageRef, PageWriteResult, PageTreeNode, PageType, SessionActivity,
+    SessionPage, SessionSummary, Space,
+};
+use crate::wikilinks;
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::{HashMap, HashSet};
 
 /// Filters for listing pages with structured queries.
 pub struct PageFilters {
@@ -22,7 +30,7 @@ pub struct PageFilters {
 
 /// Map a rusqlite Row to a Page struct.
 /// Expects columns in order: id, space_id, parent_id, title, page_type, content,
-/// created_by_user, created_by_agent, created_at, updated_at, version, sections
+/// created_by_user, created_by_agent, created_at, updated_at, version, sections, session_id
 fn row_to_page(row: &rusqlite::Row) -> Result<Page, rusqlite::Error> {
     let page_type_str: String = row.get(4)?;
     let page_type = PageType::from_str(&page_type_str)
@@ -43,10 +51,46 @@ fn row_to_page(row: &rusqlite::Row) -> Result<Page, rusqlite::Error> {
         created_at: row.get(8)?,
         updated_at: row.get(9)?,
         version: row.get(10)?,
+        session_id: row.get(12)?,
         labels: vec![],
     })
 }
 
+/// Map a rusqlite Row to a Space struct.
+/// Expects columns in order: id, slug, name, description, created_at, updated_at,
+/// allow_cross_space_links
+fn row_to_space(row: &rusqlite::Row) -> Result<Space, rusqlite::Error> {
+    Ok(Space {
+        id: row.get(0)?,
+        slug: row.get(1)?,
+        name: row.get(2)?,
+        description: row.get(3)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+        allow_cross_space_links: row.get(6)?,
+    })
+}
+
+/// Map a rusqlite Row to a Link struct.
+/// Expects columns in order: source_id, target_id, relation, created_at, updated_at,
+/// note, created_by_user, created_by_agent, session_id
+fn row_to_link(row: &rusqlite::Row) -> Result<Link, rusqlite::Error> {
+    let relation_str: String = row.get(2)?;
+    let relation = LinkRelation::from_str(&relation_str)
+        .ok_or_else(|| rusqlite::Error::InvalidColumnType(2, "relation".to_string(), rusqlite::types::Type::Text))?;
+    Ok(Link {
+        source_id: row.get(0)?,
+        target_id: row.get(1)?,
+        relation,
+        note: row.get(5)?,
+        created_by_user: row.get(6)?,
+        created_by_agent: row.get(7)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+        session_id: row.get(8)?,
+    })
+}
+
 // =============================================================================
 // Spaces
 // =============================================================================
@@ -84,6 +128,7 @@ pub fn create_space(
         slug: slug.to_string(),
         name: name.to_string(),
         description: description.to_string(),
+        allow_cross_space_links: true,
         created_at: created_at.clone(),
         updated_at: created_at,
     })
@@ -102,20 +147,14 @@ pub fn create_space(
 /// Returns `KbError::NotFound` if no space with the given slug exists.
 pub fn get_space_by_slug(conn: &Connection, slug: &str) -> Result<Space, KbError> {
     let mut stmt = conn
-        .prepare("SELECT id, slug, name, description, created_at, updated_at FROM spaces WHERE slug = ?1")
+        .prepare(
+            "SELECT id, slug, name, description, created_at, updated_at, allow_cross_space_links
+             FROM spaces WHERE slug = ?1",
+        )
         .map_err(KbError::Db)?;
 
     let space = stmt
-        .query_row([slug], |row| {
-            Ok(Space {
-                id: row.get(0)?,
-                slug: row.get(1)?,
-                name: row.get(2)?,
-                description: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-            })
-        })
+        .query_row([slug], row_to_space)
         .map_err(|e| match e {
             rusqlite::Error::QueryReturnedNoRows => {
                 KbError::NotFound(format!("Space with slug '{}' not found", slug))
@@ -126,6 +165,32 @@ pub fn get_space_by_slug(conn: &Connection, slug: &str) -> Result<Space, KbError
     Ok(space)
 }
 
+/// Retrieves a space by its ID.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `id` - The space's unique ID
+///
+/// # Returns
+/// The matching space
+///
+/// # Errors
+/// Returns `KbError::NotFound` if no space with the given ID exists.
+pub fn get_space_by_id(conn: &Connection, id: &str) -> Result<Space, KbError> {
+    conn.query_row(
+        "SELECT id, slug, name, description, created_at, updated_at, allow_cross_space_links
+         FROM spaces WHERE id = ?1",
+        [id],
+        row_to_space,
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => {
+            KbError::NotFound(format!("Space with ID '{}' not found", id))
+        }
+        _ => KbError::Db(e),
+    })
+}
+
 /// Lists all spaces in the knowledge base.
 ///
 /// # Arguments
@@ -135,20 +200,14 @@ pub fn get_space_by_slug(conn: &Connection, slug: &str) -> Result<Space, KbError
 /// A vector of all spaces, ordered by creation date (newest first)
 pub fn list_spaces(conn: &Connection) -> Result<Vec<Space>, KbError> {
     let mut stmt = conn
-        .prepare("SELECT id, slug, name, description, created_at, updated_at FROM spaces ORDER BY created_at DESC")
+        .prepare(
+            "SELECT id, slug, name, description, created_at, updated_at, allow_cross_space_links
+             FROM spaces ORDER BY created_at DESC",
+        )
         .map_err(KbError::Db)?;
 
     let spaces = stmt
-        .query_map([], |row| {
-            Ok(Space {
-                id: row.get(0)?,
-                slug: row.get(1)?,
-                name: row.get(2)?,
-                description: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-            })
-        })
+        .query_map([], row_to_space)
         .map_err(KbError::Db)?
         .collect::<Result<Vec<_>, _>>()
         .map_err(KbError::Db)?;
@@ -178,6 +237,38 @@ pub fn delete_space(conn: &Connection, slug: &str) -> Result<(), KbError> {
     Ok(())
 }
 
+/// Sets whether pages in a space may take part in cross-space links.
+///
+/// Only affects links created afterwards; existing violations are reported
+/// by `check_links`.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `slug` - The space's unique slug identifier
+/// * `allow` - True to permit links to and from other spaces
+///
+/// # Returns
+/// The updated space
+///
+/// # Errors
+/// Returns `KbError::NotFound` if no space with the given slug exists.
+pub fn set_cross_space_links(conn: &Connection, slug: &str, allow: bool) -> Result<Space, KbError> {
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let rows_affected = conn
+        .execute(
+            "UPDATE spaces SET allow_cross_space_links = ?1, updated_at = ?2 WHERE slug = ?3",
+            rusqlite::params![allow, now, slug],
+        )
+        .map_err(KbError::Db)?;
+
+    if rows_affected == 0 {
+        return Err(KbError::NotFound(format!("Space with slug '{}' not found", slug)));
+    }
+
+    get_space_by_slug(conn, slug)
+}
+
 // =============================================================================
 // Pages
 // =============================================================================
@@ -198,6 +289,10 @@ pub fn delete_space(conn: &Connection, slug: &str) -> Result<(), KbError> {
 ///
 /// # Returns
 /// The newly created page with labels populated
+///
+/// Near-duplicates aren't looked for, so bulk importers can write pages that
+/// resemble each other; other writes go through `create_page_checked`.
+#[allow(clippy::too_many_arguments)]
 pub fn create_page(
     conn: &Connection,
     space_id: &str,
@@ -244,12 +339,16 @@ pub fn create_page(
         }
     }
 
+    if let Some(parent) = parent_id {
+        validate_parent(conn, None, space_id, parent)?;
+    }
+
     let sections_json: Option<String> = sections.map(|s| serde_json::to_string(s).unwrap());
 
     // Wrap page INSERT + label INSERTs in a transaction
-    let tx = conn.unchecked_transaction()?;
+    let tx = db::begin_unless_nested(conn)?;
 
-    tx.execute(
+    conn.execute(
         "INSERT INTO pages (id, space_id, parent_id, title, page_type, content, sections, created_by_user, created_by_agent, created_at, updated_at, version)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, 1)",
         rusqlite::params![
@@ -271,7 +370,7 @@ pub fn create_page(
     // Insert labels within the same transaction
     if !labels.is_empty() {
         for label in labels {
-            tx.execute(
+            conn.execute(
                 "INSERT INTO labels (page_id, label) VALUES (?1, ?2)",
                 rusqlite::params![id, label],
             )
@@ -279,7 +378,9 @@ pub fn create_page(
         }
     }
 
-    tx.commit()?;
+    if let Some(tx) = tx {
+        tx.commit()?;
+    }
 
     Ok(Page {
         id,
@@ -294,10 +395,106 @@ pub fn create_page(
         created_at: now.clone(),
         updated_at: now,
         version: 1,
+        session_id: None,
         labels: labels.to_vec(),
     })
 }
 
+/// Creates a page like `create_page`, after looking for pages in the space
+/// at least `threshold` similar to it. If there are any, `on_duplicate`
+/// decides: `Refuse` fails, `Append` adds the content and labels to the most
+/// similar page instead, `Link` creates the page with a `relates-to` link to
+/// the most similar page, and `Create` just creates it. The write, its
+/// session record and its `mentions` links go in a single transaction.
+///
+/// # Returns
+/// The page written with its unresolved wiki links, and the similar pages,
+/// most similar first
+///
+/// # Errors
+/// Returns `KbError::Duplicate` listing the similar pages under `Refuse`,
+/// or the errors of `create_page`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_page_checked(
+    conn: &Connection,
+    space_id: &str,
+    parent_id: Option<&str>,
+    title: &str,
+    page_type: PageType,
+    content: &str,
+    sections: Option<&serde_json::Value>,
+    labels: &[String],
+    identity: &AgentIdentity,
+    on_duplicate: OnDuplicate,
+    threshold: f64,
+) -> Result<PageWriteResult, KbError> {
+    let full_content = match sections {
+        Some(secs) if content.is_empty() => sections_to_content(secs, page_type),
+        _ => content.to_string(),
+    };
+    let duplicates = dedupe::find_similar(conn, space_id, title, &full_content, threshold)?;
+
+    let tx = db::begin_unless_nested(conn)?;
+    let page_id = match (duplicates.first(), on_duplicate) {
+        (Some(_), OnDuplicate::Refuse) => {
+            return Err(KbError::Duplicate {
+                matches: duplicates
+                    .iter()
+                    .map(|d| format!("{} ({}, similarity {})", d.page.id, d.page.title, d.similarity))
+                    .collect(),
+            });
+        }
+        (Some(closest), OnDuplicate::Append) => {
+            append_to_page(conn, &closest.page.id, &full_content)?;
+            for label in labels {
+                add_label(conn, &closest.page.id, label)?;
+            }
+            closest.page.id.clone()
+        }
+        (closest, _) => {
+            let page = create_page(
+                conn, space_id, parent_id, title, page_type, content, sections, labels, &identity.user, &identity.agent,
+            )?;
+            if let (Some(closest), OnDuplicate::Link) = (closest, on_duplicate) {
+                let note = format!("Near-duplicate (similarity {})", closest.similarity);
+                create_link(
+                    conn,
+                    &page.id,
+                    &closest.page.id,
+                    LinkRelation::RelatesTo,
+                    &note,
+                    &identity.user,
+                    &identity.agent,
+                )?;
+                record_link_session(conn, &page.id, &closest.page.id, identity)?;
+            }
+            page.id
+        }
+    };
+    let result = finish_page_write(conn, &page_id, identity, duplicates)?;
+    if let Some(tx) = tx {
+        tx.commit()?;
+    }
+    Ok(result)
+}
+
+/// Follows up a page write: records it against `identity`'s session and
+/// syncs the page's `mentions` links. Run it in the write's transaction.
+///
+/// # Returns
+/// The page as written, its unresolved wiki links and `duplicates`
+pub fn finish_page_write(
+    conn: &Connection,
+    page_id: &str,
+    identity: &AgentIdentity,
+    duplicates: Vec<DuplicateMatch>,
+) -> Result<PageWriteResult, KbError> {
+    record_page_session(conn, page_id, identity)?;
+    let page = get_page(conn, page_id)?;
+    let unresolved_links = wikilinks::sync_mentions(conn, page_id, identity)?;
+    Ok(PageWriteResult { page, unresolved_links, duplicates })
+}
+
 /// Retrieves a page by its ID, with labels populated.
 ///
 /// # Arguments
@@ -313,13 +510,13 @@ pub fn get_page(conn: &Connection, id: &str) -> Result<Page, KbError> {
     let mut stmt = conn
         .prepare(
             "SELECT id, space_id, parent_id, title, page_type, content,
-                    created_by_user, created_by_agent, created_at, updated_at, version, sections
+                    created_by_user, created_by_agent, created_at, updated_at, version, sections, session_id
              FROM pages WHERE id = ?1",
         )
         .map_err(KbError::Db)?;
 
     let page = stmt
-        .query_row([id], |row| row_to_page(row))
+        .query_row([id], row_to_page)
         .map_err(|e| match e {
             rusqlite::Error::QueryReturnedNoRows => {
                 KbError::NotFound(format!("Page with ID '{}' not found", id))
@@ -476,7 +673,8 @@ pub fn append_to_page(conn: &Connection, id: &str, content_to_append: &str) -> R
 pub fn list_pages(conn: &Connection, filters: &PageFilters) -> Result<Vec<Page>, KbError> {
     let mut sql = String::from(
         "SELECT DISTINCT p.id, p.space_id, p.parent_id, p.title, p.page_type, p.content,
-                p.created_by_user, p.created_by_agent, p.created_at, p.updated_at, p.version, p.sections
+                p.created_by_user, p.created_by_agent, p.created_at, p.updated_at, p.version, p.sections,
+                p.session_id
          FROM pages p",
     );
 
@@ -526,7 +724,7 @@ pub fn list_pages(conn: &Connection, filters: &PageFilters) -> Result<Vec<Page>,
     let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
 
     let pages = stmt
-        .query_map(&param_refs[..], |row| row_to_page(row))
+        .query_map(&param_refs[..], row_to_page)
         .map_err(KbError::Db)?
         .collect::<Result<Vec<_>, _>>()
         .map_err(KbError::Db)?;
@@ -551,8 +749,20 @@ pub fn list_pages(conn: &Connection, filters: &PageFilters) -> Result<Vec<Page>,
 /// Unit on success
 ///
 /// # Errors
+/// Returns `KbError::InvalidInput` if the page has child pages; use
+/// `delete_page_recursive` to remove a whole subtree.
 /// Returns `KbError::Db` if the deletion fails.
 pub fn delete_page(conn: &Connection, id: &str) -> Result<(), KbError> {
+    let child_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM pages WHERE parent_id = ?1", [id], |row| row.get(0))
+        .map_err(KbError::Db)?;
+    if child_count > 0 {
+        return Err(KbError::InvalidInput(format!(
+            "Page '{}' has {} child page(s); delete them first, reparent them, or delete recursively",
+            id, child_count
+        )));
+    }
+
     let rows_affected = conn
         .execute("DELETE FROM pages WHERE id = ?1", [id])
         .map_err(KbError::Db)?;
@@ -582,22 +792,24 @@ pub fn delete_page(conn: &Connection, id: &str) -> Result<(), KbError> {
 /// Returns `KbError::Db` if the operation fails.
 pub fn set_labels(conn: &Connection, page_id: &str, labels: &[String]) -> Result<(), KbError> {
     // Wrap DELETE + INSERTs in a transaction
-    let tx = conn.unchecked_transaction()?;
+    let tx = db::begin_unless_nested(conn)?;
 
     // Delete existing labels
-    tx.execute("DELETE FROM labels WHERE page_id = ?1", [page_id])
+    conn.execute("DELETE FROM labels WHERE page_id = ?1", [page_id])
         .map_err(KbError::Db)?;
 
     // Insert new labels
     for label in labels {
-        tx.execute(
+        conn.execute(
             "INSERT INTO labels (page_id, label) VALUES (?1, ?2)",
             rusqlite::params![page_id, label],
         )
         .map_err(KbError::Db)?;
     }
 
-    tx.commit()?;
+    if let Some(tx) = tx {
+        tx.commit()?;
+    }
 
     Ok(())
 }
@@ -651,40 +863,207 @@ pub fn get_labels(conn: &Connection, page_id: &str) -> Result<Vec<String>, KbErr
 
 /// Creates a typed relationship between two pages.
 ///
+/// The link is checked with `validate_link` before it is inserted.
+///
 /// # Arguments
 /// * `conn` - Database connection
 /// * `source_id` - ID of the source page
 /// * `target_id` - ID of the target page
 /// * `relation` - Type of relationship
+/// * `note` - Why the pages are related (may be empty)
+/// * `user` - User who created this link
+/// * `agent` - Agent tool that created this link
 ///
 /// # Returns
 /// The newly created link
 ///
 /// # Errors
-/// Returns `KbError::Db` if the link already exists or references nonexistent pages.
+/// Returns `KbError::NotFound` if either page does not exist.
+/// Returns `KbError::InvalidInput` for self-links, forbidden cross-space links,
+/// or if a link between the two pages already exists.
+/// Returns `KbError::LinkCycle` if the link would close a cycle in an acyclic relation.
 pub fn create_link(
     conn: &Connection,
     source_id: &str,
     target_id: &str,
     relation: LinkRelation,
+    note: &str,
+    user: &str,
+    agent: &str,
 ) -> Result<Link, KbError> {
+    validate_link(conn, source_id, target_id, relation)?;
+
     let now = chrono::Utc::now().to_rfc3339();
 
+    // An explicit link replaces an automatic `mentions` link between the same pages.
     conn.execute(
-        "INSERT INTO links (source_id, target_id, relation, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-        rusqlite::params![source_id, target_id, relation.as_str(), now, now],
+        "DELETE FROM links WHERE source_id = ?1 AND target_id = ?2 AND relation = 'mentions'",
+        rusqlite::params![source_id, target_id],
     )
     .map_err(KbError::Db)?;
 
+    conn.execute(
+        "INSERT INTO links (source_id, target_id, relation, note, created_by_user, created_by_agent, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        rusqlite::params![source_id, target_id, relation.as_str(), note, user, agent, now, now],
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(ref err, _)
+            if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            KbError::InvalidInput(format!(
+                "Link from '{}' to '{}' already exists",
+                source_id, target_id
+            ))
+        }
+        _ => KbError::Db(e),
+    })?;
+
     Ok(Link {
         source_id: source_id.to_string(),
         target_id: target_id.to_string(),
         relation,
+        note: note.to_string(),
+        created_by_user: user.to_string(),
+        created_by_agent: agent.to_string(),
         created_at: now.clone(),
         updated_at: now,
+        session_id: None,
+    })
+}
+
+/// Space membership and link policy for a single page.
+struct PageSpace {
+    space_id: String,
+    slug: String,
+    allow_cross_space_links: bool,
+}
+
+/// Looks up the space a page belongs to, along with that space's link policy.
+fn get_page_space(conn: &Connection, page_id: &str) -> Result<PageSpace, KbError> {
+    conn.query_row(
+        "SELECT s.id, s.slug, s.allow_cross_space_links
+         FROM pages p JOIN spaces s ON s.id = p.space_id
+         WHERE p.id = ?1",
+        [page_id],
+        |row| {
+            Ok(PageSpace {
+                space_id: row.get(0)?,
+                slug: row.get(1)?,
+                allow_cross_space_links: row.get(2)?,
+            })
+        },
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => {
+            KbError::NotFound(format!("Page with ID '{}' not found", page_id))
+        }
+        _ => KbError::Db(e),
     })
 }
 
+/// Checks a prospective link against the link integrity rules.
+///
+/// Rules, in the order they are checked:
+/// 1. A page cannot link to itself.
+/// 2. Both pages must exist.
+/// 3. If the pages are in different spaces, both spaces must allow cross-space links.
+/// 4. For acyclic relations (`supersedes`, `depends-on`), the link must not
+///    close a cycle with existing links of the same relation.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput`, `KbError::NotFound` or `KbError::LinkCycle`
+/// describing the first rule that is broken.
+pub fn validate_link(
+    conn: &Connection,
+    source_id: &str,
+    target_id: &str,
+    relation: LinkRelation,
+) -> Result<(), KbError> {
+    if source_id == target_id {
+        return Err(KbError::InvalidInput(format!(
+            "A page cannot link to itself ('{}')",
+            source_id
+        )));
+    }
+
+    let source_space = get_page_space(conn, source_id)?;
+    let target_space = get_page_space(conn, target_id)?;
+
+    if source_space.space_id != target_space.space_id {
+        for space in [&source_space, &target_space] {
+            if !space.allow_cross_space_links {
+                return Err(KbError::InvalidInput(format!(
+                    "Space '{}' does not allow cross-space links ('{}' is in '{}', '{}' is in '{}')",
+                    space.slug, source_id, source_space.slug, target_id, target_space.slug
+                )));
+            }
+        }
+    }
+
+    if relation.is_acyclic() {
+        // Adding source -> target closes a cycle iff target already reaches source.
+        if let Some(path) = find_link_path(conn, target_id, source_id, relation)? {
+            let mut cycle = vec![source_id.to_string()];
+            cycle.extend(path);
+            return Err(KbError::LinkCycle {
+                relation: relation.as_str().to_string(),
+                path: cycle,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds a path of `relation` links from `from` to `to` using breadth-first search.
+///
+/// # Returns
+/// The page IDs along the shortest path, including both endpoints, or None if
+/// `to` is unreachable.
+fn find_link_path(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+    relation: LinkRelation,
+) -> Result<Option<Vec<String>>, KbError> {
+    let mut stmt = conn
+        .prepare("SELECT target_id FROM links WHERE source_id = ?1 AND relation = ?2")
+        .map_err(KbError::Db)?;
+
+    let mut came_from: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut queue = std::collections::VecDeque::from([from.to_string()]);
+    came_from.insert(from.to_string(), String::new());
+
+    while let Some(current) = queue.pop_front() {
+        if current == to {
+            let mut path = vec![current.clone()];
+            let mut node = current;
+            while let Some(prev) = came_from.get(&node).filter(|p| !p.is_empty()) {
+                path.push(prev.clone());
+                node = prev.clone();
+            }
+            path.reverse();
+            return Ok(Some(path));
+        }
+
+        let next: Vec<String> = stmt
+            .query_map(rusqlite::params![current, relation.as_str()], |row| row.get(0))
+            .map_err(KbError::Db)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(KbError::Db)?;
+
+        for target in next {
+            if !came_from.contains_key(&target) {
+                came_from.insert(target.clone(), current.clone());
+                queue.push_back(target);
+            }
+        }
+    }
+
+    Ok(None)
+}
+
 /// Lists all links where the given page is either the source or target.
 ///
 /// # Arguments
@@ -696,25 +1075,98 @@ pub fn create_link(
 pub fn list_links(conn: &Connection, page_id: &str) -> Result<Vec<Link>, KbError> {
     let mut stmt = conn
         .prepare(
-            "SELECT source_id, target_id, relation, created_at, updated_at
+            "SELECT source_id, target_id, relation, created_at, updated_at,
+                    note, created_by_user, created_by_agent, session_id
              FROM links
              WHERE source_id = ?1 OR target_id = ?1",
         )
         .map_err(KbError::Db)?;
 
+    let links = stmt
+        .query_map([page_id], row_to_link)
+        .map_err(KbError::Db)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(KbError::Db)?;
+
+    Ok(links)
+}
+
+/// Lists links pointing at the given page (of any relation, including
+/// automatic `mentions`), newest first.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `page_id` - The page's unique ID
+///
+/// # Returns
+/// A vector of incoming links
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the page doesn't exist.
+pub fn list_backlinks(conn: &Connection, page_id: &str) -> Result<Vec<Link>, KbError> {
+    get_page_space(conn, page_id)?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, target_id, relation, created_at, updated_at,
+                    note, created_by_user, created_by_agent, session_id
+             FROM links
+             WHERE target_id = ?1
+             ORDER BY created_at DESC",
+        )
+        .map_err(KbError::Db)?;
+
+    let links = stmt
+        .query_map([page_id], row_to_link)
+        .map_err(KbError::Db)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(KbError::Db)?;
+
+    Ok(links)
+}
+
+/// Runs a links query joined to both pages and their spaces.
+/// `condition` is a WHERE clause over the `l` (links) alias using `?1`.
+fn query_expanded_links(
+    conn: &Connection,
+    condition: &str,
+    page_id: &str,
+) -> Result<Vec<ExpandedLink>, KbError> {
+    let sql = format!(
+        "SELECT l.source_id, l.target_id, l.relation, l.created_at, l.updated_at,
+                l.note, l.created_by_user, l.created_by_agent, l.session_id,
+                sp.title, sp.page_type, ss.slug,
+                tp.title, tp.page_type, ts.slug
+         FROM links l
+         JOIN pages sp ON sp.id = l.source_id
+         JOIN spaces ss ON ss.id = sp.space_id
+         JOIN pages tp ON tp.id = l.target_id
+         JOIN spaces ts ON ts.id = tp.space_id
+         WHERE {}
+         ORDER BY l.created_at DESC",
+        condition
+    );
+    let mut stmt = conn.prepare(&sql).map_err(KbError::Db)?;
+
+    let page_ref = |row: &rusqlite::Row, id: String, offset: usize| -> Result<PageRef, rusqlite::Error> {
+        let type_str: String = row.get(offset + 1)?;
+        let page_type = PageType::from_str(&type_str).ok_or_else(|| {
+            rusqlite::Error::InvalidColumnType(offset + 1, "page_type".to_string(), rusqlite::types::Type::Text)
+        })?;
+        Ok(PageRef {
+            id,
+            title: row.get(offset)?,
+            page_type,
+            space_slug: row.get(offset + 2)?,
+        })
+    };
+
     let links = stmt
         .query_map([page_id], |row| {
-            let relation_str: String = row.get(2)?;
-            let relation = LinkRelation::from_str(&relation_str)
-                .ok_or_else(|| rusqlite::Error::InvalidColumnType(2, "relation".to_string(), rusqlite::types::Type::Text))?;
-
-            Ok(Link {
-                source_id: row.get(0)?,
-                target_id: row.get(1)?,
-                relation,
-                created_at: row.get(3)?,
-                updated_at: row.get(4)?,
-            })
+            let link = row_to_link(row)?;
+            let source = page_ref(row, link.source_id.clone(), 9)?;
+            let target = page_ref(row, link.target_id.clone(), 12)?;
+            Ok(ExpandedLink { link, source, target })
         })
         .map_err(KbError::Db)?
         .collect::<Result<Vec<_>, _>>()
@@ -723,6 +1175,237 @@ pub fn list_links(conn: &Connection, page_id: &str) -> Result<Vec<Link>, KbError
     Ok(links)
 }
 
+/// Like `list_links`, but with the title, type and space slug of both pages resolved.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `page_id` - The page's unique ID
+///
+/// # Returns
+/// A vector of expanded links involving the page, newest first
+pub fn list_links_expanded(conn: &Connection, page_id: &str) -> Result<Vec<ExpandedLink>, KbError> {
+    query_expanded_links(conn, "l.source_id = ?1 OR l.target_id = ?1", page_id)
+}
+
+/// Like `list_backlinks`, but with the title, type and space slug of both pages resolved.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the page doesn't exist.
+pub fn list_backlinks_expanded(conn: &Connection, page_id: &str) -> Result<Vec<ExpandedLink>, KbError> {
+    get_page_space(conn, page_id)?;
+    query_expanded_links(conn, "l.target_id = ?1", page_id)
+}
+
+/// Like `get_link`, but with the title, type and space slug of both pages resolved.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if no such link exists.
+pub fn get_link_expanded(conn: &Connection, source_id: &str, target_id: &str) -> Result<ExpandedLink, KbError> {
+    get_link(conn, source_id, target_id)?;
+    query_expanded_links(conn, "l.source_id = ?1", source_id)?
+        .into_iter()
+        .find(|l| l.link.target_id == target_id)
+        .ok_or_else(|| KbError::NotFound(format!("Link from '{}' to '{}' not found", source_id, target_id)))
+}
+
+/// Retrieves a page with its space slug and expanded links.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if no page with the given ID exists.
+pub fn get_page_expanded(conn: &Connection, id: &str) -> Result<ExpandedPage, KbError> {
+    let page = get_page(conn, id)?;
+    let space_slug = get_space_by_id(conn, &page.space_id)?.slug;
+    let links = list_links_expanded(conn, id)?;
+    Ok(ExpandedPage { page, space_slug, links })
+}
+
+/// Lists every link in the knowledge base, ordered by source and target ID.
+pub fn list_all_links(conn: &Connection) -> Result<Vec<Link>, KbError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, target_id, relation, created_at, updated_at,
+                    note, created_by_user, created_by_agent, session_id
+             FROM links
+             ORDER BY source_id, target_id",
+        )
+        .map_err(KbError::Db)?;
+
+    let links = stmt
+        .query_map([], row_to_link)
+        .map_err(KbError::Db)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(KbError::Db)?;
+
+    Ok(links)
+}
+
+/// Retrieves the link from `source_id` to `target_id`.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if no such link exists.
+pub fn get_link(conn: &Connection, source_id: &str, target_id: &str) -> Result<Link, KbError> {
+    conn.query_row(
+        "SELECT source_id, target_id, relation, created_at, updated_at,
+                note, created_by_user, created_by_agent, session_id
+         FROM links
+         WHERE source_id = ?1 AND target_id = ?2",
+        rusqlite::params![source_id, target_id],
+        row_to_link,
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => KbError::NotFound(format!(
+            "Link from '{}' to '{}' not found",
+            source_id, target_id
+        )),
+        _ => KbError::Db(e),
+    })
+}
+
+/// Updates a link's relation and/or note.
+///
+/// A new relation is checked with `validate_link`, so changing a link to
+/// `supersedes` or `depends-on` cannot introduce a cycle.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `source_id` - ID of the source page
+/// * `target_id` - ID of the target page
+/// * `relation` - New relationship type (if Some)
+/// * `note` - New note (if Some)
+///
+/// # Returns
+/// The updated link with a new `updated_at` timestamp
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the link doesn't exist.
+/// Returns `KbError::LinkCycle` if the new relation would close a cycle.
+pub fn update_link(
+    conn: &Connection,
+    source_id: &str,
+    target_id: &str,
+    relation: Option<LinkRelation>,
+    note: Option<&str>,
+) -> Result<Link, KbError> {
+    let existing = get_link(conn, source_id, target_id)?;
+
+    if let Some(rel) = relation {
+        if rel != existing.relation {
+            validate_link(conn, source_id, target_id, rel)?;
+        }
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+
+    conn.execute(
+        "UPDATE links
+         SET relation = COALESCE(?1, relation),
+             note = COALESCE(?2, note),
+             updated_at = ?3
+         WHERE source_id = ?4 AND target_id = ?5",
+        rusqlite::params![relation.map(|r| r.as_str()), note, now, source_id, target_id],
+    )
+    .map_err(KbError::Db)?;
+
+    get_link(conn, source_id, target_id)
+}
+
+/// Audits every existing link against the rules enforced by `validate_link`.
+///
+/// Links created before the rules existed (or before a space's policy was
+/// tightened) may break them. Each cycle is reported once, attributed to the
+/// link that closes it when the cycle is walked from its smallest page ID.
+///
+/// # Returns
+/// A report with the number of links checked and every violation found
+pub fn check_links(conn: &Connection) -> Result<LinkCheckReport, KbError> {
+    let links = list_all_links(conn)?;
+    let mut violations = Vec::new();
+
+    let mut spaces: std::collections::HashMap<String, PageSpace> = std::collections::HashMap::new();
+    for link in &links {
+        for id in [&link.source_id, &link.target_id] {
+            if !spaces.contains_key(id) {
+                spaces.insert(id.clone(), get_page_space(conn, id)?);
+            }
+        }
+    }
+
+    for link in &links {
+        if link.source_id == link.target_id {
+            violations.push(LinkViolation {
+                kind: LinkViolationKind::SelfLink,
+                source_id: link.source_id.clone(),
+                target_id: link.target_id.clone(),
+                relation: link.relation,
+                path: vec![],
+                detail: "Page links to itself".to_string(),
+            });
+            continue;
+        }
+
+        let source_space = &spaces[&link.source_id];
+        let target_space = &spaces[&link.target_id];
+        if source_space.space_id != target_space.space_id
+            && (!source_space.allow_cross_space_links || !target_space.allow_cross_space_links)
+        {
+            let forbidding = if !source_space.allow_cross_space_links {
+                &source_space.slug
+            } else {
+                &target_space.slug
+            };
+            violations.push(LinkViolation {
+                kind: LinkViolationKind::CrossSpace,
+                source_id: link.source_id.clone(),
+                target_id: link.target_id.clone(),
+                relation: link.relation,
+                path: vec![],
+                detail: format!(
+                    "Links '{}' to '{}' but space '{}' does not allow cross-space links",
+                    source_space.slug, target_space.slug, forbidding
+                ),
+            });
+        }
+    }
+
+    let mut seen_cycles = std::collections::HashSet::new();
+    for link in links.iter().filter(|l| l.relation.is_acyclic() && l.source_id != l.target_id) {
+        let Some(path) = find_link_path(conn, &link.target_id, &link.source_id, link.relation)? else {
+            continue;
+        };
+        let mut cycle = vec![link.source_id.clone()];
+        cycle.extend(path);
+
+        // Rotate so the cycle starts at its smallest ID, giving one key per cycle.
+        let ring = &cycle[..cycle.len() - 1];
+        let start = ring
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, id)| id.as_str())
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        let mut canonical: Vec<String> = ring[start..].iter().chain(&ring[..start]).cloned().collect();
+        canonical.push(canonical[0].clone());
+
+        if seen_cycles.insert((link.relation.as_str(), canonical.join(" -> "))) {
+            let detail = format!("'{}' cycle: {}", link.relation, canonical.join(" -> "));
+            let closing = canonical.len() - 2;
+            violations.push(LinkViolation {
+                kind: LinkViolationKind::Cycle,
+                source_id: canonical[closing].clone(),
+                target_id: canonical[closing + 1].clone(),
+                relation: link.relation,
+                path: canonical,
+                detail,
+            });
+        }
+    }
+
+    Ok(LinkCheckReport {
+        links_checked: links.len(),
+        violations,
+    })
+}
+
 /// Deletes a link between two pages.
 ///
 /// # Arguments
@@ -743,14 +1426,456 @@ pub fn delete_link(conn: &Connection, source_id: &str, target_id: &str) -> Resul
         )
         .map_err(KbError::Db)?;
 
-    if rows_affected == 0 {
-        return Err(KbError::NotFound(format!(
-            "Link from '{}' to '{}' not found",
-            source_id, target_id
-        )));
+    if rows_affected == 0 {
+        return Err(KbError::NotFound(format!(
+            "Link from '{}' to '{}' not found",
+            source_id, target_id
+        )));
+    }
+
+    Ok(())
+}
+
+// =============================================================================
+// Raw record writes (import)
+// =============================================================================
+//
+// These write records exactly as given — IDs, timestamps, versions and
+// creators included — and skip the validation done by the normal create
+// functions. They don't open transactions, so the caller can wrap a whole
+// import in one.
+
+/// Inserts a space, or replaces every field of the space with the same ID.
+pub fn put_space(conn: &Connection, space: &Space) -> Result<(), KbError> {
+    conn.execute(
+        "INSERT INTO spaces (id, slug, name, description, created_at, updated_at, allow_cross_space_links)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(id) DO UPDATE SET
+             slug = excluded.slug,
+             name = excluded.name,
+             description = excluded.description,
+             created_at = excluded.created_at,
+             updated_at = excluded.updated_at,
+             allow_cross_space_links = excluded.allow_cross_space_links",
+        rusqlite::params![
+            space.id,
+            space.slug,
+            space.name,
+            space.description,
+            space.created_at,
+            space.updated_at,
+            space.allow_cross_space_links,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Inserts a page, or replaces every field of the page with the same ID.
+/// The page's labels replace any existing labels.
+///
+/// The parent page, if any, must already exist.
+pub fn put_page(conn: &Connection, page: &Page) -> Result<(), KbError> {
+    let sections_json: Option<String> = page.sections.as_ref().map(|s| s.to_string());
+    conn.execute(
+        "INSERT INTO pages (id, space_id, parent_id, title, page_type, content, sections,
+                            created_by_user, created_by_agent, created_at, updated_at, version, session_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+         ON CONFLICT(id) DO UPDATE SET
+             space_id = excluded.space_id,
+             parent_id = excluded.parent_id,
+             title = excluded.title,
+             page_type = excluded.page_type,
+             content = excluded.content,
+             sections = excluded.sections,
+             created_by_user = excluded.created_by_user,
+             created_by_agent = excluded.created_by_agent,
+             created_at = excluded.created_at,
+             updated_at = excluded.updated_at,
+             version = excluded.version,
+             session_id = excluded.session_id",
+        rusqlite::params![
+            page.id,
+            page.space_id,
+            page.parent_id,
+            page.title,
+            page.page_type.as_str(),
+            page.content,
+            sections_json,
+            page.created_by_user,
+            page.created_by_agent,
+            page.created_at,
+            page.updated_at,
+            page.version,
+            page.session_id,
+        ],
+    )?;
+
+    conn.execute("DELETE FROM labels WHERE page_id = ?1", [&page.id])?;
+    for label in &page.labels {
+        conn.execute(
+            "INSERT OR IGNORE INTO labels (page_id, label) VALUES (?1, ?2)",
+            rusqlite::params![page.id, label],
+        )?;
+    }
+    Ok(())
+}
+
+/// Inserts a link, or replaces every field of the link between the same pages.
+pub fn put_link(conn: &Connection, link: &Link) -> Result<(), KbError> {
+    conn.execute(
+        "INSERT INTO links (source_id, target_id, relation, note, created_by_user, created_by_agent,
+                            created_at, updated_at, session_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+         ON CONFLICT(source_id, target_id) DO UPDATE SET
+             relation = excluded.relation,
+             note = excluded.note,
+             created_by_user = excluded.created_by_user,
+             created_by_agent = excluded.created_by_agent,
+             created_at = excluded.created_at,
+             updated_at = excluded.updated_at,
+             session_id = excluded.session_id",
+        rusqlite::params![
+            link.source_id,
+            link.target_id,
+            link.relation.as_str(),
+            link.note,
+            link.created_by_user,
+            link.created_by_agent,
+            link.created_at,
+            link.updated_at,
+            link.session_id,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Deletes a page by ID without checking for child pages. Its labels and
+/// links go with it.
+pub fn remove_page(conn: &Connection, id: &str) -> Result<(), KbError> {
+    conn.execute("DELETE FROM pages WHERE id = ?1", [id])?;
+    Ok(())
+}
+
+/// Deletes a space by ID without checking for pages.
+pub fn remove_space(conn: &Connection, id: &str) -> Result<(), KbError> {
+    conn.execute("DELETE FROM spaces WHERE id = ?1", [id])?;
+    Ok(())
+}
+
+// =============================================================================
+// Import sources
+// =============================================================================
+
+/// Looks up the page previously imported from a source file.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `kind` - The importer that recorded the source (e.g. "adr")
+/// * `source_path` - The file the page was imported from
+///
+/// # Returns
+/// The page ID, or `None` if nothing was imported from that file (or the page
+/// has since been deleted)
+pub fn get_page_source(conn: &Connection, kind: &str, source_path: &str) -> Result<Option<String>, KbError> {
+    let page_id = conn
+        .query_row(
+            "SELECT page_id FROM page_sources WHERE kind = ?1 AND source_path = ?2",
+            [kind, source_path],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(page_id)
+}
+
+/// Records that a page was imported from a source file, replacing any earlier
+/// record for the same page or file.
+pub fn set_page_source(conn: &Connection, page_id: &str, kind: &str, source_path: &str) -> Result<(), KbError> {
+    let now = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "DELETE FROM page_sources WHERE kind = ?1 AND source_path = ?2",
+        [kind, source_path],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO page_sources (page_id, kind, source_path, imported_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        [page_id, kind, source_path, &now],
+    )?;
+    Ok(())
+}
+
+/// Looks up the versions a page had on each side after the last merge from
+/// `source`.
+///
+/// # Returns
+/// `(local_version, other_version)`, or `None` if the page hasn't been merged
+/// from `source` before
+pub fn get_merge_base(conn: &Connection, source: &str, page_id: &str) -> Result<Option<(i64, i64)>, KbError> {
+    let base = conn
+        .query_row(
+            "SELECT local_version, other_version FROM merge_bases WHERE source = ?1 AND page_id = ?2",
+            [source, page_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    Ok(base)
+}
+
+/// Records the versions a page has on each side after a merge from
+/// `source`, replacing any earlier record.
+pub fn set_merge_base(
+    conn: &Connection,
+    source: &str,
+    page_id: &str,
+    local_version: i64,
+    other_version: i64,
+) -> Result<(), KbError> {
+    let now = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT OR REPLACE INTO merge_bases (source, page_id, local_version, other_version, merged_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![source, page_id, local_version, other_version, now],
+    )?;
+    Ok(())
+}
+
+// =============================================================================
+// Page hierarchy
+// =============================================================================
+
+/// Checks that `parent_id` may be the parent of a page in `space_id`.
+///
+/// The parent must exist, live in the same space, and (when `page_id` is an
+/// existing page being moved) must not be the page itself or one of its
+/// descendants.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the parent page doesn't exist.
+/// Returns `KbError::InvalidInput` for a cross-space parent or a parent cycle.
+fn validate_parent(
+    conn: &Connection,
+    page_id: Option<&str>,
+    space_id: &str,
+    parent_id: &str,
+) -> Result<(), KbError> {
+    let parent = get_page(conn, parent_id)?;
+    if parent.space_id != space_id {
+        return Err(KbError::InvalidInput(format!(
+            "Parent page '{}' is in a different space",
+            parent_id
+        )));
+    }
+
+    let Some(page_id) = page_id else {
+        return Ok(());
+    };
+
+    // Walk up from the new parent. Reaching the page itself means the move
+    // would put the page underneath its own subtree.
+    let mut path = vec![page_id.to_string()];
+    let mut current = Some(parent_id.to_string());
+    let mut seen = HashSet::new();
+    while let Some(id) = current {
+        path.push(id.clone());
+        if id == page_id {
+            path.reverse();
+            return Err(KbError::InvalidInput(format!(
+                "Page cannot be its own ancestor: {}",
+                path.join(" -> ")
+            )));
+        }
+        if !seen.insert(id.clone()) {
+            break;
+        }
+        current = conn
+            .query_row("SELECT parent_id FROM pages WHERE id = ?1", [&id], |row| row.get(0))
+            .optional()
+            .map_err(KbError::Db)?
+            .flatten();
+    }
+
+    Ok(())
+}
+
+/// Moves a page under a new parent, or to the top level of its space.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `id` - The page to move
+/// * `parent_id` - The new parent page ID, or `None` to make it top-level
+///
+/// # Returns
+/// The updated page with incremented version and updated timestamp
+///
+/// # Errors
+/// Returns `KbError::NotFound` if either page doesn't exist.
+/// Returns `KbError::InvalidInput` if the parent is in another space, or is
+/// the page itself or one of its descendants.
+pub fn reparent_page(conn: &Connection, id: &str, parent_id: Option<&str>) -> Result<Page, KbError> {
+    let page = get_page(conn, id)?;
+    if let Some(parent) = parent_id {
+        validate_parent(conn, Some(id), &page.space_id, parent)?;
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "UPDATE pages SET parent_id = ?1, updated_at = ?2, version = version + 1 WHERE id = ?3",
+        rusqlite::params![parent_id, now, id],
+    )
+    .map_err(KbError::Db)?;
+
+    get_page(conn, id)
+}
+
+/// Returns the IDs of a page and all of its descendants.
+///
+/// The page itself comes first; the rest are in breadth-first order.
+fn subtree_ids(conn: &Connection, id: &str) -> Result<Vec<String>, KbError> {
+    // UNION (not UNION ALL) so a parent cycle left over from before parents
+    // were validated cannot make the recursion run forever.
+    let mut stmt = conn
+        .prepare(
+            "WITH RECURSIVE subtree(id) AS (
+                 SELECT ?1
+                 UNION
+                 SELECT p.id FROM pages p JOIN subtree s ON p.parent_id = s.id
+             )
+             SELECT id FROM subtree",
+        )
+        .map_err(KbError::Db)?;
+    let ids = stmt
+        .query_map([id], |row| row.get(0))
+        .map_err(KbError::Db)?
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(KbError::Db)?;
+    Ok(ids)
+}
+
+/// Deletes a page together with all of its descendants.
+///
+/// Labels and links of every deleted page are removed by cascade.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `id` - The root of the subtree to delete
+///
+/// # Returns
+/// The IDs of every deleted page, starting with `id`
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the page doesn't exist.
+pub fn delete_page_recursive(conn: &Connection, id: &str) -> Result<Vec<String>, KbError> {
+    get_page(conn, id)?;
+    let ids = subtree_ids(conn, id)?;
+
+    let tx = conn.unchecked_transaction()?;
+    // Detach the subtree first so deletion order doesn't matter to the parent FK.
+    for page_id in &ids {
+        tx.execute("UPDATE pages SET parent_id = NULL WHERE id = ?1", [page_id])?;
+    }
+    for page_id in &ids {
+        tx.execute("DELETE FROM pages WHERE id = ?1", [page_id])?;
+    }
+    tx.commit()?;
+
+    Ok(ids)
+}
+
+/// Builds the page hierarchy as a tree.
+///
+/// Without a root, the tree starts at every top-level page (in `space_id`, or
+/// in all spaces). A page whose parent is outside the selected pages is
+/// treated as top-level so nothing is hidden.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `space_id` - Optional space to restrict the tree to
+/// * `root_id` - Optional page to use as the single root
+///
+/// # Returns
+/// The root nodes, ordered by space slug then title; children ordered by title
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the root page doesn't exist.
+/// Returns `KbError::InvalidInput` if the root page is not in `space_id`.
+pub fn page_tree(
+    conn: &Connection,
+    space_id: Option<&str>,
+    root_id: Option<&str>,
+) -> Result<Vec<PageTreeNode>, KbError> {
+    if let Some(root) = root_id {
+        let page = get_page(conn, root)?;
+        if space_id.is_some_and(|s| s != page.space_id) {
+            return Err(KbError::InvalidInput(format!(
+                "Page '{}' is not in the given space",
+                root
+            )));
+        }
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT p.id, p.parent_id, p.title, p.page_type, s.slug
+             FROM pages p JOIN spaces s ON s.id = p.space_id
+             WHERE ?1 IS NULL OR p.space_id = ?1
+             ORDER BY s.slug, p.title COLLATE NOCASE",
+        )
+        .map_err(KbError::Db)?;
+    let rows = stmt
+        .query_map([space_id], |row| {
+            let type_str: String = row.get(3)?;
+            Ok((
+                row.get::<_, Option<String>>(1)?,
+                PageTreeNode {
+                    id: row.get(0)?,
+                    title: row.get(2)?,
+                    page_type: PageType::from_str(&type_str).unwrap_or(PageType::Reference),
+                    space_slug: row.get(4)?,
+                    children: Vec::new(),
+                },
+            ))
+        })
+        .map_err(KbError::Db)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(KbError::Db)?;
+
+    let known: HashSet<String> = rows.iter().map(|(_, node)| node.id.clone()).collect();
+    let mut roots = Vec::new();
+    let mut children: HashMap<String, Vec<PageTreeNode>> = HashMap::new();
+    for (parent, node) in rows {
+        match parent {
+            Some(p) if known.contains(&p) => children.entry(p).or_default().push(node),
+            _ => roots.push(node),
+        }
     }
 
-    Ok(())
+    fn attach(
+        mut node: PageTreeNode,
+        children: &mut HashMap<String, Vec<PageTreeNode>>,
+    ) -> PageTreeNode {
+        // Removing the entry as we go also stops a pre-existing cycle from recursing forever.
+        if let Some(kids) = children.remove(&node.id) {
+            node.children = kids.into_iter().map(|k| attach(k, children)).collect();
+        }
+        node
+    }
+
+    match root_id {
+        Some(root) => {
+            // The root may itself be someone's child; find it wherever it landed.
+            let node = roots
+                .iter()
+                .position(|n| n.id == root)
+                .map(|i| roots.swap_remove(i))
+                .or_else(|| {
+                    children.values_mut().find_map(|kids| {
+                        kids.iter().position(|n| n.id == root).map(|i| kids.swap_remove(i))
+                    })
+                })
+                .ok_or_else(|| KbError::NotFound(format!("Page with ID '{}' not found", root)))?;
+            Ok(vec![attach(node, &mut children)])
+        }
+        None => Ok(roots.into_iter().map(|n| attach(n, &mut children)).collect()),
+    }
 }
 
 // =============================================================================
@@ -762,14 +1887,14 @@ pub fn list_top_level_pages(conn: &Connection, space_id: &str) -> Result<Vec<Pag
     let mut stmt = conn
         .prepare(
             "SELECT id, space_id, parent_id, title, page_type, content,
-                    created_by_user, created_by_agent, created_at, updated_at, version, sections
+                    created_by_user, created_by_agent, created_at, updated_at, version, sections, session_id
              FROM pages WHERE space_id = ?1 AND parent_id IS NULL
              ORDER BY title COLLATE NOCASE",
         )
         .map_err(KbError::Db)?;
 
     let pages = stmt
-        .query_map([space_id], |row| row_to_page(row))
+        .query_map([space_id], row_to_page)
         .map_err(KbError::Db)?
         .collect::<Result<Vec<_>, _>>()
         .map_err(KbError::Db)?;
@@ -787,14 +1912,14 @@ pub fn list_child_pages(conn: &Connection, parent_id: &str) -> Result<Vec<Page>,
     let mut stmt = conn
         .prepare(
             "SELECT id, space_id, parent_id, title, page_type, content,
-                    created_by_user, created_by_agent, created_at, updated_at, version, sections
+                    created_by_user, created_by_agent, created_at, updated_at, version, sections, session_id
              FROM pages WHERE parent_id = ?1
              ORDER BY title COLLATE NOCASE",
         )
         .map_err(KbError::Db)?;
 
     let pages = stmt
-        .query_map([parent_id], |row| row_to_page(row))
+        .query_map([parent_id], row_to_page)
         .map_err(KbError::Db)?
         .collect::<Result<Vec<_>, _>>()
         .map_err(KbError::Db)?;
@@ -819,6 +1944,259 @@ pub fn has_children(conn: &Connection, page_id: &str) -> Result<bool, KbError> {
     Ok(count > 0)
 }
 
+// =============================================================================
+// Sessions
+// =============================================================================
+
+/// Records that the current version of a page was written in `identity`'s
+/// session. A page still at version 1 is also marked as created in it.
+/// Does nothing outside a session.
+///
+/// Call after each page write, as `set_page_source` is called after imports.
+pub fn record_page_session(conn: &Connection, page_id: &str, identity: &AgentIdentity) -> Result<(), KbError> {
+    let Some(session) = identity.session.as_deref() else {
+        return Ok(());
+    };
+    let now = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT OR IGNORE INTO page_revisions (page_id, version, session_id, created_by_user, created_by_agent, created_at)
+         SELECT id, version, ?2, ?3, ?4, ?5 FROM pages WHERE id = ?1",
+        rusqlite::params![page_id, session, identity.user, identity.agent, now],
+    )?;
+    conn.execute(
+        "UPDATE pages SET session_id = ?2 WHERE id = ?1 AND version = 1 AND session_id IS NULL",
+        [page_id, session],
+    )?;
+    Ok(())
+}
+
+/// Marks a link as created in `identity`'s session. Does nothing outside a
+/// session, or if the link already belongs to one.
+pub fn record_link_session(
+    conn: &Connection,
+    source_id: &str,
+    target_id: &str,
+    identity: &AgentIdentity,
+) -> Result<(), KbError> {
+    let Some(session) = identity.session.as_deref() else {
+        return Ok(());
+    };
+    conn.execute(
+        "UPDATE links SET session_id = ?3 WHERE source_id = ?1 AND target_id = ?2 AND session_id IS NULL",
+        [source_id, target_id, session],
+    )?;
+    Ok(())
+}
+
+/// Lists every session that wrote something, most recently active first.
+pub fn list_sessions(conn: &Connection) -> Result<Vec<SessionSummary>, KbError> {
+    let mut stmt = conn.prepare(
+        "SELECT session_id, MIN(at), MAX(at), GROUP_CONCAT(DISTINCT agent)
+         FROM (SELECT session_id, created_at AS at, created_by_agent AS agent FROM page_revisions
+               UNION ALL
+               SELECT session_id, created_at, created_by_agent FROM pages WHERE session_id IS NOT NULL
+               UNION ALL
+               SELECT session_id, created_at, created_by_agent FROM links WHERE session_id IS NOT NULL)
+         GROUP BY session_id
+         ORDER BY MAX(at) DESC",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut sessions = Vec::new();
+    for (session_id, first_write_at, last_write_at, agents) in rows {
+        let mut agents: Vec<String> = agents.unwrap_or_default().split(',').map(str::to_string).collect();
+        agents.sort();
+        agents.dedup();
+        let activity = get_session(conn, &session_id)?;
+        sessions.push(SessionSummary {
+            session_id,
+            agents,
+            first_write_at,
+            last_write_at,
+            pages_created: activity.pages_created.len(),
+            pages_modified: activity.pages_modified.len(),
+            links_created: activity.links_created.len(),
+        });
+    }
+    Ok(sessions)
+}
+
+/// Everything a session created or modified. Pages created in the session
+/// aren't listed again as modified.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the session wrote nothing that still exists.
+pub fn get_session(conn: &Connection, session_id: &str) -> Result<SessionActivity, KbError> {
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.title, p.page_type, s.slug, p.session_id IS ?1,
+                (SELECT GROUP_CONCAT(version) FROM page_revisions r WHERE r.page_id = p.id AND r.session_id = ?1)
+         FROM pages p
+         JOIN spaces s ON s.id = p.space_id
+         WHERE p.session_id = ?1
+            OR p.id IN (SELECT page_id FROM page_revisions WHERE session_id = ?1)
+         ORDER BY p.created_at, p.title",
+    )?;
+    let pages = stmt
+        .query_map([session_id], |row| {
+            let type_str: String = row.get(2)?;
+            let page_type = PageType::from_str(&type_str).ok_or_else(|| {
+                rusqlite::Error::InvalidColumnType(2, "page_type".to_string(), rusqlite::types::Type::Text)
+            })?;
+            let versions: Option<String> = row.get(5)?;
+            let mut versions: Vec<i64> = versions.unwrap_or_default().split(',').filter_map(|v| v.parse().ok()).collect();
+            versions.sort_unstable();
+            let page = SessionPage {
+                page: PageRef {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    page_type,
+                    space_slug: row.get(3)?,
+                },
+                versions,
+            };
+            Ok((row.get::<_, bool>(4)?, page))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let (created, modified): (Vec<_>, Vec<_>) = pages.into_iter().partition(|(created, _)| *created);
+    let links_created = query_expanded_links(conn, "l.session_id = ?1", session_id)?;
+
+    if created.is_empty() && modified.is_empty() && links_created.is_empty() {
+        return Err(KbError::NotFound(format!("Session '{}' not found", session_id)));
+    }
+    Ok(SessionActivity {
+        session_id: session_id.to_string(),
+        pages_created: created.into_iter().map(|(_, page)| page).collect(),
+        pages_modified: modified.into_iter().map(|(_, page)| page).collect(),
+        links_created,
+    })
+}
+
+// =============================================================================
+// Reads
+// =============================================================================
+
+/// Counts a read of each page, for recall's usage signal. Pages that no
+/// longer exist are skipped.
+pub fn record_reads(conn: &Connection, page_ids: &[&str]) -> Result<(), KbError> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare(
+        "INSERT INTO page_reads (page_id, read_count, last_read_at)
+         SELECT id, 1, ?2 FROM pages WHERE id = ?1
+         ON CONFLICT(page_id) DO UPDATE SET read_count = read_count + 1, last_read_at = excluded.last_read_at",
+    )?;
+    for page_id in page_ids {
+        stmt.execute([*page_id, now.as_str()])?;
+    }
+    Ok(())
+}
+
+/// How many times a page has been read; 0 if never.
+pub fn read_count(conn: &Connection, page_id: &str) -> Result<i64, KbError> {
+    let count = conn
+        .query_row("SELECT read_count FROM page_reads WHERE page_id = ?1", [page_id], |row| row.get(0))
+        .optional()?;
+    Ok(count.unwrap_or(0))
+}
+
+// =============================================================================
+// Feedback
+// =============================================================================
+
+/// Records whether a page was useful for a query.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the page doesn't exist, or
+/// `KbError::InvalidInput` if the query is blank.
+pub fn record_feedback(
+    conn: &Connection,
+    page_id: &str,
+    query: &str,
+    useful: bool,
+    identity: &AgentIdentity,
+) -> Result<Feedback, KbError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Err(KbError::InvalidInput("Feedback needs the query the page was retrieved for".to_string()));
+    }
+    get_page(conn, page_id)?;
+    let now = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO feedback (page_id, query, useful, created_by_user, created_by_agent, session_id, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![page_id, query, useful, identity.user, identity.agent, identity.session, now],
+    )?;
+    Ok(Feedback {
+        id: conn.last_insert_rowid(),
+        page_id: page_id.to_string(),
+        query: query.to_string(),
+        useful,
+        created_by_user: identity.user.clone(),
+        created_by_agent: identity.agent.clone(),
+        session_id: identity.session.clone(),
+        created_at: now,
+    })
+}
+
+/// The queries a page has been given feedback for, each with its verdict,
+/// oldest first.
+pub fn list_feedback(conn: &Connection, page_id: &str) -> Result<Vec<(String, bool)>, KbError> {
+    let mut stmt = conn.prepare("SELECT query, useful FROM feedback WHERE page_id = ?1 ORDER BY id")?;
+    let feedback = stmt
+        .query_map([page_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(feedback)
+}
+
+/// Pages read at least `min_reads` times that nobody has marked useful:
+/// candidates for a rewrite or deletion. Most "not useful" verdicts first,
+/// then most reads.
+pub fn feedback_report(
+    conn: &Connection,
+    space_id: Option<&str>,
+    min_reads: i64,
+) -> Result<Vec<FeedbackReportEntry>, KbError> {
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.title, p.page_type, s.slug, r.read_count, r.last_read_at,
+                (SELECT COUNT(*) FROM feedback f WHERE f.page_id = p.id AND NOT f.useful) AS not_useful
+         FROM page_reads r
+         JOIN pages p ON p.id = r.page_id
+         JOIN spaces s ON s.id = p.space_id
+         WHERE r.read_count >= ?1
+           AND (?2 IS NULL OR p.space_id = ?2)
+           AND NOT EXISTS (SELECT 1 FROM feedback f WHERE f.page_id = p.id AND f.useful)
+         ORDER BY not_useful DESC, r.read_count DESC, p.title",
+    )?;
+    let entries = stmt
+        .query_map(rusqlite::params![min_reads, space_id], |row| {
+            let type_str: String = row.get(2)?;
+            let page_type = PageType::from_str(&type_str).ok_or_else(|| {
+                rusqlite::Error::InvalidColumnType(2, "page_type".to_string(), rusqlite::types::Type::Text)
+            })?;
+            Ok(FeedbackReportEntry {
+                page: PageRef {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    page_type,
+                    space_slug: row.get(3)?,
+                },
+                reads: row.get(4)?,
+                last_read_at: row.get(5)?,
+                not_useful: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries)
+}
+
 // =============================================================================
 // Tests
 // =============================================================================
@@ -838,6 +2216,30 @@ mod tests {
         let migration3_sql = include_str!("../migrations/003_timestamps.sql");
         conn.execute_batch(migration3_sql)
             .expect("Failed to run migration 003");
+        let migration4_sql = include_str!("../migrations/004_link_policy.sql");
+        conn.execute_batch(migration4_sql)
+            .expect("Failed to run migration 004");
+        let migration5_sql = include_str!("../migrations/005_link_annotations.sql");
+        conn.execute_batch(migration5_sql)
+            .expect("Failed to run migration 005");
+        let migration6_sql = include_str!("../migrations/006_mentions_relation.sql");
+        conn.execute_batch(migration6_sql)
+            .expect("Failed to run migration 006");
+        let migration7_sql = include_str!("../migrations/007_page_sources.sql");
+        conn.execute_batch(migration7_sql)
+            .expect("Failed to run migration 007");
+        let migration8_sql = include_str!("../migrations/008_sessions.sql");
+        conn.execute_batch(migration8_sql)
+            .expect("Failed to run migration 008");
+        let migration9_sql = include_str!("../migrations/009_page_reads.sql");
+        conn.execute_batch(migration9_sql)
+            .expect("Failed to run migration 009");
+        let migration10_sql = include_str!("../migrations/010_feedback.sql");
+        conn.execute_batch(migration10_sql)
+            .expect("Failed to run migration 010");
+        let migration11_sql = include_str!("../migrations/011_merge_bases.sql");
+        conn.execute_batch(migration11_sql)
+            .expect("Failed to run migration 011");
         conn
     }
 
@@ -1260,7 +2662,7 @@ mod tests {
             PageType::Reference,
             "Content",
             None,
-            &vec!["rust".to_string()],
+            &["rust".to_string()],
             "user",
             "agent",
         )
@@ -1273,7 +2675,7 @@ mod tests {
             PageType::Reference,
             "Content",
             None,
-            &vec!["python".to_string()],
+            &["python".to_string()],
             "user",
             "agent",
         )
@@ -1375,7 +2777,7 @@ mod tests {
             PageType::Reference,
             "Content",
             None,
-            &vec!["label1".to_string(), "label2".to_string()],
+            &["label1".to_string(), "label2".to_string()],
             "user",
             "agent",
         )
@@ -1401,13 +2803,13 @@ mod tests {
             PageType::Reference,
             "Content",
             None,
-            &vec!["old1".to_string(), "old2".to_string()],
+            &["old1".to_string(), "old2".to_string()],
             "user",
             "agent",
         )
         .expect("Failed to create page");
 
-        set_labels(&conn, &page.id, &vec!["new1".to_string(), "new2".to_string()])
+        set_labels(&conn, &page.id, &["new1".to_string(), "new2".to_string()])
             .expect("Failed to set labels");
 
         let labels = get_labels(&conn, &page.id).expect("Failed to get labels");
@@ -1449,7 +2851,7 @@ mod tests {
         )
         .expect("Failed to create page");
 
-        let link = create_link(&conn, &page1.id, &page2.id, LinkRelation::RelatesTo)
+        let link = create_link(&conn, &page1.id, &page2.id, LinkRelation::RelatesTo, "", "user", "agent")
             .expect("Failed to create link");
 
         assert_eq!(link.source_id, page1.id);
@@ -1498,7 +2900,7 @@ mod tests {
         )
         .expect("Failed to create page");
 
-        let link = create_link(&conn, &page1.id, &page2.id, LinkRelation::Supersedes)
+        let link = create_link(&conn, &page1.id, &page2.id, LinkRelation::Supersedes, "", "user", "agent")
             .expect("Failed to create link");
 
         assert_eq!(link.relation, LinkRelation::Supersedes);
@@ -1536,7 +2938,7 @@ mod tests {
         )
         .expect("Failed to create page");
 
-        create_link(&conn, &page1.id, &page2.id, LinkRelation::RelatesTo)
+        create_link(&conn, &page1.id, &page2.id, LinkRelation::RelatesTo, "", "user", "agent")
             .expect("Failed to create link");
 
         delete_link(&conn, &page1.id, &page2.id).expect("Failed to delete link");
@@ -1598,9 +3000,9 @@ mod tests {
         )
         .expect("Failed to create page");
 
-        create_link(&conn, &page1.id, &page2.id, LinkRelation::RelatesTo)
+        create_link(&conn, &page1.id, &page2.id, LinkRelation::RelatesTo, "", "user", "agent")
             .expect("Failed to create link");
-        create_link(&conn, &page2.id, &page3.id, LinkRelation::Elaborates)
+        create_link(&conn, &page2.id, &page3.id, LinkRelation::Elaborates, "", "user", "agent")
             .expect("Failed to create link");
 
         delete_page(&conn, &page2.id).expect("Failed to delete page");
@@ -1864,7 +3266,7 @@ mod tests {
             PageType::Reference,
             "Content",
             None,
-            &vec!["original".to_string()],
+            &["original".to_string()],
             "user",
             "agent",
         )
@@ -2050,4 +3452,513 @@ mod tests {
         assert_eq!(pages.len(), 1);
         assert!(pages[0].sections.is_some());
     }
+
+    fn simple_page(conn: &Connection, space_id: &str, title: &str) -> Page {
+        create_page(
+            conn, space_id, None, title, PageType::Reference, "", None, &[], "u", "a",
+        ).expect("create page")
+    }
+
+    #[test]
+    fn test_create_page_checked_handles_duplicates() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "proj", "Proj", "").expect("create space");
+        let body = "Run cargo clean and rebuild when the incremental cache is corrupted after a toolchain upgrade.";
+        let original = create_page(&conn, &space.id, None, "Corrupted build cache", PageType::Troubleshooting, body, None, &[], "u", "a")
+            .expect("create page");
+        let identity = AgentIdentity { user: "u".to_string(), agent: "a".to_string(), session: None };
+        let labels = vec!["rust".to_string()];
+        let create = |on_duplicate| {
+            create_page_checked(
+                &conn, &space.id, None, "Corrupted build cache", PageType::Troubleshooting, body, None, &labels,
+                &identity, on_duplicate, dedupe::DEFAULT_THRESHOLD,
+            )
+        };
+
+        assert!(matches!(create(OnDuplicate::Refuse), Err(KbError::Duplicate { .. })));
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM pages", [], |row| row.get(0)).expect("count");
+        assert_eq!(count, 1);
+
+        let PageWriteResult { page, duplicates, .. } = create(OnDuplicate::Append).expect("append");
+        assert_eq!(page.id, original.id);
+        assert_eq!(page.version, 2);
+        assert_eq!(page.labels, labels);
+        assert_eq!(duplicates[0].page.id, original.id);
+
+        let page = create(OnDuplicate::Link).expect("link").page;
+        assert_ne!(page.id, original.id);
+        let link = get_link(&conn, &page.id, &original.id).expect("get link");
+        assert_eq!(link.relation, LinkRelation::RelatesTo);
+
+        let PageWriteResult { page: unrelated, duplicates, .. } = create_page_checked(
+            &conn, &space.id, None, "Deploy", PageType::Runbook, "Tag and push.", None, &[], &identity,
+            OnDuplicate::Refuse, dedupe::DEFAULT_THRESHOLD,
+        )
+        .expect("create unrelated");
+        assert!(duplicates.is_empty());
+        assert_eq!(unrelated.title, "Deploy");
+    }
+
+    #[test]
+    fn test_create_link_rejects_self_link() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let page = simple_page(&conn, &space.id, "A");
+
+        let result = create_link(&conn, &page.id, &page.id, LinkRelation::RelatesTo, "", "user", "agent");
+        assert!(matches!(result, Err(KbError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_create_link_missing_page_is_not_found() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let page = simple_page(&conn, &space.id, "A");
+
+        let result = create_link(&conn, &page.id, "missing", LinkRelation::RelatesTo, "", "user", "agent");
+        assert!(matches!(result, Err(KbError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_create_link_duplicate_is_invalid_input() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+
+        create_link(&conn, &a.id, &b.id, LinkRelation::RelatesTo, "", "user", "agent").expect("first link");
+        let result = create_link(&conn, &a.id, &b.id, LinkRelation::Elaborates, "", "user", "agent");
+        assert!(matches!(result, Err(KbError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_create_link_detects_cycle_with_path() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+        let c = simple_page(&conn, &space.id, "C");
+
+        create_link(&conn, &a.id, &b.id, LinkRelation::Supersedes, "", "user", "agent").expect("a -> b");
+        create_link(&conn, &b.id, &c.id, LinkRelation::Supersedes, "", "user", "agent").expect("b -> c");
+
+        match create_link(&conn, &c.id, &a.id, LinkRelation::Supersedes, "", "user", "agent") {
+            Err(KbError::LinkCycle { relation, path }) => {
+                assert_eq!(relation, "supersedes");
+                assert_eq!(path, vec![c.id.clone(), a.id.clone(), b.id.clone(), c.id.clone()]);
+            }
+            other => panic!("Expected LinkCycle, got {:?}", other),
+        }
+
+        // Cycles are only tracked within a relation, and only for acyclic ones.
+        create_link(&conn, &c.id, &a.id, LinkRelation::RelatesTo, "", "user", "agent").expect("relates-to back-link is fine");
+    }
+
+    #[test]
+    fn test_create_link_respects_cross_space_policy() {
+        let conn = setup_test_db();
+        let open = create_space(&conn, "open", "Open", "").expect("create space");
+        let closed = create_space(&conn, "closed", "Closed", "").expect("create space");
+        let a = simple_page(&conn, &open.id, "A");
+        let b = simple_page(&conn, &closed.id, "B");
+
+        create_link(&conn, &a.id, &b.id, LinkRelation::RelatesTo, "", "user", "agent").expect("allowed by default");
+        delete_link(&conn, &a.id, &b.id).expect("delete link");
+
+        let updated = set_cross_space_links(&conn, "closed", false).expect("set policy");
+        assert!(!updated.allow_cross_space_links);
+
+        let result = create_link(&conn, &a.id, &b.id, LinkRelation::RelatesTo, "", "user", "agent");
+        assert!(matches!(result, Err(KbError::InvalidInput(_))));
+        let result = create_link(&conn, &b.id, &a.id, LinkRelation::RelatesTo, "", "user", "agent");
+        assert!(matches!(result, Err(KbError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_check_links_reports_existing_violations() {
+        let conn = setup_test_db();
+        let s1 = create_space(&conn, "s1", "S1", "").expect("create space");
+        let s2 = create_space(&conn, "s2", "S2", "").expect("create space");
+        let a = simple_page(&conn, &s1.id, "A");
+        let b = simple_page(&conn, &s1.id, "B");
+        let c = simple_page(&conn, &s2.id, "C");
+
+        // Bypass validation to simulate links created before the rules existed.
+        for (src, tgt, rel) in [
+            (&a.id, &a.id, "relates-to"),
+            (&a.id, &b.id, "depends-on"),
+            (&b.id, &a.id, "depends-on"),
+            (&b.id, &c.id, "relates-to"),
+        ] {
+            conn.execute(
+                "INSERT INTO links (source_id, target_id, relation, created_at, updated_at) VALUES (?1, ?2, ?3, '', '')",
+                rusqlite::params![src, tgt, rel],
+            ).expect("insert link");
+        }
+        set_cross_space_links(&conn, "s2", false).expect("set policy");
+
+        let report = check_links(&conn).expect("check links");
+        assert_eq!(report.links_checked, 4);
+
+        let kinds: Vec<LinkViolationKind> = report.violations.iter().map(|v| v.kind).collect();
+        assert_eq!(kinds.iter().filter(|k| **k == LinkViolationKind::SelfLink).count(), 1);
+        assert_eq!(kinds.iter().filter(|k| **k == LinkViolationKind::CrossSpace).count(), 1);
+        // The a <-> b cycle is reported once even though both links are part of it.
+        let cycles: Vec<&LinkViolation> = report.violations.iter()
+            .filter(|v| v.kind == LinkViolationKind::Cycle)
+            .collect();
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].path.len(), 3);
+        assert_eq!(cycles[0].path.first(), cycles[0].path.last());
+    }
+
+    #[test]
+    fn test_check_links_clean_graph() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+        create_link(&conn, &a.id, &b.id, LinkRelation::Supersedes, "", "user", "agent").expect("link");
+
+        let report = check_links(&conn).expect("check links");
+        assert_eq!(report.links_checked, 1);
+        assert!(report.violations.is_empty());
+    }
+
+    #[test]
+    fn test_create_link_records_note_and_identity() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+
+        create_link(&conn, &a.id, &b.id, LinkRelation::Elaborates, "Adds benchmarks", "alice", "claude-code")
+            .expect("create link");
+
+        let links = list_links(&conn, &b.id).expect("list links");
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].note, "Adds benchmarks");
+        assert_eq!(links[0].created_by_user, "alice");
+        assert_eq!(links[0].created_by_agent, "claude-code");
+    }
+
+    #[test]
+    fn test_update_link_note_and_relation() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+        create_link(&conn, &a.id, &b.id, LinkRelation::RelatesTo, "first", "u", "a").expect("create link");
+
+        let updated = update_link(&conn, &a.id, &b.id, None, Some("second")).expect("update note");
+        assert_eq!(updated.note, "second");
+        assert_eq!(updated.relation, LinkRelation::RelatesTo);
+
+        let updated = update_link(&conn, &a.id, &b.id, Some(LinkRelation::DependsOn), None)
+            .expect("update relation");
+        assert_eq!(updated.relation, LinkRelation::DependsOn);
+        assert_eq!(updated.note, "second");
+        assert_eq!(updated.created_by_user, "u");
+    }
+
+    #[test]
+    fn test_update_link_rejects_cycle() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+        create_link(&conn, &a.id, &b.id, LinkRelation::DependsOn, "", "u", "a").expect("a -> b");
+        create_link(&conn, &b.id, &a.id, LinkRelation::RelatesTo, "", "u", "a").expect("b -> a");
+
+        let result = update_link(&conn, &b.id, &a.id, Some(LinkRelation::DependsOn), None);
+        assert!(matches!(result, Err(KbError::LinkCycle { .. })));
+    }
+
+    #[test]
+    fn test_update_link_not_found() {
+        let conn = setup_test_db();
+        let result = update_link(&conn, "x", "y", None, Some("note"));
+        assert!(matches!(result, Err(KbError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_create_link_replaces_mention() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+        conn.execute(
+            "INSERT INTO links (source_id, target_id, relation) VALUES (?1, ?2, 'mentions')",
+            rusqlite::params![a.id, b.id],
+        ).expect("insert mention");
+
+        create_link(&conn, &a.id, &b.id, LinkRelation::Supersedes, "", "u", "a").expect("create link");
+        let links = list_backlinks(&conn, &b.id).expect("backlinks");
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].relation, LinkRelation::Supersedes);
+    }
+
+    #[test]
+    fn test_list_backlinks_missing_page() {
+        let conn = setup_test_db();
+        assert!(matches!(list_backlinks(&conn, "missing"), Err(KbError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_list_links_expanded_resolves_titles_and_spaces() {
+        let conn = setup_test_db();
+        let s1 = create_space(&conn, "alpha", "Alpha", "").expect("create space");
+        let s2 = create_space(&conn, "beta", "Beta", "").expect("create space");
+        let a = simple_page(&conn, &s1.id, "Chose Postgres");
+        let b = create_page(
+            &conn, &s2.id, None, "Chose MySQL", PageType::Decision, "", None, &[], "u", "a",
+        ).expect("create page");
+        create_link(&conn, &a.id, &b.id, LinkRelation::Supersedes, "", "u", "a").expect("create link");
+
+        let links = list_links_expanded(&conn, &a.id).expect("list expanded");
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].source.title, "Chose Postgres");
+        assert_eq!(links[0].source.space_slug, "alpha");
+        assert_eq!(links[0].target.title, "Chose MySQL");
+        assert_eq!(links[0].target.page_type, PageType::Decision);
+        assert_eq!(links[0].target.space_slug, "beta");
+
+        let backlinks = list_backlinks_expanded(&conn, &b.id).expect("backlinks expanded");
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].source.id, a.id);
+    }
+
+    #[test]
+    fn test_get_link_expanded() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "proj", "Proj", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+        let c = simple_page(&conn, &space.id, "C");
+        create_link(&conn, &a.id, &b.id, LinkRelation::RelatesTo, "", "u", "a").expect("create link");
+        create_link(&conn, &a.id, &c.id, LinkRelation::Elaborates, "why", "u", "a").expect("create link");
+
+        let link = get_link_expanded(&conn, &a.id, &c.id).expect("get expanded");
+        assert_eq!((link.source.title.as_str(), link.target.title.as_str()), ("A", "C"));
+        assert_eq!(link.link.note, "why");
+        assert!(matches!(get_link_expanded(&conn, &c.id, &a.id), Err(KbError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_get_page_expanded() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "proj", "Proj", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+        create_link(&conn, &b.id, &a.id, LinkRelation::Elaborates, "", "u", "a").expect("create link");
+
+        let expanded = get_page_expanded(&conn, &a.id).expect("get expanded");
+        assert_eq!(expanded.page.id, a.id);
+        assert_eq!(expanded.space_slug, "proj");
+        assert_eq!(expanded.links.len(), 1);
+        assert_eq!(expanded.links[0].source.title, "B");
+
+        let json = serde_json::to_value(&expanded).expect("serialize");
+        assert_eq!(json["title"], "A");
+        assert_eq!(json["links"][0]["target"]["title"], "A");
+    }
+
+    fn child_page(conn: &Connection, space_id: &str, parent_id: &str, title: &str) -> Page {
+        create_page(
+            conn, space_id, Some(parent_id), title, PageType::Reference, "", None, &[], "u", "a",
+        ).expect("create child page")
+    }
+
+    #[test]
+    fn test_create_page_rejects_cross_space_parent() {
+        let conn = setup_test_db();
+        let s1 = create_space(&conn, "one", "One", "").expect("create space");
+        let s2 = create_space(&conn, "two", "Two", "").expect("create space");
+        let parent = simple_page(&conn, &s1.id, "Parent");
+
+        let result = create_page(
+            &conn, &s2.id, Some(&parent.id), "Child", PageType::Reference, "", None, &[], "u", "a",
+        );
+        assert!(matches!(result, Err(KbError::InvalidInput(_))));
+
+        let result = create_page(
+            &conn, &s1.id, Some("missing"), "Child", PageType::Reference, "", None, &[], "u", "a",
+        );
+        assert!(matches!(result, Err(KbError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_reparent_page() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = simple_page(&conn, &space.id, "B");
+
+        let moved = reparent_page(&conn, &b.id, Some(&a.id)).expect("reparent");
+        assert_eq!(moved.parent_id.as_deref(), Some(a.id.as_str()));
+        assert_eq!(moved.version, 2);
+
+        let moved = reparent_page(&conn, &b.id, None).expect("make top-level");
+        assert_eq!(moved.parent_id, None);
+    }
+
+    #[test]
+    fn test_reparent_rejects_cycles() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = child_page(&conn, &space.id, &a.id, "B");
+        let c = child_page(&conn, &space.id, &b.id, "C");
+
+        match reparent_page(&conn, &a.id, Some(&c.id)) {
+            Err(KbError::InvalidInput(msg)) => {
+                assert!(msg.contains(&format!("{} -> {} -> {} -> {}", a.id, b.id, c.id, a.id)), "{}", msg);
+            }
+            other => panic!("expected cycle error, got {:?}", other.map(|p| p.id)),
+        }
+        assert!(matches!(reparent_page(&conn, &a.id, Some(&a.id)), Err(KbError::InvalidInput(_))));
+        assert_eq!(get_page(&conn, &a.id).expect("get").parent_id, None);
+    }
+
+    #[test]
+    fn test_reparent_rejects_cross_space() {
+        let conn = setup_test_db();
+        let s1 = create_space(&conn, "one", "One", "").expect("create space");
+        let s2 = create_space(&conn, "two", "Two", "").expect("create space");
+        let a = simple_page(&conn, &s1.id, "A");
+        let b = simple_page(&conn, &s2.id, "B");
+
+        assert!(matches!(reparent_page(&conn, &b.id, Some(&a.id)), Err(KbError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_delete_page_with_children_requires_recursive() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let a = simple_page(&conn, &space.id, "A");
+        let b = child_page(&conn, &space.id, &a.id, "B");
+        let c = child_page(&conn, &space.id, &b.id, "C");
+        let other = simple_page(&conn, &space.id, "Other");
+        create_link(&conn, &other.id, &c.id, LinkRelation::RelatesTo, "", "user", "agent").expect("link");
+
+        assert!(matches!(delete_page(&conn, &a.id), Err(KbError::InvalidInput(_))));
+
+        let deleted = delete_page_recursive(&conn, &a.id).expect("delete recursive");
+        assert_eq!(deleted, vec![a.id.clone(), b.id.clone(), c.id.clone()]);
+        for id in &deleted {
+            assert!(matches!(get_page(&conn, id), Err(KbError::NotFound(_))));
+        }
+        assert!(get_page(&conn, &other.id).is_ok());
+        assert!(list_links(&conn, &other.id).expect("list").is_empty());
+        assert!(matches!(delete_page_recursive(&conn, &a.id), Err(KbError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_page_tree() {
+        let conn = setup_test_db();
+        let s1 = create_space(&conn, "one", "One", "").expect("create space");
+        let s2 = create_space(&conn, "two", "Two", "").expect("create space");
+        let a = simple_page(&conn, &s1.id, "A");
+        let b = child_page(&conn, &s1.id, &a.id, "B");
+        let _c = child_page(&conn, &s1.id, &b.id, "C");
+        let _z = simple_page(&conn, &s1.id, "Z");
+        let _y = simple_page(&conn, &s2.id, "Y");
+
+        let all = page_tree(&conn, None, None).expect("tree");
+        let titles: Vec<&str> = all.iter().map(|n| n.title.as_str()).collect();
+        assert_eq!(titles, vec!["A", "Z", "Y"]);
+        assert_eq!(all[0].children[0].title, "B");
+        assert_eq!(all[0].children[0].children[0].title, "C");
+
+        let one = page_tree(&conn, Some(&s1.id), None).expect("tree");
+        assert_eq!(one.len(), 2);
+
+        let sub = page_tree(&conn, None, Some(&b.id)).expect("tree");
+        assert_eq!(sub.len(), 1);
+        assert_eq!(sub[0].title, "B");
+        assert_eq!(sub[0].children[0].title, "C");
+
+        assert!(matches!(page_tree(&conn, Some(&s2.id), Some(&b.id)), Err(KbError::InvalidInput(_))));
+        assert!(matches!(page_tree(&conn, None, Some("missing")), Err(KbError::NotFound(_))));
+    }
+
+    fn session_identity(session: Option<&str>) -> AgentIdentity {
+        AgentIdentity { user: "u".to_string(), agent: "a".to_string(), session: session.map(str::to_string) }
+    }
+
+    #[test]
+    fn test_sessions_record_pages_and_links() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let one = session_identity(Some("one"));
+        let two = session_identity(Some("two"));
+
+        let a = simple_page(&conn, &space.id, "A");
+        record_page_session(&conn, &a.id, &one).expect("record");
+        let b = simple_page(&conn, &space.id, "B");
+        record_page_session(&conn, &b.id, &session_identity(None)).expect("record");
+        update_page(&conn, &b.id, None, Some("edited"), None, None).expect("update");
+        record_page_session(&conn, &b.id, &two).expect("record");
+        update_page(&conn, &a.id, None, Some("edited"), None, None).expect("update");
+        record_page_session(&conn, &a.id, &two).expect("record");
+        create_link(&conn, &b.id, &a.id, LinkRelation::RelatesTo, "", "u", "a").expect("link");
+        record_link_session(&conn, &b.id, &a.id, &two).expect("record");
+
+        assert_eq!(get_page(&conn, &a.id).expect("get").session_id.as_deref(), Some("one"));
+        assert_eq!(get_page(&conn, &b.id).expect("get").session_id, None);
+
+        let first = get_session(&conn, "one").expect("session");
+        assert_eq!(first.pages_created.len(), 1);
+        assert_eq!(first.pages_created[0].versions, vec![1]);
+        assert!(first.pages_modified.is_empty());
+        assert!(first.links_created.is_empty());
+
+        let second = get_session(&conn, "two").expect("session");
+        assert!(second.pages_created.is_empty());
+        let modified: Vec<&str> = second.pages_modified.iter().map(|p| p.page.title.as_str()).collect();
+        assert_eq!(modified, vec!["A", "B"]);
+        assert_eq!(second.links_created.len(), 1);
+        assert_eq!(second.links_created[0].link.session_id.as_deref(), Some("two"));
+
+        let sessions = list_sessions(&conn).expect("list");
+        let ids: Vec<&str> = sessions.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["two", "one"]);
+        assert_eq!((sessions[0].pages_modified, sessions[0].links_created), (2, 1));
+        assert_eq!(sessions[0].agents, vec!["a"]);
+
+        assert!(matches!(get_session(&conn, "missing"), Err(KbError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_feedback_report_lists_read_but_never_useful_pages() {
+        let conn = setup_test_db();
+        let space = create_space(&conn, "s", "S", "").expect("create space");
+        let liked = simple_page(&conn, &space.id, "Liked");
+        let ignored = simple_page(&conn, &space.id, "Ignored");
+        let disliked = simple_page(&conn, &space.id, "Disliked");
+        let rare = simple_page(&conn, &space.id, "Rare");
+        for _ in 0..3 {
+            record_reads(&conn, &[&liked.id, &ignored.id, &disliked.id]).expect("reads");
+        }
+        record_reads(&conn, &[&ignored.id, &rare.id]).expect("reads");
+        let identity = session_identity(Some("sess"));
+        record_feedback(&conn, &liked.id, "cache", false, &identity).expect("feedback");
+        record_feedback(&conn, &liked.id, "cache", true, &identity).expect("feedback");
+        let feedback = record_feedback(&conn, &disliked.id, " cache layer ", false, &identity).expect("feedback");
+        assert_eq!(feedback.query, "cache layer");
+        assert_eq!(feedback.session_id.as_deref(), Some("sess"));
+        assert_eq!(list_feedback(&conn, &liked.id).expect("list"), vec![("cache".to_string(), false), ("cache".to_string(), true)]);
+
+        let report = feedback_report(&conn, None, 3).expect("report");
+        let titles: Vec<&str> = report.iter().map(|e| e.page.title.as_str()).collect();
+        assert_eq!(titles, vec!["Disliked", "Ignored"]);
+        assert_eq!((report[0].reads, report[0].not_useful), (3, 1));
+        assert_eq!((report[1].reads, report[1].not_useful), (4, 0));
+
+        let other = create_space(&conn, "o", "O", "").expect("create space");
+        assert!(feedback_report(&conn, Some(&other.id), 0).expect("report").is_empty());
+
+        assert!(matches!(record_feedback(&conn, "missing", "q", true, &identity), Err(KbError::NotFound(_))));
+        assert!(matches!(record_feedback(&conn, &liked.id, "  ", true, &identity), Err(KbError::InvalidInput(_))));
+    }
 }
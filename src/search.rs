@@ -5,10 +5,17 @@
 //! When a text query is provided, it uses SQLite's FTS5 index for relevance
 //! ranking and snippet extraction. Without a text query, it falls back to
 //! efficient metadata-only filtering.
+//!
+//! `recall` re-scores a text search for agent memory, blending bm25
+//! relevance with recency, read counts, the page's standing and the
+//! feedback agents gave it for similar queries.
 
+use crate::context::{extract_keywords, is_current_decision, PITFALL_LABEL};
 use crate::db::KbError;
-use crate::models::{Page, PageType, SearchResult};
+use crate::models::{LinkRelation, Page, PageType, RecallResult, ScoreBreakdown, SearchResult};
+use crate::repo;
 use rusqlite::Connection;
+use std::collections::HashMap;
 
 /// Parameters for searching pages in the knowledge base.
 #[derive(Debug, Clone)]
@@ -27,6 +34,105 @@ pub struct SearchParams {
     pub section: Option<String>,
 }
 
+/// Weights for the terms of a recall score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecallWeights {
+    pub relevance: f64,
+    pub recency: f64,
+    pub usage: f64,
+    pub pitfall: f64,
+    pub accepted: f64,
+    /// Subtracted from superseded pages.
+    pub superseded: f64,
+    pub feedback: f64,
+    /// Days for the recency term to halve.
+    pub half_life_days: f64,
+}
+
+impl Default for RecallWeights {
+    fn default() -> Self {
+        RecallWeights {
+            relevance: 1.0,
+            recency: 0.5,
+            usage: 0.3,
+            pitfall: 0.5,
+            accepted: 0.3,
+            superseded: 1.0,
+            feedback: 0.5,
+            half_life_days: 30.0,
+        }
+    }
+}
+
+impl RecallWeights {
+    const KEYS: &'static str = "relevance, recency, usage, pitfall, accepted, superseded, feedback, half-life-days";
+
+    /// The default weights with `KB_RECALL_WEIGHTS`'s overrides applied,
+    /// then `overrides` (from `--weights`) on top.
+    ///
+    /// # Errors
+    /// Returns `KbError::InvalidInput` if either has an invalid weight.
+    pub fn configured(overrides: Option<&str>) -> Result<Self, KbError> {
+        let weights = match std::env::var("KB_RECALL_WEIGHTS") {
+            Ok(env) => RecallWeights::default().with_overrides(&env)?,
+            Err(_) => RecallWeights::default(),
+        };
+        match overrides {
+            Some(overrides) => weights.with_overrides(overrides),
+            None => Ok(weights),
+        }
+    }
+
+    /// Applies comma-separated `key=value` overrides, e.g.
+    /// `recency=1,usage=0`, to these weights.
+    ///
+    /// # Errors
+    /// Returns `KbError::InvalidInput` for an unknown key, or a value that
+    /// isn't a non-negative number (a positive one for `half-life-days`).
+    pub fn with_overrides(mut self, overrides: &str) -> Result<Self, KbError> {
+        for pair in overrides.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| {
+                KbError::InvalidInput(format!("Invalid weight '{}': expected key=value", pair))
+            })?;
+            let key = key.trim();
+            let value: f64 = value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|v: &f64| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| KbError::InvalidInput(format!("Invalid value for weight '{}': {}", key, value.trim())))?;
+            let slot = match key {
+                "relevance" => &mut self.relevance,
+                "recency" => &mut self.recency,
+                "usage" => &mut self.usage,
+                "pitfall" => &mut self.pitfall,
+                "accepted" => &mut self.accepted,
+                "superseded" => &mut self.superseded,
+                "feedback" => &mut self.feedback,
+                "half-life-days" if value > 0.0 => &mut self.half_life_days,
+                "half-life-days" => {
+                    return Err(KbError::InvalidInput("half-life-days must be positive".to_string()));
+                }
+                _ => {
+                    return Err(KbError::InvalidInput(format!(
+                        "Unknown weight '{}'. Valid weights: {}",
+                        key,
+                        Self::KEYS
+                    )));
+                }
+            };
+            *slot = value;
+        }
+        Ok(self)
+    }
+}
+
+/// Quotes a search term as an FTS5 phrase, so hyphens and the like aren't
+/// read as query syntax.
+fn fts_phrase(query: &str) -> String {
+    format!("\"{}\"", query.replace('"', "\"\""))
+}
+
 /// Generate an excerpt showing the query term in context within the content.
 /// Returns up to ~100 characters centered around the first match.
 fn make_excerpt(content: &str, query: &str) -> String {
@@ -124,7 +230,7 @@ pub fn search_pages(conn: &Connection, params: &SearchParams) -> Result<Vec<Sear
             "SELECT p.id, p.space_id, p.parent_id, p.title, p.page_type, \
              p.content, p.created_by_user, p.created_by_agent, p.created_at, \
              p.updated_at, p.version, p.sections, '' as excerpt, \
-             GROUP_CONCAT(DISTINCT all_labels.label) as label_list \
+             GROUP_CONCAT(DISTINCT all_labels.label) as label_list, p.session_id \
              FROM pages_fts \
              JOIN pages p ON p.rowid = pages_fts.rowid"
         );
@@ -160,7 +266,7 @@ pub fn search_pages(conn: &Connection, params: &SearchParams) -> Result<Vec<Sear
             "SELECT p.id, p.space_id, p.parent_id, p.title, p.page_type, \
              p.content, p.created_by_user, p.created_by_agent, p.created_at, \
              p.updated_at, p.version, p.sections, '' as excerpt, \
-             GROUP_CONCAT(DISTINCT all_labels.label) as label_list \
+             GROUP_CONCAT(DISTINCT all_labels.label) as label_list, p.session_id \
              FROM pages p"
         );
 
@@ -201,10 +307,7 @@ pub fn search_pages(conn: &Connection, params: &SearchParams) -> Result<Vec<Sear
 
     let mut bound_params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = Vec::new();
     if let Some(ref q) = params.query {
-        // Quote the search term to prevent FTS5 syntax issues (e.g. hyphens
-        // being interpreted as NOT operators or column filters).
-        let quoted = format!("\"{}\"", q.replace('"', "\"\""));
-        bound_params.push((":query", Box::new(quoted)));
+        bound_params.push((":query", Box::new(fts_phrase(q))));
     }
     if let Some(ref sid) = params.space_id {
         bound_params.push((":space_id", Box::new(sid.clone())));
@@ -262,6 +365,7 @@ pub fn search_pages(conn: &Connection, params: &SearchParams) -> Result<Vec<Sear
                     created_at: row.get(8)?,
                     updated_at: row.get(9)?,
                     version: row.get(10)?,
+                    session_id: row.get(14)?,
                     labels,
                 },
                 excerpt: row.get(12)?,
@@ -298,6 +402,130 @@ pub fn search_pages(conn: &Connection, params: &SearchParams) -> Result<Vec<Sear
     Ok(results)
 }
 
+/// Searches like `search_pages`, then ranks the matches for recall: each
+/// score adds weighted bm25 relevance, recency of the last update, read
+/// count, boosts for pitfalls and standing decisions and feedback given for
+/// similar queries, less a penalty for superseded pages. Results come best
+/// first, each with its breakdown.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` without a text query, or `KbError::Db`
+/// if a query fails.
+pub fn recall(conn: &Connection, params: &SearchParams, weights: &RecallWeights) -> Result<Vec<RecallResult>, KbError> {
+    let Some(query) = params.query.as_deref() else {
+        return Err(KbError::InvalidInput("Recall needs a search query".to_string()));
+    };
+    let results = search_pages(conn, params)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT p.id, bm25(pages_fts) FROM pages_fts JOIN pages p ON p.rowid = pages_fts.rowid \
+         WHERE pages_fts MATCH ?1",
+    )?;
+    // bm25() is negative, more so for better matches.
+    let relevance: HashMap<String, f64> = stmt
+        .query_map([fts_phrase(query)], |row| Ok((row.get::<_, String>(0)?, -row.get::<_, f64>(1)?)))?
+        .collect::<Result<_, _>>()?;
+    // Normalize against the best of the pages that passed the filters.
+    let best_relevance = results
+        .iter()
+        .filter_map(|r| relevance.get(&r.page.id))
+        .cloned()
+        .fold(0.0, f64::max);
+
+    let mut reads = Vec::with_capacity(results.len());
+    for result in &results {
+        reads.push(repo::read_count(conn, &result.page.id)?);
+    }
+    let most_reads = reads.iter().copied().max().unwrap_or(0);
+    let keywords = extract_keywords(query);
+
+    let now = chrono::Utc::now();
+    let mut ranked = Vec::with_capacity(results.len());
+    for (result, reads) in results.into_iter().zip(reads) {
+        let page = &result.page;
+        let mut breakdown = ScoreBreakdown::default();
+        if best_relevance > 0.0 {
+            let bm25 = relevance.get(&page.id).copied().unwrap_or(0.0);
+            breakdown.relevance = weights.relevance * bm25 / best_relevance;
+        }
+        if let Some(updated) = parse_timestamp(&page.updated_at) {
+            let age_days = (now - updated).num_seconds().max(0) as f64 / 86_400.0;
+            breakdown.recency = weights.recency * 0.5f64.powf(age_days / weights.half_life_days);
+        }
+        if most_reads > 0 {
+            breakdown.usage = weights.usage * (reads as f64).ln_1p() / (most_reads as f64).ln_1p();
+        }
+        if page.labels.iter().any(|l| l == PITFALL_LABEL) {
+            breakdown.pitfall = weights.pitfall;
+        }
+        if page.page_type == PageType::Decision && is_current_decision(conn, page)? {
+            breakdown.accepted = weights.accepted;
+        }
+        if is_superseded(conn, page)? {
+            breakdown.superseded = -weights.superseded;
+        }
+        // Each verdict counts as much as its query resembles this one; the
+        // net tends to +/-1 as verdicts agree.
+        let net: f64 = repo::list_feedback(conn, &page.id)?
+            .iter()
+            .map(|(past, useful)| {
+                let similarity = query_similarity(&keywords, &extract_keywords(past));
+                if *useful { similarity } else { -similarity }
+            })
+            .sum();
+        breakdown.feedback = weights.feedback * net / (1.0 + net.abs());
+        let score = breakdown.relevance
+            + breakdown.recency
+            + breakdown.usage
+            + breakdown.pitfall
+            + breakdown.accepted
+            + breakdown.superseded
+            + breakdown.feedback;
+        ranked.push(RecallResult { result, score, breakdown });
+    }
+
+    ranked.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.result.page.updated_at.cmp(&a.result.page.updated_at))
+            .then_with(|| a.result.page.id.cmp(&b.result.page.id))
+    });
+    Ok(ranked)
+}
+
+/// The share of the two queries' keywords they have in common (Jaccard).
+fn query_similarity(a: &[String], b: &[String]) -> f64 {
+    let shared = a.iter().filter(|k| b.contains(k)).count();
+    let total = a.len() + b.len() - shared;
+    if total == 0 {
+        0.0
+    } else {
+        shared as f64 / total as f64
+    }
+}
+
+/// Whether another page supersedes this one, or it's labelled superseded.
+fn is_superseded(conn: &Connection, page: &Page) -> Result<bool, KbError> {
+    if page.labels.iter().any(|l| l == "status:superseded") {
+        return Ok(true);
+    }
+    Ok(repo::list_backlinks(conn, &page.id)?
+        .iter()
+        .any(|l| l.relation == LinkRelation::Supersedes))
+}
+
+/// Parses an RFC 3339 timestamp, or SQLite's `datetime()` format as UTC.
+fn parse_timestamp(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .ok()
+        .or_else(|| {
+            chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .map(|t| t.and_utc())
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -320,6 +548,38 @@ mod tests {
         conn.execute_batch(migration3_sql)
             .expect("Failed to execute migration 003");
 
+        let migration4_sql = include_str!("../migrations/004_link_policy.sql");
+        conn.execute_batch(migration4_sql)
+            .expect("Failed to execute migration 004");
+
+        let migration5_sql = include_str!("../migrations/005_link_annotations.sql");
+        conn.execute_batch(migration5_sql)
+            .expect("Failed to execute migration 005");
+
+        let migration6_sql = include_str!("../migrations/006_mentions_relation.sql");
+        conn.execute_batch(migration6_sql)
+            .expect("Failed to execute migration 006");
+
+        let migration7_sql = include_str!("../migrations/007_page_sources.sql");
+        conn.execute_batch(migration7_sql)
+            .expect("Failed to execute migration 007");
+
+        let migration8_sql = include_str!("../migrations/008_sessions.sql");
+        conn.execute_batch(migration8_sql)
+            .expect("Failed to execute migration 008");
+
+        let migration9_sql = include_str!("../migrations/009_page_reads.sql");
+        conn.execute_batch(migration9_sql)
+            .expect("Failed to execute migration 009");
+
+        let migration10_sql = include_str!("../migrations/010_feedback.sql");
+        conn.execute_batch(migration10_sql)
+            .expect("Failed to execute migration 010");
+
+        let migration11_sql = include_str!("../migrations/011_merge_bases.sql");
+        conn.execute_batch(migration11_sql)
+            .expect("Failed to execute migration 011");
+
         conn
     }
 
@@ -763,4 +1023,126 @@ mod tests {
         assert_eq!(results.len(), 1);
         assert!(results[0].page.sections.is_some());
     }
+
+    fn recall_page(conn: &Connection, space_id: &str, title: &str, page_type: PageType, labels: &[String]) -> Page {
+        repo::create_page(
+            conn, space_id, None, title, page_type, "Cache invalidation notes.", None, labels, "u", "a",
+        ).expect("create page")
+    }
+
+    fn recall_params(query: Option<&str>) -> SearchParams {
+        SearchParams {
+            query: query.map(str::to_string),
+            space_id: None,
+            page_type: None,
+            label: None,
+            created_by_agent: None,
+            section: None,
+        }
+    }
+
+    #[test]
+    fn test_recall_scores_status_recency_and_usage() {
+        let conn = setup_test_db();
+        let space = repo::create_space(&conn, "s", "S", "").expect("create space");
+        let plain = recall_page(&conn, &space.id, "Plain", PageType::Reference, &[]);
+        let pitfall = recall_page(&conn, &space.id, "Trap", PageType::Troubleshooting, &["pitfall".to_string()]);
+        let old = recall_page(&conn, &space.id, "Old", PageType::Decision, &[]);
+        let new = recall_page(&conn, &space.id, "New", PageType::Decision, &["status:accepted".to_string()]);
+        repo::create_link(&conn, &new.id, &old.id, LinkRelation::Supersedes, "", "u", "a").expect("link");
+        conn.execute("UPDATE pages SET updated_at = '2000-01-01 00:00:00' WHERE id = ?1", [&plain.id])
+            .expect("age page");
+        repo::record_reads(&conn, &[&plain.id, &plain.id, &pitfall.id]).expect("reads");
+
+        let weights = RecallWeights::default();
+        let results = recall(&conn, &recall_params(Some("cache")), &weights).expect("recall");
+        let by_id: HashMap<&str, &RecallResult> = results.iter().map(|r| (r.result.page.id.as_str(), r)).collect();
+
+        let b = &by_id[pitfall.id.as_str()].breakdown;
+        assert_eq!(b.pitfall, weights.pitfall);
+        assert!(b.recency > 0.49, "fresh page keeps its recency: {}", b.recency);
+        assert!(b.usage > 0.0 && b.usage < weights.usage);
+        assert_eq!(by_id[new.id.as_str()].breakdown.accepted, weights.accepted);
+        assert_eq!(by_id[old.id.as_str()].breakdown.superseded, -weights.superseded);
+        assert_eq!(by_id[old.id.as_str()].breakdown.accepted, 0.0);
+
+        let b = &by_id[plain.id.as_str()].breakdown;
+        assert_eq!(b.usage, weights.usage);
+        assert!(b.recency < 0.001, "stale page loses its recency: {}", b.recency);
+        assert!(b.relevance > 0.0 && b.relevance <= weights.relevance);
+
+        for r in &results {
+            let b = &r.breakdown;
+            let sum = b.relevance + b.recency + b.usage + b.pitfall + b.accepted + b.superseded + b.feedback;
+            assert!((r.score - sum).abs() < 1e-9);
+        }
+        assert_eq!(results[0].result.page.id, pitfall.id);
+        assert_eq!(results.last().expect("results").result.page.id, old.id);
+        assert_eq!(repo::read_count(&conn, &plain.id).expect("count"), 2);
+    }
+
+    #[test]
+    fn test_recall_feedback_for_similar_queries() {
+        let conn = setup_test_db();
+        let space = repo::create_space(&conn, "s", "S", "").expect("create space");
+        let helpful = recall_page(&conn, &space.id, "Helpful", PageType::Reference, &[]);
+        let unhelpful = recall_page(&conn, &space.id, "Unhelpful", PageType::Reference, &[]);
+        let identity = crate::models::AgentIdentity { user: "u".to_string(), agent: "a".to_string(), session: None };
+        repo::record_feedback(&conn, &helpful.id, "cache invalidation", true, &identity).expect("feedback");
+        repo::record_feedback(&conn, &unhelpful.id, "cache invalidation", false, &identity).expect("feedback");
+        repo::record_feedback(&conn, &unhelpful.id, "deploy rollback", true, &identity).expect("feedback");
+
+        let weights = RecallWeights::default();
+        let results = recall(&conn, &recall_params(Some("invalidation")), &weights).expect("recall");
+        let feedback = |id: &str| results.iter().find(|r| r.result.page.id == id).expect("result").breakdown.feedback;
+        // "invalidation" shares one of "cache invalidation"'s two keywords.
+        assert!((feedback(&helpful.id) - weights.feedback * 0.5 / 1.5).abs() < 1e-9);
+        assert!((feedback(&unhelpful.id) + weights.feedback * 0.5 / 1.5).abs() < 1e-9);
+        assert_eq!(results[0].result.page.id, helpful.id);
+
+        let unrelated = recall(&conn, &recall_params(Some("notes")), &weights).expect("recall");
+        assert!(unrelated.iter().all(|r| r.breakdown.feedback == 0.0));
+    }
+
+    #[test]
+    fn test_recall_normalizes_relevance_within_filters() {
+        let conn = setup_test_db();
+        let space = repo::create_space(&conn, "s", "S", "").expect("create space");
+        let other = repo::create_space(&conn, "o", "O", "").expect("create space");
+        let page = recall_page(&conn, &space.id, "Notes", PageType::Reference, &[]);
+        repo::create_page(
+            &conn, &other.id, None, "Cache", PageType::Reference, "Cache cache cache.", None, &[], "u", "a",
+        ).expect("create page");
+
+        let weights = RecallWeights::default();
+        let params = SearchParams { space_id: Some(space.id.clone()), ..recall_params(Some("cache")) };
+        let results = recall(&conn, &params, &weights).expect("recall");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].result.page.id, page.id);
+        // The best match in the space gets the full weight, whatever other
+        // spaces hold.
+        assert!((results[0].breakdown.relevance - weights.relevance).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_recall_requires_query() {
+        let conn = setup_test_db();
+        let result = recall(&conn, &recall_params(None), &RecallWeights::default());
+        assert!(matches!(result, Err(KbError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_recall_weight_overrides() {
+        let weights = RecallWeights::default().with_overrides("recency=1, usage=0,half-life-days=7").expect("parse");
+        assert_eq!((weights.recency, weights.usage, weights.half_life_days), (1.0, 0.0, 7.0));
+        assert_eq!(weights.relevance, RecallWeights::default().relevance);
+
+        for bad in ["bogus=1", "recency", "recency=-1", "recency=x", "half-life-days=0"] {
+            assert!(
+                matches!(RecallWeights::default().with_overrides(bad), Err(KbError::InvalidInput(_))),
+                "{}",
+                bad
+            );
+        }
+    }
 }
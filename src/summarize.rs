@@ -0,0 +1,465 @@
+//! Session summaries for transcript ingestion.
+//!
+//! A `Summarizer` turns a parsed transcript into a `Summary`. Two are built
+//! in:
+//!
+//! - `Extractive` works offline. It splits the conversation into sentences,
+//!   scores each by how many of the session's frequent terms it contains
+//!   (normalised for length, with a boost for the opening request and the
+//!   closing reply) and keeps the best few in their original order.
+//! - `ExternalCommand` runs a shell command with the conversation on stdin,
+//!   e.g. a script that calls an LLM. It may print a JSON object with
+//!   `title`, `summary`, `labels` and `open_questions`, or plain text, which
+//!   is taken as the summary.
+//!
+//! Ingestion uses the external command when one is configured (`--summarizer`
+//! or `KB_SUMMARIZER`) and falls back to the extractive summary if it fails
+//! or times out, so a session is always recorded.
+
+use crate::context::extract_keywords_with;
+use crate::db::KbError;
+use crate::transcript::{Speaker, Transcript};
+use std::collections::HashMap;
+use std::io::{Read, Write as _};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Most characters of conversation sent to an external summarizer: the end
+/// of the session is kept, where the outcome is.
+const MAX_COMMAND_INPUT: usize = 50_000;
+
+/// How long an external summarizer may run: under the 60 seconds Claude Code
+/// gives the session-end hook, leaving time for the extractive fallback.
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Sentences shorter or longer than these (in characters) aren't used in an
+/// extractive summary: the short ones say little, the long ones are usually
+/// pasted output.
+const MIN_SENTENCE_CHARS: usize = 20;
+const MAX_SENTENCE_CHARS: usize = 300;
+
+/// Openings of assistant narration, which describes the next step rather
+/// than what was done.
+const FILLER_PREFIXES: &[&str] = &["let me", "let's", "now let", "i'll", "i will", "i'm going to", "next, i"];
+
+/// What a summarizer has to say about a session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// A title for the session's page, if the summarizer suggests one.
+    pub title: Option<String>,
+    pub summary: String,
+    /// Extra labels for the page.
+    pub labels: Vec<String>,
+    /// Unresolved questions; when empty, ingestion lists the questions in
+    /// the assistant's last reply instead.
+    pub open_questions: Vec<String>,
+}
+
+/// Produces a summary of a session.
+pub trait Summarizer {
+    /// Name reported in the ingestion output.
+    fn name(&self) -> &'static str;
+
+    /// Summarizes a transcript.
+    ///
+    /// # Errors
+    /// Implementations return an error if they can't produce a summary;
+    /// ingestion then falls back to `Extractive`.
+    fn summarize(&self, transcript: &Transcript) -> Result<Summary, KbError>;
+}
+
+/// Offline summarizer picking the most representative sentences.
+#[derive(Debug, Clone)]
+pub struct Extractive {
+    /// Most sentences in the summary.
+    pub max_sentences: usize,
+}
+
+impl Default for Extractive {
+    fn default() -> Self {
+        Self { max_sentences: 5 }
+    }
+}
+
+/// A candidate sentence for an extractive summary.
+struct Sentence {
+    text: String,
+    terms: Vec<String>,
+    /// Multiplier for where the sentence appears in the session.
+    weight: f64,
+}
+
+impl Summarizer for Extractive {
+    fn name(&self) -> &'static str {
+        "extractive"
+    }
+
+    fn summarize(&self, transcript: &Transcript) -> Result<Summary, KbError> {
+        let first_user = transcript.turns.iter().position(|t| t.speaker == Speaker::User);
+        let last_assistant = transcript.turns.iter().rposition(|t| t.speaker == Speaker::Assistant);
+
+        let mut sentences = Vec::new();
+        for (i, turn) in transcript.turns.iter().enumerate() {
+            let weight = if Some(i) == first_user {
+                1.5
+            } else if Some(i) == last_assistant {
+                1.25
+            } else {
+                1.0
+            };
+            for text in split_sentences(&turn.text) {
+                let lower = text.to_lowercase();
+                if text.chars().count() < MIN_SENTENCE_CHARS
+                    || text.chars().count() > MAX_SENTENCE_CHARS
+                    || text.ends_with('?')
+                    || FILLER_PREFIXES.iter().any(|p| lower.starts_with(p))
+                {
+                    continue;
+                }
+                let terms = extract_keywords_with(&text, 3, usize::MAX);
+                if !terms.is_empty() {
+                    sentences.push(Sentence { text, terms, weight });
+                }
+            }
+        }
+
+        let mut frequency: HashMap<&str, usize> = HashMap::new();
+        for sentence in &sentences {
+            for term in &sentence.terms {
+                *frequency.entry(term.as_str()).or_default() += 1;
+            }
+        }
+        let score = |s: &Sentence| {
+            let total: usize = s.terms.iter().map(|t| frequency[t.as_str()]).sum();
+            s.weight * total as f64 / (s.terms.len() as f64).sqrt()
+        };
+
+        let mut ranked: Vec<(usize, f64)> = sentences.iter().enumerate().map(|(i, s)| (i, score(s))).collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        let mut chosen: Vec<usize> = Vec::new();
+        for (i, _) in ranked {
+            if chosen.len() == self.max_sentences {
+                break;
+            }
+            if !chosen.iter().any(|&c| sentences[c].text == sentences[i].text) {
+                chosen.push(i);
+            }
+        }
+        chosen.sort();
+
+        let summary = if chosen.is_empty() {
+            "No summary available.".to_string()
+        } else {
+            chosen.iter().map(|&i| sentences[i].text.as_str()).collect::<Vec<_>>().join(" ")
+        };
+        Ok(Summary {
+            summary,
+            ..Summary::default()
+        })
+    }
+}
+
+/// Splits text into sentences. Lines wrapped within a paragraph are joined
+/// first; list items stand alone, with their markers stripped. Code blocks,
+/// headings and table rows are skipped.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        let line = line.trim();
+        let is_item = line.starts_with("- ") || line.starts_with("* ") || line.starts_with("> ");
+        let fence = line.starts_with("```");
+        let skipped = in_code || fence || line.is_empty() || line.starts_with('#') || line.starts_with('|');
+        if (skipped || is_item) && !current.is_empty() {
+            paragraphs.push(std::mem::take(&mut current));
+        }
+        if fence {
+            in_code = !in_code;
+        }
+        if skipped {
+            continue;
+        }
+        if is_item {
+            paragraphs.push(line[2..].trim().to_string());
+        } else {
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(line);
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+
+    let mut sentences = Vec::new();
+    for paragraph in &paragraphs {
+        let chars: Vec<(usize, char)> = paragraph.char_indices().collect();
+        let mut start = 0;
+        for (n, &(pos, c)) in chars.iter().enumerate() {
+            let at_end = n + 1 == chars.len();
+            let boundary = matches!(c, '.' | '!' | '?') && (at_end || chars[n + 1].1.is_whitespace());
+            if boundary || at_end {
+                let sentence = paragraph[start..pos + c.len_utf8()].trim();
+                if !sentence.is_empty() {
+                    sentences.push(sentence.to_string());
+                }
+                start = pos + c.len_utf8();
+            }
+        }
+    }
+    sentences
+}
+
+/// Summarizer that runs a shell command.
+#[derive(Debug, Clone)]
+pub struct ExternalCommand {
+    pub command: String,
+    /// How long the command may run before it's killed.
+    pub timeout: Duration,
+}
+
+impl Summarizer for ExternalCommand {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn summarize(&self, transcript: &Transcript) -> Result<Summary, KbError> {
+        let conversation = transcript.conversation_text();
+        let skip = conversation.chars().count().saturating_sub(MAX_COMMAND_INPUT);
+        let input: String = conversation.chars().skip(skip).collect();
+
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Feed stdin and drain stdout and stderr on their own threads, so a
+        // command that writes before it has read everything can't block on
+        // a full pipe while we block on its stdin.
+        if let Some(mut stdin) = child.stdin.take() {
+            thread::spawn(move || {
+                // A command that doesn't read its input closes the pipe
+                // early; its output still counts.
+                let _ = stdin.write_all(input.as_bytes());
+            });
+        }
+        let stdout = child.stdout.take().map(read_on_thread);
+        let stderr = child.stderr.take().map(read_on_thread);
+
+        let deadline = Instant::now() + self.timeout;
+        let timed_out = || {
+            KbError::InvalidInput(format!("Summarizer command timed out after {}s", self.timeout.as_secs_f64()))
+        };
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(timed_out());
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        // Something the command started in the background may still hold
+        // the pipes open, so the deadline covers reading them too.
+        let read = |rx: Option<mpsc::Receiver<Vec<u8>>>| match rx {
+            Some(rx) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())).map_err(|_| timed_out()),
+            None => Ok(Vec::new()),
+        };
+        let (stdout, stderr) = (read(stdout)?, read(stderr)?);
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            return Err(KbError::InvalidInput(format!(
+                "Summarizer command exited with {}: {}",
+                status,
+                stderr.trim()
+            )));
+        }
+        parse_command_output(&String::from_utf8_lossy(&stdout))
+    }
+}
+
+/// Reads `pipe` to the end on a new thread, sending what it read.
+fn read_on_thread<R: Read + Send + 'static>(mut pipe: R) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        let _ = tx.send(buf);
+    });
+    rx
+}
+
+/// Reads a summarizer command's output: a JSON object (optionally in a
+/// Markdown code fence), or plain text taken as the summary.
+fn parse_command_output(output: &str) -> Result<Summary, KbError> {
+    let text = output.trim();
+    let unfenced = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|t| t.strip_suffix("```"))
+        .unwrap_or(text)
+        .trim();
+
+    let Ok(serde_json::Value::Object(obj)) = serde_json::from_str::<serde_json::Value>(unfenced) else {
+        if text.is_empty() {
+            return Err(KbError::InvalidInput("Summarizer command printed nothing".to_string()));
+        }
+        return Ok(Summary {
+            summary: text.to_string(),
+            ..Summary::default()
+        });
+    };
+
+    // Models are inconsistent about lists: accept arrays, comma-separated
+    // labels and bulleted questions.
+    let list = |key: &str, separator: char| -> Vec<String> {
+        let items: Vec<String> = match obj.get(key) {
+            Some(serde_json::Value::Array(items)) => items.iter().filter_map(|i| i.as_str()).map(str::to_string).collect(),
+            Some(serde_json::Value::String(s)) => s.split(separator).map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+        items
+            .iter()
+            .map(|i| i.trim().trim_start_matches(['-', '*']).trim().to_string())
+            .filter(|i| !i.is_empty() && !i.eq_ignore_ascii_case("none"))
+            .collect()
+    };
+
+    let summary = obj.get("summary").and_then(|v| v.as_str()).unwrap_or_default().trim().to_string();
+    if summary.is_empty() {
+        return Err(KbError::InvalidInput("Summarizer output has no summary".to_string()));
+    }
+    Ok(Summary {
+        title: obj.get("title").and_then(|v| v.as_str()).map(str::trim).filter(|t| !t.is_empty()).map(str::to_string),
+        summary,
+        labels: list("labels", ','),
+        open_questions: list("open_questions", '\n'),
+    })
+}
+
+/// The summarizer to use: `command` if given, otherwise `KB_SUMMARIZER` if
+/// set, otherwise `Extractive`.
+pub fn configured(command: Option<&str>) -> Box<dyn Summarizer> {
+    let command = command
+        .map(str::to_string)
+        .or_else(|| std::env::var("KB_SUMMARIZER").ok())
+        .filter(|c| !c.trim().is_empty());
+    match command {
+        Some(command) => Box::new(ExternalCommand { command, timeout: COMMAND_TIMEOUT }),
+        None => Box::new(Extractive::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::Turn;
+
+    fn transcript(turns: &[(Speaker, &str)]) -> Transcript {
+        Transcript {
+            turns: turns
+                .iter()
+                .map(|(speaker, text)| Turn {
+                    speaker: *speaker,
+                    text: text.to_string(),
+                })
+                .collect(),
+            ..Transcript::default()
+        }
+    }
+
+    #[test]
+    fn test_split_sentences() {
+        let text = "First sentence here. Second one\nwraps onto a new line!\n- A list item\n```\ncode. here.\n```\n# Heading\nVersion 1.2 is out";
+        assert_eq!(
+            split_sentences(text),
+            vec!["First sentence here.", "Second one wraps onto a new line!", "A list item", "Version 1.2 is out"]
+        );
+    }
+
+    #[test]
+    fn test_extractive_keeps_central_sentences_in_order() {
+        let t = transcript(&[
+            (Speaker::User, "The importer drops labels when parsing frontmatter. Thanks in advance for the help."),
+            (Speaker::Assistant, "Let me look at the importer code first."),
+            (Speaker::Assistant, "The weather output is unrelated to anything else here."),
+            (Speaker::Assistant, "The importer parsed labels as a string, so frontmatter labels were dropped."),
+            (Speaker::Assistant, "Fixed the importer to read labels from frontmatter lists. Should I add a test?"),
+        ]);
+        let summary = Extractive { max_sentences: 3 }.summarize(&t).unwrap();
+        assert_eq!(
+            summary.summary,
+            "The importer drops labels when parsing frontmatter. \
+             The importer parsed labels as a string, so frontmatter labels were dropped. \
+             Fixed the importer to read labels from frontmatter lists."
+        );
+        assert_eq!(summary.title, None);
+
+        let empty = Extractive::default().summarize(&Transcript::default()).unwrap();
+        assert_eq!(empty.summary, "No summary available.");
+    }
+
+    #[test]
+    fn test_parse_command_output() {
+        let json = "```json\n{\"title\": \"Fix importer\", \"summary\": \"Fixed it.\", \"labels\": \"import, bug-fix\", \"open_questions\": \"- Add tests?\\n- None\"}\n```";
+        let summary = parse_command_output(json).unwrap();
+        assert_eq!(summary.title.as_deref(), Some("Fix importer"));
+        assert_eq!(summary.summary, "Fixed it.");
+        assert_eq!(summary.labels, vec!["import", "bug-fix"]);
+        assert_eq!(summary.open_questions, vec!["Add tests?"]);
+
+        assert_eq!(parse_command_output("Just text.\n").unwrap().summary, "Just text.");
+        assert!(parse_command_output("  ").is_err());
+        assert!(parse_command_output("{\"title\": \"x\"}").is_err());
+    }
+
+    #[test]
+    fn test_external_command_receives_conversation() {
+        let t = transcript(&[(Speaker::User, "Fix the importer"), (Speaker::Assistant, "Done.")]);
+        let command = ExternalCommand {
+            command: "printf '{\"summary\": \"%s\"}' \"$(grep -c '^USER:')\"".to_string(),
+            timeout: COMMAND_TIMEOUT,
+        };
+        assert_eq!(command.summarize(&t).unwrap().summary, "1");
+
+        let failing = ExternalCommand {
+            command: "echo boom >&2; exit 3".to_string(),
+            timeout: COMMAND_TIMEOUT,
+        };
+        let err = failing.summarize(&t).unwrap_err().to_string();
+        assert!(err.contains("boom"), "{}", err);
+    }
+
+    #[test]
+    fn test_external_command_times_out() {
+        let t = transcript(&[(Speaker::User, "Fix the importer")]);
+        let slow = ExternalCommand {
+            command: "sleep 10".to_string(),
+            timeout: Duration::from_millis(200),
+        };
+        let started = Instant::now();
+        let err = slow.summarize(&t).unwrap_err().to_string();
+        assert!(err.contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_external_command_echoing_long_input() {
+        // Larger than a pipe buffer, echoed back as it's read.
+        let long = "é".repeat(MAX_COMMAND_INPUT);
+        let t = transcript(&[(Speaker::User, long.as_str())]);
+        let echo = ExternalCommand {
+            command: "cat".to_string(),
+            timeout: COMMAND_TIMEOUT,
+        };
+        assert!(echo.summarize(&t).unwrap().summary.ends_with("ééé"));
+    }
+}
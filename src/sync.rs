@@ -0,0 +1,1105 @@
+//! Git-backed sync of the knowledge base.
+//!
+//! `sync` keeps a git working tree holding the whole knowledge base in a
+//! deterministic, diff-friendly layout:
+//!
+//! ```text
+//! repo/
+//!   <space-slug>/
+//!     space.json
+//!     links.json            links from this space's pages, sorted
+//!     pages/<page-id>.md    frontmatter (metadata, labels, sections) + content
+//! ```
+//!
+//! Each sync writes the database to the tree and commits any changes as the
+//! acting identity. If the repository has an `origin` remote, the remote
+//! branch is then fetched and merged record by record against the common
+//! ancestor: a record changed on one side takes that side's version, and a
+//! page changed on both sides is merged field by field, with a three-way text
+//! merge of its content. The result is written to the database, committed as
+//! a merge and pushed.
+//!
+//! The tree belongs to `sync`: hand edits to its files are replaced by the
+//! database's state on the next sync.
+
+use crate::db::KbError;
+use crate::models::{
+    sections_to_content, AgentIdentity, Link, Page, PageType, Space, SyncConflict, SyncCounts, SyncReport,
+};
+use crate::{archive, markdown, repo, wikilinks};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::Write as _;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The remote that `sync` pulls from and pushes to.
+pub const REMOTE: &str = "origin";
+
+/// Every space, page and link, keyed for three-way comparison.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub spaces: BTreeMap<String, Space>,
+    pub pages: BTreeMap<String, Page>,
+    pub links: BTreeMap<(String, String), Link>,
+}
+
+/// Page metadata stored in a page file's frontmatter. The body is the
+/// page's content.
+#[derive(Debug, Serialize, Deserialize)]
+struct PageHeader {
+    id: String,
+    title: String,
+    #[serde(rename = "type")]
+    page_type: PageType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sections: Option<serde_json::Value>,
+    created_by_user: String,
+    created_by_agent: String,
+    created_at: String,
+    updated_at: String,
+    version: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+}
+
+/// Syncs the knowledge base with the git repository at `dir`.
+///
+/// The repository is created if needed. When `remote` is given it becomes
+/// the repository's `origin`. Without an `origin`, a sync only commits.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `dir` - The repository's working tree
+/// * `remote` - Optional URL or path to use as `origin`
+/// * `push` - Push the result to `origin`
+/// * `identity` - Author of the commits, and creator of any mention links re-derived from merged content
+///
+/// # Returns
+/// The commits made, the changes pulled into the database, and any pages
+/// that conflicted
+///
+/// # Errors
+/// Returns `KbError::Git` if a git command fails, or `KbError::InvalidInput`
+/// if a file in the repository can't be read as part of a knowledge base.
+/// The database is unchanged if the merge can't be applied.
+pub fn sync(
+    conn: &Connection,
+    dir: &Path,
+    remote: Option<&str>,
+    push: bool,
+    identity: &AgentIdentity,
+) -> Result<SyncReport, KbError> {
+    fs::create_dir_all(dir)?;
+    let git = Git::new(dir, identity);
+    // Inside another repository, `dir` still gets one of its own.
+    let toplevel = git.try_run(&["rev-parse", "--show-toplevel"])?;
+    if toplevel.map(|t| Path::new(&t).canonicalize()).transpose()? != Some(dir.canonicalize()?) {
+        git.run(&["init", "-q"])?;
+    }
+    if let Some(url) = remote {
+        if git.try_run(&["remote", "get-url", REMOTE])?.is_some() {
+            git.run(&["remote", "set-url", REMOTE, url])?;
+        } else {
+            git.run(&["remote", "add", REMOTE, url])?;
+        }
+    }
+
+    let mut report = SyncReport {
+        repo: dir.display().to_string(),
+        ..Default::default()
+    };
+    let ours = snapshot_db(conn)?;
+    write_tree(dir, &render_files(&ours))?;
+    report.committed = git.commit_layout("Sync knowledge base")?;
+
+    let has_remote = git.try_run(&["remote", "get-url", REMOTE])?.is_some();
+    if !has_remote {
+        return Ok(report);
+    }
+    git.run(&["fetch", "-q", REMOTE])?;
+    let branch = git
+        .try_run(&["symbolic-ref", "--short", "HEAD"])?
+        .ok_or_else(|| KbError::Git("HEAD is detached; check out a branch first".to_string()))?;
+    let upstream = format!("refs/remotes/{}/{}", REMOTE, branch);
+    let head = git.try_run(&["rev-parse", "--verify", "--quiet", "HEAD"])?;
+
+    if let Some(theirs_rev) = git.try_run(&["rev-parse", "--verify", "--quiet", &upstream])? {
+        let up_to_date = match &head {
+            Some(head) => git.is_ancestor(&theirs_rev, head)?,
+            None => false,
+        };
+        if !up_to_date {
+            let base = match &head {
+                Some(head) => match git.try_run(&["merge-base", head, &theirs_rev])? {
+                    Some(rev) => parse_files(&git.read_rev(&rev)?)?,
+                    None => Snapshot::default(),
+                },
+                None => Snapshot::default(),
+            };
+            let theirs = parse_files(&git.read_rev(&theirs_rev)?)?;
+
+            let merged = merge_snapshots(&base, &ours, &theirs, &mut report);
+            let changed = apply(conn, &ours, &merged, &mut report)?;
+            for page_id in &changed {
+                wikilinks::sync_mentions(conn, page_id, identity)?;
+            }
+
+            let fast_forward = match &head {
+                Some(head) => git.is_ancestor(head, &theirs_rev)?,
+                None => true,
+            };
+            if fast_forward {
+                git.run(&["reset", "-q", &theirs_rev])?;
+            } else {
+                git.run(&["merge", "-q", "--no-commit", "-s", "ours", "--allow-unrelated-histories", &theirs_rev])?;
+            }
+            write_tree(dir, &render_files(&snapshot_db(conn)?))?;
+            let message = format!("Merge {}/{} into knowledge base", REMOTE, branch);
+            report.merged = match git.commit_layout(&message)? {
+                Some(commit) => Some(commit),
+                None => Some(theirs_rev.clone()),
+            };
+        }
+    }
+
+    if push && git.try_run(&["rev-parse", "--verify", "--quiet", "HEAD"])?.is_some() {
+        git.run(&["push", "-q", REMOTE, &format!("HEAD:refs/heads/{}", branch)])?;
+        report.pushed = true;
+    }
+    Ok(report)
+}
+
+// =============================================================================
+// Layout
+// =============================================================================
+
+/// Reads every space, page and link in the database.
+pub fn snapshot_db(conn: &Connection) -> Result<Snapshot, KbError> {
+    let archive = archive::export(conn, None)?;
+    Ok(Snapshot {
+        spaces: archive.spaces.into_iter().map(|s| (s.id.clone(), s)).collect(),
+        pages: archive
+            .pages
+            .into_iter()
+            .map(|mut p| {
+                p.labels.sort();
+                (p.id.clone(), p)
+            })
+            .collect(),
+        links: archive
+            .links
+            .into_iter()
+            .map(|l| ((l.source_id.clone(), l.target_id.clone()), l))
+            .collect(),
+    })
+}
+
+/// Renders a snapshot as files, keyed by path relative to the repository root.
+pub fn render_files(snapshot: &Snapshot) -> BTreeMap<String, String> {
+    let dirs: HashMap<&str, String> = snapshot
+        .spaces
+        .values()
+        .map(|s| (s.id.as_str(), space_dir(s)))
+        .collect();
+    let mut files = BTreeMap::new();
+    for space in snapshot.spaces.values() {
+        files.insert(format!("{}/space.json", dirs[space.id.as_str()]), to_json(space));
+    }
+
+    let mut page_dirs: HashMap<&str, &str> = HashMap::new();
+    for page in snapshot.pages.values() {
+        let Some(dir) = dirs.get(page.space_id.as_str()) else {
+            continue;
+        };
+        page_dirs.insert(&page.id, dir);
+        files.insert(format!("{}/pages/{}.md", dir, file_stem(&page.id)), render_page(page));
+    }
+
+    let mut links: BTreeMap<&str, Vec<&Link>> = BTreeMap::new();
+    for link in snapshot.links.values() {
+        if let Some(dir) = page_dirs.get(link.source_id.as_str()) {
+            links.entry(dir).or_default().push(link);
+        }
+    }
+    for (dir, links) in links {
+        files.insert(format!("{}/links.json", dir), to_json(&links));
+    }
+    files
+}
+
+/// Parses files written by `render_files`. Files outside the layout are ignored.
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` naming the file if a layout file can't be
+/// parsed, or if pages sit in a folder without a `space.json`.
+pub fn parse_files(files: &BTreeMap<String, String>) -> Result<Snapshot, KbError> {
+    let invalid = |path: &str, e: &dyn std::fmt::Display| KbError::InvalidInput(format!("{}: {}", path, e));
+    let mut snapshot = Snapshot::default();
+    let mut space_ids: HashMap<&str, String> = HashMap::new();
+    for (path, text) in files {
+        if let [dir, "space.json"] = path.split('/').collect::<Vec<_>>()[..] {
+            let space: Space = serde_json::from_str(text).map_err(|e| invalid(path, &e))?;
+            space_ids.insert(dir, space.id.clone());
+            snapshot.spaces.insert(space.id.clone(), space);
+        }
+    }
+
+    for (path, text) in files {
+        match path.split('/').collect::<Vec<_>>()[..] {
+            [dir, "pages", name] if name.ends_with(".md") => {
+                let space_id = space_ids
+                    .get(dir)
+                    .ok_or_else(|| invalid(path, &"no space.json in this folder"))?;
+                let page = parse_page(text, space_id).map_err(|e| invalid(path, &e))?;
+                snapshot.pages.insert(page.id.clone(), page);
+            }
+            [_, "links.json"] => {
+                let links: Vec<Link> = serde_json::from_str(text).map_err(|e| invalid(path, &e))?;
+                for link in links {
+                    snapshot.links.insert((link.source_id.clone(), link.target_id.clone()), link);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(snapshot)
+}
+
+fn render_page(page: &Page) -> String {
+    let header = PageHeader {
+        id: page.id.clone(),
+        title: page.title.clone(),
+        page_type: page.page_type,
+        parent: page.parent_id.clone(),
+        labels: page.labels.clone(),
+        sections: page.sections.clone(),
+        created_by_user: page.created_by_user.clone(),
+        created_by_agent: page.created_by_agent.clone(),
+        created_at: page.created_at.clone(),
+        updated_at: page.updated_at.clone(),
+        version: page.version,
+        session_id: page.session_id.clone(),
+    };
+    let yaml = serde_yaml::to_string(&header).expect("failed to serialize frontmatter");
+    format!("---\n{}---\n\n{}\n", yaml, page.content)
+}
+
+fn parse_page(text: &str, space_id: &str) -> Result<Page, KbError> {
+    let (yaml, content) = markdown::split_frontmatter(text)?;
+    let header: PageHeader =
+        serde_yaml::from_str(&yaml).map_err(|e| KbError::InvalidInput(format!("Invalid frontmatter: {}", e)))?;
+    Ok(Page {
+        id: header.id,
+        space_id: space_id.to_string(),
+        parent_id: header.parent,
+        title: header.title,
+        page_type: header.page_type,
+        content,
+        sections: header.sections,
+        created_by_user: header.created_by_user,
+        created_by_agent: header.created_by_agent,
+        created_at: header.created_at,
+        updated_at: header.updated_at,
+        version: header.version,
+        session_id: header.session_id,
+        labels: header.labels,
+    })
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_string_pretty(value).expect("failed to serialize to JSON") + "\n"
+}
+
+/// A space's folder: its slug, or its ID if the slug isn't a safe file name.
+fn space_dir(space: &Space) -> String {
+    if markdown::slugify(&space.slug) == space.slug {
+        space.slug.clone()
+    } else {
+        file_stem(&space.id)
+    }
+}
+
+/// An ID as a file name: the ID itself, or a hash of it if it isn't a safe
+/// file name. The ID is read back from the file, not the name.
+fn file_stem(id: &str) -> String {
+    if markdown::slugify(id) == id {
+        id.to_string()
+    } else {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(id.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Whether a repository path is one `render_files` could have written.
+fn is_layout_path(path: &str) -> bool {
+    match path.split('/').collect::<Vec<_>>()[..] {
+        [_, "space.json"] | [_, "links.json"] => true,
+        [_, "pages", name] => name.ends_with(".md"),
+        _ => false,
+    }
+}
+
+/// Makes the layout files in `dir` match `files`, leaving other files alone.
+fn write_tree(dir: &Path, files: &BTreeMap<String, String>) -> Result<(), KbError> {
+    let mut existing = Vec::new();
+    collect_files(dir, "", &mut existing)?;
+    for path in existing {
+        if is_layout_path(&path) && !files.contains_key(&path) {
+            let full = dir.join(&path);
+            fs::remove_file(&full)?;
+            // Drop folders left empty; `remove_dir` fails harmlessly on non-empty ones.
+            for folder in full.ancestors().skip(1).take_while(|f| *f != dir) {
+                if fs::remove_dir(folder).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    for (path, text) in files {
+        let full = dir.join(path);
+        if fs::read_to_string(&full).ok().as_deref() == Some(text.as_str()) {
+            continue;
+        }
+        if let Some(parent) = full.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&full, text)?;
+    }
+    Ok(())
+}
+
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), KbError> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if prefix.is_empty() && name == ".git" {
+            continue;
+        }
+        let path = format!("{}{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", path), files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Three-way merge
+// =============================================================================
+
+/// Merges two snapshots that diverged from `base`.
+///
+/// Records changed on only one side take that side's version; a record
+/// changed on one side and deleted on the other is kept. Spaces and links
+/// changed on both sides take the more recently updated version; pages are
+/// merged by `merge_page`. Conflicts and fix-ups are added to `report`.
+pub fn merge_snapshots(base: &Snapshot, ours: &Snapshot, theirs: &Snapshot, report: &mut SyncReport) -> Snapshot {
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut spaces = merge_records(&base.spaces, &ours.spaces, &theirs.spaces, |_, o, t| newer(o, t, |s| &s.updated_at));
+
+    // A space created on both sides with the same slug becomes ours.
+    let mut space_ids: HashMap<String, String> = HashMap::new();
+    let ours_by_slug: HashMap<&str, &str> = ours.spaces.values().map(|s| (s.slug.as_str(), s.id.as_str())).collect();
+    spaces.retain(|id, space| match ours_by_slug.get(space.slug.as_str()) {
+        Some(ours_id) if *ours_id != id && !ours.spaces.contains_key(id) => {
+            space_ids.insert(id.clone(), ours_id.to_string());
+            false
+        }
+        _ => true,
+    });
+
+    let mut pages = BTreeMap::new();
+    let merged_pages = merge_records(&base.pages, &ours.pages, &theirs.pages, |b, o, t| {
+        let (page, fields) = merge_page(b, o, t, &now);
+        if !fields.is_empty() {
+            report.conflicts.push(SyncConflict {
+                page_id: page.id.clone(),
+                title: page.title.clone(),
+                fields,
+            });
+        }
+        page
+    });
+    for (id, mut page) in merged_pages {
+        if let Some(mapped) = space_ids.get(&page.space_id) {
+            page.space_id = mapped.clone();
+        }
+        if !spaces.contains_key(&page.space_id) {
+            // The space was deleted on one side while a page was added or edited in it on the other.
+            match ours.spaces.get(&page.space_id).or_else(|| theirs.spaces.get(&page.space_id)) {
+                Some(space) => {
+                    report
+                        .warnings
+                        .push(format!("Space '{}' kept: page '{}' still uses it", space.slug, page.title));
+                    spaces.insert(space.id.clone(), space.clone());
+                }
+                None => {
+                    report.warnings.push(format!("Page '{}' dropped: its space no longer exists", page.title));
+                    continue;
+                }
+            }
+        }
+        pages.insert(id, page);
+    }
+
+    // A parent deleted on the other side.
+    let orphans: Vec<String> = pages
+        .values()
+        .filter(|p| p.parent_id.as_ref().is_some_and(|parent| !pages.contains_key(parent)))
+        .map(|p| p.id.clone())
+        .collect();
+    for id in orphans {
+        let page = pages.get_mut(&id).expect("page exists");
+        report
+            .warnings
+            .push(format!("Page '{}' moved to top level: its parent no longer exists", page.title));
+        page.parent_id = None;
+    }
+    // Reparenting on both sides can form a cycle; break it at the first page found on it.
+    let ids: Vec<String> = pages.keys().cloned().collect();
+    for id in ids {
+        let mut seen = BTreeSet::new();
+        let mut parent = pages[&id].parent_id.clone();
+        while let Some(p) = parent {
+            if p == id {
+                let page = pages.get_mut(&id).expect("page exists");
+                report
+                    .warnings
+                    .push(format!("Page '{}' moved to top level: its parents formed a cycle", page.title));
+                page.parent_id = None;
+                break;
+            }
+            if !seen.insert(p.clone()) {
+                break;
+            }
+            parent = pages[&p].parent_id.clone();
+        }
+    }
+
+    let links = merge_records(&base.links, &ours.links, &theirs.links, |_, o, t| newer(o, t, |l| &l.updated_at))
+        .into_iter()
+        .filter(|((source, target), _)| pages.contains_key(source) && pages.contains_key(target))
+        .collect();
+
+    Snapshot { spaces, pages, links }
+}
+
+/// Three-way merge of keyed records. `both_changed` resolves records changed
+/// differently on both sides, given the base version if there was one.
+fn merge_records<K: Ord + Clone, V: PartialEq + Clone>(
+    base: &BTreeMap<K, V>,
+    ours: &BTreeMap<K, V>,
+    theirs: &BTreeMap<K, V>,
+    mut both_changed: impl FnMut(Option<&V>, &V, &V) -> V,
+) -> BTreeMap<K, V> {
+    let keys: BTreeSet<&K> = ours.keys().chain(theirs.keys()).collect();
+    let mut merged = BTreeMap::new();
+    for key in keys {
+        let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+        let value = if o == t || b == t {
+            o.cloned()
+        } else if b == o {
+            t.cloned()
+        } else {
+            match (o, t) {
+                (Some(o), Some(t)) => Some(both_changed(b, o, t)),
+                // Changed on one side, deleted on the other: keep the change.
+                (Some(v), None) | (None, Some(v)) => Some(v.clone()),
+                (None, None) => None,
+            }
+        };
+        if let Some(value) = value {
+            merged.insert(key.clone(), value);
+        }
+    }
+    merged
+}
+
+/// The more recently updated of two versions, ours on a tie.
+fn newer<T: Clone>(ours: &T, theirs: &T, updated_at: impl Fn(&T) -> &String) -> T {
+    if archive::is_later(updated_at(theirs), updated_at(ours)) {
+        theirs.clone()
+    } else {
+        ours.clone()
+    }
+}
+
+/// Merges a page edited on both sides, field by field.
+///
+/// Labels are merged as sets. Content, and each structured section, get a
+/// three-way text merge; where both sides changed the same lines, both
+/// versions are kept between conflict markers. Other fields changed on both
+/// sides take the more recently updated side's value. The result is one
+/// version past the higher of the two.
+///
+/// # Returns
+/// The merged page, and the fields that conflicted
+pub fn merge_page(base: Option<&Page>, ours: &Page, theirs: &Page, now: &str) -> (Page, Vec<String>) {
+    let theirs_newer = archive::is_later(&theirs.updated_at, &ours.updated_at);
+    let mut fields = Vec::new();
+    let title = pick("title", base.map(|b| &b.title), &ours.title, &theirs.title, theirs_newer, &mut fields);
+    let page_type = pick("type", base.map(|b| &b.page_type), &ours.page_type, &theirs.page_type, theirs_newer, &mut fields);
+    let space_id = pick("space", base.map(|b| &b.space_id), &ours.space_id, &theirs.space_id, theirs_newer, &mut fields);
+    let parent_id = pick("parent", base.map(|b| &b.parent_id), &ours.parent_id, &theirs.parent_id, theirs_newer, &mut fields);
+
+    let no_sections = serde_json::Map::new();
+    let sections = match (&ours.sections, &theirs.sections) {
+        (Some(serde_json::Value::Object(o)), Some(serde_json::Value::Object(t))) => {
+            let b = base
+                .and_then(|b| b.sections.as_ref())
+                .and_then(|s| s.as_object())
+                .unwrap_or(&no_sections);
+            let keys: BTreeSet<&String> = o.keys().chain(t.keys()).collect();
+            let mut merged = serde_json::Map::new();
+            for key in keys {
+                let field = format!("sections.{}", key);
+                let value = match (o.get(key), t.get(key)) {
+                    (Some(serde_json::Value::String(os)), Some(serde_json::Value::String(ts))) => {
+                        let bs = b.get(key).and_then(|v| v.as_str());
+                        Some(serde_json::Value::String(merge_text(&field, bs, os, ts, &mut fields)))
+                    }
+                    (ov, tv) => pick(&field, Some(&b.get(key).cloned()), &ov.cloned(), &tv.cloned(), theirs_newer, &mut fields),
+                };
+                if let Some(value) = value {
+                    merged.insert(key.clone(), value);
+                }
+            }
+            Some(serde_json::Value::Object(merged))
+        }
+        _ => pick("sections", base.map(|b| &b.sections), &ours.sections, &theirs.sections, theirs_newer, &mut fields),
+    };
+    let content = match &sections {
+        Some(s) => sections_to_content(s, page_type),
+        None => merge_text("content", base.map(|b| b.content.as_str()), &ours.content, &theirs.content, &mut fields),
+    };
+
+    let base_labels: BTreeSet<&String> = base.map(|b| b.labels.iter().collect()).unwrap_or_default();
+    let ours_labels: BTreeSet<&String> = ours.labels.iter().collect();
+    let theirs_labels: BTreeSet<&String> = theirs.labels.iter().collect();
+    let labels = ours_labels
+        .union(&theirs_labels)
+        .filter(|l| !base_labels.contains(*l) || (ours_labels.contains(*l) && theirs_labels.contains(*l)))
+        .map(|l| l.to_string())
+        .collect();
+
+    let page = Page {
+        id: ours.id.clone(),
+        space_id,
+        parent_id,
+        title,
+        page_type,
+        content,
+        sections,
+        created_by_user: ours.created_by_user.clone(),
+        created_by_agent: ours.created_by_agent.clone(),
+        created_at: ours.created_at.clone(),
+        updated_at: now.to_string(),
+        version: ours.version.max(theirs.version) + 1,
+        session_id: ours.session_id.clone(),
+        labels,
+    };
+    (page, fields)
+}
+
+/// Three-way pick of a field: a change on one side wins; changes on both
+/// sides are a conflict, resolved in favour of the newer side.
+fn pick<T: PartialEq + Clone>(
+    field: &str,
+    base: Option<&T>,
+    ours: &T,
+    theirs: &T,
+    theirs_newer: bool,
+    conflicts: &mut Vec<String>,
+) -> T {
+    if ours == theirs || base == Some(theirs) {
+        ours.clone()
+    } else if base == Some(ours) {
+        theirs.clone()
+    } else {
+        conflicts.push(field.to_string());
+        if theirs_newer { theirs } else { ours }.clone()
+    }
+}
+
+/// Three-way merge of text. Overlapping changes are kept between
+/// `<<<<<<<`/`=======`/`>>>>>>>` markers and reported as a conflict.
+fn merge_text(field: &str, base: Option<&str>, ours: &str, theirs: &str, conflicts: &mut Vec<String>) -> String {
+    if ours == theirs || base == Some(theirs) {
+        ours.to_string()
+    } else if base == Some(ours) {
+        theirs.to_string()
+    } else {
+        match diffy::merge(base.unwrap_or(""), ours, theirs) {
+            Ok(merged) => merged,
+            Err(marked) => {
+                conflicts.push(field.to_string());
+                marked
+            }
+        }
+    }
+}
+
+/// Writes the differences between `ours` (the database's current state) and
+/// `merged` to the database in one transaction, counting them in `report`.
+///
+/// # Returns
+/// The IDs of pages created or updated
+fn apply(conn: &Connection, ours: &Snapshot, merged: &Snapshot, report: &mut SyncReport) -> Result<Vec<String>, KbError> {
+    let tx = conn.unchecked_transaction()?;
+    // Parents, spaces and link targets are checked at commit, so order doesn't matter.
+    tx.pragma_update(None, "defer_foreign_keys", "ON")?;
+
+    for id in ours.spaces.keys().filter(|id| !merged.spaces.contains_key(*id)) {
+        repo::remove_space(&tx, id)?;
+        report.spaces.deleted += 1;
+    }
+    for space in merged.spaces.values() {
+        if changed(ours.spaces.get(&space.id), space, &mut report.spaces) {
+            repo::put_space(&tx, space)?;
+        }
+    }
+
+    let mut written = Vec::new();
+    for page in merged.pages.values() {
+        if changed(ours.pages.get(&page.id), page, &mut report.pages) {
+            repo::put_page(&tx, page)?;
+            written.push(page.id.clone());
+        }
+    }
+    for id in ours.pages.keys().filter(|id| !merged.pages.contains_key(*id)) {
+        repo::remove_page(&tx, id)?;
+        report.pages.deleted += 1;
+    }
+
+    for key in ours.links.keys() {
+        if !merged.links.contains_key(key) {
+            // Links to deleted pages are already gone.
+            if merged.pages.contains_key(&key.0) && merged.pages.contains_key(&key.1) {
+                repo::delete_link(&tx, &key.0, &key.1)?;
+            }
+            report.links.deleted += 1;
+        }
+    }
+    for link in merged.links.values() {
+        let key = (link.source_id.clone(), link.target_id.clone());
+        if changed(ours.links.get(&key), link, &mut report.links) {
+            repo::put_link(&tx, link)?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(written)
+}
+
+/// Whether a merged record differs from the current one, counting it as
+/// created or updated if so.
+fn changed<T: PartialEq>(current: Option<&T>, merged: &T, counts: &mut SyncCounts) -> bool {
+    match current {
+        None => counts.created += 1,
+        Some(current) if current != merged => counts.updated += 1,
+        Some(_) => return false,
+    }
+    true
+}
+
+// =============================================================================
+// Git
+// =============================================================================
+
+/// Runs git in a working tree, committing as an `AgentIdentity`.
+struct Git<'a> {
+    dir: &'a Path,
+    name: String,
+    email: String,
+}
+
+impl<'a> Git<'a> {
+    fn new(dir: &'a Path, identity: &AgentIdentity) -> Self {
+        let clean = |s: &str| s.chars().filter(|c| !matches!(c, '<' | '>' | '\n')).collect::<String>();
+        Git {
+            dir,
+            name: format!("{} ({})", clean(&identity.user), clean(&identity.agent)),
+            email: format!("{}@whatidid", clean(&identity.user).replace(' ', ".")),
+        }
+    }
+
+    /// Runs a command, returning its trimmed output.
+    fn run(&self, args: &[&str]) -> Result<String, KbError> {
+        let out = self.output(args, None)?;
+        Ok(String::from_utf8_lossy(&out).trim().to_string())
+    }
+
+    /// Runs a command that may fail without that being an error (such as
+    /// `rev-parse --verify`), returning `None` if it does.
+    fn try_run(&self, args: &[&str]) -> Result<Option<String>, KbError> {
+        match self.run(args) {
+            Ok(out) => Ok(Some(out)),
+            Err(KbError::Git(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn output(&self, args: &[&str], input: Option<Vec<u8>>) -> Result<Vec<u8>, KbError> {
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(self.dir)
+            .args(["-c", "commit.gpgsign=false", "-c", "init.defaultBranch=main", "-c", "core.autocrlf=false"])
+            .args(args)
+            .env("GIT_AUTHOR_NAME", &self.name)
+            .env("GIT_AUTHOR_EMAIL", &self.email)
+            .env("GIT_COMMITTER_NAME", &self.name)
+            .env("GIT_COMMITTER_EMAIL", &self.email)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| KbError::Git(format!("could not run git: {}", e)))?;
+        // Feed stdin from another thread so a full stdout pipe can't deadlock us.
+        let writer = input.map(|input| {
+            let mut stdin = child.stdin.take().expect("stdin is piped");
+            std::thread::spawn(move || stdin.write_all(&input))
+        });
+        let out = child.wait_with_output()?;
+        if let Some(writer) = writer {
+            writer.join().expect("stdin writer panicked")?;
+        }
+        if !out.status.success() {
+            return Err(KbError::Git(format!(
+                "git {}: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        Ok(out.stdout)
+    }
+
+    fn is_ancestor(&self, ancestor: &str, of: &str) -> Result<bool, KbError> {
+        Ok(self.try_run(&["merge-base", "--is-ancestor", ancestor, of])?.is_some())
+    }
+
+    /// Stages the space folders, including deleted ones, and commits if
+    /// anything changed (or a merge is in progress). Other files in the
+    /// repository are left alone. Returns the new commit.
+    fn commit_layout(&self, message: &str) -> Result<Option<String>, KbError> {
+        let mut paths = Vec::new();
+        collect_files(self.dir, "", &mut paths)?;
+        let tracked = self.output(&["ls-files", "-z"], None)?;
+        paths.extend(
+            tracked
+                .split(|b| *b == 0)
+                .filter(|p| !p.is_empty())
+                .map(|p| String::from_utf8_lossy(p).to_string()),
+        );
+        let dirs: BTreeSet<&str> = paths
+            .iter()
+            .filter(|p| is_layout_path(p))
+            .filter_map(|p| p.split('/').next())
+            .collect();
+
+        let changed = if dirs.is_empty() {
+            false
+        } else {
+            let mut add = vec!["add", "-A", "--"];
+            add.extend(&dirs);
+            self.run(&add)?;
+            let mut status = vec!["status", "--porcelain", "--"];
+            status.extend(&dirs);
+            !self.run(&status)?.is_empty()
+        };
+        let merging = self.try_run(&["rev-parse", "--verify", "--quiet", "MERGE_HEAD"])?.is_some();
+        if !merging && !changed {
+            return Ok(None);
+        }
+        self.run(&["commit", "-q", "-m", message])?;
+        Ok(Some(self.run(&["rev-parse", "HEAD"])?))
+    }
+
+    /// Reads every file in a commit, keyed by path.
+    fn read_rev(&self, rev: &str) -> Result<BTreeMap<String, String>, KbError> {
+        let listing = self.output(&["ls-tree", "-r", "-z", rev], None)?;
+        let mut entries = Vec::new();
+        for entry in listing.split(|b| *b == 0).filter(|e| !e.is_empty()) {
+            let entry = String::from_utf8_lossy(entry);
+            let Some((meta, path)) = entry.split_once('\t') else {
+                continue;
+            };
+            if let [_, "blob", sha] = meta.split(' ').collect::<Vec<_>>()[..] {
+                entries.push((path.to_string(), sha.to_string()));
+            }
+        }
+        if entries.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        let input: String = entries.iter().map(|(_, sha)| format!("{}\n", sha)).collect();
+        let out = self.output(&["cat-file", "--batch"], Some(input.into_bytes()))?;
+        let mut files = BTreeMap::new();
+        let mut rest = &out[..];
+        for (path, _) in entries {
+            let newline = rest
+                .iter()
+                .position(|b| *b == b'\n')
+                .ok_or_else(|| KbError::Git("truncated cat-file output".to_string()))?;
+            let header = String::from_utf8_lossy(&rest[..newline]).to_string();
+            let size: usize = header
+                .rsplit(' ')
+                .next()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| KbError::Git(format!("unexpected cat-file output: {}", header)))?;
+            let start = newline + 1;
+            let body = rest
+                .get(start..start + size)
+                .ok_or_else(|| KbError::Git("truncated cat-file output".to_string()))?;
+            files.insert(path, String::from_utf8_lossy(body).to_string());
+            rest = rest.get(start + size + 1..).unwrap_or(&[]);
+        }
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::LinkRelation;
+
+    fn identity(user: &str) -> AgentIdentity {
+        AgentIdentity {
+            user: user.to_string(),
+            session: None,
+            agent: "test-agent".to_string(),
+        }
+    }
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        Git::new(dir, &identity("test")).run(args).unwrap()
+    }
+
+    /// A bare "remote" and a knowledge base synced to it.
+    fn remote_with_page() -> (tempfile::TempDir, Connection, Page) {
+        let tmp = tempfile::tempdir().unwrap();
+        let remote = tmp.path().join("remote.git");
+        git(tmp.path(), &["init", "-q", "--bare", remote.to_str().unwrap()]);
+
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "team", "Team", "").unwrap();
+        let page = repo::create_page(
+            &conn,
+            &space.id,
+            None,
+            "Notes",
+            PageType::Reference,
+            "one\ntwo\nthree\n",
+            None,
+            &["shared".to_string()],
+            "alice",
+            "a",
+        )
+        .unwrap();
+        sync(&conn, &tmp.path().join("alice"), remote.to_str(), true, &identity("alice")).unwrap();
+        (tmp, conn, page)
+    }
+
+    /// A second knowledge base cloned from the remote by syncing an empty database.
+    fn clone(tmp: &tempfile::TempDir, name: &str) -> Connection {
+        let conn = db::test_connection();
+        let remote = tmp.path().join("remote.git");
+        let report = sync(&conn, &tmp.path().join(name), remote.to_str(), true, &identity(name)).unwrap();
+        assert!(report.committed.is_none());
+        conn
+    }
+
+    #[test]
+    fn test_render_and_parse_roundtrip() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "team", "Team", "d").unwrap();
+        let parent = repo::create_page(&conn, &space.id, None, "Parent", PageType::Runbook, "", None, &[], "u", "a")
+            .unwrap();
+        let sections = serde_json::json!({"overview": "Why\n\nmulti-line", "steps": "1. go"});
+        let child = repo::create_page(
+            &conn,
+            &space.id,
+            Some(&parent.id),
+            "Child",
+            PageType::Runbook,
+            "",
+            Some(&sections),
+            &["b".to_string(), "a".to_string()],
+            "u",
+            "a",
+        )
+        .unwrap();
+        repo::create_link(&conn, &child.id, &parent.id, LinkRelation::Elaborates, "note", "u", "a").unwrap();
+
+        let snapshot = snapshot_db(&conn).unwrap();
+        let files = render_files(&snapshot);
+        assert!(files.contains_key("team/space.json"));
+        assert!(files.contains_key("team/links.json"));
+        assert!(files.contains_key(&format!("team/pages/{}.md", child.id)));
+        assert_eq!(parse_files(&files).unwrap(), snapshot);
+        // Deterministic: rendering again gives the same files.
+        assert_eq!(render_files(&snapshot), files);
+    }
+
+    #[test]
+    fn test_merge_page_combines_edits() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "team", "Team", "").unwrap();
+        let base = repo::create_page(
+            &conn,
+            &space.id,
+            None,
+            "Notes",
+            PageType::Reference,
+            "one\ntwo\nthree\n",
+            None,
+            &["keep".to_string(), "drop".to_string()],
+            "u",
+            "a",
+        )
+        .unwrap();
+        let ours = Page {
+            content: "ONE\ntwo\nthree\n".to_string(),
+            labels: vec!["keep".to_string(), "mine".to_string()],
+            version: 2,
+            ..base.clone()
+        };
+        let theirs = Page {
+            title: "Better notes".to_string(),
+            content: "one\ntwo\nTHREE\n".to_string(),
+            labels: vec!["drop".to_string(), "keep".to_string(), "theirs".to_string()],
+            version: 3,
+            ..base.clone()
+        };
+
+        let (merged, fields) = merge_page(Some(&base), &ours, &theirs, "2026-01-01T00:00:00+00:00");
+        assert!(fields.is_empty());
+        assert_eq!(merged.title, "Better notes");
+        assert_eq!(merged.content, "ONE\ntwo\nTHREE\n");
+        assert_eq!(merged.labels, vec!["keep", "mine", "theirs"]);
+        assert_eq!(merged.version, 4);
+    }
+
+    #[test]
+    fn test_merge_page_marks_conflicting_lines() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "team", "Team", "").unwrap();
+        let base =
+            repo::create_page(&conn, &space.id, None, "Notes", PageType::Reference, "one\n", None, &[], "u", "a")
+                .unwrap();
+        let ours = Page { content: "mine\n".to_string(), ..base.clone() };
+        let theirs = Page { content: "theirs\n".to_string(), ..base.clone() };
+
+        let (merged, fields) = merge_page(Some(&base), &ours, &theirs, "2026-01-01T00:00:00+00:00");
+        assert_eq!(fields, vec!["content"]);
+        assert!(merged.content.contains("<<<<<<<"));
+        assert!(merged.content.contains("mine") && merged.content.contains("theirs"));
+    }
+
+    #[test]
+    fn test_sync_without_remote_commits_changes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("kb");
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "team", "Team", "").unwrap();
+        let page = repo::create_page(&conn, &space.id, None, "Notes", PageType::Reference, "x", None, &[], "u", "a")
+            .unwrap();
+
+        let report = sync(&conn, &dir, None, true, &identity("alice")).unwrap();
+        assert!(report.committed.is_some());
+        assert!(!report.pushed);
+        assert_eq!(git(&dir, &["log", "-1", "--format=%an"]), "alice (test-agent)");
+
+        // Nothing changed: no new commit. A deleted page's file is removed.
+        assert!(sync(&conn, &dir, None, true, &identity("alice")).unwrap().committed.is_none());
+        repo::delete_page(&conn, &page.id).unwrap();
+        assert!(sync(&conn, &dir, None, true, &identity("alice")).unwrap().committed.is_some());
+        assert!(!dir.join(format!("team/pages/{}.md", page.id)).exists());
+    }
+
+    #[test]
+    fn test_sync_inside_another_repository() {
+        let tmp = tempfile::tempdir().unwrap();
+        git(tmp.path(), &["init", "-q"]);
+        fs::write(tmp.path().join("notes.txt"), "not ours").unwrap();
+        let dir = tmp.path().join("kb");
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "team", "Team", "").unwrap();
+        repo::create_page(&conn, &space.id, None, "Notes", PageType::Reference, "x", None, &[], "u", "a").unwrap();
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("README.md"), "hand-written").unwrap();
+        assert!(sync(&conn, &dir, None, false, &identity("alice")).unwrap().committed.is_some());
+        assert!(dir.join(".git").exists());
+        // The enclosing repository has nothing staged, and the knowledge
+        // base's commit holds only layout files.
+        assert_eq!(git(tmp.path(), &["diff", "--cached", "--name-only"]), "");
+        let committed = git(&dir, &["ls-tree", "-r", "--name-only", "HEAD"]);
+        assert!(committed.lines().all(is_layout_path), "{}", committed);
+        assert!(sync(&conn, &dir, None, false, &identity("alice")).unwrap().committed.is_none());
+    }
+
+    #[test]
+    fn test_sync_pulls_and_merges_concurrent_edits() {
+        let (tmp, alice, page) = remote_with_page();
+        let bob = clone(&tmp, "bob");
+        assert_eq!(repo::get_page(&bob, &page.id).unwrap().content, "one\ntwo\nthree\n");
+
+        // Both edit different lines of the same page; bob also adds a page.
+        repo::update_page(&alice, &page.id, None, Some("ONE\ntwo\nthree\n"), None, None).unwrap();
+        repo::update_page(&bob, &page.id, None, Some("one\ntwo\nTHREE\n"), None, None).unwrap();
+        let space = repo::get_space_by_slug(&bob, "team").unwrap();
+        let extra =
+            repo::create_page(&bob, &space.id, None, "Extra", PageType::Reference, "", None, &[], "bob", "b").unwrap();
+        sync(&bob, &tmp.path().join("bob"), None, true, &identity("bob")).unwrap();
+
+        let report = sync(&alice, &tmp.path().join("alice"), None, true, &identity("alice")).unwrap();
+        assert!(report.committed.is_some());
+        assert!(report.merged.is_some());
+        assert!(report.pushed);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.pages.created, 1);
+        assert_eq!(report.pages.updated, 1);
+        assert_eq!(repo::get_page(&alice, &page.id).unwrap().content, "ONE\ntwo\nTHREE\n");
+        assert_eq!(repo::get_page(&alice, &extra.id).unwrap().title, "Extra");
+
+        // Bob fast-forwards to the merge and ends up with the same knowledge base.
+        let report = sync(&bob, &tmp.path().join("bob"), None, true, &identity("bob")).unwrap();
+        assert!(report.committed.is_none());
+        assert_eq!(snapshot_db(&bob).unwrap(), snapshot_db(&alice).unwrap());
+    }
+
+    #[test]
+    fn test_sync_pulls_deletions_and_reports_conflicts() {
+        let (tmp, alice, page) = remote_with_page();
+        let bob = clone(&tmp, "bob");
+        let space = repo::get_space_by_slug(&alice, "team").unwrap();
+        let doomed =
+            repo::create_page(&alice, &space.id, None, "Doomed", PageType::Reference, "", None, &[], "u", "a").unwrap();
+        sync(&alice, &tmp.path().join("alice"), None, true, &identity("alice")).unwrap();
+        sync(&bob, &tmp.path().join("bob"), None, true, &identity("bob")).unwrap();
+
+        repo::delete_page(&bob, &doomed.id).unwrap();
+        repo::update_page(&bob, &page.id, None, Some("bob\n"), None, None).unwrap();
+        sync(&bob, &tmp.path().join("bob"), None, true, &identity("bob")).unwrap();
+        repo::update_page(&alice, &page.id, None, Some("alice\n"), None, None).unwrap();
+
+        let report = sync(&alice, &tmp.path().join("alice"), None, true, &identity("alice")).unwrap();
+        assert_eq!(report.pages.deleted, 1);
+        assert!(matches!(repo::get_page(&alice, &doomed.id), Err(KbError::NotFound(_))));
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].fields, vec!["content"]);
+        let content = repo::get_page(&alice, &page.id).unwrap().content;
+        assert!(content.contains("alice") && content.contains("bob"));
+    }
+}
@@ -0,0 +1,585 @@
+//! Ingestion of Claude Code session transcripts as `session-log` pages.
+//!
+//! A transcript is a JSONL file with one entry per message. Assistant
+//! entries carry text and `tool_use` blocks; user entries carry the user's
+//! requests and the `tool_result` blocks answering each tool call. From
+//! these `parse` collects the requests, files written, shell commands,
+//! commits and failed tool calls. Subagents write their own transcripts in
+//! `<transcript>/subagents/*.jsonl`; their tool activity is folded into the
+//! session's, but their prompts aren't the user's requests and are skipped.
+//!
+//! `ingest` writes the result as a page whose sections follow the
+//! session-log schema. The session ID is recorded in `page_sources`, so
+//! ingesting the same session again updates its page instead of creating a
+//! second one.
+
+use crate::context::truncate;
+use crate::db::{self, KbError};
+use crate::markdown::slugify;
+use crate::models::{AgentIdentity, IngestAction, PageType, ToolError, TranscriptReport};
+use crate::repo;
+use crate::summarize::{Extractive, Summarizer, Summary};
+use crate::wikilinks;
+use rusqlite::Connection;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `page_sources.kind` for pages ingested from transcripts. The source path
+/// is the session ID.
+pub const SOURCE_KIND: &str = "transcript";
+
+/// Label on every ingested session log.
+pub const SESSION_LABEL: &str = "session";
+
+/// Tools whose calls write the file named in their input.
+const FILE_TOOLS: &[&str] = &["Write", "Edit", "MultiEdit", "NotebookEdit"];
+
+/// Longest request or error message kept, in characters.
+const MAX_LINE_CHARS: usize = 200;
+
+/// Who said something in a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speaker {
+    User,
+    Assistant,
+}
+
+/// One message's text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub speaker: Speaker,
+    pub text: String,
+}
+
+/// What happened in a session, as recorded in its transcript.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transcript {
+    pub session_id: Option<String>,
+    /// The session's working directory.
+    pub cwd: Option<PathBuf>,
+    /// Timestamp of the first entry, as written (RFC 3339).
+    pub started_at: Option<String>,
+    /// The user's requests, in order.
+    pub requests: Vec<String>,
+    /// The conversation's text: requests and the assistant's replies, in order.
+    pub turns: Vec<Turn>,
+    /// Files written, sorted and without repeats.
+    pub files_changed: Vec<String>,
+    /// Shell commands run, in order.
+    pub commands: Vec<String>,
+    /// First line of each successful `git commit`'s output.
+    pub commits: Vec<String>,
+    pub errors: Vec<ToolError>,
+}
+
+impl Transcript {
+    /// Adds a subagent's tool activity to this session's.
+    fn absorb(&mut self, sub: Transcript) {
+        self.files_changed.extend(sub.files_changed);
+        self.files_changed.sort();
+        self.files_changed.dedup();
+        self.commands.extend(sub.commands);
+        for commit in sub.commits {
+            if !self.commits.contains(&commit) {
+                self.commits.push(commit);
+            }
+        }
+        self.errors.extend(sub.errors);
+    }
+
+    /// Files written, relative to the working directory where they're inside it.
+    pub fn relative_files(&self) -> Vec<String> {
+        self.files_changed
+            .iter()
+            .map(|file| {
+                self.cwd
+                    .as_ref()
+                    .and_then(|cwd| Path::new(file).strip_prefix(cwd).ok())
+                    .map(|rel| rel.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| file.clone())
+            })
+            .collect()
+    }
+
+    /// The conversation as plain text, one `USER:` or `ASSISTANT:` paragraph
+    /// per message.
+    pub fn conversation_text(&self) -> String {
+        self.turns
+            .iter()
+            .map(|turn| match turn.speaker {
+                Speaker::User => format!("USER: {}", turn.text),
+                Speaker::Assistant => format!("ASSISTANT: {}", turn.text),
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Parses a transcript's JSONL text. Lines that aren't JSON, and entries of
+/// kinds other than user and assistant messages, are skipped.
+pub fn parse(text: &str) -> Transcript {
+    let mut transcript = Transcript::default();
+    // tool_use ID -> (tool name, shell command if it's a Bash call)
+    let mut tool_uses: HashMap<String, (String, Option<String>)> = HashMap::new();
+
+    for line in text.lines() {
+        let Ok(entry) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        if transcript.session_id.is_none() {
+            transcript.session_id = entry["sessionId"].as_str().map(str::to_string);
+        }
+        if transcript.cwd.is_none() {
+            transcript.cwd = entry["cwd"].as_str().map(PathBuf::from);
+        }
+        if transcript.started_at.is_none() {
+            transcript.started_at = entry["timestamp"].as_str().map(str::to_string);
+        }
+        let flagged = |key: &str| entry[key].as_bool().unwrap_or(false);
+        let content = &entry["message"]["content"];
+
+        match entry["type"].as_str() {
+            Some("assistant") if !flagged("isApiErrorMessage") => {
+                for block in content.as_array().into_iter().flatten() {
+                    match block["type"].as_str() {
+                        Some("text") => {
+                            if let Some(text) = block["text"].as_str().filter(|t| !t.trim().is_empty()) {
+                                transcript.turns.push(Turn {
+                                    speaker: Speaker::Assistant,
+                                    text: text.to_string(),
+                                });
+                            }
+                        }
+                        Some("tool_use") => {
+                            let name = block["name"].as_str().unwrap_or_default().to_string();
+                            let input = &block["input"];
+                            let command = input["command"].as_str().filter(|_| name == "Bash").map(str::to_string);
+                            if let Some(command) = &command {
+                                transcript.commands.push(command.clone());
+                            }
+                            if FILE_TOOLS.contains(&name.as_str()) {
+                                if let Some(file) = input["file_path"].as_str().or(input["notebook_path"].as_str()) {
+                                    transcript.files_changed.push(file.to_string());
+                                }
+                            }
+                            if let Some(id) = block["id"].as_str() {
+                                tool_uses.insert(id.to_string(), (name, command));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Some("user") if !flagged("isMeta") && !flagged("isCompactSummary") => {
+                let mut request = Vec::new();
+                match content {
+                    Value::String(text) => request.push(text.as_str()),
+                    Value::Array(blocks) => {
+                        for block in blocks {
+                            match block["type"].as_str() {
+                                Some("text") => request.extend(block["text"].as_str()),
+                                Some("tool_result") => {
+                                    let Some((tool, command)) =
+                                        block["tool_use_id"].as_str().and_then(|id| tool_uses.get(id))
+                                    else {
+                                        continue;
+                                    };
+                                    record_result(&mut transcript, tool, command.as_deref(), block);
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                let request = request.join("\n");
+                let request = request.trim();
+                // Command output, reminders and interruptions aren't requests.
+                if !request.is_empty() && !request.starts_with('<') && !request.starts_with("[Request interrupted") {
+                    transcript.requests.push(request.to_string());
+                    transcript.turns.push(Turn {
+                        speaker: Speaker::User,
+                        text: request.to_string(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    transcript.files_changed.sort();
+    transcript.files_changed.dedup();
+    transcript
+}
+
+/// Records a tool result: an error if it failed, a commit if it's the output
+/// of a successful `git commit`.
+fn record_result(transcript: &mut Transcript, tool: &str, command: Option<&str>, block: &Value) {
+    let output = match &block["content"] {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().filter_map(|p| p["text"].as_str()).collect::<Vec<_>>().join("\n"),
+        _ => String::new(),
+    };
+    let first_line = output.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or_default();
+
+    if block["is_error"].as_bool().unwrap_or(false) {
+        transcript.errors.push(ToolError {
+            tool: tool.to_string(),
+            message: truncate(first_line, MAX_LINE_CHARS),
+        });
+    } else if command.is_some_and(|c| c.contains("git commit"))
+        && first_line.starts_with('[')
+        && !transcript.commits.iter().any(|c| c == first_line)
+    {
+        transcript.commits.push(first_line.to_string());
+    }
+}
+
+/// Reads a transcript and its subagents' transcripts. The session ID falls
+/// back to the file name when no entry carries one.
+///
+/// # Errors
+/// Returns `KbError::Io` if a file can't be read.
+pub fn read(path: &Path) -> Result<Transcript, KbError> {
+    let mut transcript = parse(&fs::read_to_string(path)?);
+    if transcript.session_id.is_none() {
+        transcript.session_id = path.file_stem().map(|s| s.to_string_lossy().into_owned());
+    }
+
+    let subagents = path.with_extension("").join("subagents");
+    if subagents.is_dir() {
+        let mut files: Vec<PathBuf> = fs::read_dir(&subagents)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+            .collect();
+        files.sort();
+        for file in files {
+            transcript.absorb(parse(&fs::read_to_string(&file)?));
+        }
+    }
+    Ok(transcript)
+}
+
+/// The page title for a session: its first request, shortened.
+pub fn default_title(transcript: &Transcript) -> String {
+    match transcript.requests.first().and_then(|r| r.lines().map(str::trim).find(|l| !l.is_empty())) {
+        Some(line) => truncate(line, 80),
+        None => format!("Session {}", transcript.session_id.as_deref().unwrap_or("(unknown)")),
+    }
+}
+
+/// Builds the session-log sections for a transcript and its summary.
+pub fn sections(transcript: &Transcript, summary: &Summary) -> Value {
+    let activity = format!(
+        "{} requests, {} commands run, {} files changed, {} commits, {} failed tool calls.",
+        transcript.requests.len(),
+        transcript.commands.len(),
+        transcript.files_changed.len(),
+        transcript.commits.len(),
+        transcript.errors.len()
+    );
+    let questions = if summary.open_questions.is_empty() {
+        open_questions(transcript)
+    } else {
+        summary.open_questions.clone()
+    };
+
+    serde_json::json!({
+        "summary": format!("{}\n\n{}", summary.summary, activity),
+        "open_questions": bullet_list(questions),
+        "files_changed": bullet_list(transcript.relative_files()),
+        "commits": bullet_list(transcript.commits.clone()),
+    })
+}
+
+/// Questions the assistant left the user with in its last reply.
+pub fn open_questions(transcript: &Transcript) -> Vec<String> {
+    let Some(last) = transcript.turns.iter().rev().find(|t| t.speaker == Speaker::Assistant) else {
+        return Vec::new();
+    };
+    last.text
+        .lines()
+        .flat_map(|line| line.split_inclusive(['.', '!', '?']))
+        .map(|sentence| sentence.trim().trim_start_matches(['-', '*', ' ']).trim())
+        .filter(|sentence| sentence.ends_with('?'))
+        .map(str::to_string)
+        .collect()
+}
+
+fn bullet_list(items: Vec<String>) -> String {
+    if items.is_empty() {
+        "None".to_string()
+    } else {
+        items.iter().map(|item| format!("- {}", item)).collect::<Vec<_>>().join("\n")
+    }
+}
+
+/// Adds `text` to `labels` as a slug, unless it has no letters or digits to
+/// make one from or the label is already there.
+fn push_label(labels: &mut Vec<String>, text: &str) {
+    if !text.chars().any(|c| c.is_ascii_alphanumeric()) {
+        return;
+    }
+    let label = slugify(text);
+    if !labels.contains(&label) {
+        labels.push(label);
+    }
+}
+
+/// Writes a transcript as a session-log page in a space, or updates the page
+/// written for the same session before.
+///
+/// The page is labelled `session` and with the working directory's name, so
+/// logs can be filtered by project, plus any labels the summarizer suggests.
+/// If the summarizer fails, the extractive summary is used instead and the
+/// failure is reported as a warning.
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `transcript` - The parsed transcript (see `read`)
+/// * `space_id` - Space to create the page in
+/// * `title` - Page title (default: the summarizer's, else `default_title`)
+/// * `summarizer` - Writes the summary section
+/// * `identity` - Creator of a new page
+///
+/// # Errors
+/// Returns `KbError::InvalidInput` if the transcript has no session ID, or
+/// `KbError::Db` if a write fails.
+pub fn ingest(
+    conn: &Connection,
+    transcript: &Transcript,
+    space_id: &str,
+    title: Option<&str>,
+    summarizer: &dyn Summarizer,
+    identity: &AgentIdentity,
+) -> Result<TranscriptReport, KbError> {
+    let session_id = transcript
+        .session_id
+        .clone()
+        .ok_or_else(|| KbError::InvalidInput("The transcript has no session ID".to_string()))?;
+    let mut warnings = Vec::new();
+    let (summary, summarizer_name) = match summarizer.summarize(transcript) {
+        Ok(summary) => (summary, summarizer.name()),
+        Err(e) => {
+            let fallback = Extractive::default();
+            warnings.push(format!(
+                "The {} summarizer failed ({}); used the {} summary instead",
+                summarizer.name(),
+                e,
+                fallback.name()
+            ));
+            (fallback.summarize(transcript)?, fallback.name())
+        }
+    };
+    let title = title
+        .map(str::to_string)
+        .or_else(|| summary.title.clone())
+        .unwrap_or_else(|| default_title(transcript));
+    let sections = sections(transcript, &summary);
+
+    let mut labels = vec![SESSION_LABEL.to_string()];
+    if let Some(name) = transcript.cwd.as_ref().and_then(|cwd| cwd.file_name()) {
+        push_label(&mut labels, &name.to_string_lossy());
+    }
+    for label in &summary.labels {
+        push_label(&mut labels, label);
+    }
+
+    // The page, its source and its links land together or not at all.
+    let tx = db::begin_unless_nested(conn)?;
+    let existing = match repo::get_page_source(conn, SOURCE_KIND, &session_id)? {
+        Some(id) => match repo::get_page(conn, &id) {
+            Ok(page) => Some(page),
+            Err(KbError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        },
+        None => None,
+    };
+
+    let (page_id, action) = match existing {
+        Some(page) => {
+            let missing: Vec<String> = labels.iter().filter(|l| !page.labels.contains(l)).cloned().collect();
+            if page.title == title && page.sections.as_ref() == Some(&sections) && missing.is_empty() {
+                (page.id, IngestAction::Unchanged)
+            } else {
+                repo::update_page(conn, &page.id, Some(&title), None, Some(&sections), None)?;
+                repo::record_page_session(conn, &page.id, identity)?;
+                if !missing.is_empty() {
+                    let mut all = page.labels.clone();
+                    all.extend(missing);
+                    repo::set_labels(conn, &page.id, &all)?;
+                }
+                (page.id, IngestAction::Updated)
+            }
+        }
+        None => {
+            let page = repo::create_page(
+                conn,
+                space_id,
+                None,
+                &title,
+                PageType::SessionLog,
+                "",
+                Some(&sections),
+                &labels,
+                &identity.user,
+                &identity.agent,
+            )?;
+            repo::set_page_source(conn, &page.id, SOURCE_KIND, &session_id)?;
+            repo::record_page_session(conn, &page.id, identity)?;
+            (page.id, IngestAction::Created)
+        }
+    };
+    if action != IngestAction::Unchanged {
+        wikilinks::sync_mentions_reporting(conn, &page_id, identity, &mut warnings)?;
+    }
+    if let Some(tx) = tx {
+        tx.commit()?;
+    }
+
+    Ok(TranscriptReport {
+        session_id,
+        page_id,
+        title,
+        action,
+        summarizer: summarizer_name.to_string(),
+        warnings,
+        requests: transcript.requests.clone(),
+        files_changed: transcript.relative_files(),
+        commands: transcript.commands.clone(),
+        commits: transcript.commits.clone(),
+        errors: transcript.errors.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> AgentIdentity {
+        AgentIdentity {
+            user: "u".to_string(),
+            agent: "claude-code".to_string(),
+            session: None,
+        }
+    }
+
+    fn line(value: Value) -> String {
+        format!("{}\n", value)
+    }
+
+    fn user(content: Value) -> String {
+        line(serde_json::json!({"type": "user", "sessionId": "s-1", "cwd": "/work/my-app", "message": {"role": "user", "content": content}}))
+    }
+
+    fn assistant(content: Value) -> String {
+        line(serde_json::json!({"type": "assistant", "sessionId": "s-1", "cwd": "/work/my-app", "message": {"role": "assistant", "content": content}}))
+    }
+
+    fn tool_use(id: &str, name: &str, input: Value) -> String {
+        assistant(serde_json::json!([{"type": "tool_use", "id": id, "name": name, "input": input}]))
+    }
+
+    fn tool_result(id: &str, content: &str, is_error: bool) -> String {
+        user(serde_json::json!([{"type": "tool_result", "tool_use_id": id, "content": content, "is_error": is_error}]))
+    }
+
+    fn sample() -> String {
+        let mut text = String::new();
+        text.push_str(&line(serde_json::json!({"type": "summary", "summary": "old"})));
+        text.push_str(&user(serde_json::json!("Fix the flaky parser test")));
+        text.push_str(&line(serde_json::json!({"type": "user", "isMeta": true, "message": {"content": "<command-name>/clear</command-name>"}})));
+        text.push_str(&assistant(serde_json::json!([{"type": "text", "text": "Looking at the parser."}])));
+        text.push_str(&tool_use("t1", "Edit", serde_json::json!({"file_path": "/work/my-app/src/parser.rs"})));
+        text.push_str(&tool_result("t1", "ok", false));
+        text.push_str(&tool_use("t2", "Bash", serde_json::json!({"command": "cargo test"})));
+        text.push_str(&tool_result("t2", "error: test failed\nmore", true));
+        text.push_str(&tool_use("t3", "Bash", serde_json::json!({"command": "git commit -m 'Fix parser'"})));
+        text.push_str(&tool_result("t3", "[main 1a2b3c4] Fix parser\n 1 file changed", false));
+        text.push_str("not json\n");
+        text.push_str(&user(serde_json::json!([{"type": "text", "text": "Thanks, also update the README"}])));
+        text.push_str(&tool_use("t4", "Write", serde_json::json!({"file_path": "/work/my-app/README.md"})));
+        text.push_str(&assistant(serde_json::json!([{"type": "text", "text": "Done. Should I also bump the version? Let me know."}])));
+        text
+    }
+
+    #[test]
+    fn test_parse_extracts_activity() {
+        let t = parse(&sample());
+        assert_eq!(t.session_id.as_deref(), Some("s-1"));
+        assert_eq!(t.requests, vec!["Fix the flaky parser test", "Thanks, also update the README"]);
+        assert_eq!(t.files_changed, vec!["/work/my-app/README.md", "/work/my-app/src/parser.rs"]);
+        assert_eq!(t.relative_files(), vec!["README.md", "src/parser.rs"]);
+        assert_eq!(t.commands, vec!["cargo test", "git commit -m 'Fix parser'"]);
+        assert_eq!(t.commits, vec!["[main 1a2b3c4] Fix parser"]);
+        assert_eq!(
+            t.errors,
+            vec![ToolError {
+                tool: "Bash".to_string(),
+                message: "error: test failed".to_string()
+            }]
+        );
+        assert_eq!(open_questions(&t), vec!["Should I also bump the version?"]);
+    }
+
+    #[test]
+    fn test_push_label_skips_empty_and_repeated_slugs() {
+        let mut labels = vec![SESSION_LABEL.to_string()];
+        for text in ["Session", "日本語", "--", "My App", "my-app"] {
+            push_label(&mut labels, text);
+        }
+        assert_eq!(labels, vec!["session".to_string(), "my-app".to_string()]);
+    }
+
+    #[test]
+    fn test_read_includes_subagents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s-1.jsonl");
+        fs::write(&path, sample()).unwrap();
+        let subagents = dir.path().join("s-1").join("subagents");
+        fs::create_dir_all(&subagents).unwrap();
+        let mut sub = user(serde_json::json!("Subagent prompt, not a user request"));
+        sub.push_str(&tool_use("x1", "Write", serde_json::json!({"file_path": "/work/my-app/src/lexer.rs"})));
+        fs::write(subagents.join("agent-1.jsonl"), sub).unwrap();
+
+        let t = read(&path).unwrap();
+        assert_eq!(t.requests.len(), 2);
+        assert!(t.files_changed.contains(&"/work/my-app/src/lexer.rs".to_string()));
+    }
+
+    #[test]
+    fn test_ingest_is_idempotent_per_session() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "session-log", "Session Log", "").unwrap();
+        let transcript = parse(&sample());
+
+        let first = ingest(&conn, &transcript, &space.id, None, &Extractive::default(), &identity()).unwrap();
+        assert_eq!(first.action, IngestAction::Created);
+        assert_eq!(first.title, "Fix the flaky parser test");
+        let page = repo::get_page(&conn, &first.page_id).unwrap();
+        assert_eq!(page.page_type, PageType::SessionLog);
+        assert_eq!(page.labels, vec!["my-app", "session"]);
+        assert!(page.content.starts_with(
+            "## Summary\nFix the flaky parser test Looking at the parser. Thanks, also update the README\n\n\
+             2 requests, 2 commands run, 2 files changed, 1 commits, 1 failed tool calls."
+        ));
+        assert!(page.content.contains("## Open Questions\n- Should I also bump the version?"));
+        assert!(page.content.contains("## Git Commits\n- [main 1a2b3c4] Fix parser"));
+        assert_eq!(page.sections.as_ref().unwrap()["files_changed"], "- README.md\n- src/parser.rs");
+
+        let again = ingest(&conn, &transcript, &space.id, None, &Extractive::default(), &identity()).unwrap();
+        assert_eq!(again.action, IngestAction::Unchanged);
+        assert_eq!(again.page_id, first.page_id);
+
+        let mut longer = sample();
+        longer.push_str(&user(serde_json::json!("One more thing")));
+        let updated = ingest(&conn, &parse(&longer), &space.id, Some("Parser fixes"), &Extractive::default(), &identity()).unwrap();
+        assert_eq!(updated.action, IngestAction::Updated);
+        assert_eq!(updated.page_id, first.page_id);
+        let page = repo::get_page(&conn, &first.page_id).unwrap();
+        assert_eq!(page.title, "Parser fixes");
+        assert_eq!(page.version, 2);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::db::KbError;
-use crate::models::{Link, Page, SearchResult, Space};
-use crate::{repo, search};
+use crate::models::{Link, Page, SearchResult, SessionSummary, Space};
+use crate::{output, repo, search};
 use rusqlite::Connection;
 
 /// Which pane has focus.
@@ -33,6 +33,15 @@ pub enum NavState {
         /// The state to return to when pressing Esc.
         previous: Box<NavState>,
     },
+    SessionList {
+        /// The state to return to when pressing Esc.
+        previous: Box<NavState>,
+    },
+    /// The pages one session created or modified.
+    SessionPages {
+        session_id: String,
+        previous: Box<NavState>,
+    },
 }
 
 /// Display item in the left pane list.
@@ -41,6 +50,7 @@ pub enum ListItem {
     Space(Space),
     Page { page: Page, expandable: bool },
     SearchResult(SearchResult),
+    Session(SessionSummary),
 }
 
 impl ListItem {
@@ -54,6 +64,9 @@ impl ListItem {
             ListItem::SearchResult(r) => {
                 format!("{} [{}]", r.page.title, r.page.page_type)
             }
+            ListItem::Session(s) => {
+                format!("{} ({} created, {} modified)", s.session_id, s.pages_created, s.pages_modified)
+            }
         }
     }
 }
@@ -146,6 +159,18 @@ impl App {
                 let results = search::search_pages(conn, &params)?;
                 results.into_iter().map(ListItem::SearchResult).collect()
             }
+            NavState::SessionList { .. } => {
+                let sessions = repo::list_sessions(conn)?;
+                sessions.into_iter().map(ListItem::Session).collect()
+            }
+            NavState::SessionPages { session_id, .. } => {
+                let activity = repo::get_session(conn, session_id)?;
+                let mut pages = Vec::new();
+                for written in activity.pages_created.iter().chain(&activity.pages_modified) {
+                    pages.push(repo::get_page(conn, &written.page.id)?);
+                }
+                pages.into_iter().map(|page| ListItem::Page { page, expandable: false }).collect()
+            }
         };
 
         // Clamp cursor
@@ -198,6 +223,16 @@ impl App {
             ListItem::Page { page, .. } => {
                 self.build_page_content(conn, page)?;
             }
+            ListItem::Session(s) => {
+                self.content_lines.push(format!("Session: {}", s.session_id));
+                self.content_lines.push(format!("Agents:  {}", s.agents.join(", ")));
+                self.content_lines.push(format!("First:   {}", s.first_write_at));
+                self.content_lines.push(format!("Last:    {}", s.last_write_at));
+                self.content_lines.push(String::new());
+                self.content_lines.push(format!("Pages created:  {}", s.pages_created));
+                self.content_lines.push(format!("Pages modified: {}", s.pages_modified));
+                self.content_lines.push(format!("Links created:  {}", s.links_created));
+            }
             ListItem::SearchResult(r) => {
                 self.build_page_content(conn, &r.page)?;
                 if !r.excerpt.is_empty() {
@@ -218,6 +253,9 @@ impl App {
             self.content_lines.push(format!("Labels:  {}", page.labels.join(", ")));
         }
         self.content_lines.push(format!("Author:  {} / {}", page.created_by_user, page.created_by_agent));
+        if let Some(session) = &page.session_id {
+            self.content_lines.push(format!("Session: {}", session));
+        }
         self.content_lines.push(format!("Created: {}", page.created_at));
         self.content_lines.push(format!("Updated: {}", page.updated_at));
         self.content_lines.push(format!("Version: {}", page.version));
@@ -277,19 +315,35 @@ impl App {
             }
         }
 
-        // Links
-        let page_links = repo::list_links(conn, &page.id)?;
+        // Links, with the page on the other end shown by title
+        let page_links = repo::list_links_expanded(conn, &page.id)?;
         if !page_links.is_empty() {
             self.content_lines.push(String::new());
             self.content_lines.push("--- Links ---".to_string());
-            for link in &page_links {
+            for expanded in &page_links {
+                let link = &expanded.link;
                 if link.source_id == page.id {
-                    self.content_lines.push(format!("  {} -> {}", link.relation, link.target_id));
+                    self.content_lines.push(format!(
+                        "  {} -> {}",
+                        link.relation,
+                        output::format_page_ref(&expanded.target)
+                    ));
                 } else {
-                    self.content_lines.push(format!("  {} <- {}", link.relation, link.source_id));
+                    self.content_lines.push(format!(
+                        "  {} <- {}",
+                        link.relation,
+                        output::format_page_ref(&expanded.source)
+                    ));
+                }
+                if !link.note.is_empty() {
+                    self.content_lines.push(format!("      {}", link.note));
                 }
+                self.content_lines.push(format!(
+                    "      by {} / {}",
+                    link.created_by_user, link.created_by_agent
+                ));
             }
-            self.links = page_links;
+            self.links = page_links.into_iter().map(|l| l.link).collect();
         }
 
         Ok(())
@@ -375,6 +429,16 @@ impl App {
                 self.focus = Focus::Content;
                 Ok(true)
             }
+            ListItem::Session(session) => {
+                let previous = Box::new(self.nav_state.clone());
+                self.nav_state = NavState::SessionPages {
+                    session_id: session.session_id,
+                    previous,
+                };
+                self.cursor = 0;
+                self.load_items(conn)?;
+                Ok(true)
+            }
         }
     }
 
@@ -406,7 +470,9 @@ impl App {
                 self.cursor = 0;
                 self.load_items(conn)?;
             }
-            NavState::SearchResults { previous, .. } => {
+            NavState::SearchResults { previous, .. }
+            | NavState::SessionList { previous }
+            | NavState::SessionPages { previous, .. } => {
                 self.nav_state = *previous.clone();
                 self.cursor = 0;
                 self.load_items(conn)?;
@@ -415,6 +481,19 @@ impl App {
         Ok(())
     }
 
+    /// Show the sessions that wrote to the knowledge base; selecting one
+    /// filters the list to its pages.
+    pub fn show_sessions(&mut self, conn: &Connection) -> Result<(), KbError> {
+        if matches!(self.nav_state, NavState::SessionList { .. }) {
+            return Ok(());
+        }
+        let previous = Box::new(self.nav_state.clone());
+        self.nav_state = NavState::SessionList { previous };
+        self.cursor = 0;
+        self.focus = Focus::List;
+        self.load_items(conn)
+    }
+
     /// Enter search mode.
     pub fn enter_search(&mut self) {
         self.mode = Mode::Search;
@@ -451,7 +530,7 @@ impl App {
         }
 
         let page = match &self.items[self.cursor] {
-            ListItem::Space(_) => return Ok(None),
+            ListItem::Space(_) | ListItem::Session(_) => return Ok(None),
             ListItem::Page { page, .. } => repo::get_page(conn, &page.id)?,
             ListItem::SearchResult(r) => repo::get_page(conn, &r.page.id)?,
         };
@@ -473,7 +552,7 @@ impl App {
         }
 
         let page = match &self.items[self.cursor] {
-            ListItem::Space(_) => return Ok(None),
+            ListItem::Space(_) | ListItem::Session(_) => return Ok(None),
             ListItem::Page { page, .. } => repo::get_page(conn, &page.id)?,
             ListItem::SearchResult(r) => repo::get_page(conn, &r.page.id)?,
         };
@@ -511,6 +590,8 @@ impl App {
                 format!("{} / {}", space.slug, parent.title)
             }
             NavState::SearchResults { query, .. } => format!("Search: {}", query),
+            NavState::SessionList { .. } => "Sessions".to_string(),
+            NavState::SessionPages { session_id, .. } => format!("Session: {}", session_id),
         }
     }
 
@@ -519,7 +600,7 @@ impl App {
         match self.mode {
             Mode::Search => "Type query, Enter:submit, Esc:cancel",
             Mode::Normal => match self.focus {
-                Focus::List => "j/k:nav  Enter:select  e:edit  L:labels  Esc:back  /:search  q:quit",
+                Focus::List => "j/k:nav  Enter:select  e:edit  L:labels  Esc:back  /:search  S:sessions  q:quit",
                 Focus::Content => "j/k:scroll  e:edit  L:labels  h/Esc:back  /:search  q:quit",
             },
         }
@@ -539,6 +620,22 @@ mod tests {
         conn.execute_batch(sql2).expect("run migration 002");
         let sql3 = include_str!("../../migrations/003_timestamps.sql");
         conn.execute_batch(sql3).expect("run migration 003");
+        let sql4 = include_str!("../../migrations/004_link_policy.sql");
+        conn.execute_batch(sql4).expect("run migration 004");
+        let sql5 = include_str!("../../migrations/005_link_annotations.sql");
+        conn.execute_batch(sql5).expect("run migration 005");
+        let sql6 = include_str!("../../migrations/006_mentions_relation.sql");
+        conn.execute_batch(sql6).expect("run migration 006");
+        let sql7 = include_str!("../../migrations/007_page_sources.sql");
+        conn.execute_batch(sql7).expect("run migration 007");
+        let sql8 = include_str!("../../migrations/008_sessions.sql");
+        conn.execute_batch(sql8).expect("run migration 008");
+        let sql9 = include_str!("../../migrations/009_page_reads.sql");
+        conn.execute_batch(sql9).expect("run migration 009");
+        let sql10 = include_str!("../../migrations/010_feedback.sql");
+        conn.execute_batch(sql10).expect("run migration 010");
+        let sql11 = include_str!("../../migrations/011_merge_bases.sql");
+        conn.execute_batch(sql11).expect("run migration 011");
         conn
     }
 
@@ -645,6 +742,43 @@ mod tests {
         assert_eq!(app.mode, Mode::Normal);
     }
 
+    #[test]
+    fn test_app_sessions() {
+        let conn = setup_test_db();
+        let space = repo::create_space(&conn, "s", "S", "").unwrap();
+        let page = repo::create_page(
+            &conn, &space.id, None, "Session Page", crate::models::PageType::Reference,
+            "content", None, &[], "u", "a",
+        ).unwrap();
+        repo::create_page(
+            &conn, &space.id, None, "Other Page", crate::models::PageType::Reference,
+            "content", None, &[], "u", "a",
+        ).unwrap();
+        let identity = crate::models::AgentIdentity {
+            user: "u".to_string(),
+            agent: "a".to_string(),
+            session: Some("sess-1".to_string()),
+        };
+        repo::record_page_session(&conn, &page.id, &identity).unwrap();
+
+        let mut app = App::new();
+        app.load_initial(&conn).unwrap();
+        app.show_sessions(&conn).unwrap();
+        assert_eq!(app.left_pane_title(), "Sessions");
+        assert_eq!(app.items.len(), 1);
+        assert_eq!(app.items[0].display_text(), "sess-1 (1 created, 0 modified)");
+
+        app.select(&conn).unwrap();
+        assert_eq!(app.left_pane_title(), "Session: sess-1");
+        assert_eq!(app.items.len(), 1);
+        assert_eq!(app.items[0].display_text(), "    Session Page");
+
+        app.go_back(&conn).unwrap();
+        assert!(matches!(app.nav_state, NavState::SessionList { .. }));
+        app.go_back(&conn).unwrap();
+        assert!(matches!(app.nav_state, NavState::SpaceList));
+    }
+
     #[test]
     fn test_app_nav_state_titles() {
         let app = App::new();
@@ -751,10 +885,34 @@ mod tests {
             slug: "slug".into(),
             name: "Name".into(),
             description: String::new(),
+            allow_cross_space_links: true,
             created_at: String::new(),
             updated_at: String::new(),
         };
         let item = ListItem::Space(space);
         assert_eq!(item.display_text(), "Name (slug)");
     }
+
+    #[test]
+    fn test_page_content_shows_link_titles() {
+        let conn = setup_test_db();
+        let space = repo::create_space(&conn, "s", "S", "").unwrap();
+        let a = repo::create_page(
+            &conn, &space.id, None, "Alpha", crate::models::PageType::Reference,
+            "content", None, &[], "u", "a",
+        ).unwrap();
+        let b = repo::create_page(
+            &conn, &space.id, None, "Beta", crate::models::PageType::Decision,
+            "content", None, &[], "u", "a",
+        ).unwrap();
+        repo::create_link(&conn, &a.id, &b.id, crate::models::LinkRelation::RelatesTo, "why", "u", "a").unwrap();
+
+        let mut app = App::new();
+        app.load_initial(&conn).unwrap();
+        app.select(&conn).unwrap(); // Drill into space; Alpha is first by title
+        assert!(app.content_lines.iter().any(|l| l == "  relates-to -> Beta [decision] (s)"));
+        assert!(app.content_lines.iter().any(|l| l.trim() == "why"));
+        assert!(!app.content_lines.iter().any(|l| l.contains(&b.id)));
+        assert_eq!(app.links.len(), 1);
+    }
 }
@@ -24,6 +24,7 @@ pub enum Action {
     SearchBackspace,
     Edit,
     EditLabels,
+    ShowSessions,
     None,
 }
 
@@ -64,6 +65,7 @@ fn map_list_key(app: &App, key: KeyEvent) -> Action {
         KeyCode::Char('/') => Action::EnterSearch,
         KeyCode::Char('e') => Action::Edit,
         KeyCode::Char('L') => Action::EditLabels,
+        KeyCode::Char('S') => Action::ShowSessions,
         KeyCode::Char('G') => Action::JumpToBottom,
         KeyCode::Char('g') => {
             if app.pending_g {
@@ -184,6 +186,9 @@ pub fn apply_action(
                 app.pending_label_edit = Some(edit_info);
             }
         }
+        Action::ShowSessions => {
+            app.show_sessions(conn)?;
+        }
         Action::None => {}
     }
     Ok(())
@@ -233,6 +238,7 @@ mod tests {
         assert_eq!(map_key(&app, make_key(KeyCode::Tab)), Action::FocusContent);
         assert_eq!(map_key(&app, make_key(KeyCode::Char('/'))), Action::EnterSearch);
         assert_eq!(map_key(&app, make_key(KeyCode::Char('G'))), Action::JumpToBottom);
+        assert_eq!(map_key(&app, make_key(KeyCode::Char('S'))), Action::ShowSessions);
     }
 
     #[test]
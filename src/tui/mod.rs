@@ -16,8 +16,9 @@ use ratatui::Terminal;
 use rusqlite::Connection;
 
 use crate::db::KbError;
+use crate::models::AgentIdentity;
 
-use crate::repo;
+use crate::{repo, wikilinks};
 
 use self::app::App;
 use self::event::{map_key, apply_action, Action};
@@ -41,7 +42,9 @@ impl Drop for TerminalGuard {
 }
 
 /// Entry point for the TUI browser. Called from main.rs on `browse` subcommand.
-pub fn run_browse(conn: &Connection) -> Result<(), KbError> {
+/// `identity` is recorded on links created from [[wiki links]] in edited
+/// pages, and its session on the edits.
+pub fn run_browse(conn: &Connection, identity: &AgentIdentity) -> Result<(), KbError> {
     // Install a panic hook that restores the terminal before printing the panic.
     let original_hook = panic::take_hook();
     panic::set_hook(Box::new(move |info| {
@@ -135,6 +138,8 @@ pub fn run_browse(conn: &Connection) -> Result<(), KbError> {
                                 ) {
                                     Ok(_) => {
                                         let _ = repo::add_label(conn, &page_id, "human-edited");
+                                        let _ = repo::record_page_session(conn, &page_id, identity);
+                                        let _ = wikilinks::sync_mentions(conn, &page_id, identity);
                                         app.load_items(conn)?;
                                     }
                                     Err(KbError::VersionConflict { expected, actual }) => {
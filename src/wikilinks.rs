@@ -0,0 +1,443 @@
+//! Wiki-style `[[references]]` in page content.
+//!
+//! Pages can refer to other pages inline with `[[Page Title]]`, `[[page-id]]`
+//! or `[[space-slug:Page Title]]`. After every write the references in a
+//! page's content and sections are resolved and mirrored into the `links`
+//! table as `mentions` links, so backlinks work without an explicit
+//! `link create`. References that cannot be resolved are returned to the
+//! caller rather than treated as errors.
+
+use crate::db::{self, KbError};
+use crate::models::{AgentIdentity, LinkRelation, Page, UnresolvedLink};
+use crate::repo;
+use rusqlite::Connection;
+
+/// Extract the distinct `[[...]]` references from markdown text, in order of
+/// first appearance.
+///
+/// Obsidian-style suffixes are stripped: `[[Title|alias]]` and
+/// `[[Title#Heading]]` both refer to `Title`. References inside fenced code
+/// blocks are ignored, as are empty or nested brackets.
+pub fn parse_references(text: &str) -> Vec<String> {
+    let mut refs: Vec<String> = Vec::new();
+    let mut in_fence = false;
+
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(start) = rest.find("[[") {
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("]]") else {
+                break;
+            };
+            let inner = &after[..end];
+            rest = &after[end + 2..];
+
+            if inner.contains('[') || inner.contains(']') {
+                continue;
+            }
+            let target = inner.split('|').next().unwrap_or("");
+            let target = target.split('#').next().unwrap_or("").trim();
+            if !target.is_empty() && !refs.iter().any(|r| r == target) {
+                refs.push(target.to_string());
+            }
+        }
+    }
+
+    refs
+}
+
+/// Replace the target of every `[[...]]` reference in markdown text.
+///
+/// `rewrite` receives the target (without any `#heading` or `|alias`) and
+/// returns the new target, or `None` to leave the reference as it is. Any
+/// heading or alias suffix is kept. References are found by the same rules
+/// as `parse_references`, so code blocks are left alone.
+pub fn rewrite_references(text: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    replace_references(text, |target, suffix| {
+        rewrite(target).map(|new_target| format!("[[{}{}]]", new_target, suffix))
+    })
+}
+
+/// Replace every `[[...]]` reference in markdown text, brackets included.
+///
+/// `replace` receives the target and the `#heading` / `|alias` suffix (empty
+/// if there is none) and returns the replacement text, or `None` to leave the
+/// reference as it is. Code blocks are left alone.
+pub fn replace_references(text: &str, mut replace: impl FnMut(&str, &str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_fence = false;
+
+    for line in text.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        if in_fence || line.trim_start().starts_with("```") {
+            out.push_str(line);
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(start) = rest.find("[[") {
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("]]") else {
+                break;
+            };
+            let inner = &after[..end];
+            out.push_str(&rest[..start]);
+            rest = &after[end + 2..];
+
+            let split = inner.find(['#', '|']).unwrap_or(inner.len());
+            let (target, suffix) = inner.split_at(split);
+            let replacement = if inner.contains('[') || inner.contains(']') || target.trim().is_empty() {
+                None
+            } else {
+                replace(target.trim(), suffix)
+            };
+            match replacement {
+                Some(text) => out.push_str(&text),
+                None => {
+                    out.push_str("[[");
+                    out.push_str(inner);
+                    out.push_str("]]");
+                }
+            }
+        }
+        out.push_str(rest);
+    }
+
+    out
+}
+
+/// Collect references from a page's content and every string section.
+pub fn page_references(page: &Page) -> Vec<String> {
+    let mut refs = parse_references(&page.content);
+    if let Some(obj) = page.sections.as_ref().and_then(|s| s.as_object()) {
+        for text in obj.values().filter_map(|v| v.as_str()) {
+            for r in parse_references(text) {
+                if !refs.contains(&r) {
+                    refs.push(r);
+                }
+            }
+        }
+    }
+    refs
+}
+
+/// Look up page IDs by title (case-insensitive), optionally within one space.
+fn find_by_title(conn: &Connection, title: &str, space_id: Option<&str>) -> Result<Vec<String>, KbError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id FROM pages
+             WHERE title = ?1 COLLATE NOCASE AND (?2 IS NULL OR space_id = ?2)
+             ORDER BY created_at",
+        )
+        .map_err(KbError::Db)?;
+    let ids = stmt
+        .query_map(rusqlite::params![title, space_id], |row| row.get(0))
+        .map_err(KbError::Db)?
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(KbError::Db)?;
+    Ok(ids)
+}
+
+/// Resolve one reference to a page ID, relative to the page containing it.
+///
+/// Resolution order:
+/// 1. `space-slug:Title` where the prefix is an existing space slug
+/// 2. An exact page ID
+/// 3. A title in the source page's own space
+/// 4. A title that is unique across all spaces
+///
+/// # Returns
+/// `Ok(Ok(id))` when resolved, `Ok(Err(reason))` when the reference is
+/// missing or ambiguous.
+pub fn resolve_reference(
+    conn: &Connection,
+    source: &Page,
+    reference: &str,
+) -> Result<Result<String, String>, KbError> {
+    if let Some((prefix, title)) = reference.split_once(':') {
+        match repo::get_space_by_slug(conn, prefix.trim()) {
+            Ok(space) => {
+                let title = title.trim();
+                let ids = find_by_title(conn, title, Some(&space.id))?;
+                return Ok(match ids.len() {
+                    0 => Err(format!("No page titled '{}' in space '{}'", title, space.slug)),
+                    1 => Ok(ids[0].clone()),
+                    n => Err(format!("Ambiguous: {} pages titled '{}' in space '{}'", n, title, space.slug)),
+                });
+            }
+            Err(KbError::NotFound(_)) => {} // Not a space prefix; the colon is part of a title.
+            Err(e) => return Err(e),
+        }
+    }
+
+    match repo::get_page(conn, reference) {
+        Ok(_) => return Ok(Ok(reference.to_string())),
+        Err(KbError::NotFound(_)) => {}
+        Err(e) => return Err(e),
+    }
+
+    let local = find_by_title(conn, reference, Some(&source.space_id))?;
+    match local.len() {
+        1 => return Ok(Ok(local[0].clone())),
+        n if n > 1 => {
+            return Ok(Err(format!("Ambiguous: {} pages titled '{}' in this space", n, reference)));
+        }
+        _ => {}
+    }
+
+    let global = find_by_title(conn, reference, None)?;
+    Ok(match global.len() {
+        0 => Err(format!("No page titled '{}'", reference)),
+        1 => Ok(global[0].clone()),
+        n => Err(format!(
+            "Ambiguous: {} pages titled '{}' in other spaces; use [[space:title]]",
+            n, reference
+        )),
+    })
+}
+
+/// Runs `sync_mentions` for a page written in bulk, describing each
+/// unresolved reference as a warning.
+pub fn sync_mentions_reporting(
+    conn: &Connection,
+    page_id: &str,
+    identity: &AgentIdentity,
+    warnings: &mut Vec<String>,
+) -> Result<(), KbError> {
+    let unresolved = sync_mentions(conn, page_id, identity)?;
+    if !unresolved.is_empty() {
+        let title = repo::get_page(conn, page_id)?.title;
+        for link in unresolved {
+            warnings.push(format!("Page '{}': unresolved [[{}]]: {}", title, link.reference, link.reason));
+        }
+    }
+    Ok(())
+}
+
+/// Bring a page's outgoing `mentions` links in line with the references in
+/// its content and sections.
+///
+/// Mentions that are no longer referenced are removed. A reference to a page
+/// the source already links to explicitly is left alone — the explicit
+/// relation wins. Self-references are ignored. References that do not
+/// resolve, or that break the link rules (e.g. a cross-space policy), are
+/// returned as unresolved. New links are created by `identity`, in its
+/// session.
+///
+/// # Errors
+/// Returns `KbError::NotFound` if the page doesn't exist, or `KbError::Db`
+/// if the update fails.
+pub fn sync_mentions(
+    conn: &Connection,
+    page_id: &str,
+    identity: &AgentIdentity,
+) -> Result<Vec<UnresolvedLink>, KbError> {
+    let page = repo::get_page(conn, page_id)?;
+    let mut unresolved = Vec::new();
+    let mut targets: Vec<String> = Vec::new();
+
+    for reference in page_references(&page) {
+        match resolve_reference(conn, &page, &reference)? {
+            Ok(id) if id == page.id => {}
+            Ok(id) => {
+                match repo::validate_link(conn, &page.id, &id, LinkRelation::Mentions) {
+                    Ok(()) => {
+                        if !targets.contains(&id) {
+                            targets.push(id);
+                        }
+                    }
+                    Err(e @ (KbError::InvalidInput(_) | KbError::LinkCycle { .. })) => {
+                        unresolved.push(UnresolvedLink { reference, reason: e.to_string() });
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(reason) => unresolved.push(UnresolvedLink { reference, reason }),
+        }
+    }
+
+    let tx = db::begin_unless_nested(conn)?;
+
+    let existing: Vec<String> = {
+        let mut stmt = conn
+            .prepare("SELECT target_id FROM links WHERE source_id = ?1 AND relation = 'mentions'")
+            .map_err(KbError::Db)?;
+        let ids = stmt
+            .query_map([&page.id], |row| row.get(0))
+            .map_err(KbError::Db)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(KbError::Db)?;
+        ids
+    };
+
+    for stale in existing.iter().filter(|id| !targets.contains(id)) {
+        conn.execute(
+            "DELETE FROM links WHERE source_id = ?1 AND target_id = ?2 AND relation = 'mentions'",
+            rusqlite::params![page.id, stale],
+        )
+        .map_err(KbError::Db)?;
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    for target in targets.iter().filter(|id| !existing.contains(id)) {
+        // OR IGNORE: an explicit link between the two pages takes precedence.
+        let inserted = conn
+            .execute(
+                "INSERT OR IGNORE INTO links (source_id, target_id, relation, note, created_by_user, created_by_agent, created_at, updated_at)
+                 VALUES (?1, ?2, 'mentions', '', ?3, ?4, ?5, ?5)",
+                rusqlite::params![page.id, target, identity.user, identity.agent, now],
+            )
+            .map_err(KbError::Db)?;
+        if inserted > 0 {
+            repo::record_link_session(conn, &page.id, target, identity)?;
+        }
+    }
+
+    if let Some(tx) = tx {
+        tx.commit()?;
+    }
+
+    Ok(unresolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PageType;
+
+    fn make_page(conn: &Connection, space_id: &str, title: &str, content: &str) -> Page {
+        repo::create_page(conn, space_id, None, title, PageType::Reference, content, None, &[], "u", "a")
+            .expect("create page")
+    }
+
+    fn identity() -> AgentIdentity {
+        AgentIdentity { user: "u".to_string(), agent: "a".to_string(), session: None }
+    }
+
+    #[test]
+    fn test_rewrite_references() {
+        let text = "See [[a]], [[b#Intro|the b page]] and [[missing]].\n```\n[[a]]\n```\n[[ ]] [[a]]";
+        let rewritten = rewrite_references(text, |target| match target {
+            "a" => Some("Alpha".to_string()),
+            "b" => Some("Beta".to_string()),
+            _ => None,
+        });
+        assert_eq!(
+            rewritten,
+            "See [[Alpha]], [[Beta#Intro|the b page]] and [[missing]].\n```\n[[a]]\n```\n[[ ]] [[Alpha]]"
+        );
+        assert_eq!(rewrite_references("no links [[open", |_| None), "no links [[open");
+    }
+
+    #[test]
+    fn test_parse_references_variants() {
+        let text = "See [[Postgres decision]] and [[ops:Runbook|the runbook]].\n\
+                    Also [[Postgres decision#Context]] and [[]] and [[a [b] c]].";
+        assert_eq!(parse_references(text), vec!["Postgres decision", "ops:Runbook"]);
+    }
+
+    #[test]
+    fn test_parse_references_skips_code_fences() {
+        let text = "```\n[[Not a link]]\n```\n[[Real link]]";
+        assert_eq!(parse_references(text), vec!["Real link"]);
+    }
+
+    #[test]
+    fn test_page_references_include_sections() {
+        let page = Page {
+            id: "p".into(),
+            space_id: "s".into(),
+            parent_id: None,
+            title: "T".into(),
+            page_type: PageType::Decision,
+            content: "[[A]]".into(),
+            sections: Some(serde_json::json!({"context": "[[B]] and [[A]]"})),
+            created_by_user: String::new(),
+            created_by_agent: String::new(),
+            created_at: String::new(),
+            updated_at: String::new(),
+            version: 1,
+            labels: vec![],
+            session_id: None,
+        };
+        assert_eq!(page_references(&page), vec!["A", "B"]);
+    }
+
+    #[test]
+    fn test_sync_mentions_creates_and_prunes_links() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "s", "S", "").unwrap();
+        let target = make_page(&conn, &space.id, "Postgres decision", "");
+        let source = make_page(&conn, &space.id, "Notes", "See [[postgres DECISION]] and [[Missing]].");
+
+        let in_session = AgentIdentity { session: Some("s1".to_string()), ..identity() };
+        let unresolved = sync_mentions(&conn, &source.id, &in_session).expect("sync");
+        assert_eq!(unresolved.len(), 1);
+        assert_eq!(unresolved[0].reference, "Missing");
+
+        let backlinks = repo::list_backlinks(&conn, &target.id).expect("backlinks");
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].source_id, source.id);
+        assert_eq!(backlinks[0].relation, LinkRelation::Mentions);
+        assert_eq!(backlinks[0].session_id.as_deref(), Some("s1"));
+
+        repo::update_page(&conn, &source.id, None, Some("No more references"), None, None).unwrap();
+        sync_mentions(&conn, &source.id, &identity()).expect("sync");
+        assert!(repo::list_backlinks(&conn, &target.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sync_mentions_resolves_space_prefix_and_id() {
+        let conn = db::test_connection();
+        let here = repo::create_space(&conn, "here", "Here", "").unwrap();
+        let ops = repo::create_space(&conn, "ops", "Ops", "").unwrap();
+        let remote = make_page(&conn, &ops.id, "Deploy", "");
+        let local = make_page(&conn, &here.id, "Deploy", "");
+        let content = format!("[[ops:Deploy]] [[{}]] [[Deploy]]", local.id);
+        let source = make_page(&conn, &here.id, "Index", &content);
+
+        let unresolved = sync_mentions(&conn, &source.id, &identity()).expect("sync");
+        assert!(unresolved.is_empty());
+        assert_eq!(repo::list_backlinks(&conn, &remote.id).unwrap().len(), 1);
+        assert_eq!(repo::list_backlinks(&conn, &local.id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_sync_mentions_keeps_explicit_link() {
+        let conn = db::test_connection();
+        let space = repo::create_space(&conn, "s", "S", "").unwrap();
+        let target = make_page(&conn, &space.id, "Target", "");
+        let source = make_page(&conn, &space.id, "Source", "[[Target]]");
+        repo::create_link(&conn, &source.id, &target.id, LinkRelation::DependsOn, "", "u", "a").unwrap();
+
+        sync_mentions(&conn, &source.id, &identity()).expect("sync");
+        let links = repo::list_backlinks(&conn, &target.id).unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].relation, LinkRelation::DependsOn);
+    }
+
+    #[test]
+    fn test_sync_mentions_reports_ambiguous_titles() {
+        let conn = db::test_connection();
+        let a = repo::create_space(&conn, "a", "A", "").unwrap();
+        let b = repo::create_space(&conn, "b", "B", "").unwrap();
+        let c = repo::create_space(&conn, "c", "C", "").unwrap();
+        make_page(&conn, &a.id, "Shared", "");
+        make_page(&conn, &b.id, "Shared", "");
+        let source = make_page(&conn, &c.id, "Source", "[[Shared]]");
+
+        let unresolved = sync_mentions(&conn, &source.id, &identity()).expect("sync");
+        assert_eq!(unresolved.len(), 1);
+        assert!(unresolved[0].reason.starts_with("Ambiguous"));
+    }
+}